@@ -0,0 +1,270 @@
+use crate::common::Version;
+use crate::warnings;
+use semver::VersionReq;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Display};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+#[derive(Debug)]
+pub enum Error {
+    FetchDb(git2::Error),
+    RemoveStaleDb(io::Error),
+    ReadDb {
+        path: PathBuf,
+        error: io::Error,
+    },
+    ParseAdvisory {
+        path: PathBuf,
+        error: Box<dyn std::error::Error + Send + Sync + 'static>,
+    },
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::FetchDb(e) => {
+                write!(f, "failed to fetch the RustSec advisory database: {e}")
+            }
+            Error::RemoveStaleDb(e) => {
+                write!(
+                    f,
+                    "failed to remove the previously fetched RustSec advisory database: {e}"
+                )
+            }
+            Error::ReadDb { path, error } => {
+                write!(
+                    f,
+                    "failed to read the RustSec advisory database at {}: {error}",
+                    path.to_string_lossy()
+                )
+            }
+            Error::ParseAdvisory { path, error } => {
+                write!(
+                    f,
+                    "failed to parse the advisory {}: {error}",
+                    path.to_string_lossy()
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::FetchDb(e) => Some(e),
+            Error::RemoveStaleDb(e) => Some(e),
+            Error::ReadDb { error, .. } => Some(error),
+            Error::ParseAdvisory { error, .. } => Some(error.as_ref()),
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+const ADVISORY_DB_URL: &str = "https://github.com/rustsec/advisory-db.git";
+
+#[derive(Deserialize)]
+struct AdvisoryFile {
+    advisory: AdvisoryMetadata,
+    #[serde(default)]
+    versions: AdvisoryVersions,
+}
+
+#[derive(Deserialize)]
+struct AdvisoryMetadata {
+    id: String,
+    package: String,
+    informational: Option<String>,
+    withdrawn: Option<toml::Value>,
+}
+
+#[derive(Default, Deserialize)]
+struct AdvisoryVersions {
+    #[serde(default)]
+    patched: Vec<String>,
+    #[serde(default)]
+    unaffected: Vec<String>,
+}
+
+pub struct Advisory {
+    pub id: String,
+    pub title: String,
+    /// Set for advisories that are not vulnerabilities, e.g. "unmaintained" or "unsound".
+    pub informational: Option<String>,
+    patched: Vec<VersionReq>,
+    unaffected: Vec<VersionReq>,
+}
+
+impl Advisory {
+    pub fn is_vulnerability(&self) -> bool {
+        self.informational.is_none()
+    }
+
+    fn affects(&self, version: &semver::Version) -> bool {
+        !self
+            .patched
+            .iter()
+            .chain(self.unaffected.iter())
+            .any(|req| req.matches(version))
+    }
+}
+
+/// A vulnerable crate version replaced by a patched, semver-compatible version.
+pub struct Substitution {
+    pub vulnerable: Version,
+    pub patched: Version,
+    /// The IDs of the vulnerabilities the patched version fixes, e.g. `RUSTSEC-2023-0001`.
+    pub advisory_ids: Vec<String>,
+}
+
+/// The RustSec advisory database, indexed by crate name.
+pub struct AdvisoryDb {
+    advisories: HashMap<String, Vec<Advisory>>,
+}
+
+impl AdvisoryDb {
+    /// Clones a fresh copy of the advisory database into the specified directory and loads it.
+    pub fn fetch<P: AsRef<Path>>(db_dir_path: P) -> Result<Self> {
+        let db_dir_path = db_dir_path.as_ref();
+        if db_dir_path.exists() {
+            fs::remove_dir_all(db_dir_path).map_err(Error::RemoveStaleDb)?;
+        }
+        git2::Repository::clone(ADVISORY_DB_URL, db_dir_path).map_err(Error::FetchDb)?;
+        AdvisoryDb::open(db_dir_path)
+    }
+
+    /// Loads the advisory database from a local clone.
+    pub fn open<P: AsRef<Path>>(db_dir_path: P) -> Result<Self> {
+        let crates_dir_path = db_dir_path.as_ref().join("crates");
+        let mut advisories: HashMap<String, Vec<Advisory>> = HashMap::new();
+        for crate_dir in read_dir(&crates_dir_path)? {
+            for advisory_path in read_dir(&crate_dir)? {
+                if advisory_path.extension().is_none_or(|ext| ext != "md") {
+                    continue;
+                }
+                let (package, advisory) = match parse_advisory(&advisory_path)? {
+                    Some(parsed) => parsed,
+                    None => continue,
+                };
+                advisories.entry(package).or_default().push(advisory);
+            }
+        }
+        Ok(AdvisoryDb { advisories })
+    }
+
+    /// Returns the advisories that apply to the specified crate version.
+    pub fn advisories_for(&self, crat: &Version) -> Vec<&Advisory> {
+        let advisories = match self.advisories.get(crat.name()) {
+            Some(advisories) => advisories,
+            None => return Vec::new(),
+        };
+        let version = match semver::Version::parse(crat.version()) {
+            Ok(version) => version,
+            Err(e) => {
+                warn!(
+                    target: warnings::ADVISORY,
+                    crate_name = crat.name(),
+                    crate_version = crat.version(),
+                    "unable to check for advisories: {e}"
+                );
+                return Vec::new();
+            }
+        };
+        advisories.iter().filter(|a| a.affects(&version)).collect()
+    }
+
+    /// Returns the crate versions in the set that have at least one applicable advisory.
+    pub fn scan<'c>(&self, crates: &'c HashSet<Version>) -> Vec<(&'c Version, Vec<&Advisory>)> {
+        let mut findings = Vec::new();
+        for crat in crates {
+            let advisories = self.advisories_for(crat);
+            if !advisories.is_empty() {
+                findings.push((crat, advisories));
+            }
+        }
+        findings.sort_by(|(a, _), (b, _)| (a.name(), a.version()).cmp(&(b.name(), b.version())));
+        findings
+    }
+}
+
+fn read_dir(dir_path: &Path) -> Result<Vec<PathBuf>> {
+    let entries = fs::read_dir(dir_path).map_err(|e| Error::ReadDb {
+        path: dir_path.to_path_buf(),
+        error: e,
+    })?;
+    let mut paths = Vec::new();
+    for entry in entries {
+        let entry = entry.map_err(|e| Error::ReadDb {
+            path: dir_path.to_path_buf(),
+            error: e,
+        })?;
+        paths.push(entry.path());
+    }
+    Ok(paths)
+}
+
+/// Parses an advisory file, which holds TOML front matter in a ```toml block
+/// followed by a markdown description whose first heading is the title.
+///
+/// Returns `None` if the advisory has been withdrawn.
+fn parse_advisory(advisory_path: &Path) -> Result<Option<(String, Advisory)>> {
+    let contents = fs::read_to_string(advisory_path).map_err(|e| Error::ReadDb {
+        path: advisory_path.to_path_buf(),
+        error: e,
+    })?;
+    let parse_error = |msg: &str| Error::ParseAdvisory {
+        path: advisory_path.to_path_buf(),
+        error: msg.into(),
+    };
+
+    let front_matter = contents
+        .strip_prefix("```toml")
+        .ok_or_else(|| parse_error("missing TOML front matter"))?;
+    let end = front_matter
+        .find("```")
+        .ok_or_else(|| parse_error("unterminated TOML front matter"))?;
+    let (front_matter, description) = front_matter.split_at(end);
+
+    let advisory_file: AdvisoryFile =
+        toml::from_str(front_matter).map_err(|e| Error::ParseAdvisory {
+            path: advisory_path.to_path_buf(),
+            error: Box::new(e),
+        })?;
+    if advisory_file.advisory.withdrawn.is_some() {
+        return Ok(None);
+    }
+
+    let parse_reqs = |reqs: &[String]| -> Result<Vec<VersionReq>> {
+        reqs.iter()
+            .map(|req| {
+                VersionReq::parse(req).map_err(|e| Error::ParseAdvisory {
+                    path: advisory_path.to_path_buf(),
+                    error: Box::new(e),
+                })
+            })
+            .collect()
+    };
+    let patched = parse_reqs(&advisory_file.versions.patched)?;
+    let unaffected = parse_reqs(&advisory_file.versions.unaffected)?;
+
+    let title = description
+        .lines()
+        .find_map(|line| line.strip_prefix("# "))
+        .unwrap_or_default()
+        .trim()
+        .to_string();
+
+    let advisory = Advisory {
+        id: advisory_file.advisory.id,
+        title,
+        informational: advisory_file.advisory.informational,
+        patched,
+        unaffected,
+    };
+    Ok(Some((advisory_file.advisory.package, advisory)))
+}
@@ -0,0 +1,353 @@
+//! Reads the crates.io dependencies declared in a project's Cargo.toml, and in those of its
+//! workspace members, so they can be selected without maintaining a crate list by hand.
+
+use crate::selection::CrateSpec;
+use semver::VersionReq;
+use std::collections::HashSet;
+use std::fmt::{self, Display};
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+
+#[derive(Debug)]
+pub enum Error {
+    ReadManifest {
+        path: PathBuf,
+        error: io::Error,
+    },
+    ParseManifest {
+        path: PathBuf,
+        error: toml::de::Error,
+    },
+    ReadMembers {
+        path: PathBuf,
+        error: io::Error,
+    },
+    InvalidDependency {
+        name: String,
+        path: PathBuf,
+    },
+    InvalidVersionReq {
+        name: String,
+        path: PathBuf,
+        error: semver::Error,
+    },
+    NoWorkspaceDependency {
+        name: String,
+        path: PathBuf,
+    },
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::ReadManifest { path, error } => {
+                write!(
+                    f,
+                    "failed to read the Cargo manifest {}: {error}",
+                    path.to_string_lossy()
+                )
+            }
+            Error::ParseManifest { path, error } => {
+                write!(
+                    f,
+                    "invalid Cargo manifest {}: {error}",
+                    path.to_string_lossy()
+                )
+            }
+            Error::ReadMembers { path, error } => {
+                write!(
+                    f,
+                    "failed to list the workspace members in {}: {error}",
+                    path.to_string_lossy()
+                )
+            }
+            Error::InvalidDependency { name, path } => {
+                write!(
+                    f,
+                    "invalid dependency {name} in the Cargo manifest {}",
+                    path.to_string_lossy()
+                )
+            }
+            Error::InvalidVersionReq { name, path, error } => {
+                write!(
+                    f,
+                    "invalid version requirement of the dependency {name} in the Cargo manifest {}: {error}",
+                    path.to_string_lossy()
+                )
+            }
+            Error::NoWorkspaceDependency { name, path } => {
+                write!(
+                    f,
+                    "the dependency {name} in the Cargo manifest {} is inherited from a workspace that doesn't declare it",
+                    path.to_string_lossy()
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::ReadManifest { error, .. } => Some(error),
+            Error::ParseManifest { error, .. } => Some(error),
+            Error::ReadMembers { error, .. } => Some(error),
+            Error::InvalidDependency { .. } => None,
+            Error::InvalidVersionReq { error, .. } => Some(error),
+            Error::NoWorkspaceDependency { .. } => None,
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// The dependency tables of a manifest, at its top level or under a `[target.*]` table.
+const DEPENDENCY_TABLES: [&str; 3] = ["dependencies", "build-dependencies", "dev-dependencies"];
+
+/// Returns whether the file is a Cargo manifest rather than a selection manifest.
+pub fn is_cargo_manifest(path: &Path) -> bool {
+    path.ends_with("Cargo.toml")
+}
+
+/// Returns the crates.io dependencies declared in the Cargo.toml file, of every kind and for
+/// every target, with their version requirements. If the manifest is a workspace root, those of
+/// its members are included. Dependencies inherited with `workspace = true` take the workspace
+/// root's declaration, found by searching the parent directories if the manifest isn't the root.
+///
+/// Path and git dependencies, and those of other registries, are skipped, since they don't come
+/// from crates.io. Features aren't carried over: the whole of each dependency is selected, since
+/// the project's own features can enable more of it.
+pub fn dependencies(manifest_path: &Path) -> Result<Vec<CrateSpec>> {
+    let manifest = read_manifest(manifest_path)?;
+    let workspace = match manifest.get("workspace") {
+        Some(_) => Some(manifest.clone()),
+        None => find_workspace_root(manifest_path)?,
+    };
+
+    let mut crates = Vec::new();
+    let mut seen = HashSet::new();
+    let mut add = |manifest: &toml::Value, path: &Path| -> Result<()> {
+        for table in dependency_tables(manifest) {
+            for (key, dependency) in table {
+                let dependency = inherited(key, dependency, path, workspace.as_ref())?;
+                if let Some(spec) = crate_spec(key, dependency, path)? {
+                    if seen.insert((spec.name.clone(), spec.req.clone())) {
+                        crates.push(spec);
+                    }
+                }
+            }
+        }
+        Ok(())
+    };
+    add(&manifest, manifest_path)?;
+    if manifest.get("workspace").is_some() {
+        let root_dir_path = manifest_path.parent().unwrap_or_else(|| Path::new(""));
+        for member_path in members(root_dir_path, &manifest)? {
+            add(&read_manifest(&member_path)?, &member_path)?;
+        }
+    }
+    Ok(crates)
+}
+
+fn read_manifest(path: &Path) -> Result<toml::Value> {
+    let contents = fs::read_to_string(path).map_err(|e| Error::ReadManifest {
+        path: path.to_path_buf(),
+        error: e,
+    })?;
+    toml::from_str(&contents).map_err(|e| Error::ParseManifest {
+        path: path.to_path_buf(),
+        error: e,
+    })
+}
+
+/// Finds the manifest of the workspace the manifest belongs to, if any, in its parent
+/// directories.
+fn find_workspace_root(manifest_path: &Path) -> Result<Option<toml::Value>> {
+    let dir_path = match manifest_path.parent() {
+        Some(dir_path) => dir_path,
+        None => return Ok(None),
+    };
+    for ancestor in dir_path.ancestors().skip(1) {
+        let path = ancestor.join("Cargo.toml");
+        if !path.is_file() {
+            continue;
+        }
+        let manifest = read_manifest(&path)?;
+        if manifest.get("workspace").is_some() {
+            return Ok(Some(manifest));
+        }
+    }
+    Ok(None)
+}
+
+/// Returns the manifests of the workspace's members, expanding the `*` and `?` wildcards of
+/// their paths, minus the excluded ones.
+fn members(root_dir_path: &Path, manifest: &toml::Value) -> Result<Vec<PathBuf>> {
+    let workspace = &manifest["workspace"];
+    let strings = |key: &str| {
+        workspace
+            .get(key)
+            .and_then(|v| v.as_array())
+            .map(|a| Vec::from_iter(a.iter().filter_map(|v| v.as_str())))
+            .unwrap_or_default()
+    };
+    let excluded = HashSet::<PathBuf>::from_iter(
+        strings("exclude")
+            .into_iter()
+            .map(|path| root_dir_path.join(path)),
+    );
+    let mut manifest_paths = Vec::new();
+    for pattern in strings("members") {
+        for dir_path in expand(root_dir_path, Path::new(pattern))? {
+            let path = dir_path.join("Cargo.toml");
+            if !excluded.contains(&dir_path) && path.is_file() && !manifest_paths.contains(&path) {
+                manifest_paths.push(path);
+            }
+        }
+    }
+    Ok(manifest_paths)
+}
+
+/// Returns the directories matching the path pattern, relative to the directory.
+fn expand(dir_path: &Path, pattern: &Path) -> Result<Vec<PathBuf>> {
+    let mut dir_paths = vec![dir_path.to_path_buf()];
+    for component in pattern.components() {
+        let component = match component {
+            Component::Normal(component) => component.to_string_lossy(),
+            Component::CurDir => continue,
+            _ => {
+                dir_paths = Vec::from_iter(dir_paths.iter().map(|p| p.join(component)));
+                continue;
+            }
+        };
+        if !component.contains(['*', '?']) {
+            dir_paths = Vec::from_iter(dir_paths.iter().map(|p| p.join(&*component)));
+            continue;
+        }
+        let mut matches = Vec::new();
+        for dir_path in &dir_paths {
+            let entries = match fs::read_dir(dir_path) {
+                Ok(entries) => entries,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(e) => {
+                    return Err(Error::ReadMembers {
+                        path: dir_path.clone(),
+                        error: e,
+                    })
+                }
+            };
+            for entry in entries {
+                let entry = entry.map_err(|e| Error::ReadMembers {
+                    path: dir_path.clone(),
+                    error: e,
+                })?;
+                let name = entry.file_name();
+                if entry.path().is_dir() && wildcard_match(&component, &name.to_string_lossy()) {
+                    matches.push(entry.path());
+                }
+            }
+        }
+        matches.sort();
+        dir_paths = matches;
+    }
+    Ok(dir_paths)
+}
+
+/// Whether the name matches the pattern, where `*` matches any characters and `?` any single
+/// character.
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let pattern = Vec::from_iter(pattern.chars());
+    let name = Vec::from_iter(name.chars());
+    let (mut p, mut n) = (0, 0);
+    // Where the last `*` was, and the name position it matched up to.
+    let mut star = None;
+    while n < name.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+            p += 1;
+            n += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, n));
+            p += 1;
+        } else if let Some((star_p, star_n)) = star {
+            p = star_p + 1;
+            n = star_n + 1;
+            star = Some((star_p, star_n + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// Returns the dependency tables of the manifest, those of its `[target.*]` tables included.
+fn dependency_tables(manifest: &toml::Value) -> Vec<&toml::value::Table> {
+    let mut values = vec![manifest];
+    if let Some(targets) = manifest.get("target").and_then(|t| t.as_table()) {
+        values.extend(targets.values());
+    }
+    let mut tables = Vec::new();
+    for value in values {
+        for key in DEPENDENCY_TABLES {
+            if let Some(table) = value.get(key).and_then(|t| t.as_table()) {
+                tables.push(table);
+            }
+        }
+    }
+    tables
+}
+
+/// Returns the dependency, or the workspace's declaration of it if it is inherited with
+/// `workspace = true`.
+fn inherited<'a>(
+    key: &str,
+    dependency: &'a toml::Value,
+    path: &Path,
+    workspace: Option<&'a toml::Value>,
+) -> Result<&'a toml::Value> {
+    if dependency.get("workspace").and_then(|w| w.as_bool()) != Some(true) {
+        return Ok(dependency);
+    }
+    workspace
+        .and_then(|manifest| manifest["workspace"].get("dependencies"))
+        .and_then(|dependencies| dependencies.get(key))
+        .ok_or_else(|| Error::NoWorkspaceDependency {
+            name: key.to_string(),
+            path: path.to_path_buf(),
+        })
+}
+
+/// Returns the crate the dependency declares, unless it doesn't come from crates.io.
+fn crate_spec(key: &str, dependency: &toml::Value, path: &Path) -> Result<Option<CrateSpec>> {
+    let (name, req) = match dependency {
+        toml::Value::String(req) => (key, Some(req.as_str())),
+        toml::Value::Table(table) => {
+            if ["path", "git", "registry", "registry-index"]
+                .iter()
+                .any(|k| table.contains_key(*k))
+            {
+                return Ok(None);
+            }
+            let name = table.get("package").and_then(|p| p.as_str()).unwrap_or(key);
+            (name, table.get("version").and_then(|v| v.as_str()))
+        }
+        _ => {
+            return Err(Error::InvalidDependency {
+                name: key.to_string(),
+                path: path.to_path_buf(),
+            })
+        }
+    };
+    let spec = match req {
+        Some(req) => {
+            let req = VersionReq::parse(req).map_err(|e| Error::InvalidVersionReq {
+                name: name.to_string(),
+                path: path.to_path_buf(),
+                error: e,
+            })?;
+            CrateSpec::with_req(name, req)
+        }
+        None => CrateSpec::new(name),
+    };
+    Ok(Some(spec))
+}
@@ -0,0 +1,206 @@
+use crate::dst_registry::STATE_DIR;
+use memmap2::Mmap;
+use rayon::prelude::*;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fmt::{self, Display};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+#[derive(Debug)]
+pub enum Error {
+    ListFiles(walkdir::Error),
+    HashFile { path: PathBuf, error: io::Error },
+    WriteManifest(io::Error),
+    ReadManifest(io::Error),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::ListFiles(e) => {
+                write!(
+                    f,
+                    "error writing checksums: failed to list mirror files: {e}"
+                )
+            }
+            Error::HashFile { path, error } => {
+                write!(
+                    f,
+                    "error writing checksums: failed to hash {}: {error}",
+                    path.to_string_lossy()
+                )
+            }
+            Error::WriteManifest(e) => {
+                write!(
+                    f,
+                    "error writing checksums: failed to write the {SHA256SUMS_FILE} file: {e}"
+                )
+            }
+            Error::ReadManifest(e) => {
+                write!(
+                    f,
+                    "error verifying checksums: failed to read the {SHA256SUMS_FILE} file: {e}"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::ListFiles(e) => Some(e),
+            Error::HashFile { error, .. } => Some(error),
+            Error::WriteManifest(e) => Some(e),
+            Error::ReadManifest(e) => Some(e),
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+pub const SHA256SUMS_FILE: &str = "SHA256SUMS";
+
+/// The size from which files are hashed through a memory map rather than read into a buffer,
+/// which saves copying them but costs setting up the map.
+const MMAP_MIN_SIZE: u64 = 1024 * 1024;
+
+/// Returns the SHA256 digest of the bytes as a lowercase hex string.
+pub fn sha256(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
+
+/// Returns the SHA256 digest of the file as a lowercase hex string.
+pub fn sha256_file<P: AsRef<Path>>(path: P) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    if file.metadata()?.len() < MMAP_MIN_SIZE {
+        io::copy(&mut file, &mut hasher)?;
+    } else {
+        // SAFETY: the map is only read while it exists, and mirror files aren't written while
+        // they're hashed: a .crate file is written once when its version is stored, and the
+        // scheduler never verifies while a refresh is running. A file truncated by something
+        // else meanwhile would fault rather than hash wrongly.
+        let map = unsafe { Mmap::map(&file)? };
+        hasher.update(&map[..]);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Returns the paths, relative to the directory, of every file under it, sorted.
+pub fn list_files<P: AsRef<Path>>(dir_path: P) -> Result<Vec<PathBuf>> {
+    let dir_path = dir_path.as_ref();
+    let mut files = Vec::new();
+    for entry in WalkDir::new(dir_path).sort_by_file_name() {
+        let entry = entry.map_err(Error::ListFiles)?;
+        if entry.file_type().is_file() {
+            files.push(entry.path().strip_prefix(dir_path).unwrap().to_path_buf());
+        }
+    }
+    Ok(files)
+}
+
+/// Returns the paths, relative to the mirror directory, of every file that is part of the mirror,
+/// i.e. excluding micrio's own state, sorted.
+pub fn list_mirror_files<P: AsRef<Path>>(mirror_dir_path: P) -> Result<Vec<PathBuf>> {
+    Ok(list_files(mirror_dir_path)?
+        .into_iter()
+        .filter(|f| !f.starts_with(STATE_DIR))
+        .collect())
+}
+
+/// Writes a SHA256SUMS file in the mirror directory covering every file in the mirror,
+/// in the format understood by `sha256sum --check`.
+///
+/// Returns the number of files covered.
+pub fn write_sha256sums<P: AsRef<Path>>(mirror_dir_path: P) -> Result<usize> {
+    Ok(write_sha256sums_with(mirror_dir_path, &HashMap::new())?.len())
+}
+
+/// Like `write_sha256sums`, but takes the digests of the files in `known_digests`, by path
+/// relative to the mirror directory, as they are instead of hashing the files, e.g. those of
+/// .crate files checked against their index checksum when stored.
+///
+/// Returns the files covered with their digests.
+pub fn write_sha256sums_with<P: AsRef<Path>>(
+    mirror_dir_path: P,
+    known_digests: &HashMap<PathBuf, String>,
+) -> Result<Vec<(PathBuf, String)>> {
+    let mirror_dir_path = mirror_dir_path.as_ref();
+    let files = list_mirror_files(mirror_dir_path)?
+        .into_iter()
+        .filter(|f| f != Path::new(SHA256SUMS_FILE))
+        .collect::<Vec<_>>();
+
+    let digests = files
+        .par_iter()
+        .map(|file| match known_digests.get(file) {
+            Some(digest) => Ok(digest.clone()),
+            None => sha256_file(mirror_dir_path.join(file)).map_err(|e| Error::HashFile {
+                path: file.clone(),
+                error: e,
+            }),
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let manifest =
+        File::create(mirror_dir_path.join(SHA256SUMS_FILE)).map_err(Error::WriteManifest)?;
+    let mut manifest = BufWriter::new(manifest);
+    let files = Vec::from_iter(files.into_iter().zip(digests));
+    for (file, digest) in &files {
+        let file = file.to_string_lossy().replace('\\', "/");
+        writeln!(manifest, "{digest}  {file}").map_err(Error::WriteManifest)?;
+    }
+    manifest.flush().map_err(Error::WriteManifest)?;
+    Ok(files)
+}
+
+/// Checks every file listed in the mirror's SHA256SUMS file against its digest. The files are
+/// hashed on all cores, with idle threads taking work from busy ones, since a few large files
+/// would otherwise hold up the rest.
+///
+/// Returns the paths, relative to the mirror directory, of the files that are missing
+/// or whose contents changed, in the order they're listed.
+pub fn verify_sha256sums<P: AsRef<Path>>(mirror_dir_path: P) -> Result<Vec<PathBuf>> {
+    let mirror_dir_path = mirror_dir_path.as_ref();
+    let manifest = std::fs::read_to_string(mirror_dir_path.join(SHA256SUMS_FILE))
+        .map_err(Error::ReadManifest)?;
+    let digests = Vec::from_iter(
+        manifest
+            .lines()
+            .filter_map(|line| line.split_once("  "))
+            .map(|(digest, file)| (PathBuf::from(file), digest.to_string())),
+    );
+    verify_digests(mirror_dir_path, &digests)
+}
+
+/// Checks the files, given by path relative to the mirror directory, against their digests,
+/// e.g. those recorded in the state database, as `verify_sha256sums` does.
+///
+/// Returns the paths of the files that are missing or whose contents changed, in the order
+/// they're given.
+pub fn verify_digests<P: AsRef<Path>>(
+    mirror_dir_path: P,
+    digests: &[(PathBuf, String)],
+) -> Result<Vec<PathBuf>> {
+    let mirror_dir_path = mirror_dir_path.as_ref();
+    let mismatched = digests
+        .par_iter()
+        .map(|(file, digest)| {
+            let file = file.clone();
+            match sha256_file(mirror_dir_path.join(&file)) {
+                Ok(actual) if actual == *digest => Ok(None),
+                Ok(_) => Ok(Some(file)),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Some(file)),
+                Err(e) => Err(Error::HashFile {
+                    path: file,
+                    error: e,
+                }),
+            }
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(Vec::from_iter(mismatched.into_iter().flatten()))
+}
@@ -5,23 +5,940 @@ micrio.exe --from-file crates.txt
     ----------
     tokio
     rayon
+micrio.exe --from-file https://internal.example.com/crates.txt mirror
+micrio.exe --from-file crates.json mirror
+    crates.json
+    -----------
+    [{ "name": "tokio", "version": "1.28", "features": ["full"] }, { "name": "serde" }]
+cargo tree --prefix none -f {p} | cut -d' ' -f1 | sort -u | micrio.exe --from-file - mirror
 micrio.exe --most-downloaded 50
+micrio.exe --category embedded --category wasm=100 mirror
+micrio.exe --keyword parser=20 mirror
+micrio.exe --owned-by github:rust-lang:libs --owned-by dtolnay mirror
+micrio.exe --search "http client" --limit 20 mirror
+micrio.exe --recently-updated 200 --min-downloads 10000 mirror
+micrio.exe --category embedded --min-downloads 100000 mirror
+micrio.exe ./mirror tokio serde@1.0.188 clap@^4
+micrio.exe mirror --most-downloaded 50 mirror
+micrio.exe --most-downloaded 50 --dry-run mirror
+micrio.exe --most-downloaded 50 --layout sharded mirror
+micrio.exe --from-file crates.txt --index-only mirror
+micrio.exe --download-only mirror
+micrio.exe --from-manifest selection.toml mirror
+micrio.exe --from-manifest ../app/Cargo.toml mirror
+micrio.exe --from-lockfile Cargo.lock mirror
+micrio.exe --from-projects ~/src/services mirror
+micrio.exe --profile embedded mirror
+    micrio.toml
+    -----------
+    [profile.embedded]
+    crates = ["heapless", "embedded-hal@1"]
+    targets = ["thumbv7em-none-eabihf"]
+micrio.exe --config /etc/micrio/mirror.toml
+    mirror.toml
+    -----------
+    [mirror]
+    mirror-dir = "/srv/mirror"
+    from-file = ["crates.txt"]
+    max-concurrent-requests = 16
+MICRIO_MIRROR_DIR_PATH=/srv/mirror MICRIO_MOST_DOWNLOADED=50 micrio.exe
+micrio.exe --from-mirror https://git.example.com/crates-mirror-index.git mirror
+micrio.exe --most-downloaded 50 --serve-url https://mirror.example.com/registry mirror
+micrio.exe --from-file crates.txt --publish-to https://artifactory.example.com/artifactory/api/cargo/crates mirror
+micrio.exe export mirror mirror.tar --max-part-size fat32
+micrio.exe export mirror update.tar --since-last
+micrio.exe export mirror mirror.tar.zst --zstd 19
+micrio.exe import mirror.tar.001 mirror
+micrio.exe vendor mirror vendor
+micrio.exe --from-vendor vendor mirror
+micrio.exe keygen export.key export.pub
+micrio.exe --most-downloaded 50 --serve-url http://mirror.example.com:8080/registry --auth-required mirror
+micrio.exe serve mirror --addr 0.0.0.0:8080 --tokens-file tokens.txt
+micrio.exe dashboard mirror
+micrio.exe grep mirror "fn from_str_radix" --crate num-traits
+micrio.exe --from-file crates.txt --with-metadata mirror
+micrio.exe --from-file crates.txt --with-repos mirror
+micrio.exe search mirror "async http client" --limit 5
+micrio.exe --from-file crates.txt --state-db --sha256sums mirror
+micrio.exe list mirror
+micrio.exe verify mirror
+micrio completions bash > /etc/bash_completion.d/micrio
+micrio --profile full --daemon 1d --print-systemd-unit /srv/mirror > /etc/systemd/system/micrio.service
  */
 
-use clap::Parser;
+use clap::{ArgAction, Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use micrio::dst_registry::RegistryLayout;
+use micrio::replicate::Replica;
+use micrio::report::ReportFormat;
+use micrio::selection::{
+    self, CategorySpec, CrateSpec, FeatureSpec, KeywordSpec, SearchSpec, SelectionSpec,
+};
+use micrio::warnings;
+use std::io::IsTerminal;
+use std::net::SocketAddr;
 use std::path::PathBuf;
+use std::time::Duration;
 
 #[derive(Parser)]
-#[command(about = "Mirrors a subset of crates from crates.io to a local registry.")]
+#[command(
+    about = "Mirrors a subset of crates from crates.io to a local registry.",
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
 pub struct Cli {
-    /// Path to the directory where the crates should be mirrored.
-    #[arg(value_name = "MIRROR-DIR-PATH")]
-    pub mirror_dir_path: String,
-    /// Mirror the crates listed in the specified file.
-    /// Each line in the file must contain a crate name.
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    /// The arguments of a mirror run, also accepted without the mirror subcommand.
+    #[command(flatten)]
+    pub mirror: MirrorArgs,
+    /// Log more diagnostics to stderr: -v for warnings, -vv for selected and downloaded crates,
+    /// -vvv for dependency resolution, -vvvv for everything.
+    #[arg(short, long, action = clap::ArgAction::Count, global = true, verbatim_doc_comment)]
+    pub verbose: u8,
+    /// Don't log the warnings in the category. Can be repeated.
+    #[arg(long, value_name = "CATEGORY", value_enum, global = true)]
+    pub suppress: Vec<WarningCategory>,
+    /// Don't log the lines containing PATTERN, e.g. a crate name. Can be repeated.
+    #[arg(long, value_name = "PATTERN", global = true)]
+    pub suppress_warning: Vec<String>,
+    /// Also log to the specified file, appending to it. The file gets at least the
+    /// selected and downloaded crates, and everything logged to stderr.
+    #[arg(long, value_name = "FILE-PATH", global = true, verbatim_doc_comment)]
+    pub log_file: Option<PathBuf>,
+    /// Rotate the log file once it would grow past SIZE.
+    /// Accepts a byte count with an optional K/M/G/KiB/MiB/GiB suffix.
+    #[arg(
+        long,
+        value_name = "SIZE",
+        value_parser = parse_size,
+        requires = "log_file",
+        global = true,
+        verbatim_doc_comment
+    )]
+    pub log_max_size: Option<u64>,
+    /// Rotate the log file every hour or day.
+    #[arg(
+        long,
+        value_name = "INTERVAL",
+        value_enum,
+        requires = "log_file",
+        global = true
+    )]
+    pub log_rotate: Option<LogRotation>,
+    /// How many rotated log files to keep, e.g. micrio.log.1 to micrio.log.5.
+    #[arg(long, value_name = "N", default_value_t = 5, global = true)]
+    pub log_keep: usize,
+}
+
+/// The arguments of a mirror run.
+#[derive(Args)]
+pub struct MirrorArgs {
+    /// How to report progress on stdout. "json" emits each event as a JSON object on its own line.
+    #[arg(long, value_name = "FORMAT", value_enum, default_value_t = ProgressFormat::Human)]
+    pub progress: ProgressFormat,
+    /// When to color the human progress output. "auto" colors it when stdout is a terminal
+    /// and the NO_COLOR environment variable isn't set.
+    #[arg(long, value_name = "WHEN", value_enum, default_value_t = ColorChoice::Auto)]
+    pub color: ColorChoice,
+    /// Mirror the crates from the Cargo registry with the index URL instead of crates.io,
+    /// e.g. an organization's internal registry.
+    #[arg(long, value_name = "INDEX-URL", verbatim_doc_comment)]
+    pub registry: Option<String>,
+    /// Token to authenticate with when downloading from the registry.
+    #[arg(
+        long,
+        value_name = "TOKEN",
+        env = "MICRIO_REGISTRY_TOKEN",
+        hide_env_values = true,
+        requires = "registry"
+    )]
+    pub registry_token: Option<String>,
+    /// Base URL of the registry's web API, crates.io's by default.
+    #[arg(long, value_name = "URL")]
+    pub registry_api: Option<String>,
+    /// Download the crates from the URLs of the template instead of those of the registry's
+    /// config.json, e.g. a caching proxy. Takes the markers of config.json's dl field,
+    /// e.g. https://proxy.example.com/crates/{crate}/{crate}-{version}.crate
+    #[arg(long, value_name = "TEMPLATE", verbatim_doc_comment)]
+    pub registry_dl: Option<String>,
+    /// Base URL the mirror's crates will be served from, e.g. by a web server, written to
+    /// the mirror's config.json instead of the file URL of the mirror directory.
+    #[arg(long, value_name = "URL", verbatim_doc_comment)]
+    pub serve_url: Option<String>,
+    /// Set auth-required in the mirror's config.json, so Cargo sends its token for the
+    /// registry with every request, as the serve subcommand with --tokens-file checks.
+    /// Cargo only supports it for registries used with the sparse protocol.
+    #[arg(long, verbatim_doc_comment)]
+    pub auth_required: bool,
+    /// Use the git clone of the index at the path as is, instead of Cargo's copy of the
+    /// crates.io index or a fresh clone of the registry's, e.g. on hermetic build machines.
+    #[arg(long, value_name = "DIR", verbatim_doc_comment)]
+    pub cargo_index_path: Option<PathBuf>,
+    /// Also resolve the dependencies on the Cargo registry with the index URL, e.g. crates.io
+    /// when mirroring an internal registry, and mirror them. Dependencies on registries
+    /// that aren't given are left out. Can be repeated.
+    #[arg(long, value_name = "INDEX-URL", verbatim_doc_comment)]
+    pub secondary_registry: Vec<String>,
+    /// Token to authenticate with when downloading from the secondary registry with the
+    /// index URL, given as INDEX-URL=TOKEN. Can be repeated.
+    #[arg(
+        long,
+        value_name = "INDEX-URL=TOKEN",
+        requires = "secondary_registry",
+        verbatim_doc_comment
+    )]
+    pub secondary_registry_token: Vec<String>,
+    /// Download from the registries given without a token with the token Cargo has for them,
+    /// from Cargo's credentials.toml or config.toml, or its CARGO_REGISTRY_TOKEN and
+    /// CARGO_REGISTRIES_<NAME>_TOKEN environment variables.
+    #[arg(long, verbatim_doc_comment)]
+    pub cargo_credentials: bool,
+    /// Only use local data: the indexes already fetched, the --advisory-db clone, and the
+    /// .crate files in Cargo's download cache. Fails right away with the step needing
+    /// the network instead, e.g. --most-downloaded, or with the crates missing from the cache.
+    #[arg(long, verbatim_doc_comment)]
+    pub offline: bool,
+    /// Start at most N requests per second to the registry, counting both the crates.io
+    /// API queries and the .crate file downloads, e.g. to keep to its crawler policy.
+    #[arg(long, value_name = "N", verbatim_doc_comment)]
+    pub max_requests_per_sec: Option<f64>,
+    /// Have at most N requests to the registry in flight at once.
+    #[arg(long, value_name = "N")]
+    pub max_concurrent_requests: Option<usize>,
+    /// Fail the run instead of leaving crates out of the mirror with a warning, e.g. when
+    /// a dependency has no compatible version or a crate fails to download.
+    #[arg(long, verbatim_doc_comment)]
+    pub deny_warnings: bool,
+    /// Path to the directory where the crates should be mirrored. Can also be given as
+    /// mirror-dir in the configuration file.
+    #[arg(value_name = "MIRROR-DIR-PATH", verbatim_doc_comment)]
+    pub mirror_dir_path: Option<String>,
+    /// Mirror the crates, given as NAME or NAME@REQ, e.g. tokio, serde@1.0.188, or clap@^4.
+    /// As in Cargo.toml, serde@1.0.188 selects the highest compatible version;
+    /// serde@=1.0.188 selects exactly that one.
+    #[arg(value_name = "CRATE", verbatim_doc_comment)]
+    pub crates: Vec<CrateSpec>,
+    /// Mirror the crates listed in the specified file, or at the specified http:// or https://
+    /// URL, or on standard input if the path is -. Each line in the file must contain a crate
+    /// name. A .toml or .json file lists crates with their own version requirement, features,
+    /// targets, and dev-dependencies instead, like the [[crate]] entries of --from-manifest.
     #[arg(long, value_name = "FILE-PATH", verbatim_doc_comment)]
     pub from_file: Option<PathBuf>,
+    /// Mirror the crates listed in the specified TOML selection manifest, where each crate
+    /// can have its own version requirement, features, targets, and dev-dependencies.
+    /// A Cargo.toml file is read as a project or workspace manifest instead: the crates.io
+    /// dependencies it and its workspace members declare are mirrored.
+    #[arg(long, value_name = "FILE-PATH", verbatim_doc_comment)]
+    pub from_manifest: Option<PathBuf>,
+    /// Mirror the exact crates.io crate versions locked in the Cargo.lock file, e.g. to
+    /// reproduce a specific build offline. Their dependencies aren't resolved, since the
+    /// lockfile already locks them.
+    #[arg(long, value_name = "FILE-PATH", verbatim_doc_comment)]
+    pub from_lockfile: Option<PathBuf>,
+    /// Mirror the crates.io dependencies of every Rust project under the specified directory,
+    /// e.g. a monorepo or a folder of services: the exact versions locked in their Cargo.lock
+    /// files, or the dependencies declared in the Cargo.toml files of projects without one.
+    #[arg(long, value_name = "DIR-PATH", verbatim_doc_comment)]
+    pub from_projects: Option<PathBuf>,
+    /// Mirror the exact crate versions in the `cargo vendor` directory,
+    /// e.g. to move a project from vendoring to the mirror.
+    #[arg(long, value_name = "DIR-PATH", verbatim_doc_comment)]
+    pub from_vendor: Option<PathBuf>,
+    /// Mirror the exact crate versions in another micrio mirror, given by the path of its
+    /// directory or the URL of its index git repo, e.g. to rebuild it from scratch.
+    #[arg(long, value_name = "PATH-OR-URL", verbatim_doc_comment)]
+    pub from_mirror: Option<String>,
+    /// Read the settings of the runs from the TOML file instead of micrio.toml in the current
+    /// directory: the mirror directory, crates, crate lists, exclusions, targets, features,
+    /// request limits, and registry URLs of its [mirror] table, and its profiles.
+    #[arg(long, value_name = "FILE-PATH", verbatim_doc_comment)]
+    pub config: Option<PathBuf>,
+    /// Use the settings of the named profile in the configuration file, e.g. its crates,
+    /// targets, and features. The crates given on the command line are added to the
+    /// profile's; the other flags given override the profile's settings.
+    #[arg(long, value_name = "NAME", verbatim_doc_comment)]
+    pub profile: Option<String>,
     /// Mirror the top N most downloaded crates on crates.io.
     #[arg(long, value_name = "N")]
     pub most_downloaded: Option<u64>,
-}
\ No newline at end of file
+    /// Mirror the N crates most recently updated on crates.io, e.g. to test against the
+    /// moving edge of the ecosystem.
+    #[arg(long, value_name = "N", verbatim_doc_comment)]
+    pub recently_updated: Option<u64>,
+    /// Mirror the crates in the crates.io category, given by its slug, most downloaded
+    /// first, e.g. embedded or web-programming::http-client. SLUG=LIMIT only mirrors the top
+    /// LIMIT crates of the category, e.g. wasm=100. Can be repeated.
+    #[arg(long, value_name = "SLUG[=LIMIT]", verbatim_doc_comment)]
+    pub category: Vec<CategorySpec>,
+    /// Mirror the crates tagged with the crates.io keyword, most downloaded first, e.g. async
+    /// or parser. KEYWORD=LIMIT only mirrors the top LIMIT crates with the keyword, e.g.
+    /// parser=20. Can be repeated.
+    #[arg(long, value_name = "KEYWORD[=LIMIT]", verbatim_doc_comment)]
+    pub keyword: Vec<KeywordSpec>,
+    /// Mirror every crate owned by the crates.io user, given by login, e.g. dtolnay, or team,
+    /// given as github:ORG:TEAM, e.g. github:rust-lang:libs. Can be repeated.
+    #[arg(long, value_name = "USER-OR-TEAM", verbatim_doc_comment)]
+    pub owned_by: Vec<String>,
+    /// Mirror the crates a crates.io search for the query finds, most relevant first,
+    /// as with the search box on crates.io.
+    #[arg(long, value_name = "QUERY", verbatim_doc_comment)]
+    pub search: Option<String>,
+    /// Only mirror the top N crates the --search finds.
+    #[arg(long, value_name = "N", requires = "search")]
+    pub limit: Option<u64>,
+    /// Leave out the crates selected with --most-downloaded, --recently-updated, --category,
+    /// --keyword, --owned-by, or --search that were downloaded fewer than N times, e.g. to
+    /// keep obscure crates out of broad selections.
+    #[arg(long, value_name = "N", verbatim_doc_comment)]
+    pub min_downloads: Option<u64>,
+    /// Only mirror the platform-specific dependencies needed to build for the target triple,
+    /// e.g. x86_64-pc-windows-msvc. Can be repeated to serve builders on several platforms.
+    /// If not specified, the dependencies for every platform are mirrored.
+    #[arg(long, value_name = "TRIPLE", verbatim_doc_comment)]
+    pub target: Vec<String>,
+    /// Only mirror the dependencies at most N dependency edges away from a top-level crate,
+    /// e.g. 1 for their direct dependencies only. The dependencies left out are listed
+    /// for review.
+    #[arg(long, value_name = "N", verbatim_doc_comment)]
+    pub max_depth: Option<usize>,
+    /// Activate all features of the top-level crates,
+    /// only mirroring the optional dependencies that enabled features require.
+    #[arg(long, verbatim_doc_comment)]
+    pub all_features: bool,
+    /// Don't activate the default feature of the top-level crates,
+    /// only mirroring the optional dependencies that enabled features require.
+    #[arg(long, verbatim_doc_comment)]
+    pub no_default_features: bool,
+    /// Comma-separated features to activate on the top-level crates,
+    /// only mirroring the optional dependencies that enabled features require.
+    /// Without any feature flag, every optional dependency is mirrored.
+    #[arg(
+        long,
+        value_name = "FEATURES",
+        value_delimiter = ',',
+        verbatim_doc_comment
+    )]
+    pub features: Vec<String>,
+    /// Never mirror the specified crate, even when a selected crate depends on it.
+    /// Accepts NAME or NAME@REQ, e.g. openssl-sys or time@<0.2. Can be repeated.
+    #[arg(long, value_name = "CRATE", verbatim_doc_comment)]
+    pub exclude: Vec<CrateSpec>,
+    /// Resolve the selection and report how many crates would be mirrored and their expected
+    /// size, from the sizes the registry reports, without touching the mirror directory.
+    #[arg(long, verbatim_doc_comment)]
+    pub dry_run: bool,
+    /// List the selected top-level crates with their sizes and dependency counts,
+    /// to uncheck the ones not to mirror before their dependencies are resolved.
+    #[arg(long, verbatim_doc_comment)]
+    pub interactive: bool,
+    /// Only write the index of the selection, without downloading the crates, e.g. on a
+    /// machine that can reach the registry's index but not its downloads. Copy the mirror
+    /// directory to a machine that can and finish it there with --download-only.
+    #[arg(long, conflicts_with_all = ["dry_run", "streaming"], verbatim_doc_comment)]
+    pub index_only: bool,
+    /// Download the crates listed in the existing index of the mirror directory, e.g. written
+    /// with --index-only on another machine, verifying them against their index checksums.
+    /// Nothing is selected or resolved, and the index is kept as it is.
+    #[arg(
+        long,
+        conflicts_with_all = [
+            "from_file",
+            "from_manifest",
+            "from_lockfile",
+            "from_projects",
+            "from_vendor",
+            "from_mirror",
+            "profile",
+            "most_downloaded",
+            "recently_updated",
+            "category",
+            "keyword",
+            "owned_by",
+            "search",
+            "dry_run",
+            "interactive",
+            "index_only",
+        ],
+        verbatim_doc_comment
+    )]
+    pub download_only: bool,
+    /// Start downloading crates while their dependencies are still being resolved,
+    /// for very large selections.
+    #[arg(
+        long,
+        conflicts_with_all = ["exclude_vulnerable", "substitute_patched"],
+        verbatim_doc_comment
+    )]
+    pub streaming: bool,
+    /// Build the mirror so that the same selection against the same crates.io index commit
+    /// produces bit-identical files on every machine, for auditing.
+    #[arg(long, conflicts_with = "streaming", verbatim_doc_comment)]
+    pub reproducible: bool,
+    /// Resolve against the specified commit of the local crates.io index
+    /// instead of the latest one fetched.
+    #[arg(long, value_name = "COMMIT", verbatim_doc_comment)]
+    pub index_commit: Option<String>,
+    /// Write an inventory report listing every mirrored crate to the specified file.
+    #[arg(long, value_name = "FILE-PATH")]
+    pub report: Option<PathBuf>,
+    /// Write a report attributing registry disk usage to each top-level crate to the specified file.
+    #[arg(long, value_name = "FILE-PATH")]
+    pub size_report: Option<PathBuf>,
+    /// Write a report grouping the mirrored crates by owner to the specified file.
+    #[arg(long, value_name = "FILE-PATH", requires = "with_owners")]
+    pub publisher_report: Option<PathBuf>,
+    /// Format of the inventory, size, and publisher reports.
+    #[arg(long, value_name = "FORMAT", value_enum, default_value_t = ReportFormat::Csv)]
+    pub report_format: ReportFormat,
+    /// Write the dependency graph of the mirrored crates to the specified file as JSON.
+    #[arg(long, value_name = "FILE-PATH")]
+    pub dependency_graph: Option<PathBuf>,
+    /// Store each crate version's README and metadata (description, keywords, links)
+    /// next to its .crate file for offline browsing, and index them for the search
+    /// subcommand.
+    #[arg(long, verbatim_doc_comment)]
+    pub with_metadata: bool,
+    /// Extract the LICENSE, COPYING, and NOTICE files of each crate version
+    /// to the licenses/NAME@VERSION directories of the mirror, for legal review.
+    #[arg(long, verbatim_doc_comment)]
+    pub with_licenses: bool,
+    /// Fetch the owners of each crate from the registry's web API, one request per second,
+    /// to api/v1/crates/NAME/owners in the mirror, where a web server serving the mirror
+    /// answers ownership queries as crates.io does. The inventory report then lists them.
+    #[arg(long, verbatim_doc_comment)]
+    pub with_owners: bool,
+    /// Clone the source repositories of the top-level crates, from the repository URLs
+    /// in their Cargo.toml, into the repos directory of the mirror as bare repositories.
+    /// The directory is kept between runs, which only fetch into the repositories.
+    #[arg(long, verbatim_doc_comment)]
+    pub with_repos: bool,
+    /// Write an index.html file to the mirror directory for browsing the mirrored crates.
+    #[arg(long)]
+    pub html_index: bool,
+    /// Check that every top-level crate resolves against the mirror alone, as Cargo would
+    /// resolve it, warning about each dependency without a compatible version in the mirror.
+    #[arg(long, verbatim_doc_comment)]
+    pub check_resolvable: bool,
+    /// Write a SHA256SUMS file covering every file in the mirror,
+    /// so its integrity can be checked with `sha256sum --check`.
+    #[arg(long, verbatim_doc_comment)]
+    pub sha256sums: bool,
+    /// Record the mirrored crates, why each was mirrored, and the digests of the mirror's
+    /// files in an SQLite database in the .micrio directory of the mirror, which the list
+    /// and grep subcommands, download-only runs, and the verify job query instead of
+    /// scanning the mirror. Runs without it delete the database.
+    #[arg(long, verbatim_doc_comment)]
+    pub state_db: bool,
+    /// Only keep the reports of the N most recent runs in the .micrio/runs directory
+    /// of the mirror, removing the older ones after each run.
+    #[arg(long, value_name = "N", verbatim_doc_comment)]
+    pub keep_run_reports: Option<usize>,
+    /// Layout of the .crate files in the registry directory: nested NAME/VERSION/download
+    /// paths, or NAME-VERSION.crate files, flat or in directories sharded by name prefix,
+    /// which are simpler to host on static file servers and CDNs.
+    #[arg(
+        long,
+        value_name = "LAYOUT",
+        value_enum,
+        default_value_t = RegistryLayout::Nested,
+        verbatim_doc_comment
+    )]
+    pub layout: RegistryLayout,
+    /// Hard-link the .crate files identical to those in DIR instead of downloading them,
+    /// to share disk space with another mirror on the same server. DIR is a micrio mirror
+    /// or a directory of NAME-VERSION.crate files, e.g. a Cargo download cache. Can be repeated.
+    #[arg(long, value_name = "DIR", verbatim_doc_comment)]
+    pub link_from: Vec<PathBuf>,
+    /// Copy the .crate files identical to those in DIR instead of downloading them,
+    /// e.g. files downloaded ahead of time or a previous partial mirror. DIR is a directory
+    /// of NAME-VERSION.crate files or a micrio mirror. Only files matching the index checksum
+    /// are copied. Can be repeated.
+    #[arg(long, value_name = "DIR", verbatim_doc_comment)]
+    pub seed_dir: Vec<PathBuf>,
+    /// Scan the mirrored crates for known vulnerabilities using the RustSec advisory database.
+    #[arg(long)]
+    pub audit: bool,
+    /// Path to a local clone of the RustSec advisory database.
+    /// If not specified, a fresh copy is cloned from GitHub.
+    #[arg(
+        long,
+        value_name = "DIR-PATH",
+        requires = "audit",
+        verbatim_doc_comment
+    )]
+    pub advisory_db: Option<PathBuf>,
+    /// Exclude crate versions with known vulnerabilities from the mirror.
+    #[arg(long, requires = "audit")]
+    pub exclude_vulnerable: bool,
+    /// Replace crate versions with known vulnerabilities by the newest semver-compatible
+    /// version without any, if there is one, reporting each substitution.
+    #[arg(long, requires = "audit", verbatim_doc_comment)]
+    pub substitute_patched: bool,
+    /// Run the shell command before downloading each crate. A non-zero exit status
+    /// keeps the crate out of the mirror. The crate is passed in the
+    /// MICRIO_CRATE_NAME and MICRIO_CRATE_VERSION environment variables.
+    #[arg(long, value_name = "COMMAND", verbatim_doc_comment)]
+    pub pre_download_hook: Option<String>,
+    /// Run the shell command on each downloaded .crate file, e.g. a malware scanner,
+    /// before it is admitted into the mirror. A non-zero exit status keeps the crate out.
+    /// The file's path is passed in the MICRIO_CRATE_FILE environment variable.
+    #[arg(long, value_name = "COMMAND", verbatim_doc_comment)]
+    pub post_download_hook: Option<String>,
+    /// Run the shell command when the run ends. Whether the run succeeded
+    /// is passed in the MICRIO_RUN_STATUS environment variable.
+    #[arg(long, value_name = "COMMAND", verbatim_doc_comment)]
+    pub run_end_hook: Option<String>,
+    /// Also upload the mirrored crates to an existing Artifactory or Nexus Cargo repository.
+    /// URL is the repository's API base URL, i.e. the "api" field of its config.json.
+    #[arg(long, value_name = "URL", verbatim_doc_comment)]
+    pub publish_to: Option<String>,
+    /// Token to authenticate with when publishing.
+    #[arg(
+        long,
+        value_name = "TOKEN",
+        env = "MICRIO_PUBLISH_TOKEN",
+        hide_env_values = true,
+        requires = "publish_to"
+    )]
+    pub publish_token: Option<String>,
+    /// Push the mirror to the replica after each successful run, transferring only
+    /// what changed. REPLICA is a directory, e.g. on a network share, or an rsync
+    /// destination, [USER@]HOST:PATH over SSH or rsync://HOST/MODULE/PATH.
+    /// Can be repeated to keep several replicas in sync.
+    #[arg(long, value_name = "REPLICA", verbatim_doc_comment)]
+    pub replicate_to: Vec<Replica>,
+    /// Keep running, mirroring again INTERVAL after each run finishes, e.g. 6h or 1d.
+    /// SIGHUP reloads micrio.toml for the next run; SIGTERM, like Ctrl-C, stops once
+    /// the crate in progress is done. The runs are recorded for the dashboard subcommand.
+    #[arg(
+        long,
+        value_name = "INTERVAL",
+        value_parser = parse_interval,
+        conflicts_with_all = ["dry_run", "interactive"],
+        verbatim_doc_comment
+    )]
+    pub daemon: Option<Duration>,
+    /// Serve /healthz on the address while running as a daemon, e.g. 0.0.0.0:8080,
+    /// for load balancers to tell whether the mirror is safe to serve from.
+    #[arg(long, value_name = "ADDR", requires = "daemon", verbatim_doc_comment)]
+    pub health_addr: Option<SocketAddr>,
+    /// Print a systemd unit running the daemon with the other arguments given, from the
+    /// current directory, instead of running it, e.g. to
+    /// /etc/systemd/system/micrio.service. Arguments given in MICRIO_ environment
+    /// variables aren't included.
+    #[arg(long, requires = "daemon", verbatim_doc_comment)]
+    pub print_systemd_unit: bool,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ProgressFormat {
+    Human,
+    Json,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum WarningCategory {
+    /// Dependencies whose platform couldn't be parsed.
+    Platform,
+    /// Dependencies without a version matching their requirement.
+    MissingDependency,
+    /// Selected crates without versions available.
+    NoVersions,
+    /// Crate versions that couldn't be checked for advisories.
+    Advisory,
+    /// Packaged Cargo.toml files that couldn't be parsed.
+    PackagedManifest,
+    /// Top-level crates that can't be resolved against the mirror.
+    Unresolvable,
+    /// Git dependencies in lockfiles and vendor directories, which can't be mirrored.
+    Unmirrorable,
+}
+
+impl WarningCategory {
+    /// The tracing target the category's warnings are logged with.
+    pub fn target(self) -> &'static str {
+        match self {
+            WarningCategory::Platform => warnings::PLATFORM,
+            WarningCategory::MissingDependency => warnings::MISSING_DEPENDENCY,
+            WarningCategory::NoVersions => warnings::NO_VERSIONS,
+            WarningCategory::Advisory => warnings::ADVISORY,
+            WarningCategory::PackagedManifest => warnings::PACKAGED_MANIFEST,
+            WarningCategory::Unresolvable => warnings::UNRESOLVABLE,
+            WarningCategory::Unmirrorable => warnings::UNMIRRORABLE,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum LogRotation {
+    Hourly,
+    Daily,
+}
+
+impl LogRotation {
+    pub fn interval(self) -> Duration {
+        match self {
+            LogRotation::Hourly => Duration::from_secs(60 * 60),
+            LogRotation::Daily => Duration::from_secs(24 * 60 * 60),
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    pub fn use_color(self) -> bool {
+        match self {
+            ColorChoice::Auto => {
+                std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none()
+            }
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+        }
+    }
+}
+
+/// The prefix of the environment variables options can be given with.
+const ENV_PREFIX: &str = "MICRIO_";
+
+impl Cli {
+    /// The command line parser, taking each option not given on the command line from its
+    /// environment variable: MICRIO_ followed by the option's name in upper snake case,
+    /// e.g. MICRIO_MOST_DOWNLOADED or MICRIO_MIRROR_DIR_PATH. A subcommand's options
+    /// also have its name, e.g. MICRIO_EXPORT_MAX_PART_SIZE, except those of the mirror
+    /// subcommand, which are the options of the command itself. Flags take true or false.
+    pub fn command_with_env() -> clap::Command {
+        with_env_vars(Cli::command(), ENV_PREFIX)
+    }
+
+    /// Parses the command line and the environment variables, exiting on errors.
+    pub fn parse_with_env() -> Self {
+        let matches = Cli::command_with_env().get_matches();
+        Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit())
+    }
+}
+
+impl MirrorArgs {
+    /// Builds the selection spec from the crate selection flags.
+    pub fn selection_spec(&self) -> Result<SelectionSpec, selection::Error> {
+        let mut spec = SelectionSpec {
+            crates: self.crates.clone(),
+            files: self.from_file.iter().cloned().collect(),
+            most_downloaded: self.most_downloaded,
+            recently_updated: self.recently_updated,
+            categories: self.category.clone(),
+            keywords: self.keyword.clone(),
+            owners: self.owned_by.clone(),
+            searches: Vec::from_iter(self.search.iter().map(|query| SearchSpec {
+                query: query.clone(),
+                limit: self.limit,
+            })),
+            min_downloads: self.min_downloads,
+            lockfiles: self.from_lockfile.iter().cloned().collect(),
+            vendor_dirs: self.from_vendor.iter().cloned().collect(),
+            mirrors: self.from_mirror.iter().cloned().collect(),
+            exclusions: self.exclude.clone(),
+            features: self.feature_spec(),
+        };
+        if let Some(manifest_path) = &self.from_manifest {
+            spec.add_manifest(manifest_path)?;
+        }
+        if let Some(projects_dir_path) = &self.from_projects {
+            spec.add_projects(projects_dir_path)?;
+        }
+        Ok(spec)
+    }
+
+    /// Builds the feature spec from the feature flags, if any were given.
+    fn feature_spec(&self) -> Option<FeatureSpec> {
+        if !self.all_features && !self.no_default_features && self.features.is_empty() {
+            return None;
+        }
+        Some(FeatureSpec {
+            all_features: self.all_features,
+            no_default_features: self.no_default_features,
+            features: self.features.clone(),
+        })
+    }
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Mirror the selected crates and their dependencies to a local registry. The options
+    /// are also accepted without the subcommand, e.g. micrio --most-downloaded 50 mirror,
+    /// as before the subcommands existed.
+    #[command(verbatim_doc_comment)]
+    Mirror(Box<MirrorArgs>),
+    /// Check the files of a mirror against the digests recorded in its state database,
+    /// or else its SHA256SUMS file, listing those missing or changed.
+    #[command(verbatim_doc_comment)]
+    Verify {
+        /// Path to the mirror directory to verify.
+        #[arg(value_name = "MIRROR-DIR-PATH")]
+        mirror_dir_path: PathBuf,
+    },
+    /// Pack a mirror into an archive for transfer to another machine.
+    Export {
+        /// Path to the mirror directory to export.
+        #[arg(value_name = "MIRROR-DIR-PATH")]
+        mirror_dir_path: PathBuf,
+        /// Path of the archive to create.
+        #[arg(value_name = "ARCHIVE-PATH")]
+        archive_path: PathBuf,
+        /// Split the archive into numbered parts no larger than SIZE.
+        /// Accepts a byte count with an optional K/M/G/KiB/MiB/GiB suffix,
+        /// or "fat32" for the largest file a FAT32 drive can hold.
+        #[arg(long, value_name = "SIZE", value_parser = parse_size, verbatim_doc_comment)]
+        max_part_size: Option<u64>,
+        /// Only include the crates and index changes added since the previous export.
+        #[arg(long)]
+        since_last: bool,
+        /// Sign the export manifest with the ed25519 key in the specified file.
+        #[arg(long, value_name = "KEY-PATH")]
+        signing_key: Option<PathBuf>,
+        /// Leave file timestamps, owners, and permissions out of the archive,
+        /// so reproducible mirrors export to bit-identical archives.
+        #[arg(long, verbatim_doc_comment)]
+        reproducible: bool,
+        /// Compress the archive with zstd at LEVEL, from 1 (fastest) to 22 (smallest),
+        /// or 3 if not given. Import detects compressed archives.
+        #[arg(
+            long,
+            value_name = "LEVEL",
+            num_args = 0..=1,
+            default_missing_value = "3",
+            value_parser = clap::value_parser!(i32).range(1..=22),
+            verbatim_doc_comment
+        )]
+        zstd: Option<i32>,
+    },
+    /// Unpack an exported archive into a mirror directory, reassembling split parts
+    /// and decompressing zstd-compressed archives.
+    Import {
+        /// Path to the archive, or to its first part if it was split.
+        #[arg(value_name = "ARCHIVE-PATH")]
+        archive_path: PathBuf,
+        /// Path to the directory where the mirror should be unpacked.
+        #[arg(value_name = "MIRROR-DIR-PATH")]
+        mirror_dir_path: PathBuf,
+        /// Require the export manifest to be signed by the ed25519 key
+        /// whose public half is in the specified file.
+        #[arg(long, value_name = "KEY-PATH", verbatim_doc_comment)]
+        verify_key: Option<PathBuf>,
+    },
+    /// Check that Cargo can fetch crates from the mirror, by fetching a sample of them
+    /// into a temporary project that uses the mirror instead of crates.io.
+    SmokeTest {
+        /// Path to the mirror directory to test.
+        #[arg(value_name = "MIRROR-DIR-PATH")]
+        mirror_dir_path: PathBuf,
+        /// The number of crates to depend on.
+        #[arg(long, value_name = "N", default_value_t = 20)]
+        sample: usize,
+        /// Also compile the crates with cargo check. Crates needing system libraries
+        /// to build can fail it without the mirror being at fault.
+        #[arg(long, verbatim_doc_comment)]
+        check: bool,
+        /// Keep the temporary project instead of deleting it, e.g. to investigate a failure.
+        #[arg(long)]
+        keep_project: bool,
+    },
+    /// Unpack every crate in a mirror into a directory Cargo can build from,
+    /// in the layout `cargo vendor` produces.
+    Vendor {
+        /// Path to the mirror directory to vendor.
+        #[arg(value_name = "MIRROR-DIR-PATH")]
+        mirror_dir_path: PathBuf,
+        /// Path of the vendor directory to write.
+        #[arg(value_name = "VENDOR-DIR-PATH")]
+        vendor_dir_path: PathBuf,
+        /// Name every package directory NAME-VERSION, not only those of crates
+        /// with several versions.
+        #[arg(long, verbatim_doc_comment)]
+        versioned_dirs: bool,
+    },
+    /// Watch a mirror kept up to date by a long-lived daemon: the progress of the run
+    /// in progress, the scheduled jobs and when they run next, recent failures,
+    /// and disk usage.
+    #[command(verbatim_doc_comment)]
+    Dashboard {
+        /// Path to the mirror directory to watch.
+        #[arg(value_name = "MIRROR-DIR-PATH")]
+        mirror_dir_path: PathBuf,
+    },
+    /// Search the sources of the mirrored crates for a regular expression, reading them
+    /// straight from the .crate files. Prints NAME@VERSION:PATH:LINE: and the matching line.
+    #[command(verbatim_doc_comment)]
+    Grep {
+        /// Path to the mirror directory to search.
+        #[arg(value_name = "MIRROR-DIR-PATH")]
+        mirror_dir_path: PathBuf,
+        /// The regular expression to search for, in Rust regex syntax.
+        #[arg(value_name = "PATTERN")]
+        pattern: String,
+        /// Only search the specified crate, NAME or NAME@REQ, e.g. serde or tokio@1.
+        /// Can be repeated.
+        #[arg(long = "crate", value_name = "CRATE", verbatim_doc_comment)]
+        crates: Vec<CrateSpec>,
+        /// Match the pattern against the paths of the files in the crates instead of their
+        /// contents, printing NAME@VERSION:PATH.
+        #[arg(long, verbatim_doc_comment)]
+        paths: bool,
+        /// Match without regard to case.
+        #[arg(long, short = 'i')]
+        ignore_case: bool,
+    },
+    /// List the mirrored crate versions, with the selection sources of the top-level crates
+    /// and the crates requiring the others, from the state database of the mirror, or from
+    /// its index, without the reasons, if it has none.
+    #[command(verbatim_doc_comment)]
+    List {
+        /// Path to the mirror directory to list.
+        #[arg(value_name = "MIRROR-DIR-PATH")]
+        mirror_dir_path: PathBuf,
+    },
+    /// Search the names, keywords, descriptions, and READMEs of the mirrored crates,
+    /// printing the most relevant first. Needs a mirror populated with --with-metadata.
+    #[command(verbatim_doc_comment)]
+    Search {
+        /// Path to the mirror directory to search.
+        #[arg(value_name = "MIRROR-DIR-PATH")]
+        mirror_dir_path: PathBuf,
+        /// The words to search for.
+        #[arg(value_name = "QUERY")]
+        query: String,
+        /// Print at most the specified number of crates.
+        #[arg(long, value_name = "N", default_value_t = 10)]
+        limit: usize,
+    },
+    /// Serve a mirror over HTTP, its index as a sparse registry at /index/, e.g. for
+    /// sparse+http://HOST:PORT/index/ in Cargo's config. Populate the mirror with
+    /// --serve-url http://HOST:PORT/registry so its crates are downloaded from the server.
+    #[command(verbatim_doc_comment)]
+    Serve {
+        /// Path to the mirror directory to serve.
+        #[arg(value_name = "MIRROR-DIR-PATH")]
+        mirror_dir_path: PathBuf,
+        /// Address to listen on.
+        #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:8080")]
+        addr: SocketAddr,
+        /// Require every request to carry one of the tokens in the file, one per line,
+        /// which Cargo sends for mirrors populated with --auth-required.
+        #[arg(long, value_name = "FILE-PATH", verbatim_doc_comment)]
+        tokens_file: Option<PathBuf>,
+    },
+    /// Generate an ed25519 key pair for signing and verifying exports.
+    /// Existing key files are never overwritten.
+    #[command(verbatim_doc_comment)]
+    Keygen {
+        /// Path of the file to write the secret signing key to.
+        #[arg(value_name = "SIGNING-KEY-PATH")]
+        signing_key_path: PathBuf,
+        /// Path of the file to write the public verifying key to.
+        #[arg(value_name = "VERIFY-KEY-PATH")]
+        verify_key_path: PathBuf,
+    },
+    /// Print the tab completion script for the shell, for example to
+    /// /etc/bash_completion.d/micrio or a directory in zsh's fpath.
+    #[command(verbatim_doc_comment)]
+    Completions {
+        /// The shell to complete micrio's subcommands and options in.
+        #[arg(value_name = "SHELL", value_enum)]
+        shell: Shell,
+    },
+}
+
+/// Gives each argument of the command and its subcommands without an environment variable
+/// one named after it.
+fn with_env_vars(mut cmd: clap::Command, prefix: &str) -> clap::Command {
+    let ids = Vec::from_iter(
+        cmd.get_arguments()
+            .filter(|arg| {
+                arg.get_env().is_none()
+                    && !matches!(arg.get_action(), ArgAction::Help | ArgAction::Version)
+            })
+            .map(|arg| arg.get_id().clone()),
+    );
+    for id in ids {
+        let env_var = format!("{prefix}{}", env_var_name(id.as_str()));
+        cmd = cmd.mut_arg(id, |arg| arg.env(env_var));
+    }
+
+    let names = Vec::from_iter(cmd.get_subcommands().map(|sub| sub.get_name().to_string()));
+    for name in names {
+        // The mirror subcommand takes the options of the command itself, so their variables
+        // work either way.
+        let sub_prefix = if name == "mirror" {
+            prefix.to_string()
+        } else {
+            format!("{prefix}{}_", env_var_name(&name))
+        };
+        cmd = cmd.mut_subcommand(name, |sub| with_env_vars(sub, &sub_prefix));
+    }
+    cmd
+}
+
+fn env_var_name(name: &str) -> String {
+    name.to_ascii_uppercase().replace('-', "_")
+}
+
+fn parse_interval(interval: &str) -> Result<Duration, String> {
+    let num_end = interval
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(interval.len());
+    let (num, suffix) = interval.split_at(num_end);
+    let num = num
+        .parse::<u64>()
+        .map_err(|e| format!("invalid interval {interval}: {e}"))?;
+    let multiplier: u64 = match suffix.trim().to_ascii_lowercase().as_str() {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        _ => {
+            return Err(format!(
+                "invalid interval {interval}: expected a number followed by s, m, h, or d"
+            ))
+        }
+    };
+    match num.checked_mul(multiplier) {
+        Some(0) | None => Err(format!("invalid interval {interval}")),
+        Some(secs) => Ok(Duration::from_secs(secs)),
+    }
+}
+
+fn parse_size(size: &str) -> Result<u64, String> {
+    const FAT32_MAX_FILE_SIZE: u64 = 4 * 1024 * 1024 * 1024 - 1;
+    if size.eq_ignore_ascii_case("fat32") {
+        return Ok(FAT32_MAX_FILE_SIZE);
+    }
+
+    let num_end = size
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(size.len());
+    let (num, suffix) = size.split_at(num_end);
+    let num = num
+        .parse::<u64>()
+        .map_err(|e| format!("invalid size {size}: {e}"))?;
+    let multiplier: u64 = match suffix.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" => 1000,
+        "m" | "mb" => 1000 * 1000,
+        "g" | "gb" => 1000 * 1000 * 1000,
+        "kib" => 1024,
+        "mib" => 1024 * 1024,
+        "gib" => 1024 * 1024 * 1024,
+        _ => return Err(format!("invalid size suffix in {size}")),
+    };
+    match num.checked_mul(multiplier) {
+        Some(0) => Err(format!("invalid size {size}: must be greater than zero")),
+        None => Err(format!("invalid size {size}: too large")),
+        Some(size) => Ok(size),
+    }
+}
@@ -1,7 +1,16 @@
+use crate::index::Index;
+use crate::warnings;
 use crates_index;
+use flate2::read::GzDecoder;
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use serde::{Deserialize, Deserializer};
+use std::collections::HashMap;
 use std::fmt;
 use std::fmt::Display;
 use std::hash::{Hash, Hasher};
+use std::io::{self, Read};
+use std::sync::Arc;
+use tracing::warn;
 
 #[derive(Debug)]
 pub enum Error {
@@ -35,12 +44,172 @@ impl std::error::Error for Error {
 
 type Result<T> = std::result::Result<T, Error>;
 
+/// The URL of the crates.io index, as other registries' dependencies on crates.io give it.
+pub const CRATES_IO_INDEX: &str = "https://github.com/rust-lang/crates.io-index";
+
+/// Normalizes a registry index URL, so the same registry compares equal however it is written.
+pub fn canonical_registry_url(index_url: &str) -> String {
+    let url = index_url.trim_end_matches('/');
+    url.strip_suffix(".git").unwrap_or(url).to_string()
+}
+
+/// The characters percent-encoded in versions put in URL paths: those not allowed in a path
+/// segment, plus `+`, which object stores such as S3 decode as a space.
+const URL_PATH_VERSION: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'+')
+    .add(b'/')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+/// Percent-encodes the version for URL paths, notably the `+` introducing build metadata,
+/// e.g. the one in `2.0.9+zstd.1.5.5`, which object stores such as S3 would otherwise decode
+/// as a space. File paths and index entries keep the version as it is.
+pub fn url_path_version(version: &str) -> String {
+    utf8_percent_encode(version, URL_PATH_VERSION).to_string()
+}
+
+/// Whether the crate name is safe to put in URLs and file paths as it is: ASCII letters,
+/// digits, `-` and `_` only, as crates.io and Cargo require.
+pub fn is_valid_crate_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// A crate version from the index.
+///
+/// Cloning is cheap: clones share the underlying index entry, so the same version can be held
+/// in the resolved set, the provenance graph, and the download list without copying it.
+///
+/// Versions are equal if their names and versions are, whichever registry they are from,
+/// since a mirror can only hold one of them.
 #[derive(Clone)]
-pub struct Version(pub crates_index::Version);
+pub struct Version(
+    pub Arc<crates_index::Version>,
+    Option<Arc<str>>,
+    Option<Arc<Extras>>,
+);
+
+/// The fields of an index entry that `crates_index::Version` drops when it is parsed.
+#[derive(Default)]
+struct Extras {
+    rust_version: Option<Box<str>>,
+    /// The `registry` field of each dependency, in the order of `Version::dependencies`.
+    registries: Box<[Option<Box<str>>]>,
+}
 
 impl Version {
+    pub fn new(version: crates_index::Version) -> Self {
+        Version(Arc::new(version), None, None)
+    }
+
+    /// Parses an index entry, i.e. a line of a crate's index file, keeping the fields
+    /// `crates_index::Version` drops. Features from `features2` are merged into `features`,
+    /// as crates_index does when it reads an index file.
+    pub fn from_json(entry: &str) -> serde_json::Result<Self> {
+        serde_json::from_str(entry)
+    }
+
+    fn from_value(mut value: serde_json::Value) -> serde_json::Result<Self> {
+        let mut extras = Extras::default();
+        if let Some(entry) = value.as_object_mut() {
+            merge_features2(entry);
+            extras.rust_version = entry
+                .get("rust_version")
+                .and_then(|v| v.as_str())
+                .map(Box::from);
+            if let Some(deps) = entry.get("deps").and_then(|deps| deps.as_array()) {
+                extras.registries = deps
+                    .iter()
+                    .map(|dep| dep.get("registry").and_then(|r| r.as_str()).map(Box::from))
+                    .collect();
+            }
+        }
+        let version = serde_json::from_value(value)?;
+        let has_extras =
+            extras.rust_version.is_some() || extras.registries.iter().any(|r| r.is_some());
+        Ok(Version(
+            Arc::new(version),
+            None,
+            has_extras.then(|| Arc::new(extras)),
+        ))
+    }
+
+    /// Marks the version as coming from the secondary registry with the canonical index URL,
+    /// or from the registry resolved against if `None`.
+    pub fn with_registry(mut self, registry: Option<Arc<str>>) -> Self {
+        self.1 = registry;
+        self
+    }
+
+    /// Returns the canonical index URL of the secondary registry the version is from,
+    /// or `None` if it is from the registry resolved against.
+    pub fn registry(&self) -> Option<&str> {
+        self.1.as_deref()
+    }
+
     pub fn to_json(&self) -> Result<String> {
-        serde_json::to_string(&self.0).map_err(|e| Error::SerializeVersion(e))
+        serde_json::to_string(&self.to_value()?).map_err(Error::SerializeVersion)
+    }
+
+    /// Serializes the version back into an index entry, with the fields
+    /// `crates_index::Version` drops.
+    fn to_value(&self) -> Result<serde_json::Value> {
+        let mut value = serde_json::to_value(self.0.as_ref()).map_err(Error::SerializeVersion)?;
+        if let Some(rust_version) = self.rust_version() {
+            if let Some(entry) = value.as_object_mut() {
+                entry.insert("rust_version".to_string(), rust_version.into());
+            }
+        }
+        let registries = self.2.as_ref().map(|extras| &extras.registries[..]);
+        if let (Some(deps), Some(registries)) = (
+            value.get_mut("deps").and_then(|deps| deps.as_array_mut()),
+            registries,
+        ) {
+            for (dep, registry) in deps.iter_mut().zip(registries.iter()) {
+                if let (Some(dep), Some(registry)) = (dep.as_object_mut(), registry) {
+                    dep.insert("registry".to_string(), registry.as_ref().into());
+                }
+            }
+        }
+        Ok(value)
+    }
+
+    /// Serializes the version for the index of a mirror holding the crates of the registries
+    /// with the canonical index URLs. Dependencies on those registries are on the mirror itself
+    /// then, so their `registry` field is removed. A yanked version stays yanked in the mirror,
+    /// so Cargo keeps using it only where a lockfile pins it. The `rust_version` is kept for
+    /// Cargo's MSRV-aware resolver. Features are split between `features` and `features2`
+    /// as crates.io does; see `split_namespaced_features`.
+    pub fn to_mirror_json(&self, mirrored_registries: &[&str]) -> Result<String> {
+        let mut value = self.to_value()?;
+        if let Some(entry) = value.as_object_mut() {
+            entry.insert("yanked".to_string(), self.is_yanked().into());
+            split_namespaced_features(entry);
+        }
+        if let Some(deps) = value.get_mut("deps").and_then(|deps| deps.as_array_mut()) {
+            for dep in deps.iter_mut().filter_map(|dep| dep.as_object_mut()) {
+                let is_mirrored = match dep.get("registry").and_then(|r| r.as_str()) {
+                    Some(registry) => {
+                        mirrored_registries.contains(&canonical_registry_url(registry).as_str())
+                    }
+                    None => false,
+                };
+                if is_mirrored {
+                    dep.remove("registry");
+                }
+            }
+        }
+        serde_json::to_string(&value).map_err(Error::SerializeVersion)
     }
 
     pub fn name(&self) -> &str {
@@ -51,9 +220,50 @@ impl Version {
         self.0.version()
     }
 
+    /// Whether the version was yanked. Cargo only uses yanked versions already in a lockfile.
+    pub fn is_yanked(&self) -> bool {
+        self.0.is_yanked()
+    }
+
+    /// Returns the minimum supported Rust version the crate declares, if any.
+    pub fn rust_version(&self) -> Option<&str> {
+        self.2.as_ref()?.rust_version.as_deref()
+    }
+
     pub fn dependencies(&self) -> &[crates_index::Dependency] {
         self.0.dependencies()
     }
+
+    /// Returns the index URL of the registry the dependency, one of the version's own, is on,
+    /// as the index entry gives it, or `None` if it is on the crate's own registry.
+    pub fn dependency_registry(&self, dependency: &crates_index::Dependency) -> Option<&str> {
+        let i = self
+            .dependencies()
+            .iter()
+            .position(|dep| std::ptr::eq(dep, dependency))?;
+        self.2.as_ref()?.registries.get(i)?.as_deref()
+    }
+
+    /// Returns the crate's features, each with the features and dependencies it enables.
+    pub fn features(&self) -> &HashMap<String, Vec<String>> {
+        self.0.features()
+    }
+
+    /// Returns the SHA256 checksum of the .crate file as a lowercase hex string.
+    pub fn checksum(&self) -> String {
+        self.0
+            .checksum()
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect()
+    }
+}
+
+impl<'de> Deserialize<'de> for Version {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let value = serde_json::Value::deserialize(deserializer)?;
+        Version::from_value(value).map_err(serde::de::Error::custom)
+    }
 }
 
 impl PartialEq for Version {
@@ -70,8 +280,175 @@ impl Hash for Version {
     }
 }
 
-pub fn get_crate(index: &crates_index::Index, name: &str) -> Result<crates_index::Crate> {
+/// Merges the features of the entry's `features2` into its `features`.
+fn merge_features2(entry: &mut serde_json::Map<String, serde_json::Value>) {
+    let features2 = match entry.remove("features2") {
+        Some(serde_json::Value::Object(features2)) => features2,
+        _ => return,
+    };
+    let features = entry
+        .entry("features")
+        .or_insert_with(|| serde_json::Map::new().into());
+    if let Some(features) = features.as_object_mut() {
+        for (name, values) in features2 {
+            match (features.get_mut(&name), values) {
+                (Some(serde_json::Value::Array(existing)), serde_json::Value::Array(values)) => {
+                    existing.extend(values)
+                }
+                (_, values) => {
+                    features.insert(name, values);
+                }
+            }
+        }
+    }
+}
+
+/// Moves the features using the `dep:` or `?/` syntax, which Cargo versions before 1.60 can't
+/// parse, from `features` to `features2`, and sets the entry's `v` to 2 if there are any, so
+/// those Cargo versions skip the entry instead of failing. Cargo merges the two fields back.
+fn split_namespaced_features(entry: &mut serde_json::Map<String, serde_json::Value>) {
+    let mut features = match entry.remove("features") {
+        Some(serde_json::Value::Object(features)) => features,
+        _ => serde_json::Map::new(),
+    };
+    if let Some(serde_json::Value::Object(features2)) = entry.remove("features2") {
+        features.extend(features2);
+    }
+    let is_namespaced = |values: &serde_json::Value| match values.as_array() {
+        Some(values) => values
+            .iter()
+            .filter_map(|v| v.as_str())
+            .any(|v| v.starts_with("dep:") || v.contains("?/")),
+        None => false,
+    };
+    let (features2, features): (serde_json::Map<_, _>, serde_json::Map<_, _>) = features
+        .into_iter()
+        .partition(|(_, values)| is_namespaced(values));
+    entry.insert("features".to_string(), features.into());
+    if !features2.is_empty() {
+        entry.insert("features2".to_string(), features2.into());
+        let v = entry.get("v").and_then(|v| v.as_u64()).unwrap_or(1);
+        entry.insert("v".to_string(), v.max(2).into());
+    }
+}
+
+/// A crate in the index, with all its versions in the order they were published.
+pub struct Crate {
+    versions: Vec<Version>,
+}
+
+impl Crate {
+    /// Parses the crate's index file, one entry per line.
+    /// Returns `None` if it has no entries.
+    pub fn from_index_file(contents: &str) -> serde_json::Result<Option<Self>> {
+        let versions = contents
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(Version::from_json)
+            .collect::<serde_json::Result<Vec<_>>>()?;
+        Ok((!versions.is_empty()).then_some(Crate { versions }))
+    }
+
+    pub fn name(&self) -> &str {
+        self.versions[0].name()
+    }
+
+    pub fn versions(&self) -> &[Version] {
+        &self.versions
+    }
+
+    /// Returns the highest version that is neither yanked nor a pre-release, if any.
+    pub fn highest_normal_version(&self) -> Option<&Version> {
+        self.versions
+            .iter()
+            .filter(|v| !v.is_yanked())
+            .filter_map(|v| Some((v, semver::Version::parse(v.version()).ok()?)))
+            .filter(|(_, version)| version.pre.is_empty())
+            .max_by(|a, b| a.1.cmp(&b.1))
+            .map(|(v, _)| v)
+    }
+}
+
+pub fn get_crate(index: &Index, name: &str) -> Result<Crate> {
     index.crate_(name).ok_or(Error::CrateNotFound {
         crate_name: name.to_string(),
     })
 }
+
+/// Reads a file packaged inside the .crate file, given its path relative to the package root.
+/// Returns `None` if the .crate file doesn't contain the file.
+pub fn read_packaged_file(
+    crate_file: &[u8],
+    crat: &Version,
+    file_path: &str,
+) -> io::Result<Option<String>> {
+    let entry_path = format!("{}-{}/{file_path}", crat.name(), crat.version());
+    let mut archive = tar::Archive::new(GzDecoder::new(crate_file));
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if entry.path()?.to_string_lossy() != entry_path {
+            continue;
+        }
+        let mut contents = String::new();
+        entry.read_to_string(&mut contents)?;
+        return Ok(Some(contents));
+    }
+    Ok(None)
+}
+
+/// Reads the Cargo.toml packaged inside the .crate file.
+/// Returns `None` if it is missing or can't be parsed.
+pub fn read_packaged_manifest(
+    crate_file: &[u8],
+    crat: &Version,
+) -> io::Result<Option<toml::Value>> {
+    let contents = match read_packaged_file(crate_file, crat, "Cargo.toml")? {
+        Some(contents) => contents,
+        None => return Ok(None),
+    };
+    match contents.parse::<toml::Value>() {
+        Ok(manifest) => Ok(Some(manifest)),
+        Err(e) => {
+            warn!(
+                target: warnings::PACKAGED_MANIFEST,
+                crate_name = crat.name(),
+                crate_version = crat.version(),
+                "failed to parse packaged Cargo.toml: {e}"
+            );
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENTRY: &str = r#"{"name":"zstd-sys","vers":"2.0.9+zstd.1.5.5","deps":[],"cksum":"0000000000000000000000000000000000000000000000000000000000000000","features":{},"yanked":false}"#;
+
+    #[test]
+    fn build_metadata_is_kept_when_parsing() {
+        let crat = Version::from_json(ENTRY).unwrap();
+        assert_eq!(crat.version(), "2.0.9+zstd.1.5.5");
+        let version = semver::Version::parse(crat.version()).unwrap();
+        assert_eq!(version.build.as_str(), "zstd.1.5.5");
+        // Build metadata doesn't take part in version requirements.
+        assert!(semver::VersionReq::parse("^2.0.9")
+            .unwrap()
+            .matches(&version));
+    }
+
+    #[test]
+    fn build_metadata_round_trips_through_index_entries() {
+        let crat = Version::from_json(ENTRY).unwrap();
+        let reparsed = Version::from_json(&crat.to_json().unwrap()).unwrap();
+        assert_eq!(reparsed.version(), "2.0.9+zstd.1.5.5");
+        assert!(reparsed == crat);
+    }
+
+    #[test]
+    fn build_metadata_is_percent_encoded_in_url_paths() {
+        assert_eq!(url_path_version("2.0.9+zstd.1.5.5"), "2.0.9%2Bzstd.1.5.5");
+        assert_eq!(url_path_version("1.0.0-rc.1"), "1.0.0-rc.1");
+    }
+}
@@ -0,0 +1,344 @@
+use anyhow::{anyhow, Context};
+use micrio::notify::{EmailNotifier, Notifier, SlackNotifier};
+use micrio::selection::{self, CrateSpec, FeatureSpec, SelectionSpec};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// The configuration file looked up in the current directory, read if it exists, unless another
+/// one is given with --config.
+pub const CONFIG_FILE_NAME: &str = "micrio.toml";
+
+/// The contents of a micrio.toml file.
+///
+/// The `[mirror]` table holds the settings of every run, so they needn't be repeated on the
+/// command line. Each profile bundles the settings of one flavor of mirror on top of them, so
+/// several can be maintained from one file:
+///
+/// ```toml
+/// [mirror]
+/// mirror-dir = "/srv/mirror"
+/// from-file = ["crates.txt"]
+/// exclude = ["openssl-sys"]
+/// max-concurrent-requests = 16
+/// registry = "https://git.example.com/crates-index.git"
+/// keep-run-reports = 30
+///
+/// [profile.embedded]
+/// crates = ["heapless", "embedded-hal@1"]
+/// targets = ["thumbv7em-none-eabihf"]
+/// no-default-features = true
+///
+/// [profile.ci-minimal]
+/// from-file = ["ci-crates.txt"]
+/// max-depth = 2
+/// keep-run-reports = 5
+///
+/// [profile.ci-locked]
+/// from-lockfile = ["../app/Cargo.lock"]
+///
+/// [profile.full]
+/// most-downloaded = 1000
+/// categories = ["embedded", "wasm=100"]
+/// exclude = ["openssl-sys"]
+///
+/// [overrides]
+/// time = "=0.3.35"
+///
+/// [notifications.email]
+/// host = "smtp.example.com"
+/// security = "starttls"
+/// username = "micrio"
+/// password-env = "MICRIO_SMTP_PASSWORD"
+/// from = "micrio@example.com"
+/// to = ["mirror-admins@example.com"]
+///
+/// [notifications.slack]
+/// webhook-url = "https://hooks.slack.com/services/T000/B000/XXXX"
+/// only-failures = true
+/// ```
+///
+/// The options given on the command line take precedence over the profile's settings, which
+/// take precedence over those of the `[mirror]` table. Crates, crate lists and exclusions add up
+/// instead. The overrides apply to every profile, forcing the version every dependency on the crate is
+/// resolved to, e.g. to work around a broken upstream release. The notifications are sent when
+/// a mirror run finishes, see `micrio::notify`.
+#[derive(Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// The settings of every run.
+    #[serde(default)]
+    mirror: Profile,
+    #[serde(default)]
+    profile: HashMap<String, Profile>,
+    /// Version requirements by crate name.
+    #[serde(default)]
+    pub overrides: BTreeMap<String, String>,
+    #[serde(default)]
+    pub notifications: Notifications,
+    /// The directory relative paths in the profiles are resolved against.
+    #[serde(skip)]
+    base_dir: PathBuf,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+/// A set of selection, target, feature, upstream, and retention settings, of a profile or the
+/// `[mirror]` table.
+#[derive(Clone, Default, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Profile {
+    pub mirror_dir: Option<PathBuf>,
+    /// Crates given as `NAME` or `NAME@REQ`.
+    #[serde(default)]
+    crates: Vec<String>,
+    #[serde(default)]
+    from_file: Vec<PathBuf>,
+    #[serde(default)]
+    from_manifest: Vec<PathBuf>,
+    #[serde(default)]
+    from_lockfile: Vec<PathBuf>,
+    #[serde(default)]
+    from_projects: Vec<PathBuf>,
+    #[serde(default)]
+    from_mirror: Vec<String>,
+    most_downloaded: Option<u64>,
+    recently_updated: Option<u64>,
+    /// Categories given as `SLUG` or `SLUG=LIMIT`.
+    #[serde(default)]
+    categories: Vec<String>,
+    /// Keywords given as `KEYWORD` or `KEYWORD=LIMIT`.
+    #[serde(default)]
+    keywords: Vec<String>,
+    /// crates.io users and teams whose crates are all selected.
+    #[serde(default)]
+    owned_by: Vec<String>,
+    min_downloads: Option<u64>,
+    #[serde(default)]
+    exclude: Vec<String>,
+    #[serde(default)]
+    pub targets: Vec<String>,
+    pub max_depth: Option<usize>,
+    #[serde(default)]
+    all_features: bool,
+    #[serde(default)]
+    no_default_features: bool,
+    #[serde(default)]
+    features: Vec<String>,
+    pub max_requests_per_sec: Option<f64>,
+    pub max_concurrent_requests: Option<usize>,
+    /// The index URL of the registry to mirror instead of crates.io.
+    pub registry: Option<String>,
+    pub registry_api: Option<String>,
+    pub registry_dl: Option<String>,
+    pub serve_url: Option<String>,
+    /// How many run reports to keep in the mirror's state directory.
+    pub keep_run_reports: Option<usize>,
+}
+
+/// Where to send the outcome of mirror runs.
+#[derive(Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Notifications {
+    email: Option<EmailNotifier>,
+    slack: Option<SlackNotifier>,
+}
+
+impl Notifications {
+    pub fn notifiers(&self) -> Vec<Arc<dyn Notifier>> {
+        let mut notifiers: Vec<Arc<dyn Notifier>> = Vec::new();
+        if let Some(email) = &self.email {
+            notifiers.push(Arc::new(email.clone()));
+        }
+        if let Some(slack) = &self.slack {
+            notifiers.push(Arc::new(slack.clone()));
+        }
+        notifiers
+    }
+}
+
+/// Reads the configuration file.
+pub fn load_config(config_path: &Path) -> anyhow::Result<Config> {
+    let contents = fs::read_to_string(config_path).with_context(|| {
+        format!(
+            "failed to read the configuration file {}",
+            config_path.to_string_lossy()
+        )
+    })?;
+    let mut config: Config = toml::from_str(&contents).with_context(|| {
+        format!(
+            "invalid configuration file {}",
+            config_path.to_string_lossy()
+        )
+    })?;
+    config.base_dir = config_path.parent().unwrap_or(Path::new("")).to_path_buf();
+    config.path = config_path.to_path_buf();
+    Ok(config)
+}
+
+impl Config {
+    /// Takes the settings of a run out of the configuration: those of the profile with the
+    /// name, if any, on top of those of the `[mirror]` table. Relative paths are resolved
+    /// against the configuration file's directory.
+    pub fn take_profile(&mut self, name: Option<&str>) -> anyhow::Result<Profile> {
+        let mut defaults = std::mem::take(&mut self.mirror);
+        defaults.resolve_paths(&self.base_dir);
+        let name = match name {
+            Some(name) => name,
+            None => return Ok(defaults),
+        };
+        let mut profile = self.profile.remove(name).ok_or_else(|| {
+            anyhow!(
+                "profile {name} not found in the configuration file {}",
+                self.path.to_string_lossy()
+            )
+        })?;
+        profile.resolve_paths(&self.base_dir);
+        Ok(profile.on_top_of(defaults))
+    }
+}
+
+impl Profile {
+    fn resolve_paths(&mut self, base_dir: &Path) {
+        if let Some(mirror_dir) = &mut self.mirror_dir {
+            *mirror_dir = base_dir.join(&*mirror_dir);
+        }
+        for path in self
+            .from_file
+            .iter_mut()
+            .filter(|path| selection::list_url(path).is_none() && !selection::is_stdin_list(path))
+            .chain(self.from_manifest.iter_mut())
+            .chain(self.from_lockfile.iter_mut())
+            .chain(self.from_projects.iter_mut())
+        {
+            *path = base_dir.join(&*path);
+        }
+        for mirror in &mut self.from_mirror {
+            if !mirror.contains("://") {
+                *mirror = base_dir.join(&*mirror).to_string_lossy().into_owned();
+            }
+        }
+    }
+
+    /// Returns the profile's settings, with those it doesn't have taken from the defaults.
+    /// The crates, crate lists, and exclusions of both add up.
+    fn on_top_of(mut self, defaults: Profile) -> Profile {
+        let mut crates = defaults.crates;
+        crates.append(&mut self.crates);
+        let mut from_file = defaults.from_file;
+        from_file.append(&mut self.from_file);
+        let mut from_manifest = defaults.from_manifest;
+        from_manifest.append(&mut self.from_manifest);
+        let mut from_lockfile = defaults.from_lockfile;
+        from_lockfile.append(&mut self.from_lockfile);
+        let mut from_projects = defaults.from_projects;
+        from_projects.append(&mut self.from_projects);
+        let mut from_mirror = defaults.from_mirror;
+        from_mirror.append(&mut self.from_mirror);
+        let mut categories = defaults.categories;
+        categories.append(&mut self.categories);
+        let mut keywords = defaults.keywords;
+        keywords.append(&mut self.keywords);
+        let mut owned_by = defaults.owned_by;
+        owned_by.append(&mut self.owned_by);
+        let mut exclude = defaults.exclude;
+        exclude.append(&mut self.exclude);
+        let has_features =
+            self.all_features || self.no_default_features || !self.features.is_empty();
+        Profile {
+            mirror_dir: self.mirror_dir.or(defaults.mirror_dir),
+            crates,
+            from_file,
+            from_manifest,
+            from_lockfile,
+            from_projects,
+            from_mirror,
+            most_downloaded: self.most_downloaded.or(defaults.most_downloaded),
+            recently_updated: self.recently_updated.or(defaults.recently_updated),
+            categories,
+            keywords,
+            owned_by,
+            min_downloads: self.min_downloads.or(defaults.min_downloads),
+            exclude,
+            targets: if self.targets.is_empty() {
+                defaults.targets
+            } else {
+                self.targets
+            },
+            max_depth: self.max_depth.or(defaults.max_depth),
+            all_features: if has_features {
+                self.all_features
+            } else {
+                defaults.all_features
+            },
+            no_default_features: if has_features {
+                self.no_default_features
+            } else {
+                defaults.no_default_features
+            },
+            features: if has_features {
+                self.features
+            } else {
+                defaults.features
+            },
+            max_requests_per_sec: self.max_requests_per_sec.or(defaults.max_requests_per_sec),
+            max_concurrent_requests: self
+                .max_concurrent_requests
+                .or(defaults.max_concurrent_requests),
+            registry: self.registry.or(defaults.registry),
+            registry_api: self.registry_api.or(defaults.registry_api),
+            registry_dl: self.registry_dl.or(defaults.registry_dl),
+            serve_url: self.serve_url.or(defaults.serve_url),
+            keep_run_reports: self.keep_run_reports.or(defaults.keep_run_reports),
+        }
+    }
+
+    /// Adds the profile's crates and exclusions to the selection given on the command line.
+    /// The top N most downloaded crates and the features given on the command line,
+    /// if any, take precedence over the profile's.
+    pub fn add_to_selection(&self, spec: &mut SelectionSpec) -> anyhow::Result<()> {
+        for crat in &self.crates {
+            spec.crates.push(crat.parse::<CrateSpec>()?);
+        }
+        spec.files.extend(self.from_file.iter().cloned());
+        for manifest_path in &self.from_manifest {
+            spec.add_manifest(manifest_path)?;
+        }
+        spec.lockfiles.extend(self.from_lockfile.iter().cloned());
+        for projects_dir_path in &self.from_projects {
+            spec.add_projects(projects_dir_path)?;
+        }
+        spec.mirrors.extend(self.from_mirror.iter().cloned());
+        if spec.most_downloaded.is_none() {
+            spec.most_downloaded = self.most_downloaded;
+        }
+        if spec.recently_updated.is_none() {
+            spec.recently_updated = self.recently_updated;
+        }
+        for category in &self.categories {
+            spec.categories.push(category.parse()?);
+        }
+        for keyword in &self.keywords {
+            spec.keywords.push(keyword.parse()?);
+        }
+        spec.owners.extend(self.owned_by.iter().cloned());
+        if spec.min_downloads.is_none() {
+            spec.min_downloads = self.min_downloads;
+        }
+        for exclusion in &self.exclude {
+            spec.exclusions.push(exclusion.parse()?);
+        }
+        if spec.features.is_none()
+            && (self.all_features || self.no_default_features || !self.features.is_empty())
+        {
+            spec.features = Some(FeatureSpec {
+                all_features: self.all_features,
+                no_default_features: self.no_default_features,
+                features: self.features.clone(),
+            });
+        }
+        Ok(())
+    }
+}
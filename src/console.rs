@@ -0,0 +1,511 @@
+use micrio::advisory::Advisory;
+use micrio::common::Version;
+use micrio::progress::{Phase, PhaseTimer, ProgressObserver};
+use micrio::provenance::GraphStats;
+use micrio::site::format_size;
+use serde_json::{json, Value};
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+const BOLD: &str = "1";
+const RED: &str = "31";
+const GREEN: &str = "32";
+const YELLOW: &str = "33";
+
+/// Reports progress on the console, and summarizes the run in a table when it ends.
+pub struct ConsoleProgress {
+    color: bool,
+    stats: Mutex<RunStats>,
+}
+
+/// What the run did so far, for the summary.
+#[derive(Default)]
+struct RunStats {
+    num_top_level: usize,
+    num_dependencies: usize,
+    num_downloaded: usize,
+    num_failed: usize,
+    total_size: u64,
+    phases: PhaseTimer,
+    graph: Option<GraphStats>,
+}
+
+impl RunStats {
+    /// The download throughput so far, and the estimated time remaining if the number of crates
+    /// to download is known. The remaining crates are assumed to be the average size so far.
+    fn download_rate(&self, completed: usize, total: usize) -> Option<String> {
+        let (_, started) = self.phases.current()?;
+        let elapsed = started.elapsed().as_secs_f64();
+        if elapsed == 0.0 || self.num_downloaded == 0 {
+            return None;
+        }
+        let throughput = self.total_size as f64 / elapsed;
+        let mut rate = format!("{}/s", format_size(throughput as u64));
+        if total > completed && throughput > 0.0 {
+            let average_size = self.total_size as f64 / self.num_downloaded as f64;
+            let remaining = (total - completed) as f64 * average_size / throughput;
+            rate += &format!(
+                ", ETA {}",
+                format_duration(Duration::from_secs_f64(remaining))
+            );
+        }
+        Some(rate)
+    }
+}
+
+impl ConsoleProgress {
+    /// Colors the status in the output with ANSI escape codes if `color` is set.
+    pub fn new(color: bool) -> Self {
+        ConsoleProgress {
+            color,
+            stats: Mutex::new(RunStats::default()),
+        }
+    }
+
+    fn paint(&self, style: &str, s: &str) -> String {
+        if self.color {
+            format!("\x1b[{style}m{s}\x1b[0m")
+        } else {
+            s.to_string()
+        }
+    }
+
+    fn stats(&self) -> std::sync::MutexGuard<'_, RunStats> {
+        // The stats are only counters, so they're still usable if a thread panicked.
+        self.stats.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn print_summary(&self, succeeded: bool) {
+        let stats = self.stats();
+        let num_crates = stats.num_top_level + stats.num_dependencies;
+        // Crates kept out of the mirror, e.g. by exclusions or cancellation.
+        let num_skipped = num_crates.saturating_sub(stats.num_downloaded + stats.num_failed);
+        let mut rows = vec![
+            (
+                "Top-level crates selected".to_string(),
+                stats.num_top_level.to_string(),
+                None,
+            ),
+            (
+                "Dependencies resolved".to_string(),
+                stats.num_dependencies.to_string(),
+                None,
+            ),
+        ];
+        if let Some(graph) = &stats.graph {
+            rows.push((
+                "Maximum dependency depth".to_string(),
+                graph.max_depth.to_string(),
+                None,
+            ));
+            rows.push((
+                "Average fan-out".to_string(),
+                format!("{:.1}", graph.average_fan_out),
+                None,
+            ));
+            rows.push((
+                "Crates in longest chain".to_string(),
+                graph.longest_chain.len().to_string(),
+                None,
+            ));
+        }
+        rows.extend([
+            (
+                "Crates downloaded".to_string(),
+                stats.num_downloaded.to_string(),
+                None,
+            ),
+            ("Crates skipped".to_string(), num_skipped.to_string(), None),
+            (
+                "Crates failed".to_string(),
+                stats.num_failed.to_string(),
+                (stats.num_failed > 0).then_some(RED),
+            ),
+            (
+                "Total size".to_string(),
+                format_size(stats.total_size),
+                None,
+            ),
+        ]);
+        let download_time = stats
+            .phases
+            .times()
+            .iter()
+            .find(|time| time.phase == Phase::Download)
+            .map(|time| time.elapsed.as_secs_f64());
+        if let Some(download_time) = download_time.filter(|t| *t > 0.0) {
+            let throughput = stats.total_size as f64 / download_time;
+            rows.push((
+                "Download throughput".to_string(),
+                format!("{}/s", format_size(throughput as u64)),
+                None,
+            ));
+        }
+        for phase_time in stats.phases.times() {
+            let mut time = format_duration(phase_time.elapsed);
+            if let Some(num_crates) = phase_time.num_crates {
+                time = format!("{num_crates} crates in {time}");
+            }
+            rows.push((capitalize(&phase_time.phase.to_string()), time, None));
+        }
+        if let Some(elapsed) = stats.phases.elapsed() {
+            rows.push(("Total time".to_string(), format_duration(elapsed), None));
+        }
+
+        let status = if succeeded {
+            self.paint(GREEN, "succeeded")
+        } else {
+            self.paint(RED, "failed")
+        };
+        println!("{} {status}", self.paint(BOLD, "Run"));
+        let label_width = rows
+            .iter()
+            .map(|(label, _, _)| label.len())
+            .max()
+            .unwrap_or(0);
+        let value_width = rows
+            .iter()
+            .map(|(_, value, _)| value.len())
+            .max()
+            .unwrap_or(0);
+        for (label, value, style) in rows {
+            // Padded before painting, since the escape codes take no space on the terminal.
+            let value = format!("{value:>value_width$}");
+            let value = match style {
+                Some(style) => self.paint(style, &value),
+                None => value,
+            };
+            println!("\t{label:<label_width$}  {value}");
+        }
+        if let Some(graph) = &stats.graph {
+            print_graph_details(graph);
+        }
+    }
+}
+
+impl ProgressObserver for ConsoleProgress {
+    fn phase_started(&self, phase: Phase) {
+        self.stats().phases.start(phase);
+        println!(
+            "{}",
+            self.paint(BOLD, &format!("{}...", capitalize(&phase.to_string())))
+        );
+    }
+
+    fn phase_finished(&self, phase: Phase) {
+        self.stats().phases.end(true);
+        println!("{} {phase}.", self.paint(GREEN, "Done"));
+    }
+
+    fn phase_crates(&self, _phase: Phase, num_crates: usize) {
+        self.stats().phases.set_num_crates(num_crates);
+    }
+
+    fn fetching_most_downloaded_page(&self, page: u64, num_pages: u64) {
+        println!("Most downloaded crates - retrieving page {page:>3} of {num_pages:>3}...");
+    }
+
+    fn resolution_started(&self, num_top_level: usize) {
+        self.stats().num_top_level = num_top_level;
+        println!("{num_top_level} top level crates selected.");
+    }
+
+    fn top_level_analyzed(&self, crat: &Version, completed: usize, total: usize) {
+        println!(
+            "Analyzing {completed:>4} of {total}: {} version {}",
+            crat.name(),
+            crat.version()
+        );
+    }
+
+    fn dependency_analyzed(&self, crat: &Version) {
+        println!(
+            "\tAnalyzing dependency {} version {}",
+            crat.name(),
+            crat.version()
+        );
+    }
+
+    fn resolution_finished(&self, num_dependencies: usize) {
+        self.stats().num_dependencies = num_dependencies;
+        println!("{num_dependencies} total dependencies identified.");
+    }
+
+    fn graph_analyzed(&self, stats: &GraphStats) {
+        self.stats().graph = Some(stats.clone());
+    }
+
+    fn advisory_found(&self, crat: &Version, advisory: &Advisory) {
+        let kind = advisory.informational.as_deref().unwrap_or("vulnerability");
+        println!(
+            "\t{} version {}: {}: {}",
+            crat.name(),
+            crat.version(),
+            self.paint(YELLOW, &format!("{} ({kind})", advisory.id)),
+            advisory.title
+        );
+    }
+
+    fn crate_downloaded(&self, crat: &Version, completed: usize, total: usize) {
+        let rate = {
+            let mut stats = self.stats();
+            stats.num_downloaded += 1;
+            match stats.download_rate(completed, total) {
+                Some(rate) => format!(" ({rate})"),
+                None => String::new(),
+            }
+        };
+        let downloaded = self.paint(GREEN, "Downloaded");
+        if total == 0 {
+            println!(
+                "{downloaded} {completed:>4}: {} version {}{rate}",
+                crat.name(),
+                crat.version()
+            );
+            return;
+        }
+        println!(
+            "{downloaded} {completed:>4} of {total:>4}: {} version {}{rate}",
+            crat.name(),
+            crat.version()
+        );
+    }
+
+    fn crate_stored(&self, _crat: &Version, size: u64) {
+        self.stats().total_size += size;
+    }
+
+    fn crate_published(&self, crat: &Version, completed: usize, total: usize) {
+        println!(
+            "{} {completed:>4} of {total:>4}: {} version {}",
+            self.paint(GREEN, "Published"),
+            crat.name(),
+            crat.version()
+        );
+    }
+
+    fn failure(
+        &self,
+        phase: Phase,
+        crat: Option<&Version>,
+        error: &(dyn std::error::Error + 'static),
+    ) {
+        // Failures that abort the run are reported by main.
+        match crat {
+            Some(crat) => {
+                if phase == Phase::Download {
+                    self.stats().num_failed += 1;
+                }
+                println!(
+                    "\t{} {phase} for {} version {}: {error}",
+                    self.paint(RED, "Failed"),
+                    crat.name(),
+                    crat.version()
+                );
+            }
+            None => self.stats().phases.end(false),
+        }
+    }
+
+    fn info(&self, msg: &str) {
+        println!("{msg}");
+    }
+
+    fn run_finished(&self, succeeded: bool) {
+        self.print_summary(succeeded);
+    }
+}
+
+/// Prints the crates too many to fit in the summary table: the most depended upon crates
+/// and the longest dependency chain.
+fn print_graph_details(graph: &GraphStats) {
+    if !graph.most_depended_upon.is_empty() {
+        let most_depended_upon = Vec::from_iter(
+            graph
+                .most_depended_upon
+                .iter()
+                .map(|(crat, count)| format!("{} {} ({count})", crat.name(), crat.version())),
+        );
+        println!("\tMost depended upon: {}", most_depended_upon.join(", "));
+    }
+    if graph.longest_chain.len() > 1 {
+        let chain = Vec::from_iter(
+            graph
+                .longest_chain
+                .iter()
+                .map(|crat| format!("{} {}", crat.name(), crat.version())),
+        );
+        println!("\tLongest chain: {}", chain.join(" -> "));
+    }
+}
+
+fn capitalize(s: &str) -> String {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+/// Formats a duration in seconds, or in hours, minutes, and seconds for a minute or more.
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match seconds {
+        0..=59 => format!("{:.1}s", duration.as_secs_f64()),
+        60..=3599 => format!("{}m{:02}s", seconds / 60, seconds % 60),
+        _ => format!(
+            "{}h{:02}m{:02}s",
+            seconds / 3600,
+            seconds % 3600 / 60,
+            seconds % 60
+        ),
+    }
+}
+
+/// Reports progress on stdout as JSON lines, one object per event, for CI dashboards.
+///
+/// Every object has an "event" field naming the event; crate events also have "crate"
+/// and "version" fields.
+pub struct JsonProgress;
+
+impl JsonProgress {
+    fn emit(&self, event: &str, mut fields: Value) {
+        fields["event"] = json!(event);
+        let mut stdout = std::io::stdout().lock();
+        // Progress is best effort; a closed stdout must not fail the run.
+        let _ = writeln!(stdout, "{fields}");
+        let _ = stdout.flush();
+    }
+
+    fn emit_crate(&self, event: &str, crat: &Version, mut fields: Value) {
+        fields["crate"] = json!(crat.name());
+        fields["version"] = json!(crat.version());
+        self.emit(event, fields);
+    }
+}
+
+impl ProgressObserver for JsonProgress {
+    fn phase_started(&self, phase: Phase) {
+        self.emit("phase_started", json!({ "phase": phase.as_str() }));
+    }
+
+    fn phase_finished(&self, phase: Phase) {
+        self.emit("phase_finished", json!({ "phase": phase.as_str() }));
+    }
+
+    fn phase_crates(&self, phase: Phase, num_crates: usize) {
+        self.emit(
+            "phase_crates",
+            json!({ "phase": phase.as_str(), "num_crates": num_crates }),
+        );
+    }
+
+    fn fetching_most_downloaded_page(&self, page: u64, num_pages: u64) {
+        self.emit(
+            "fetching_most_downloaded_page",
+            json!({ "page": page, "num_pages": num_pages }),
+        );
+    }
+
+    fn resolution_started(&self, num_top_level: usize) {
+        self.emit(
+            "resolution_started",
+            json!({ "num_top_level": num_top_level }),
+        );
+    }
+
+    fn top_level_analyzed(&self, crat: &Version, completed: usize, total: usize) {
+        self.emit_crate(
+            "crate_resolved",
+            crat,
+            json!({ "top_level": true, "completed": completed, "total": total }),
+        );
+    }
+
+    fn dependency_analyzed(&self, crat: &Version) {
+        self.emit_crate("crate_resolved", crat, json!({ "top_level": false }));
+    }
+
+    fn resolution_finished(&self, num_dependencies: usize) {
+        self.emit(
+            "resolution_finished",
+            json!({ "num_dependencies": num_dependencies }),
+        );
+    }
+
+    fn advisory_found(&self, crat: &Version, advisory: &Advisory) {
+        self.emit_crate(
+            "advisory_found",
+            crat,
+            json!({
+                "id": advisory.id,
+                "title": advisory.title,
+                "kind": advisory.informational.as_deref().unwrap_or("vulnerability"),
+            }),
+        );
+    }
+
+    fn crate_downloaded(&self, crat: &Version, completed: usize, total: usize) {
+        // A total of 0 means it isn't known yet.
+        let total = if total == 0 { None } else { Some(total) };
+        self.emit_crate(
+            "crate_downloaded",
+            crat,
+            json!({ "completed": completed, "total": total }),
+        );
+    }
+
+    fn crate_stored(&self, crat: &Version, size: u64) {
+        self.emit_crate("crate_stored", crat, json!({ "size": size }));
+    }
+
+    fn crate_published(&self, crat: &Version, completed: usize, total: usize) {
+        self.emit_crate(
+            "crate_published",
+            crat,
+            json!({ "completed": completed, "total": total }),
+        );
+    }
+
+    fn failure(
+        &self,
+        phase: Phase,
+        crat: Option<&Version>,
+        error: &(dyn std::error::Error + 'static),
+    ) {
+        let fields = json!({ "phase": phase.as_str(), "error": error.to_string() });
+        match crat {
+            Some(crat) => self.emit_crate("failed", crat, fields),
+            None => self.emit("failed", fields),
+        }
+    }
+
+    fn info(&self, msg: &str) {
+        self.emit("info", json!({ "message": msg }));
+    }
+
+    fn graph_analyzed(&self, stats: &GraphStats) {
+        let crate_json =
+            |crat: &Version| json!({ "crate": crat.name(), "version": crat.version() });
+        self.emit(
+            "graph_analyzed",
+            json!({
+                "max_depth": stats.max_depth,
+                "average_fan_out": stats.average_fan_out,
+                "most_depended_upon": Vec::from_iter(stats.most_depended_upon.iter().map(
+                    |(crat, count)| {
+                        let mut crat = crate_json(crat);
+                        crat["dependents"] = json!(count);
+                        crat
+                    }
+                )),
+                "longest_chain": Vec::from_iter(stats.longest_chain.iter().map(crate_json)),
+            }),
+        );
+    }
+
+    fn run_finished(&self, succeeded: bool) {
+        self.emit("run_finished", json!({ "succeeded": succeeded }));
+    }
+}
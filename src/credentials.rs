@@ -0,0 +1,146 @@
+//! Looks up the tokens of registries the way Cargo does, so the downloads from registries
+//! requiring authentication work with the tokens `cargo login` saved.
+
+use crate::common;
+use std::env;
+use std::fmt::{self, Display};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum Error {
+    CargoHomeNotFound,
+    ReadFile {
+        path: PathBuf,
+        error: io::Error,
+    },
+    ParseFile {
+        path: PathBuf,
+        error: toml::de::Error,
+    },
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::CargoHomeNotFound => {
+                write!(
+                    f,
+                    "failed to find Cargo's home directory: neither CARGO_HOME nor the home directory is set"
+                )
+            }
+            Error::ReadFile { path, error } => {
+                write!(f, "failed to read {}: {error}", path.to_string_lossy())
+            }
+            Error::ParseFile { path, error } => {
+                write!(f, "invalid Cargo file {}: {error}", path.to_string_lossy())
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::CargoHomeNotFound => None,
+            Error::ReadFile { error, .. } => Some(error),
+            Error::ParseFile { error, .. } => Some(error),
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// The files in Cargo's home directory a token can be in, highest precedence first.
+const CARGO_FILES: [&str; 4] = ["credentials.toml", "credentials", "config.toml", "config"];
+
+/// Returns Cargo's home directory: `CARGO_HOME` if set, or `.cargo` in the home directory.
+pub fn cargo_home() -> Option<PathBuf> {
+    if let Some(cargo_home) = env::var_os("CARGO_HOME") {
+        return Some(PathBuf::from(cargo_home));
+    }
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(|home| Path::new(&home).join(".cargo"))
+}
+
+/// Returns the token Cargo would use for the registry with the index URL, or `None` if there
+/// is none.
+///
+/// The crates.io token is taken from `CARGO_REGISTRY_TOKEN` or the `[registry]` table of
+/// Cargo's credentials and config files. Other registries are looked up by the name their index
+/// URL is given in the `[registries]` table of Cargo's config, then their token is taken from
+/// `CARGO_REGISTRIES_<NAME>_TOKEN` or the registry's table in the same files. Tokens are
+/// returned as is: as with Cargo, one that needs a scheme, e.g. `Bearer`, must include it.
+pub fn cargo_token(index_url: &str) -> Result<Option<String>> {
+    let cargo_home = cargo_home().ok_or(Error::CargoHomeNotFound)?;
+    let mut files = Vec::new();
+    for file_name in CARGO_FILES {
+        if let Some(file) = read_cargo_file(&cargo_home.join(file_name))? {
+            files.push(file);
+        }
+    }
+
+    let index_url = common::canonical_registry_url(index_url);
+    if index_url == common::CRATES_IO_INDEX {
+        if let Ok(token) = env::var("CARGO_REGISTRY_TOKEN") {
+            return Ok(Some(token));
+        }
+        let token = files
+            .iter()
+            .find_map(|file| string_at(file, &["registry", "token"]));
+        return Ok(token);
+    }
+
+    let name = files.iter().find_map(|file| {
+        let registries = file.get("registries")?.as_table()?;
+        registries.iter().find_map(|(name, registry)| {
+            let url = registry.get("index")?.as_str()?;
+            (common::canonical_registry_url(url) == index_url).then(|| name.clone())
+        })
+    });
+    let name = match name {
+        Some(name) => name,
+        None => return Ok(None),
+    };
+    let env_name = format!(
+        "CARGO_REGISTRIES_{}_TOKEN",
+        name.to_uppercase().replace('-', "_")
+    );
+    if let Ok(token) = env::var(env_name) {
+        return Ok(Some(token));
+    }
+    let token = files
+        .iter()
+        .find_map(|file| string_at(file, &["registries", &name, "token"]));
+    Ok(token)
+}
+
+/// Reads and parses one of Cargo's TOML files, returning `None` if it doesn't exist.
+pub(crate) fn read_cargo_file(path: &Path) -> Result<Option<toml::Value>> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => {
+            return Err(Error::ReadFile {
+                path: path.to_path_buf(),
+                error: e,
+            })
+        }
+    };
+    let file = contents.parse().map_err(|e| Error::ParseFile {
+        path: path.to_path_buf(),
+        error: e,
+    })?;
+    Ok(Some(file))
+}
+
+/// Returns the string at the path of keys in the TOML value, if any.
+pub(crate) fn string_at(value: &toml::Value, keys: &[&str]) -> Option<String> {
+    let mut value = value;
+    for key in keys {
+        value = value.get(key)?;
+    }
+    value.as_str().map(|s| s.to_string())
+}
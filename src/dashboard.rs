@@ -0,0 +1,374 @@
+use crate::console::format_duration;
+use crate::tui::TerminalGuard;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use micrio::run_report::{self, FailureRecord, RunReport};
+use micrio::site::format_size;
+use micrio::status::{self, RunStatus, ScheduleStatus};
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, Gauge, List, ListItem, Paragraph, Row, Table};
+use ratatui::Frame;
+use std::path::Path;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use walkdir::WalkDir;
+
+/// How often the status of the mirror is read again.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How often the disk usage of the mirror is measured again, which walks the whole mirror.
+const DISK_USAGE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// A run in progress whose status wasn't updated for longer is flagged, since the process
+/// running it may have stopped.
+const STALE_AFTER_SECS: u64 = 60;
+
+const NUM_RECENT_RUNS: usize = 5;
+
+const HELP: &str = "q/esc quit";
+
+/// Shows the live progress of the mirror's current run, the jobs of the scheduler maintaining
+/// it, recent failures, and its disk usage, refreshed every second until the user quits.
+///
+/// The dashboard reads the status files the runs and the scheduler keep in the mirror's
+/// state directory, so it can watch a daemon running in another process.
+pub fn show_dashboard(mirror_dir_path: &Path) -> anyhow::Result<()> {
+    let mut dashboard = Dashboard {
+        mirror_dir_path,
+        run: None,
+        schedule: None,
+        recent_runs: Vec::new(),
+        disk_usage: None,
+        read_error: None,
+    };
+    let mut terminal = TerminalGuard::enter()?;
+    loop {
+        dashboard.refresh();
+        terminal.draw(|frame| dashboard.render(frame))?;
+        if !event::poll(REFRESH_INTERVAL)? {
+            continue;
+        }
+        let key = match event::read()? {
+            // Windows also reports key releases.
+            Event::Key(key) if key.kind == KeyEventKind::Press => key,
+            _ => continue,
+        };
+        let ctrl_c =
+            key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL);
+        if ctrl_c || matches!(key.code, KeyCode::Char('q') | KeyCode::Esc) {
+            return Ok(());
+        }
+    }
+}
+
+struct DiskUsage {
+    total_bytes: u64,
+    num_crate_files: usize,
+    measured: Instant,
+}
+
+struct Dashboard<'a> {
+    mirror_dir_path: &'a Path,
+    run: Option<RunStatus>,
+    schedule: Option<ScheduleStatus>,
+    /// Newest first.
+    recent_runs: Vec<RunReport>,
+    disk_usage: Option<DiskUsage>,
+    /// Why the status of the mirror couldn't be read, if it couldn't.
+    read_error: Option<String>,
+}
+
+impl Dashboard<'_> {
+    fn refresh(&mut self) {
+        let result = status::read_run_status(self.mirror_dir_path).and_then(|run| {
+            let schedule = status::read_schedule_status(self.mirror_dir_path)?;
+            let recent_runs = run_report::read_recent(self.mirror_dir_path, NUM_RECENT_RUNS)?;
+            Ok((run, schedule, recent_runs))
+        });
+        match result {
+            Ok((run, schedule, recent_runs)) => {
+                self.run = run;
+                self.schedule = schedule;
+                self.recent_runs = recent_runs;
+                self.read_error = None;
+            }
+            // Keep showing the last status read, e.g. if a file was being replaced.
+            Err(e) => self.read_error = Some(e.to_string()),
+        }
+
+        let outdated = match &self.disk_usage {
+            Some(disk_usage) => disk_usage.measured.elapsed() >= DISK_USAGE_INTERVAL,
+            None => true,
+        };
+        if outdated {
+            self.disk_usage = Some(measure_disk_usage(self.mirror_dir_path));
+        }
+    }
+
+    fn render(&self, frame: &mut Frame) {
+        let num_jobs = match &self.schedule {
+            Some(schedule) => schedule.jobs.len().max(1),
+            None => 1,
+        };
+        let areas = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(6),
+                Constraint::Length(num_jobs as u16 + 3),
+                Constraint::Length(NUM_RECENT_RUNS as u16 + 3),
+                Constraint::Min(4),
+                Constraint::Length(1),
+            ])
+            .split(frame.size());
+        let now = unix_now();
+        self.render_run(frame, areas[0], now);
+        self.render_schedule(frame, areas[1], now);
+        self.render_recent_runs(frame, areas[2], now);
+        self.render_failures(frame, areas[3]);
+        self.render_footer(frame, areas[4]);
+    }
+
+    fn render_run(&self, frame: &mut Frame, area: Rect, now: u64) {
+        let block = panel(&format!(
+            "micrio: {}",
+            self.mirror_dir_path.to_string_lossy()
+        ));
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(3), Constraint::Length(1)])
+            .split(inner);
+
+        let run = match &self.run {
+            Some(run) => run,
+            None => {
+                frame.render_widget(Paragraph::new("No run recorded yet."), rows[0]);
+                return;
+            }
+        };
+        let mut lines = Vec::new();
+        if run.running {
+            let mut line = format!(
+                "Running in process {}, started {}",
+                run.pid,
+                ago(now, run.started_at)
+            );
+            let silent_secs = now.saturating_sub(run.updated_at);
+            if silent_secs > STALE_AFTER_SECS {
+                line += &format!(
+                    " (no update for {}, the process may have stopped)",
+                    format_duration(Duration::from_secs(silent_secs))
+                );
+            }
+            lines.push(Line::from(line));
+            lines.push(Line::from(format!(
+                "Phase: {}",
+                run.phase.as_deref().unwrap_or("starting")
+            )));
+        } else {
+            let (outcome, color) = match run.succeeded {
+                Some(false) => ("failed", Color::Red),
+                _ => ("succeeded", Color::Green),
+            };
+            lines.push(Line::styled(
+                format!(
+                    "Idle. The last run {outcome}, {}.",
+                    ago(now, run.updated_at)
+                ),
+                Style::default().fg(color),
+            ));
+        }
+        lines.push(Line::from(format!(
+            "Downloaded: {}",
+            format_size(run.bytes_downloaded)
+        )));
+        frame.render_widget(Paragraph::new(lines), rows[0]);
+
+        if run.running {
+            let (ratio, label) = if run.total > 0 {
+                let ratio = (run.completed as f64 / run.total as f64).min(1.0);
+                (ratio, format!("{} / {}", run.completed, run.total))
+            } else {
+                (0.0, format!("{} done", run.completed))
+            };
+            let gauge = Gauge::default()
+                .gauge_style(Style::default().fg(Color::Cyan))
+                .ratio(ratio)
+                .label(label);
+            frame.render_widget(gauge, rows[1]);
+        }
+    }
+
+    fn render_schedule(&self, frame: &mut Frame, area: Rect, now: u64) {
+        let block = panel("Schedule");
+        let schedule = match &self.schedule {
+            Some(schedule) => schedule,
+            None => {
+                let msg = "No scheduler is recording its jobs in this mirror.";
+                frame.render_widget(Paragraph::new(msg).block(block), area);
+                return;
+            }
+        };
+        let rows = schedule.jobs.iter().map(|job| {
+            let last_run = match job.last_started {
+                Some(started) => ago(now, started),
+                None => "never".to_string(),
+            };
+            let (outcome, color) = match (&job.last_error, job.last_finished) {
+                _ if job.running => ("running".to_string(), Color::Cyan),
+                (Some(error), _) => (format!("failed: {error}"), Color::Red),
+                (None, Some(_)) => ("ok".to_string(), Color::Green),
+                (None, None) => ("-".to_string(), Color::Reset),
+            };
+            let next_run = match job.next_run {
+                Some(next_run) if next_run > now => format!(
+                    "in {}",
+                    format_duration(Duration::from_secs(next_run - now))
+                ),
+                Some(_) => "due".to_string(),
+                None => "-".to_string(),
+            };
+            Row::new(vec![
+                job.name.clone(),
+                format_duration(Duration::from_secs(job.interval_secs)),
+                last_run,
+                next_run,
+                outcome,
+            ])
+            .style(Style::default().fg(color))
+        });
+        let widths = [
+            Constraint::Length(10),
+            Constraint::Length(12),
+            Constraint::Length(16),
+            Constraint::Length(16),
+            Constraint::Min(10),
+        ];
+        let table = Table::new(rows, widths)
+            .header(header(vec![
+                "Job", "Every", "Last run", "Next run", "Outcome",
+            ]))
+            .block(block);
+        frame.render_widget(table, area);
+    }
+
+    fn render_recent_runs(&self, frame: &mut Frame, area: Rect, now: u64) {
+        let rows = self.recent_runs.iter().map(|report| {
+            let (outcome, color) = if report.succeeded {
+                ("succeeded", Color::Green)
+            } else {
+                ("failed", Color::Red)
+            };
+            Row::new(vec![
+                ago(now, report.started_at),
+                format_duration(Duration::from_secs_f64(report.duration_secs)),
+                outcome.to_string(),
+                format!(
+                    "{} ({})",
+                    report.num_downloaded,
+                    format_size(report.bytes_downloaded)
+                ),
+                report.num_failed.to_string(),
+            ])
+            .style(Style::default().fg(color))
+        });
+        let widths = [
+            Constraint::Length(16),
+            Constraint::Length(12),
+            Constraint::Length(10),
+            Constraint::Length(24),
+            Constraint::Min(6),
+        ];
+        let table = Table::new(rows, widths)
+            .header(header(vec![
+                "Started",
+                "Duration",
+                "Outcome",
+                "Downloaded",
+                "Failed",
+            ]))
+            .block(panel("Recent runs"));
+        frame.render_widget(table, area);
+    }
+
+    /// Lists the failures of the current or latest run, newest first.
+    fn render_failures(&self, frame: &mut Frame, area: Rect) {
+        let failures: &[FailureRecord] = match (&self.run, self.recent_runs.first()) {
+            (Some(run), _) => &run.recent_failures,
+            (None, Some(report)) => &report.failures,
+            (None, None) => &[],
+        };
+        let items = Vec::from_iter(failures.iter().rev().map(|failure| {
+            let mut line = format!("[{}] ", failure.phase);
+            if let (Some(name), Some(version)) = (&failure.crate_name, &failure.crate_version) {
+                line += &format!("{name}@{version}: ");
+            }
+            line += &failure.error;
+            ListItem::new(line).style(Style::default().fg(Color::Red))
+        }));
+        let title = format!("Recent failures ({})", failures.len());
+        frame.render_widget(List::new(items).block(panel(&title)), area);
+    }
+
+    fn render_footer(&self, frame: &mut Frame, area: Rect) {
+        let mut footer = match &self.disk_usage {
+            Some(disk_usage) => format!(
+                "Disk usage: {}, {} .crate files",
+                format_size(disk_usage.total_bytes),
+                disk_usage.num_crate_files
+            ),
+            None => "Disk usage: measuring...".to_string(),
+        };
+        if let Some(e) = &self.read_error {
+            footer += &format!("  |  failed to read the status: {e}");
+        }
+        footer += &format!("  |  {HELP}");
+        frame.render_widget(Paragraph::new(footer), area);
+    }
+}
+
+fn panel(title: &str) -> Block<'static> {
+    Block::default()
+        .borders(Borders::ALL)
+        .title(format!(" {title} "))
+}
+
+fn header(titles: Vec<&str>) -> Row<'_> {
+    Row::new(titles).style(Style::default().add_modifier(Modifier::BOLD))
+}
+
+/// Measures the size of every file in the mirror. Files that can't be read are left out.
+fn measure_disk_usage(mirror_dir_path: &Path) -> DiskUsage {
+    let mut disk_usage = DiskUsage {
+        total_bytes: 0,
+        num_crate_files: 0,
+        measured: Instant::now(),
+    };
+    for entry in WalkDir::new(mirror_dir_path).into_iter().flatten() {
+        if !entry.file_type().is_file() {
+            continue;
+        }
+        if let Ok(metadata) = entry.metadata() {
+            disk_usage.total_bytes += metadata.len();
+        }
+        if entry.path().extension().and_then(|e| e.to_str()) == Some("crate") {
+            disk_usage.num_crate_files += 1;
+        }
+    }
+    disk_usage
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn ago(now: u64, time: u64) -> String {
+    format!(
+        "{} ago",
+        format_duration(Duration::from_secs(now.saturating_sub(time)))
+    )
+}
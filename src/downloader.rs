@@ -0,0 +1,184 @@
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use tokio::sync::Semaphore;
+use tracing::debug;
+use url::Url;
+
+/// Why a download failed.
+pub type DownloadError = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+/// The download of a .crate file in progress.
+pub type DownloadFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<Bytes, DownloadError>> + Send + 'a>>;
+
+/// The size of a .crate file, looked up without downloading it.
+pub type SizeFuture<'a> =
+    Pin<Box<dyn Future<Output = Result<Option<u64>, DownloadError>> + Send + 'a>>;
+
+/// Fetches .crate files from their registry.
+///
+/// The mirror downloads over HTTP with `HttpDownloader` by default. Other implementations can
+/// serve the files from elsewhere, e.g. `FixtureDownloader` to exercise the mirror against
+/// canned responses, failures included.
+pub trait CrateDownloader: Send + Sync {
+    /// Downloads the file at the URL, sending the token, if any, as the Authorization header.
+    /// Error statuses are failures, since a registry refusing the token answers with an error
+    /// page, not the .crate file. The contents are checked against the index checksum
+    /// by the caller.
+    fn download<'a>(&'a self, url: &'a Url, token: Option<&'a str>) -> DownloadFuture<'a>;
+
+    /// Returns the size of the file at the URL without downloading it, if the registry tells.
+    /// By default, sizes aren't known.
+    fn size<'a>(&'a self, _url: &'a Url, _token: Option<&'a str>) -> SizeFuture<'a> {
+        Box::pin(async { Ok(None) })
+    }
+}
+
+/// Looks up the sizes of the files at the URLs, each with its token, concurrently.
+/// Sizes that aren't known or fail to be looked up are `None`.
+pub fn sizes(
+    downloader: &Arc<dyn CrateDownloader>,
+    requests: Vec<(Url, Option<String>)>,
+) -> io::Result<Vec<Option<u64>>> {
+    const MAX_CONCURRENT_REQUESTS: usize = 100;
+    let rt = tokio::runtime::Runtime::new()?;
+    let sizes = rt.block_on(async {
+        let sem = Arc::new(Semaphore::new(MAX_CONCURRENT_REQUESTS));
+        let tasks = Vec::from_iter(requests.into_iter().map(|(url, token)| {
+            let (downloader, sem) = (Arc::clone(downloader), Arc::clone(&sem));
+            tokio::spawn(async move {
+                let _permit = sem.acquire().await.expect("acquire semaphore");
+                match downloader.size(&url, token.as_deref()).await {
+                    Ok(size) => size,
+                    Err(e) => {
+                        debug!("failed to look up the size of {url}: {e}");
+                        None
+                    }
+                }
+            })
+        }));
+        let mut sizes = Vec::with_capacity(tasks.len());
+        for task in tasks {
+            sizes.push(task.await.unwrap_or(None));
+        }
+        sizes
+    });
+    Ok(sizes)
+}
+
+/// Downloads .crate files over HTTP.
+pub struct HttpDownloader {
+    client: reqwest::Client,
+}
+
+impl HttpDownloader {
+    pub fn new() -> Self {
+        HttpDownloader {
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Downloads with the client, e.g. one with the settings of `http_config::HttpConfig`.
+    pub fn with_client(client: reqwest::Client) -> Self {
+        HttpDownloader { client }
+    }
+}
+
+impl Default for HttpDownloader {
+    fn default() -> Self {
+        HttpDownloader::new()
+    }
+}
+
+impl CrateDownloader for HttpDownloader {
+    fn download<'a>(&'a self, url: &'a Url, token: Option<&'a str>) -> DownloadFuture<'a> {
+        Box::pin(async move {
+            let mut request = self.client.get(url.clone());
+            if let Some(token) = token {
+                request = request.header(reqwest::header::AUTHORIZATION, token);
+            }
+            let response = request.send().await?.error_for_status()?;
+            Ok(response.bytes().await?)
+        })
+    }
+
+    /// Asks with a HEAD request, whose Content-Length header is the size of the file.
+    fn size<'a>(&'a self, url: &'a Url, token: Option<&'a str>) -> SizeFuture<'a> {
+        Box::pin(async move {
+            let mut request = self.client.head(url.clone());
+            if let Some(token) = token {
+                request = request.header(reqwest::header::AUTHORIZATION, token);
+            }
+            let response = request.send().await?.error_for_status()?;
+            let size = response
+                .headers()
+                .get(reqwest::header::CONTENT_LENGTH)
+                .and_then(|value| value.to_str().ok()?.parse().ok());
+            Ok(size)
+        })
+    }
+}
+
+/// A canned response of `FixtureDownloader`.
+#[derive(Clone)]
+pub enum FixtureResponse {
+    /// The file's contents, which may be corrupt.
+    Body(Bytes),
+    /// An HTTP error status, e.g. 404.
+    Status(u16),
+    /// No response before the request timed out.
+    Timeout,
+}
+
+/// Serves canned responses from memory, by URL, e.g. to test the mirror without a network.
+/// URLs without a response are not found.
+#[derive(Default)]
+pub struct FixtureDownloader {
+    responses: Mutex<HashMap<String, FixtureResponse>>,
+}
+
+impl FixtureDownloader {
+    pub fn new() -> Self {
+        FixtureDownloader::default()
+    }
+
+    /// Answers the requests for the URL with the response from now on.
+    pub fn respond(&self, url: &Url, response: FixtureResponse) {
+        self.responses().insert(url.as_str().to_string(), response);
+    }
+
+    fn response(&self, url: &Url) -> Result<Bytes, DownloadError> {
+        match self.responses().get(url.as_str()).cloned() {
+            Some(FixtureResponse::Body(bytes)) => Ok(bytes),
+            Some(FixtureResponse::Status(status)) => {
+                Err(format!("HTTP status {status} for {url}").into())
+            }
+            Some(FixtureResponse::Timeout) => Err(Box::new(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("request for {url} timed out"),
+            ))),
+            None => Err(format!("HTTP status 404 for {url}").into()),
+        }
+    }
+
+    fn responses(&self) -> std::sync::MutexGuard<'_, HashMap<String, FixtureResponse>> {
+        // The responses are only replaced whole, so they're still usable if a thread panicked.
+        self.responses.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl CrateDownloader for FixtureDownloader {
+    fn download<'a>(&'a self, url: &'a Url, _token: Option<&'a str>) -> DownloadFuture<'a> {
+        let response = self.response(url);
+        Box::pin(async move { response })
+    }
+
+    fn size<'a>(&'a self, url: &'a Url, _token: Option<&'a str>) -> SizeFuture<'a> {
+        let size = self.response(url).map(|bytes| Some(bytes.len() as u64));
+        Box::pin(async move { size })
+    }
+}
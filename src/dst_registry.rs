@@ -1,13 +1,29 @@
-use crate::common::Version;
+use crate::cancel::CancellationToken;
+use crate::checksums;
+use crate::common::{self, Version};
+use crate::credentials;
+use crate::downloader::{CrateDownloader, HttpDownloader};
+use crate::endpoints::{self, DL_MARKERS};
+use crate::hooks::{self, Hook};
+use crate::progress::{Phase, ProgressObserver};
+use crate::repos::REPOS_DIR;
+use crate::storage::{LocalStorage, StorageBackend};
+use clap::ValueEnum;
 use git2::Repository;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::env;
 use std::fmt::{self, Display};
 use std::fs::{self, DirEntry, OpenOptions};
 use std::io::{self, Write};
 use std::path::{Path, PathBuf};
-use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::Receiver;
+use std::sync::Arc;
+use std::thread;
 use tokio::{task, sync};
+use tracing::{info, info_span, Instrument};
+use url::Url;
+use walkdir::WalkDir;
 
 #[derive(Debug)]
 pub enum Error {
@@ -15,8 +31,17 @@ pub enum Error {
         msg: String,
         error: io::Error,
     },
+    Open {
+        msg: String,
+        error: io::Error,
+    },
+    ReadIndex {
+        path: PathBuf,
+        error: Box<dyn std::error::Error + Send + Sync + 'static>,
+    },
     CreateIndexDir(io::Error),
     InitGitRepo(git2::Error),
+    OpenGitRepo(git2::Error),
     WriteConfigJson(io::Error),
     AddCrateToIndex {
         crate_name: String,
@@ -26,8 +51,16 @@ pub enum Error {
     },
     AddFileToGitRepo(Box<dyn std::error::Error + Send + Sync + 'static>),
     CommitGitRepo(git2::Error),
-    CreateRegistryDir(io::Error),
     CreateRuntime(io::Error),
+    InvalidCrateName {
+        crate_name: String,
+        crate_version: String,
+    },
+    CrateUrl {
+        crate_name: String,
+        crate_version: String,
+        error: url::ParseError,
+    },
     DownloadCrate {
         crate_name: String,
         crate_version: String,
@@ -39,6 +72,23 @@ pub enum Error {
         msg: String,
         error: io::Error,
     },
+    Cancelled {
+        num_downloaded: usize,
+        num_crates: usize,
+    },
+    DownloadsFailed {
+        num_failed: usize,
+        num_crates: usize,
+    },
+    NotCached {
+        crate_name: String,
+        crate_version: String,
+    },
+    ChecksumMismatch {
+        crate_name: String,
+        crate_version: String,
+    },
+    Hook(hooks::Error),
 }
 
 impl Display for Error {
@@ -50,6 +100,19 @@ impl Display for Error {
                     "failed to create fresh destination registry directory: {msg}: {error}"
                 )
             }
+            Error::Open { msg, error } => {
+                write!(
+                    f,
+                    "failed to open existing destination registry directory: {msg}: {error}"
+                )
+            }
+            Error::ReadIndex { path, error } => {
+                write!(
+                    f,
+                    "failed to read the index file {}: {error}",
+                    path.to_string_lossy()
+                )
+            }
             Error::CreateIndexDir(e) => {
                 write!(
                     f,
@@ -62,6 +125,9 @@ impl Display for Error {
                     "error populating index: failed to initialize git repo: {e}"
                 )
             }
+            Error::OpenGitRepo(e) => {
+                write!(f, "failed to open the index git repo: {e}")
+            }
             Error::WriteConfigJson(e) => {
                 write!(
                     f,
@@ -88,15 +154,22 @@ impl Display for Error {
             Error::CommitGitRepo(e) => {
                 write!(f, "error populating index: failed to commit git repo: {e}")
             }
-            Error::CreateRegistryDir(e) => {
-                write!(
-                    f,
-                    "error populating registry: failed to create the registry directory: {e}"
-                )
-            }
             Error::CreateRuntime(e) => {
                 write!(f, "error populating registry: failed to create tokio runtime to download crates: {e}")
             }
+            Error::InvalidCrateName {
+                crate_name,
+                crate_version,
+            } => {
+                write!(f, "error populating registry: can't download version {crate_version} of the crate named {crate_name:?}: crate names can only have ASCII letters, digits, - and _")
+            }
+            Error::CrateUrl {
+                crate_name,
+                crate_version,
+                error,
+            } => {
+                write!(f, "error populating registry: the download URL of {crate_name} version {crate_version} is invalid: {error}")
+            }
             Error::DownloadCrate {
                 crate_name,
                 crate_version,
@@ -112,6 +185,36 @@ impl Display for Error {
             } => {
                 write!(f, "error populating registry: failed to write {crate_name} version {crate_version} to its file on disk: {msg}: {error}")
             }
+            Error::Cancelled {
+                num_downloaded,
+                num_crates,
+            } => {
+                write!(f, "populating registry was cancelled after downloading {num_downloaded} of {num_crates} crates")
+            }
+            Error::DownloadsFailed {
+                num_failed,
+                num_crates,
+            } => {
+                write!(
+                    f,
+                    "error populating registry: {num_failed} of {num_crates} crates failed to download"
+                )
+            }
+            Error::NotCached {
+                crate_name,
+                crate_version,
+            } => {
+                write!(f, "error populating registry: {crate_name} version {crate_version} isn't in Cargo's download cache, and offline mode forbids downloading it")
+            }
+            Error::ChecksumMismatch {
+                crate_name,
+                crate_version,
+            } => {
+                write!(f, "error populating registry: the downloaded .crate file of {crate_name} version {crate_version} doesn't match its index checksum")
+            }
+            Error::Hook(e) => {
+                write!(f, "error populating registry: {e}")
+            }
         }
     }
 }
@@ -120,94 +223,695 @@ impl std::error::Error for Error {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
             Error::Create { error, .. } => Some(error),
+            Error::Open { error, .. } => Some(error),
+            Error::ReadIndex { error, .. } => Some(error.as_ref()),
             Error::CreateIndexDir(e) => Some(e),
             Error::InitGitRepo(e) => Some(e),
+            Error::OpenGitRepo(e) => Some(e),
             Error::WriteConfigJson(e) => Some(e),
             Error::AddCrateToIndex { error, .. } => Some(error.as_ref()),
             Error::AddFileToGitRepo(e) => Some(e.as_ref()),
             Error::CommitGitRepo(e) => Some(e),
-            Error::CreateRegistryDir(e) => Some(e),
             Error::CreateRuntime(e) => Some(e),
+            Error::InvalidCrateName { .. } => None,
+            Error::CrateUrl { error, .. } => Some(error),
             Error::DownloadCrate { error, .. } => Some(error.as_ref()),
             Error::WriteRegistryFile { error, .. } => Some(error),
+            Error::Cancelled { .. } => None,
+            Error::DownloadsFailed { .. } => None,
+            Error::NotCached { .. } => None,
+            Error::ChecksumMismatch { .. } => None,
+            Error::Hook(e) => Some(e),
         }
     }
 }
 
 type Result<T> = std::result::Result<T, Error>;
 
-const INDEX_DIR: &'static str = "index";
-const REGISTRY_DIR: &'static str = "registry";
+pub const INDEX_DIR: &str = "index";
+const REGISTRY_DIR: &str = "registry";
+/// Directory inside the mirror where micrio keeps its own state between runs.
+pub const STATE_DIR: &str = ".micrio";
+
+/// How the .crate files are laid out in storage, with the `dl` template of config.json
+/// matching it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum RegistryLayout {
+    /// `{name}/{version}/download`, the path Cargo appends to a `dl` URL without markers.
+    #[default]
+    Nested,
+    /// `{name}-{version}.crate` files in a single directory, as static.crates.io serves them.
+    /// Simpler to host on static file servers and CDNs.
+    Flat,
+    /// `{name}-{version}.crate` files in directories named after the crate's lowercased
+    /// index directory, e.g. `se/rd`, so no directory grows too large.
+    Sharded,
+}
+
+impl RegistryLayout {
+    const ALL: [RegistryLayout; 3] = [
+        RegistryLayout::Nested,
+        RegistryLayout::Flat,
+        RegistryLayout::Sharded,
+    ];
+
+    /// Returns the storage key of the .crate file for the specified crate version.
+    fn crate_file_key(self, name: &str, version: &str) -> String {
+        match self {
+            RegistryLayout::Nested => format!("{name}/{version}/download"),
+            RegistryLayout::Flat => format!("{name}-{version}.crate"),
+            RegistryLayout::Sharded => format!(
+                "{}/{name}-{version}.crate",
+                crate_prefix(name).to_lowercase()
+            ),
+        }
+    }
+
+    /// Returns the `dl` field of config.json for .crate files stored under the URL.
+    fn dl(self, dl_url: &Url) -> String {
+        let base_url = dl_url.as_str().trim_end_matches('/');
+        match self {
+            RegistryLayout::Nested => dl_url.as_str().to_string(),
+            RegistryLayout::Flat => format!("{base_url}/{{crate}}-{{version}}.crate"),
+            RegistryLayout::Sharded => {
+                format!("{base_url}/{{lowerprefix}}/{{crate}}-{{version}}.crate")
+            }
+        }
+    }
+
+    /// Recognizes the layout of an existing mirror from the `dl` field of its config.json.
+    /// Mirrors whose config.json can't be read are assumed to be nested, the original layout.
+    fn of_mirror(index_dir_path: &Path) -> Self {
+        let dl = fs::read_to_string(index_dir_path.join("config.json"))
+            .ok()
+            .and_then(|contents| serde_json::from_str::<serde_json::Value>(&contents).ok())
+            .and_then(|config| Some(config.get("dl")?.as_str()?.to_string()));
+        match dl {
+            Some(dl) if dl.ends_with("/{lowerprefix}/{crate}-{version}.crate") => {
+                RegistryLayout::Sharded
+            }
+            Some(dl) if dl.ends_with("/{crate}-{version}.crate") => RegistryLayout::Flat,
+            _ => RegistryLayout::Nested,
+        }
+    }
+}
+
+/// Where the .crate files are downloaded from: crates.io, or the registry the crates were
+/// resolved against.
+#[derive(Clone, Debug)]
+pub struct CrateSource {
+    /// The canonical URL of the registry's index.
+    index_url: String,
+    dl: String,
+    token: Option<String>,
+}
+
+impl CrateSource {
+    pub fn crates_io() -> Self {
+        CrateSource {
+            index_url: common::CRATES_IO_INDEX.to_string(),
+            dl: endpoints::CRATES_IO_DL.to_string(),
+            token: None,
+        }
+    }
+
+    /// Downloads from the registry with the index URL and the `dl` field of its config.json,
+    /// sending the token, if any, as the Authorization header, as Cargo does for registries
+    /// requiring it.
+    pub fn new<S: Into<String>>(index_url: &str, dl: S, token: Option<String>) -> Self {
+        CrateSource {
+            index_url: common::canonical_registry_url(index_url),
+            dl: dl.into(),
+            token,
+        }
+    }
+
+    /// Returns the canonical URL of the registry's index.
+    pub fn index_url(&self) -> &str {
+        &self.index_url
+    }
+
+    pub(crate) fn token(&self) -> Option<&str> {
+        self.token.as_deref()
+    }
+
+    /// Returns the download URL of the crate version. Like Cargo, the `dl` markers are replaced,
+    /// or `/{crate}/{version}/download` is appended if there are none. The version is
+    /// percent-encoded, see `common::url_path_version`, and names that aren't safe in a URL
+    /// are rejected rather than encoded, since no registry would serve them.
+    pub fn crate_url(&self, crat: &Version) -> Result<Url> {
+        let mut dl = self.dl.clone();
+        if !DL_MARKERS.iter().any(|marker| dl.contains(marker)) {
+            dl = format!(
+                "{}/{{crate}}/{{version}}/download",
+                dl.trim_end_matches('/')
+            );
+        }
+        let (name, version) = (crat.name(), crat.version());
+        if !common::is_valid_crate_name(name) {
+            return Err(Error::InvalidCrateName {
+                crate_name: name.to_string(),
+                crate_version: version.to_string(),
+            });
+        }
+        let prefix = crate_prefix(name);
+        let url = dl
+            .replace("{crate}", name)
+            .replace("{version}", &common::url_path_version(version))
+            .replace("{prefix}", &prefix)
+            .replace("{lowerprefix}", &prefix.to_lowercase())
+            .replace("{sha256-checksum}", &crat.checksum());
+        Url::parse(&url).map_err(|e| Error::CrateUrl {
+            crate_name: name.to_string(),
+            crate_version: version.to_string(),
+            error: e,
+        })
+    }
+}
 
 pub struct DstRegistry {
     path: PathBuf,
+    storage: Arc<dyn StorageBackend>,
+    layout: RegistryLayout,
+    source: CrateSource,
+    /// The sources of the crates from secondary registries.
+    secondary_sources: Vec<CrateSource>,
+    reproducible: bool,
+    require_all: bool,
+    offline: bool,
+    /// Directories with .crate files to hard-link instead of downloading.
+    link_dirs: Vec<PathBuf>,
+    /// Directories with .crate files to copy instead of downloading.
+    seed_dirs: Vec<PathBuf>,
+    serve_url: Option<Url>,
+    auth_required: bool,
+    downloader: Arc<dyn CrateDownloader>,
 }
 
 impl DstRegistry {
+    /// Creates a fresh destination registry that stores .crate files
+    /// in the registry directory next to the index.
     pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let mut path = path.as_ref().to_path_buf();
+        let path = normalize_path(path.as_ref()).map_err(|e| Error::Create {
+            msg: "failed to get current directory to make absolute path".to_string(),
+            error: e,
+        })?;
+        let storage = Arc::new(LocalStorage::new(path.join(REGISTRY_DIR)));
+        DstRegistry::with_storage(path, storage)
+    }
 
-        // Ensure the path to the destination registry is an absolute path
-        // with forward slashes separating the components.
-        if !path.is_absolute() {
-            let mut rel_path = path.as_path();
-            if rel_path.starts_with("./") {
-                rel_path = path.strip_prefix("./").unwrap();
-            } else if rel_path.starts_with(".\\") {
-                rel_path = path.strip_prefix(".\\").unwrap();
-            }
+    /// Creates a fresh destination registry that stores .crate files in the storage backend.
+    /// The index is always kept in the directory since it's a git repo.
+    pub fn with_storage<P: AsRef<Path>>(path: P, storage: Arc<dyn StorageBackend>) -> Result<Self> {
+        let path = normalize_path(path.as_ref()).map_err(|e| Error::Create {
+            msg: "failed to get current directory to make absolute path".to_string(),
+            error: e,
+        })?;
 
-            let cur_dir = env::current_dir().map_err(|e| Error::Create {
-                msg: "failed to get current directory to make absolute path".to_string(),
+        // Empty the directory so we can start with a clean directory,
+        // keeping the state left behind by previous runs and the cloned repositories,
+        // which are only fetched into again.
+        if path.exists() {
+            let entries = fs::read_dir(&path).map_err(|e| Error::Create {
+                msg: "failed to read existing directory".to_string(),
                 error: e,
             })?;
-            path = cur_dir.join(&rel_path);
-        }
-        path = PathBuf::from_str(path.to_string_lossy().replace("\\", "/").as_str()).unwrap();
-
-        // Remove the directory then re-create it so we can start with a clean directory.
-        if path.exists() {
-            fs::remove_dir_all(&path).map_err(|e| Error::Create {
-                msg: "failed to remove existing directory".to_string(),
+            for entry in entries {
+                let entry = entry.map_err(|e| Error::Create {
+                    msg: "failed to read existing directory".to_string(),
+                    error: e,
+                })?;
+                if entry.file_name() == STATE_DIR || entry.file_name() == REPOS_DIR {
+                    continue;
+                }
+                let entry_path = entry.path();
+                let result = if entry_path.is_dir() {
+                    fs::remove_dir_all(&entry_path)
+                } else {
+                    fs::remove_file(&entry_path)
+                };
+                result.map_err(|e| Error::Create {
+                    msg: "failed to remove existing directory contents".to_string(),
+                    error: e,
+                })?;
+            }
+        } else {
+            fs::create_dir(&path).map_err(|e| Error::Create {
+                msg: "failed to create new directory".to_string(),
                 error: e,
             })?;
         }
-        fs::create_dir(&path).map_err(|e| Error::Create {
-            msg: "failed to create new directory".to_string(),
+        Ok(DstRegistry {
+            path,
+            storage,
+            layout: RegistryLayout::default(),
+            source: CrateSource::crates_io(),
+            secondary_sources: Vec::new(),
+            reproducible: false,
+            require_all: false,
+            offline: false,
+            link_dirs: Vec::new(),
+            seed_dirs: Vec::new(),
+            serve_url: None,
+            auth_required: false,
+            downloader: Arc::new(HttpDownloader::new()),
+        })
+    }
+
+    /// Opens an existing destination registry without modifying it.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = normalize_path(path.as_ref()).map_err(|e| Error::Open {
+            msg: "failed to get current directory to make absolute path".to_string(),
             error: e,
         })?;
-        Ok(DstRegistry { path })
+        let storage = Arc::new(LocalStorage::new(path.join(REGISTRY_DIR)));
+        DstRegistry::open_with_storage(path, storage)
     }
 
-    pub fn populate(&self, crates: &HashSet<Version>) -> Result<()> {
-        let top_dir_path = self.path.to_string_lossy();
-        populate_index(top_dir_path.as_ref(), crates)?;
-        populate_registry(top_dir_path.as_ref(), crates)?;
-        Ok(())
+    /// Opens an existing destination registry whose .crate files are in the storage backend,
+    /// without modifying it.
+    pub fn open_with_storage<P: AsRef<Path>>(
+        path: P,
+        storage: Arc<dyn StorageBackend>,
+    ) -> Result<Self> {
+        let path = normalize_path(path.as_ref()).map_err(|e| Error::Open {
+            msg: "failed to get current directory to make absolute path".to_string(),
+            error: e,
+        })?;
+        if !path.is_dir() {
+            return Err(Error::Open {
+                msg: "directory does not exist".to_string(),
+                error: io::ErrorKind::NotFound.into(),
+            });
+        }
+        let layout = RegistryLayout::of_mirror(&path.join(INDEX_DIR));
+        let auth_required = is_auth_required(&path.join(INDEX_DIR));
+        Ok(DstRegistry {
+            path,
+            storage,
+            layout,
+            source: CrateSource::crates_io(),
+            secondary_sources: Vec::new(),
+            reproducible: false,
+            require_all: false,
+            offline: false,
+            link_dirs: Vec::new(),
+            seed_dirs: Vec::new(),
+            serve_url: None,
+            auth_required,
+            downloader: Arc::new(HttpDownloader::new()),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Makes the index git repo bit-identical wherever it is created from the same crate versions:
+    /// index lines are written in a fixed order, file timestamps are left out of the git index,
+    /// no reflog is kept, and commits get a fixed time, `SOURCE_DATE_EPOCH` if set,
+    /// or the Unix epoch otherwise.
+    pub fn reproducible(mut self, reproducible: bool) -> Self {
+        self.reproducible = reproducible;
+        self
+    }
+
+    /// Stores the .crate files in the layout instead of the nested one.
+    /// Opened registries keep the layout they were created with.
+    pub fn layout(mut self, layout: RegistryLayout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// Points the mirror's config.json at the URL the .crate files are served from, e.g. by a
+    /// web server, instead of the storage's own URL.
+    pub fn serve_url(mut self, serve_url: Url) -> Self {
+        self.serve_url = Some(serve_url);
+        self
+    }
+
+    /// Sets `auth-required` in the mirror's config.json, so Cargo sends its token for the
+    /// registry with every request, to the index as well as for the .crate files. Cargo only
+    /// supports it for sparse registries, e.g. the mirror served by `serve::MirrorServer`.
+    /// Opened registries keep the setting they were created with.
+    pub fn auth_required(mut self, auth_required: bool) -> Self {
+        self.auth_required = auth_required;
+        self
+    }
+
+    /// Downloads the .crate files from the source instead of crates.io.
+    pub fn source(mut self, source: CrateSource) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Downloads the .crate files of the crates from a secondary registry, as given by
+    /// `Version::registry`, from its source. The index entries of the crates depending on them
+    /// then point at the mirror instead.
+    pub fn secondary_source(mut self, source: CrateSource) -> Self {
+        self.secondary_sources.push(source);
+        self
+    }
+
+    /// Fails populating the registry if any crate fails to download, once the others are
+    /// downloaded, instead of leaving the crate out.
+    pub fn require_all(mut self, require_all: bool) -> Self {
+        self.require_all = require_all;
+        self
+    }
+
+    /// Copies the .crate files from Cargo's download cache instead of downloading them,
+    /// failing for the crates that aren't in it.
+    pub fn offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Hard-links the .crate files identical to those in the directory instead of downloading
+    /// them and storing a copy, so mirrors hosted side by side share the disk space of the
+    /// crate versions they have in common. The directory is another micrio mirror, or holds
+    /// `{name}-{version}.crate` files, like Cargo's download cache of a registry. Files are
+    /// only linked if they match the index checksum, and are copied where the storage backend
+    /// can't link them, e.g. across file systems. Call repeatedly to link from several.
+    pub fn link_from<P: Into<PathBuf>>(mut self, dir_path: P) -> Self {
+        self.link_dirs.push(dir_path.into());
+        self
+    }
+
+    /// Copies the .crate files identical to those in the directory instead of downloading them,
+    /// e.g. files downloaded ahead of time or left by a previous partial mirror. The directory
+    /// holds `{name}-{version}.crate` files, or is another micrio mirror. Files are only copied
+    /// if they match the index checksum. Call repeatedly to seed from several.
+    pub fn seed_from<P: Into<PathBuf>>(mut self, dir_path: P) -> Self {
+        self.seed_dirs.push(dir_path.into());
+        self
+    }
+
+    /// Fetches the .crate files with the downloader instead of over HTTP.
+    pub fn downloader(mut self, downloader: Arc<dyn CrateDownloader>) -> Self {
+        self.downloader = downloader;
+        self
+    }
+
+    /// Points the index's config.json at the registry's current location and commits the change.
+    /// This is required after a mirror has been moved, e.g. by importing it on another machine.
+    pub fn relocate(&self) -> Result<()> {
+        let index_dir_path = self.path.join(INDEX_DIR);
+        let repo = Repository::open(&index_dir_path).map_err(Error::OpenGitRepo)?;
+        write_config_json_file(&index_dir_path, &self.config_json()?)?;
+
+        let mut index = repo
+            .index()
+            .map_err(|e| Error::AddFileToGitRepo(Box::new(e)))?;
+        index
+            .add_path(Path::new("config.json"))
+            .map_err(|e| Error::AddFileToGitRepo(Box::new(e)))?;
+        index
+            .write()
+            .map_err(|e| Error::AddFileToGitRepo(Box::new(e)))?;
+        commit_git_repo(&repo, &mut index, "Relocate registry", self.reproducible)
+    }
+
+    /// Reads the .crate file for the specified crate version from the registry.
+    pub fn read_crate_file(&self, name: &str, version: &str) -> io::Result<Vec<u8>> {
+        self.storage
+            .read(&self.layout.crate_file_key(name, version))
+    }
+
+    /// Returns the path of the .crate file for the specified crate version
+    /// relative to the mirror directory, for linking to it from files in the mirror.
+    /// Build metadata in the version is kept as it is, so links in web pages need
+    /// `common::url_path_version`.
+    pub fn crate_file_link(&self, name: &str, version: &str) -> String {
+        format!(
+            "{REGISTRY_DIR}/{}",
+            self.layout.crate_file_key(name, version)
+        )
+    }
+
+    /// Stores an additional file, e.g. a README, next to the .crate file for the specified crate version.
+    pub fn write_extra_file(
+        &self,
+        name: &str,
+        version: &str,
+        file_name: &str,
+        contents: &[u8],
+    ) -> io::Result<()> {
+        self.storage
+            .write(&extra_file_key(name, version, file_name), contents)
+    }
+
+    /// Returns the path of an additional file stored by `write_extra_file`
+    /// relative to the mirror directory, for linking to it from files in the mirror.
+    pub fn extra_file_link(&self, name: &str, version: &str, file_name: &str) -> String {
+        format!(
+            "{REGISTRY_DIR}/{}",
+            extra_file_key(name, version, file_name)
+        )
+    }
+
+    /// Reads every crate version listed in the index.
+    pub fn read_index(&self) -> Result<HashSet<Version>> {
+        let index_dir_path = self.path.join(INDEX_DIR);
+        let read_error = |path: &Path, e| Error::ReadIndex {
+            path: path.to_path_buf(),
+            error: e,
+        };
+        if !index_dir_path.is_dir() {
+            return Err(read_error(&index_dir_path, "not a directory".into()));
+        }
+        let mut crates = HashSet::new();
+        let walker = WalkDir::new(&index_dir_path)
+            .into_iter()
+            .filter_entry(|e| e.file_name() != ".git");
+        for dir_entry in walker {
+            let dir_entry = dir_entry.map_err(|e| read_error(&index_dir_path, e.into()))?;
+            if !dir_entry.file_type().is_file() || dir_entry.file_name() == "config.json" {
+                continue;
+            }
+            let path = dir_entry.path();
+            let contents = fs::read_to_string(path).map_err(|e| read_error(path, e.into()))?;
+            for line in contents.lines().filter(|line| !line.trim().is_empty()) {
+                let version = Version::from_json(line).map_err(|e| read_error(path, e.into()))?;
+                crates.insert(version);
+            }
+        }
+        Ok(crates)
+    }
+
+    /// Creates the index git repo listing the crate versions.
+    pub fn populate_index(&self, crates: &HashSet<Version>) -> Result<()> {
+        populate_index(
+            &self.path,
+            &self.config_json()?,
+            crates,
+            &self.mirrored_registries(),
+            self.reproducible,
+        )
+    }
+
+    /// Downloads the .crate files of the crate versions into storage.
+    /// Crate versions rejected by one of the hooks are reported as failures and not stored.
+    ///
+    /// If cancelled, the crates already downloaded are left in storage.
+    pub fn populate_registry(
+        &self,
+        crates: &HashSet<Version>,
+        hooks: &[Arc<dyn Hook>],
+        progress: &dyn ProgressObserver,
+        cancel: &CancellationToken,
+    ) -> Result<()> {
+        populate_registry(
+            &self.storage,
+            &self.sources(),
+            crates,
+            hooks,
+            progress,
+            cancel,
+            self.require_all,
+        )
+    }
+
+    /// Creates the index git repo and downloads the .crate files of the crate versions
+    /// as they are received, so downloads can start before the whole selection is resolved.
+    /// The index is committed once the sender hangs up.
+    ///
+    /// Returns the crate versions received. If cancelled, the index is committed with the
+    /// crate versions received so far.
+    pub fn populate_streaming(
+        &self,
+        crates: Receiver<Version>,
+        hooks: &[Arc<dyn Hook>],
+        progress: &dyn ProgressObserver,
+        cancel: &CancellationToken,
+    ) -> Result<HashSet<Version>> {
+        populate_streaming(self, crates, hooks, progress, cancel)
+    }
+
+    /// Returns the contents of the mirror's config.json.
+    fn config_json(&self) -> Result<serde_json::Value> {
+        let dl_url = match &self.serve_url {
+            Some(serve_url) => serve_url.clone(),
+            None => self.storage.dl_url().map_err(Error::WriteConfigJson)?,
+        };
+        let mut config = serde_json::json!({ "dl": self.layout.dl(&dl_url) });
+        if self.auth_required {
+            config["auth-required"] = true.into();
+        }
+        Ok(config)
+    }
+
+    /// Returns the canonical index URLs of the registries whose crates are mirrored.
+    fn mirrored_registries(&self) -> Vec<&str> {
+        let secondary = self.secondary_sources.iter().map(|s| s.index_url());
+        std::iter::once(self.source.index_url())
+            .chain(secondary)
+            .collect()
+    }
+
+    fn sources(&self) -> CrateSources {
+        CrateSources {
+            primary: self.source.clone(),
+            secondary: self.secondary_sources.clone(),
+            local: LocalCrates {
+                offline: self.offline,
+                link_dirs: self.link_dirs.clone().into(),
+                seed_dirs: self.seed_dirs.clone().into(),
+            },
+            downloader: Arc::clone(&self.downloader),
+            layout: self.layout,
+        }
     }
 }
 
-fn populate_index(top_dir_path: &str, crates: &HashSet<Version>) -> Result<()> {
-    let index_dir_path = format!("{top_dir_path}/{INDEX_DIR}");
-    fs::create_dir(&index_dir_path).map_err(|e| Error::CreateIndexDir(e))?;
+/// The sources to download crates from, each crate from the one of its registry.
+struct CrateSources {
+    primary: CrateSource,
+    secondary: Vec<CrateSource>,
+    local: LocalCrates,
+    downloader: Arc<dyn CrateDownloader>,
+    /// The layout to store the .crate files in.
+    layout: RegistryLayout,
+}
 
-    let repo = create_git_repo(&index_dir_path)?;
-    write_config_json_file(top_dir_path)?;
-    add_crates_to_index(top_dir_path, &crates)?;
-    add_files_to_git_repo(&index_dir_path, &repo)?;
+/// Where to take the .crate files from on the local file system instead of downloading them.
+#[derive(Clone)]
+struct LocalCrates {
+    /// Whether to take the crates from Cargo's download cache instead.
+    offline: bool,
+    /// The directories to hard-link identical .crate files from.
+    link_dirs: Arc<[PathBuf]>,
+    /// The directories to copy identical .crate files from.
+    seed_dirs: Arc<[PathBuf]>,
+}
 
-    Ok(())
+impl CrateSources {
+    fn for_crate(&self, crat: &Version) -> &CrateSource {
+        let secondary = match crat.registry() {
+            Some(registry) => self.secondary.iter().find(|s| s.index_url() == registry),
+            None => None,
+        };
+        secondary.unwrap_or(&self.primary)
+    }
+}
+
+/// Makes the path absolute, and extended-length on Windows. The path is kept as is otherwise,
+/// so paths that aren't valid UTF-8 survive; the file URL Cargo downloads from is built from it
+/// by the storage backend.
+fn normalize_path(path: &Path) -> io::Result<PathBuf> {
+    if path.is_absolute() {
+        return Ok(extended_length_path(path.to_path_buf()));
+    }
+    let rel_path = path.strip_prefix(".").unwrap_or(path);
+    Ok(extended_length_path(env::current_dir()?.join(rel_path)))
 }
 
-fn populate_registry(top_dir_path: &str, crates: &HashSet<Version>) -> Result<()> {
-    let registry_dir_path = format!("{top_dir_path}/{REGISTRY_DIR}");
-    fs::create_dir(&registry_dir_path).map_err(|e| Error::CreateRegistryDir(e))?;
+/// Prefixes the absolute path with `\\?\`, so the files of large mirrors can be deeper than
+/// the 260 characters Windows otherwise allows. Windows doesn't normalize such paths,
+/// so `.` and `..` components are resolved first. Paths already verbatim are left as is.
+#[cfg(windows)]
+fn extended_length_path(path: PathBuf) -> PathBuf {
+    use std::ffi::OsString;
+    use std::path::{Component, Prefix};
+
+    let mut components = path.components();
+    let mut extended = match components.next() {
+        Some(Component::Prefix(prefix)) => match prefix.kind() {
+            Prefix::Disk(letter) => OsString::from(format!(r"\\?\{}:", letter as char)),
+            Prefix::UNC(server, share) => {
+                let mut extended = OsString::from(r"\\?\UNC\");
+                extended.push(server);
+                extended.push(r"\");
+                extended.push(share);
+                extended
+            }
+            _ => return path,
+        },
+        _ => return path,
+    };
+    let mut names = Vec::new();
+    for component in components {
+        match component {
+            Component::Normal(name) => names.push(name),
+            Component::ParentDir => {
+                names.pop();
+            }
+            _ => (),
+        }
+    }
+    for name in names {
+        extended.push(r"\");
+        extended.push(name);
+    }
+    PathBuf::from(extended)
+}
+
+#[cfg(not(windows))]
+fn extended_length_path(path: PathBuf) -> PathBuf {
+    path
+}
+
+fn populate_index(
+    top_dir_path: &Path,
+    config_json: &serde_json::Value,
+    crates: &HashSet<Version>,
+    mirrored_registries: &[&str],
+    reproducible: bool,
+) -> Result<()> {
+    let index_dir_path = top_dir_path.join(INDEX_DIR);
+    fs::create_dir(&index_dir_path).map_err(Error::CreateIndexDir)?;
 
+    let repo = create_git_repo(&index_dir_path, reproducible)?;
+    write_config_json_file(&index_dir_path, config_json)?;
+    let mut crates = Vec::from_iter(crates);
+    if reproducible {
+        // The lines of a crate's index file are in the order its versions are added.
+        crates.sort_by(|a, b| (a.name(), a.version()).cmp(&(b.name(), b.version())));
+    }
+    add_crates_to_index(&index_dir_path, &crates, mirrored_registries)?;
+    add_files_to_git_repo(&index_dir_path, &repo, reproducible)?;
+
+    Ok(())
+}
+
+fn populate_registry(
+    storage: &Arc<dyn StorageBackend>,
+    sources: &CrateSources,
+    crates: &HashSet<Version>,
+    hooks: &[Arc<dyn Hook>],
+    progress: &dyn ProgressObserver,
+    cancel: &CancellationToken,
+    require_all: bool,
+) -> Result<()> {
     let crates = Vec::from_iter(crates.iter().cloned());
-    let rt = tokio::runtime::Runtime::new().map_err(|e| Error::CreateRuntime(e))?;
+    let rt = tokio::runtime::Runtime::new().map_err(Error::CreateRuntime)?;
 
     let sem = sync::Semaphore::new(100);
-    let results = rt.block_on(download_crates(crates.clone(), &registry_dir_path, &sem));
+    let results = rt.block_on(download_crates(
+        &crates, storage, sources, hooks, &sem, progress, cancel,
+    ));
+    let num_downloaded = results.len();
+    let num_failed = results.iter().filter(|r| matches!(r, Ok(Err(_)))).count();
 
     for (i, result) in results.into_iter().enumerate() {
         match result {
@@ -223,36 +927,177 @@ fn populate_registry(top_dir_path: &str, crates: &HashSet<Version>) -> Result<()
         }
     }
 
+    if num_downloaded < crates.len() {
+        return Err(Error::Cancelled {
+            num_downloaded,
+            num_crates: crates.len(),
+        });
+    }
+    if require_all && num_failed > 0 {
+        return Err(Error::DownloadsFailed {
+            num_failed,
+            num_crates: crates.len(),
+        });
+    }
     Ok(())
 }
 
-fn create_git_repo(index_dir_path: &str) -> Result<Repository> {
-    Repository::init(index_dir_path).map_err(|e| Error::InitGitRepo(e))
+fn populate_streaming(
+    dst_registry: &DstRegistry,
+    crates: Receiver<Version>,
+    hooks: &[Arc<dyn Hook>],
+    progress: &dyn ProgressObserver,
+    cancel: &CancellationToken,
+) -> Result<HashSet<Version>> {
+    let DstRegistry {
+        path: top_dir_path,
+        storage,
+        reproducible,
+        require_all,
+        ..
+    } = dst_registry;
+    let (reproducible, require_all) = (*reproducible, *require_all);
+    let sources = dst_registry.sources();
+    let mirrored_registries = dst_registry.mirrored_registries();
+    let index_dir_path = top_dir_path.join(INDEX_DIR);
+    fs::create_dir(&index_dir_path).map_err(Error::CreateIndexDir)?;
+    let repo = create_git_repo(&index_dir_path, reproducible)?;
+    write_config_json_file(&index_dir_path, &dst_registry.config_json()?)?;
+
+    let rt = tokio::runtime::Runtime::new().map_err(Error::CreateRuntime)?;
+    let mut received = HashSet::new();
+    let mut num_failed = 0;
+    for crat in crates.iter() {
+        if cancel.is_cancelled() {
+            add_files_to_git_repo(&index_dir_path, &repo, reproducible)?;
+            return Err(Error::Cancelled {
+                num_downloaded: received.len(),
+                // Only the crates resolved so far are known.
+                num_crates: received.len() + 1 + crates.try_iter().count(),
+            });
+        }
+        if !received.insert(crat.clone()) {
+            continue;
+        }
+        add_crate_to_index(&index_dir_path, &crat, &mirrored_registries)?;
+        let span = info_span!(
+            "download",
+            crate_name = crat.name(),
+            crate_version = crat.version()
+        );
+        let source = sources.for_crate(&crat);
+        let download = download_crate(
+            &crat,
+            storage,
+            sources.layout,
+            source,
+            &sources.local,
+            sources.downloader.as_ref(),
+            hooks,
+        )
+        .instrument(span);
+        match rt.block_on(download) {
+            Ok(size) => {
+                progress.crate_stored(&crat, size);
+                // The total isn't known until resolution finishes.
+                progress.crate_downloaded(&crat, received.len(), 0);
+            }
+            Err(e) => {
+                num_failed += 1;
+                progress.failure(Phase::Download, Some(&crat), &e);
+            }
+        }
+    }
+
+    add_files_to_git_repo(&index_dir_path, &repo, reproducible)?;
+    if require_all && num_failed > 0 {
+        return Err(Error::DownloadsFailed {
+            num_failed,
+            num_crates: received.len(),
+        });
+    }
+    Ok(received)
+}
+
+fn create_git_repo(index_dir_path: &Path, reproducible: bool) -> Result<Repository> {
+    let repo = Repository::init(index_dir_path).map_err(Error::InitGitRepo)?;
+    if reproducible {
+        // Reflog entries record the local user and the current time.
+        repo.config()
+            .and_then(|mut config| config.set_bool("core.logAllRefUpdates", false))
+            .map_err(Error::InitGitRepo)?;
+    }
+    if cfg!(windows) {
+        // Otherwise libgit2 refuses to add files deeper than MAX_PATH, like Git for Windows.
+        repo.config()
+            .and_then(|mut config| config.set_bool("core.longpaths", true))
+            .map_err(Error::InitGitRepo)?;
+    }
+    Ok(repo)
+}
+
+/// Whether the config.json of the index sets `auth-required`.
+fn is_auth_required(index_dir_path: &Path) -> bool {
+    let auth_required = fs::read_to_string(index_dir_path.join("config.json"))
+        .ok()
+        .and_then(|contents| serde_json::from_str::<serde_json::Value>(&contents).ok())
+        .and_then(|config| config.get("auth-required")?.as_bool());
+    auth_required == Some(true)
 }
 
-fn write_config_json_file(top_dir_path: &str) -> Result<()> {
-    let config_json_path = format!("{top_dir_path}/{INDEX_DIR}/config.json");
-    let config_json_contents = format!(
-        r#"{{
-    "dl": "file://{}/{REGISTRY_DIR}"
-}}"#,
-        top_dir_path
-    );
-    fs::write(config_json_path, config_json_contents).map_err(|e| Error::WriteConfigJson(e))?;
+fn write_config_json_file(index_dir_path: &Path, config_json: &serde_json::Value) -> Result<()> {
+    let config_json_path = index_dir_path.join("config.json");
+    // Serialized rather than formatted so the URL is escaped as a JSON string.
+    let config_json_contents =
+        serde_json::to_string_pretty(config_json).map_err(|e| Error::WriteConfigJson(e.into()))?;
+    fs::write(config_json_path, config_json_contents).map_err(Error::WriteConfigJson)?;
     Ok(())
 }
 
-fn add_crates_to_index(top_dir_path: &str, crates: &HashSet<Version>) -> Result<()> {
+/// Adds the crate versions to the index on as many threads as there are CPUs. The versions
+/// of a crate are all added by the same thread, in order, since they share an index file.
+fn add_crates_to_index(
+    index_dir_path: &Path,
+    crates: &[&Version],
+    mirrored_registries: &[&str],
+) -> Result<()> {
+    let mut by_file: HashMap<String, Vec<&Version>> = HashMap::new();
     for crat in crates {
-        add_crate_to_index(top_dir_path, crat)?;
+        by_file
+            .entry(crat.name().to_lowercase())
+            .or_default()
+            .push(crat);
     }
-    Ok(())
+    let groups = Vec::from_iter(by_file.into_values());
+    let num_threads = match thread::available_parallelism() {
+        Ok(n) => n.get().min(groups.len()),
+        Err(_) => 1,
+    };
+    let next_group = AtomicUsize::new(0);
+    let add_groups = || -> Result<()> {
+        while let Some(group) = groups.get(next_group.fetch_add(1, Ordering::Relaxed)) {
+            for crat in group {
+                add_crate_to_index(index_dir_path, crat, mirrored_registries)?;
+            }
+        }
+        Ok(())
+    };
+    thread::scope(|scope| {
+        let writers = Vec::from_iter((0..num_threads).map(|_| scope.spawn(add_groups)));
+        writers
+            .into_iter()
+            .try_for_each(|writer| writer.join().expect("index writer thread panicked"))
+    })
 }
 
-fn add_crate_to_index(top_dir_path: &str, crat: &Version) -> Result<()> {
-    let crate_path = get_crate_index_path(top_dir_path, crat)?;
+fn add_crate_to_index(
+    index_dir_path: &Path,
+    crat: &Version,
+    mirrored_registries: &[&str],
+) -> Result<()> {
+    let crate_path = get_crate_index_path(index_dir_path, crat)?;
 
-    let crate_path = format!("{crate_path}/{}", crat.name().to_lowercase());
+    let crate_path = crate_path.join(crat.name().to_lowercase());
     let mut crate_file = OpenOptions::new()
         .create(true)
         .append(true)
@@ -264,12 +1109,14 @@ fn add_crate_to_index(top_dir_path: &str, crat: &Version) -> Result<()> {
             error: Box::new(e),
         })?;
 
-    let mut crate_version_info = crat.to_json().map_err(|e| Error::AddCrateToIndex {
-        crate_name: crat.name().to_string(),
-        crate_version: crat.version().to_string(),
-        msg: "failed to serialize crate version information to a string".to_string(),
-        error: Box::new(e),
-    })?;
+    let mut crate_version_info =
+        crat.to_mirror_json(mirrored_registries)
+            .map_err(|e| Error::AddCrateToIndex {
+                crate_name: crat.name().to_string(),
+                crate_version: crat.version().to_string(),
+                msg: "failed to serialize crate version information to a string".to_string(),
+                error: Box::new(e),
+            })?;
     crate_version_info += "\n";
 
 
@@ -285,87 +1132,96 @@ fn add_crate_to_index(top_dir_path: &str, crat: &Version) -> Result<()> {
     Ok(())
 }
 
-fn get_crate_index_path(top_dir_path: &str, crat: &Version) -> Result<String> {
+/// Creates the directory in the index unless it exists, e.g. because another thread just
+/// created it.
+fn create_index_dir(path: &Path) -> io::Result<()> {
+    match fs::create_dir(path) {
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Ok(()),
+        result => result,
+    }
+}
+
+fn get_crate_index_path(index_dir_path: &Path, crat: &Version) -> Result<PathBuf> {
     let crate_name = crat.name().to_lowercase();
     match crate_name.len() {
         1 => {
-            let crate_path = format!("{top_dir_path}/{INDEX_DIR}/1");
-            if !Path::new(&crate_path).exists() {
-                fs::create_dir(&crate_path).map_err(|e| Error::AddCrateToIndex {
-                    crate_name: crat.name().to_string(),
-                    crate_version: crat.version().to_string(),
-                    msg: "failed to create '1' directory".to_string(),
-                    error: Box::new(e),
-                })?;
-            }
+            let crate_path = index_dir_path.join("1");
+            create_index_dir(&crate_path).map_err(|e| Error::AddCrateToIndex {
+                crate_name: crat.name().to_string(),
+                crate_version: crat.version().to_string(),
+                msg: "failed to create '1' directory".to_string(),
+                error: Box::new(e),
+            })?;
             Ok(crate_path)
         }
         2 => {
-            let crate_path = format!("{top_dir_path}/{INDEX_DIR}/2");
-            if !Path::new(&crate_path).exists() {
-                fs::create_dir(&crate_path).map_err(|e| Error::AddCrateToIndex {
-                    crate_name: crat.name().to_string(),
-                    crate_version: crat.version().to_string(),
-                    msg: "failed to create '2' directory".to_string(),
-                    error: Box::new(e),
-                })?;
-            }
+            let crate_path = index_dir_path.join("2");
+            create_index_dir(&crate_path).map_err(|e| Error::AddCrateToIndex {
+                crate_name: crat.name().to_string(),
+                crate_version: crat.version().to_string(),
+                msg: "failed to create '2' directory".to_string(),
+                error: Box::new(e),
+            })?;
             Ok(crate_path)
         }
         3 => {
-            let crate_path = format!("{top_dir_path}/{INDEX_DIR}/3");
-            if !Path::new(&crate_path).exists() {
-                fs::create_dir(&crate_path).map_err(|e| Error::AddCrateToIndex {
-                    crate_name: crat.name().to_string(),
-                    crate_version: crat.version().to_string(),
-                    msg: "failed to create '3' directory".to_string(),
-                    error: Box::new(e),
-                })?;
-            }
+            let crate_path = index_dir_path.join("3");
+            create_index_dir(&crate_path).map_err(|e| Error::AddCrateToIndex {
+                crate_name: crat.name().to_string(),
+                crate_version: crat.version().to_string(),
+                msg: "failed to create '3' directory".to_string(),
+                error: Box::new(e),
+            })?;
 
-            let crate_path = format!(
-                "{crate_path}/{}",
-                crate_name.chars().take(1).collect::<String>()
-            );
-            if !Path::new(&crate_path).exists() {
-                fs::create_dir(&crate_path).map_err(|e| Error::AddCrateToIndex {
-                    crate_name: crat.name().to_string(),
-                    crate_version: crat.version().to_string(),
-                    msg: "failed to create crate directory in '3' directory".to_string(),
-                    error: Box::new(e),
-                })?;
-            }
+            let crate_path = crate_path.join(crate_name.chars().take(1).collect::<String>());
+            create_index_dir(&crate_path).map_err(|e| Error::AddCrateToIndex {
+                crate_name: crat.name().to_string(),
+                crate_version: crat.version().to_string(),
+                msg: "failed to create crate directory in '3' directory".to_string(),
+                error: Box::new(e),
+            })?;
             Ok(crate_path)
         }
         _ => {
             let dir1_name = crate_name.chars().take(2).collect::<String>();
-            let crate_path = format!("{top_dir_path}/{INDEX_DIR}/{dir1_name}");
-            if !Path::new(&crate_path).exists() {
-                fs::create_dir(&crate_path).map_err(|e| Error::AddCrateToIndex {
-                    crate_name: crat.name().to_string(),
-                    crate_version: crat.version().to_string(),
-                    msg: format!("failed to create {dir1_name} directory"),
-                    error: Box::new(e),
-                })?;
-            }
+            let crate_path = index_dir_path.join(&dir1_name);
+            create_index_dir(&crate_path).map_err(|e| Error::AddCrateToIndex {
+                crate_name: crat.name().to_string(),
+                crate_version: crat.version().to_string(),
+                msg: format!("failed to create {dir1_name} directory"),
+                error: Box::new(e),
+            })?;
 
             let dir2_name = crate_name.chars().skip(2).take(2).collect::<String>();
-            let crate_path = format!("{crate_path}/{dir2_name}");
-            if !Path::new(&crate_path).exists() {
-                fs::create_dir(&crate_path).map_err(|e| Error::AddCrateToIndex {
-                    crate_name: crat.name().to_string(),
-                    crate_version: crat.version().to_string(),
-                    msg: format!("failed to create {dir2_name} directory"),
-                    error: Box::new(e),
-                })?;
-            }
+            let crate_path = crate_path.join(&dir2_name);
+            create_index_dir(&crate_path).map_err(|e| Error::AddCrateToIndex {
+                crate_name: crat.name().to_string(),
+                crate_version: crat.version().to_string(),
+                msg: format!("failed to create {dir2_name} directory"),
+                error: Box::new(e),
+            })?;
 
             Ok(crate_path)
         }
     }
 }
 
-fn add_files_to_git_repo(index_dir_path: &str, repo: &Repository) -> Result<()> {
+/// Returns the directory of the crate's index file, relative to the index, e.g. `3/s` or `se/rd`,
+/// as used in the `{prefix}` marker of download URLs.
+fn crate_prefix(name: &str) -> String {
+    match name.len() {
+        1 => "1".to_string(),
+        2 => "2".to_string(),
+        3 => format!("3/{}", &name[..1]),
+        _ => format!("{}/{}", &name[..2], &name[2..4]),
+    }
+}
+
+fn add_files_to_git_repo(
+    index_dir_path: &Path,
+    repo: &Repository,
+    reproducible: bool,
+) -> Result<()> {
     let mut index = repo
         .index()
         .map_err(|e| Error::AddFileToGitRepo(Box::new(e)))?;
@@ -375,15 +1231,18 @@ fn add_files_to_git_repo(index_dir_path: &str, repo: &Repository) -> Result<()>
         add_file_to_git_repo(index_dir_path, &mut index, &entry)
             .map_err(|e| Error::AddFileToGitRepo(Box::new(e)))?;
     }
+    if reproducible {
+        clear_file_stats(&mut index)?;
+    }
     index
         .write()
         .map_err(|e| Error::AddFileToGitRepo(Box::new(e)))?;
-    commit_git_repo(repo, &mut index)?;
+    commit_git_repo(repo, &mut index, "Initial commit", reproducible)?;
     Ok(())
 }
 
 fn add_file_to_git_repo(
-    index_dir_path: &str,
+    index_dir_path: &Path,
     index: &mut git2::Index,
     entry: &DirEntry,
 ) -> Result<()> {
@@ -395,107 +1254,559 @@ fn add_file_to_git_repo(
         index
             .add_path(&path)
             .map_err(|e| Error::AddFileToGitRepo(Box::new(e)))?;
-    } else if metadata.is_dir() {
-        if entry.file_name() != ".git" {
-            let entries =
-                fs::read_dir(entry.path()).map_err(|e| Error::AddFileToGitRepo(Box::new(e)))?;
-            for entry in entries {
-                let entry = entry.map_err(|e| Error::AddFileToGitRepo(Box::new(e)))?;
-                add_file_to_git_repo(index_dir_path, index, &entry)?
-            }
+    } else if metadata.is_dir() && entry.file_name() != ".git" {
+        let entries =
+            fs::read_dir(entry.path()).map_err(|e| Error::AddFileToGitRepo(Box::new(e)))?;
+        for entry in entries {
+            let entry = entry.map_err(|e| Error::AddFileToGitRepo(Box::new(e)))?;
+            add_file_to_git_repo(index_dir_path, index, &entry)?
         }
     }
     Ok(())
 }
 
-fn commit_git_repo(repo: &Repository, index: &mut git2::Index) -> Result<()> {
-    let oid = index.write_tree().map_err(|e| Error::CommitGitRepo(e))?;
-    let signature = git2::Signature::now("Russ Goetz", "russgoetz@gmail.com")
-        .map_err(|e| Error::CommitGitRepo(e))?;
-    //let parent_commit = find_last_commit(&repo)?;
-    let tree = repo.find_tree(oid).map_err(|e| Error::CommitGitRepo(e))?;
+/// Zeroes the file system details, e.g. timestamps and inode numbers, git records for each file
+/// in its index. Git then rehashes the files to check for changes, which is all it loses.
+fn clear_file_stats(index: &mut git2::Index) -> Result<()> {
+    let entries = Vec::from_iter(index.iter());
+    for mut entry in entries {
+        entry.ctime = git2::IndexTime::new(0, 0);
+        entry.mtime = git2::IndexTime::new(0, 0);
+        entry.dev = 0;
+        entry.ino = 0;
+        entry.uid = 0;
+        entry.gid = 0;
+        index
+            .add(&entry)
+            .map_err(|e| Error::AddFileToGitRepo(Box::new(e)))?;
+    }
+    Ok(())
+}
+
+fn commit_git_repo(
+    repo: &Repository,
+    index: &mut git2::Index,
+    message: &str,
+    reproducible: bool,
+) -> Result<()> {
+    let oid = index.write_tree().map_err(Error::CommitGitRepo)?;
+    let signature = if reproducible {
+        git2::Signature::new("Russ Goetz", "russgoetz@gmail.com", &source_date())
+    } else {
+        git2::Signature::now("Russ Goetz", "russgoetz@gmail.com")
+    }
+    .map_err(Error::CommitGitRepo)?;
+    let parent_commit = find_last_commit(repo)?;
+    let parents = Vec::from_iter(parent_commit.iter());
+    let tree = repo.find_tree(oid).map_err(Error::CommitGitRepo)?;
     repo.commit(
-        Some("HEAD"),     //  point HEAD to our new commit
-        &signature,       // author
-        &signature,       // committer
-        "Initial commit", // commit message
-        &tree,            // tree
-        &[],
-        //&[&parent_commit],
+        Some("HEAD"), //  point HEAD to our new commit
+        &signature,   // author
+        &signature,   // committer
+        message,      // commit message
+        &tree,        // tree
+        &parents,     // parents
     )
-    .map_err(|e| Error::CommitGitRepo(e))?; // parents
+    .map_err(Error::CommitGitRepo)?;
     Ok(())
 }
 
+/// The time of reproducible commits: `SOURCE_DATE_EPOCH`, following the reproducible builds
+/// convention, or the Unix epoch if it isn't set.
+fn source_date() -> git2::Time {
+    let seconds = env::var("SOURCE_DATE_EPOCH")
+        .ok()
+        .and_then(|seconds| seconds.parse().ok())
+        .unwrap_or(0);
+    git2::Time::new(seconds, 0)
+}
+
+/// Returns the commit HEAD points to, or `None` if the repo has no commits yet.
+fn find_last_commit(repo: &Repository) -> Result<Option<git2::Commit<'_>>> {
+    let head = match repo.head() {
+        Ok(head) => head,
+        Err(e) if e.code() == git2::ErrorCode::UnbornBranch => return Ok(None),
+        Err(e) => return Err(Error::CommitGitRepo(e)),
+    };
+    let commit = head.peel_to_commit().map_err(Error::CommitGitRepo)?;
+    Ok(Some(commit))
+}
+
 async fn download_crates(
-    crates: Vec<Version>,
-    registry_dir_path: &str,
+    crates: &[Version],
+    storage: &Arc<dyn StorageBackend>,
+    sources: &CrateSources,
+    hooks: &[Arc<dyn Hook>],
     sem: &sync::Semaphore,
-) -> Vec<std::result::Result<Result<()>, task::JoinError>> {
+    progress: &dyn ProgressObserver,
+    cancel: &CancellationToken,
+) -> Vec<std::result::Result<Result<u64>, task::JoinError>> {
     let mut results = Vec::new();
     for (i, crat) in crates.iter().enumerate() {
+        if cancel.is_cancelled() {
+            break;
+        }
         let _permit = sem.acquire().await.expect("acquire semaphore");
-        let name = crat.name().to_string();
-        let version = crat.version().to_string();
-        let path = registry_dir_path.to_string();
-        let result = tokio::spawn(async move {
-            download_crate(&name, &version, &path).await
-        }).await;
+        let owned_crat = crat.clone();
+        let (storage, layout) = (Arc::clone(storage), sources.layout);
+        let source = sources.for_crate(crat).clone();
+        let local = sources.local.clone();
+        let downloader = Arc::clone(&sources.downloader);
+        let hooks = hooks.to_vec();
+        let span = info_span!(
+            "download",
+            crate_name = crat.name(),
+            crate_version = crat.version()
+        );
+        let download = async move {
+            download_crate(
+                &owned_crat,
+                &storage,
+                layout,
+                &source,
+                &local,
+                downloader.as_ref(),
+                &hooks,
+            )
+            .await
+        };
+        let result = tokio::spawn(download.instrument(span)).await;
+        match &result {
+            Ok(Ok(size)) => {
+                progress.crate_stored(crat, *size);
+                progress.crate_downloaded(crat, i + 1, crates.len());
+            }
+            Ok(Err(e)) => progress.failure(Phase::Download, Some(crat), e),
+            // Task panicked. Reported by the caller.
+            Err(_) => (),
+        }
         results.push(result);
-        println!("Downloaded {:>4} of {:>4}: {} version {}", i+1, crates.len(), crates[i].name(), crates[i].version());
     }
     results
 }
 
-async fn download_crate(name: &str, version: &str, registry_dir_path: &str) -> Result<()> {
-    const DL_URL: &'static str = "https://static.crates.io/crates";
-    let crate_url = format!("{DL_URL}/{name}/{name}-{version}.crate");
-
-    let response = reqwest::get(crate_url)
+/// Downloads the .crate file of the crate version and stores it. The file system work and the
+/// hooks, which may run external commands, run on Tokio's blocking thread pool, so neither
+/// stalls the other downloads.
+async fn download_crate(
+    crat: &Version,
+    storage: &Arc<dyn StorageBackend>,
+    layout: RegistryLayout,
+    source: &CrateSource,
+    local: &LocalCrates,
+    downloader: &dyn CrateDownloader,
+    hooks: &[Arc<dyn Hook>],
+) -> Result<u64> {
+    if !hooks.is_empty() {
+        let (crat, hooks) = (crat.clone(), hooks.to_vec());
+        blocking(move || {
+            hooks
+                .iter()
+                .try_for_each(|hook| hook.before_download(&crat))
+        })
         .await
-        .map_err(|e| Error::DownloadCrate {
+        .map_err(Error::Hook)?;
+    }
+
+    let (name, version) = (crat.name(), crat.version());
+    let identical = {
+        let (crat, link_dirs) = (crat.clone(), Arc::clone(&local.link_dirs));
+        blocking(move || find_identical_crate(&crat, &link_dirs)).await
+    };
+    let seeded = match identical {
+        Some(_) => None,
+        None => {
+            let (crat, seed_dirs) = (crat.clone(), Arc::clone(&local.seed_dirs));
+            blocking(move || find_identical_crate(&crat, &seed_dirs)).await
+        }
+    };
+    let bytes = if let Some((path, bytes)) = &identical {
+        info!(
+            size = bytes.len(),
+            path = %path.display(),
+            "identical crate found"
+        );
+        bytes.clone()
+    } else if let Some((path, bytes)) = seeded {
+        info!(
+            size = bytes.len(),
+            path = %path.display(),
+            "crate copied from the seed directory"
+        );
+        bytes
+    } else if local.offline {
+        let cached = {
+            let crat = crat.clone();
+            blocking(move || read_cached_crate(&crat)).await
+        };
+        let bytes = cached.ok_or_else(|| Error::NotCached {
             crate_name: name.to_string(),
             crate_version: version.to_string(),
-            error: Box::new(e),
         })?;
+        info!(
+            size = bytes.len(),
+            "crate copied from Cargo's download cache"
+        );
+        bytes
+    } else {
+        fetch_crate(crat, source, downloader).await?
+    };
 
-    let bytes = response.bytes().await.map_err(|e| Error::DownloadCrate {
-        crate_name: name.to_string(),
-        crate_version: version.to_string(),
-        error: Box::new(e),
-    })?;
+    if !hooks.is_empty() {
+        // Cloning the bytes only clones a reference to them.
+        let (crat, hooks, bytes) = (crat.clone(), hooks.to_vec(), bytes.clone());
+        blocking(move || {
+            hooks
+                .iter()
+                .try_for_each(|hook| hook.after_download(&crat, &bytes))
+        })
+        .await
+        .map_err(Error::Hook)?;
+    }
 
-    add_crate_to_registry(registry_dir_path, name, version, bytes)
+    let size = bytes.len() as u64;
+    let storage = Arc::clone(storage);
+    let (name, version) = (name.to_string(), version.to_string());
+    blocking(move || match identical {
+        Some((path, _)) => {
+            link_crate_to_registry(storage.as_ref(), layout, &name, &version, &path, bytes)
+        }
+        None => add_crate_to_registry(storage.as_ref(), layout, &name, &version, bytes),
+    })
+    .await?;
+    Ok(size)
+}
+
+/// Runs the blocking function on Tokio's blocking thread pool, resuming its panic, if any,
+/// in the calling task.
+async fn blocking<T, F>(f: F) -> T
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    match task::spawn_blocking(f).await {
+        Ok(result) => result,
+        Err(e) if e.is_panic() => std::panic::resume_unwind(e.into_panic()),
+        Err(e) => panic!("blocking task failed: {e}"),
+    }
+}
+
+/// Downloads the .crate file of the crate version from its source, checking it against
+/// the index checksum.
+async fn fetch_crate(
+    crat: &Version,
+    source: &CrateSource,
+    downloader: &dyn CrateDownloader,
+) -> Result<bytes::Bytes> {
+    let (name, version) = (crat.name(), crat.version());
+    let download_error =
+        |e: Box<dyn std::error::Error + Send + Sync + 'static>| Error::DownloadCrate {
+            crate_name: name.to_string(),
+            crate_version: version.to_string(),
+            error: e,
+        };
+    let crate_url = source.crate_url(crat)?;
+    let bytes = downloader
+        .download(&crate_url, source.token.as_deref())
+        .await
+        .map_err(download_error)?;
+    if checksums::sha256(&bytes) != crat.checksum() {
+        return Err(Error::ChecksumMismatch {
+            crate_name: name.to_string(),
+            crate_version: version.to_string(),
+        });
+    }
+    info!(size = bytes.len(), "crate downloaded");
+    Ok(bytes)
+}
+
+/// Reads the .crate file of the crate version from Cargo's download cache, which has a
+/// directory per registry.
+fn read_cached_crate(crat: &Version) -> Option<bytes::Bytes> {
+    let cache_dir_path = credentials::cargo_home()?.join("registry").join("cache");
+    let file_name = format!("{}-{}.crate", crat.name(), crat.version());
+    fs::read_dir(cache_dir_path)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .find_map(|entry| read_identical_crate(crat, &entry.path().join(&file_name)))
+}
+
+/// Returns the first .crate file of the crate version in the directories, either micrio
+/// mirrors, in any layout, or directories of `{name}-{version}.crate` files, with its contents.
+fn find_identical_crate(crat: &Version, dir_paths: &[PathBuf]) -> Option<(PathBuf, bytes::Bytes)> {
+    let file_name = format!("{}-{}.crate", crat.name(), crat.version());
+    dir_paths.iter().find_map(|dir_path| {
+        let mirrored = RegistryLayout::ALL.map(|layout| {
+            layout
+                .crate_file_key(crat.name(), crat.version())
+                .split('/')
+                .fold(dir_path.join(REGISTRY_DIR), |path, c| path.join(c))
+        });
+        mirrored
+            .into_iter()
+            .chain([dir_path.join(&file_name)])
+            .find_map(|path| read_identical_crate(crat, &path).map(|bytes| (path, bytes)))
+    })
+}
+
+/// Reads the .crate file if it matches the crate version's index checksum.
+fn read_identical_crate(crat: &Version, path: &Path) -> Option<bytes::Bytes> {
+    let bytes = fs::read(path).ok()?;
+    (checksums::sha256(&bytes) == crat.checksum()).then(|| bytes.into())
+}
+
+/// Stores the .crate file as a hard link to the identical local file, or as a copy of its
+/// contents if the storage backend can't link it.
+fn link_crate_to_registry(
+    storage: &dyn StorageBackend,
+    layout: RegistryLayout,
+    name: &str,
+    version: &str,
+    path: &Path,
+    file_contents: bytes::Bytes,
+) -> Result<()> {
+    match storage.link(&layout.crate_file_key(name, version), path) {
+        Ok(()) => Ok(()),
+        Err(e) => {
+            info!("failed to hard-link the crate, storing a copy: {e}");
+            add_crate_to_registry(storage, layout, name, version, file_contents)
+        }
+    }
 }
 
 fn add_crate_to_registry(
-    registry_dir_path: &str,
+    storage: &dyn StorageBackend,
+    layout: RegistryLayout,
     name: &str,
     version: &str,
     file_contents: bytes::Bytes,
 ) -> Result<()> {
-    let crate_dir_path = format!("{registry_dir_path}/{name}");
-    if !Path::new(&crate_dir_path).exists() {
-        fs::create_dir(&crate_dir_path).map_err(|e| Error::WriteRegistryFile {
+    storage
+        .write(&layout.crate_file_key(name, version), &file_contents)
+        .map_err(|e| Error::WriteRegistryFile {
             crate_name: name.to_string(),
             crate_version: version.to_string(),
-            msg: format!("failed to create {name} directory"),
+            msg: "failed to write contents to storage".to_string(),
             error: e,
-        })?;
+        })
+}
+
+/// Returns the storage key of an additional file stored next to the .crate file.
+fn extra_file_key(name: &str, version: &str, file_name: &str) -> String {
+    format!("{name}/{version}/{file_name}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::downloader::{FixtureDownloader, FixtureResponse};
+    use std::sync::Mutex;
+
+    /// Records the crate-specific failures reported, by crate name.
+    #[derive(Default)]
+    struct Failures(Mutex<Vec<(String, String)>>);
+
+    impl ProgressObserver for Failures {
+        fn failure(
+            &self,
+            _phase: Phase,
+            crat: Option<&Version>,
+            error: &(dyn std::error::Error + 'static),
+        ) {
+            let kind = match error.downcast_ref::<Error>() {
+                Some(Error::DownloadCrate { error, .. }) => {
+                    match error.downcast_ref::<io::Error>() {
+                        Some(e) if e.kind() == io::ErrorKind::TimedOut => "timeout".to_string(),
+                        _ => format!("download: {error}"),
+                    }
+                }
+                Some(Error::ChecksumMismatch { .. }) => "checksum mismatch".to_string(),
+                _ => format!("other: {error}"),
+            };
+            let name = crat.map(|crat| crat.name().to_string()).unwrap_or_default();
+            self.0.lock().unwrap().push((name, kind));
+        }
+    }
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir_path = env::temp_dir().join(format!("micrio-test-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir_path);
+        dir_path
+    }
+
+    /// Returns a crate version whose index checksum is that of the contents.
+    fn crate_version(name: &str, version: &str, contents: &[u8]) -> Version {
+        Version::from_json(&format!(
+            r#"{{"name":"{name}","vers":"{version}","deps":[],"cksum":"{}","features":{{}},"yanked":false}}"#,
+            checksums::sha256(contents)
+        ))
+        .unwrap()
+    }
+
+    /// Sets up a registry downloading from the fixture, and the crates it has responses for:
+    /// one served correctly, one not found, one timing out, and one corrupt.
+    fn fixture_registry(dir_path: &Path, require_all: bool) -> (DstRegistry, HashSet<Version>) {
+        let source = CrateSource::new(
+            "https://index.example.com/",
+            "https://dl.example.com/api/v1/crates",
+            None,
+        );
+        let downloader = FixtureDownloader::new();
+        let good = crate_version("good", "1.0.0", b"good contents");
+        let missing = crate_version("missing", "1.0.0", b"missing contents");
+        let slow = crate_version("slow", "1.0.0", b"slow contents");
+        let corrupt = crate_version("corrupt", "1.0.0", b"corrupt contents");
+        let responses = [
+            (&good, FixtureResponse::Body("good contents".into())),
+            (&missing, FixtureResponse::Status(404)),
+            (&slow, FixtureResponse::Timeout),
+            (&corrupt, FixtureResponse::Body("tampered contents".into())),
+        ];
+        for (crat, response) in responses {
+            downloader.respond(&source.crate_url(crat).unwrap(), response);
+        }
+        let dst_registry = DstRegistry::new(dir_path)
+            .unwrap()
+            .source(source)
+            .downloader(Arc::new(downloader))
+            .require_all(require_all);
+        (dst_registry, HashSet::from([good, missing, slow, corrupt]))
+    }
+
+    #[test]
+    fn failed_downloads_are_reported_and_left_out() {
+        let dir_path = test_dir("failed-downloads");
+        let (dst_registry, crates) = fixture_registry(&dir_path, false);
+        let failures = Failures::default();
+        dst_registry
+            .populate_registry(&crates, &[], &failures, &CancellationToken::new())
+            .unwrap();
+
+        let mut failures = failures.0.into_inner().unwrap();
+        failures.sort();
+        assert_eq!(failures.len(), 3);
+        assert_eq!(
+            failures[0],
+            ("corrupt".to_string(), "checksum mismatch".to_string())
+        );
+        assert_eq!(failures[1].0, "missing");
+        assert!(failures[1].1.starts_with("download: HTTP status 404"));
+        assert_eq!(failures[2], ("slow".to_string(), "timeout".to_string()));
+
+        assert_eq!(
+            dst_registry.read_crate_file("good", "1.0.0").unwrap(),
+            b"good contents"
+        );
+        for name in ["missing", "slow", "corrupt"] {
+            assert!(dst_registry.read_crate_file(name, "1.0.0").is_err());
+        }
+        fs::remove_dir_all(&dir_path).unwrap();
+    }
+
+    #[test]
+    fn failed_downloads_fail_the_run_if_all_are_required() {
+        let dir_path = test_dir("require-all");
+        let (dst_registry, crates) = fixture_registry(&dir_path, true);
+        let result = dst_registry.populate_registry(
+            &crates,
+            &[],
+            &Failures::default(),
+            &CancellationToken::new(),
+        );
+        match result {
+            Err(Error::DownloadsFailed {
+                num_failed,
+                num_crates,
+            }) => assert_eq!((num_failed, num_crates), (3, 4)),
+            Err(e) => panic!("unexpected error: {e}"),
+            Ok(()) => panic!("the failed downloads didn't fail the run"),
+        }
+        // The crates that did download are kept.
+        assert!(dst_registry.read_crate_file("good", "1.0.0").is_ok());
+        fs::remove_dir_all(&dir_path).unwrap();
+    }
+
+    /// A version with build metadata, like those of the -sys crates bundling a C library.
+    const BUILD_METADATA_VERSION: &str = "2.0.9+zstd.1.5.5";
+
+    #[test]
+    fn build_metadata_is_percent_encoded_in_download_urls() {
+        let crat = crate_version("zstd-sys", BUILD_METADATA_VERSION, b"zstd-sys contents");
+        let nested = CrateSource::new(
+            "https://index.example.com/",
+            "https://dl.example.com/api/v1/crates",
+            None,
+        );
+        assert_eq!(
+            nested.crate_url(&crat).unwrap().as_str(),
+            "https://dl.example.com/api/v1/crates/zstd-sys/2.0.9%2Bzstd.1.5.5/download"
+        );
+        let flat = CrateSource::new(
+            "https://index.example.com/",
+            "https://dl.example.com/{crate}-{version}.crate",
+            None,
+        );
+        assert_eq!(
+            flat.crate_url(&crat).unwrap().as_str(),
+            "https://dl.example.com/zstd-sys-2.0.9%2Bzstd.1.5.5.crate"
+        );
+    }
+
+    #[test]
+    fn build_metadata_is_kept_in_file_paths() {
+        let keys = RegistryLayout::ALL
+            .map(|layout| layout.crate_file_key("zstd-sys", BUILD_METADATA_VERSION));
+        assert_eq!(
+            keys,
+            [
+                "zstd-sys/2.0.9+zstd.1.5.5/download",
+                "zstd-sys-2.0.9+zstd.1.5.5.crate",
+                "zs/td/zstd-sys-2.0.9+zstd.1.5.5.crate",
+            ]
+        );
+    }
+
+    #[test]
+    fn build_metadata_versions_are_downloaded_and_indexed() {
+        let dir_path = test_dir("build-metadata");
+        let crat = crate_version("zstd-sys", BUILD_METADATA_VERSION, b"zstd-sys contents");
+        let source = CrateSource::new(
+            "https://index.example.com/",
+            "https://dl.example.com/api/v1/crates",
+            None,
+        );
+        let downloader = FixtureDownloader::new();
+        downloader.respond(
+            &source.crate_url(&crat).unwrap(),
+            FixtureResponse::Body("zstd-sys contents".into()),
+        );
+        let dst_registry = DstRegistry::new(&dir_path)
+            .unwrap()
+            .source(source)
+            .downloader(Arc::new(downloader))
+            .require_all(true);
+        let crates = HashSet::from([crat.clone()]);
+        dst_registry.populate_index(&crates).unwrap();
+        dst_registry
+            .populate_registry(
+                &crates,
+                &[],
+                &Failures::default(),
+                &CancellationToken::new(),
+            )
+            .unwrap();
+
+        assert_eq!(
+            dst_registry
+                .read_crate_file("zstd-sys", BUILD_METADATA_VERSION)
+                .unwrap(),
+            b"zstd-sys contents"
+        );
+        let index_file =
+            fs::read_to_string(dir_path.join(INDEX_DIR).join("zs/td/zstd-sys")).unwrap();
+        let indexed = Version::from_json(index_file.trim_end()).unwrap();
+        assert_eq!(indexed.version(), BUILD_METADATA_VERSION);
+        assert_eq!(indexed.checksum(), crat.checksum());
+        fs::remove_dir_all(&dir_path).unwrap();
     }
-    let crate_dir_path = format!("{crate_dir_path}/{version}");
-    fs::create_dir(&crate_dir_path).map_err(|e| Error::WriteRegistryFile {
-        crate_name: name.to_string(),
-        crate_version: version.to_string(),
-        msg: format!("failed to create {version} directory"),
-        error: e,
-    })?;
-    let crate_file_path = format!("{crate_dir_path}/download");
-    fs::write(crate_file_path, file_contents).map_err(|e| Error::WriteRegistryFile {
-        crate_name: name.to_string(),
-        crate_version: version.to_string(),
-        msg: "failed to write contents to file".to_string(),
-        error: e,
-    })?;
-    Ok(())
 }
@@ -0,0 +1,204 @@
+//! Where the registry mirrored from and the mirror itself are reached, in one place so the URLs
+//! can be checked before a run starts rather than failing halfway through it.
+
+use crate::common;
+use std::fmt::{self, Display};
+use url::Url;
+
+/// The base URL of the crates.io web API.
+pub const CRATES_IO_API: &str = "https://crates.io/api/v1";
+
+/// The template of the crates.io .crate file download URLs.
+pub const CRATES_IO_DL: &str = "https://static.crates.io/crates/{crate}/{crate}-{version}.crate";
+
+/// The markers Cargo replaces in the `dl` template of a registry's config.json.
+pub(crate) const DL_MARKERS: [&str; 5] = [
+    "{crate}",
+    "{version}",
+    "{prefix}",
+    "{lowerprefix}",
+    "{sha256-checksum}",
+];
+
+#[derive(Debug)]
+pub enum Error {
+    InvalidUrl {
+        endpoint: &'static str,
+        url: String,
+        error: url::ParseError,
+    },
+    UnsupportedScheme {
+        endpoint: &'static str,
+        url: String,
+    },
+    UnknownMarker {
+        dl: String,
+        marker: String,
+    },
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidUrl {
+                endpoint,
+                url,
+                error,
+            } => {
+                write!(f, "invalid {endpoint} URL {url}: {error}")
+            }
+            Error::UnsupportedScheme { endpoint, url } => {
+                write!(f, "unsupported scheme in the {endpoint} URL {url}")
+            }
+            Error::UnknownMarker { dl, marker } => {
+                write!(f, "unknown marker {marker} in the download template {dl}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::InvalidUrl { error, .. } => Some(error),
+            Error::UnsupportedScheme { .. } => None,
+            Error::UnknownMarker { .. } => None,
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// The URLs of the registry the crates are mirrored from and of the mirror. Those left out
+/// default to crates.io and to the mirror directory.
+///
+/// ```
+/// use micrio::endpoints::Endpoints;
+///
+/// let endpoints = Endpoints {
+///     index_url: Some("https://git.example.com/crates-index.git".to_string()),
+///     dl: Some("https://crates.example.com/{crate}/{version}/download".to_string()),
+///     serve_url: Some("https://mirror.example.com/registry".to_string()),
+///     ..Endpoints::crates_io()
+/// };
+/// endpoints.validate()?;
+/// # Ok::<(), micrio::endpoints::Error>(())
+/// ```
+#[derive(Clone, Debug)]
+pub struct Endpoints {
+    /// The URL of the index of the registry the crates are resolved against. `None` for
+    /// crates.io, read from Cargo's local copy of its index.
+    pub index_url: Option<String>,
+    /// The base URL of the registry's web API, for what the index doesn't tell.
+    pub api_url: String,
+    /// The template of the .crate file download URLs, with the markers of the `dl` field of
+    /// config.json. `None` to take the registry's own from its index.
+    pub dl: Option<String>,
+    /// The base URL the mirror's .crate files are served from, written to the `dl` field of
+    /// the mirror's config.json. `None` for the file URL of the mirror directory.
+    pub serve_url: Option<String>,
+}
+
+impl Default for Endpoints {
+    fn default() -> Self {
+        Endpoints::crates_io()
+    }
+}
+
+impl Endpoints {
+    pub fn crates_io() -> Self {
+        Endpoints {
+            index_url: None,
+            api_url: CRATES_IO_API.to_string(),
+            dl: None,
+            serve_url: None,
+        }
+    }
+
+    /// Returns the URL of the registry's index, crates.io's if none was given.
+    pub fn index_url(&self) -> &str {
+        self.index_url.as_deref().unwrap_or(common::CRATES_IO_INDEX)
+    }
+
+    /// Checks that every URL parses with a scheme micrio can use, and that the download
+    /// template only has markers Cargo knows.
+    pub fn validate(&self) -> Result<()> {
+        if let Some(index_url) = &self.index_url {
+            parse_url("index", index_url, &["http", "https", "file", "ssh", "git"])?;
+        }
+        parse_url("API", &self.api_url, &["http", "https"])?;
+        if let Some(dl) = &self.dl {
+            check_markers(dl)?;
+            // Any crate version yields a valid URL if this one does, since download URLs are
+            // built from validated names and percent-encoded versions.
+            let sample = dl
+                .replace("{crate}", "serde")
+                .replace("{version}", "1.0.0")
+                .replace("{prefix}", "se/rd")
+                .replace("{lowerprefix}", "se/rd")
+                .replace("{sha256-checksum}", &"0".repeat(64));
+            let url = Url::parse(&sample).map_err(|e| Error::InvalidUrl {
+                endpoint: "download",
+                url: dl.clone(),
+                error: e,
+            })?;
+            if !["http", "https", "file"].contains(&url.scheme()) {
+                return Err(Error::UnsupportedScheme {
+                    endpoint: "download",
+                    url: dl.clone(),
+                });
+            }
+        }
+        if let Some(serve_url) = &self.serve_url {
+            parse_url("serve", serve_url, &["http", "https", "file"])?;
+        }
+        Ok(())
+    }
+
+    /// Returns the base URL the mirror's .crate files are served from, if one was given.
+    pub fn serve_url(&self) -> Result<Option<Url>> {
+        match &self.serve_url {
+            Some(serve_url) => Ok(Some(parse_url(
+                "serve",
+                serve_url,
+                &["http", "https", "file"],
+            )?)),
+            None => Ok(None),
+        }
+    }
+}
+
+fn parse_url(endpoint: &'static str, url: &str, schemes: &[&str]) -> Result<Url> {
+    let parsed = Url::parse(url).map_err(|e| Error::InvalidUrl {
+        endpoint,
+        url: url.to_string(),
+        error: e,
+    })?;
+    if !schemes.contains(&parsed.scheme()) {
+        return Err(Error::UnsupportedScheme {
+            endpoint,
+            url: url.to_string(),
+        });
+    }
+    Ok(parsed)
+}
+
+/// Fails on the first `{...}` in the template that isn't one of Cargo's markers, since it
+/// would end up in the download URLs as it is.
+fn check_markers(dl: &str) -> Result<()> {
+    let mut rest = dl;
+    while let Some(start) = rest.find('{') {
+        let marker = match rest[start..].find('}') {
+            Some(end) => &rest[start..start + end + 1],
+            None => &rest[start..],
+        };
+        if !DL_MARKERS.contains(&marker) {
+            return Err(Error::UnknownMarker {
+                dl: dl.to_string(),
+                marker: marker.to_string(),
+            });
+        }
+        rest = &rest[start + marker.len()..];
+    }
+    Ok(())
+}
@@ -0,0 +1,827 @@
+use crate::checksums;
+use crate::dst_registry::{self, DstRegistry, STATE_DIR};
+use crate::signing;
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::{self, Display};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Component, Path, PathBuf};
+
+#[derive(Debug)]
+pub enum Error {
+    HashFiles(checksums::Error),
+    ZeroPartSize,
+    NoPreviousExport,
+    ReadExportRecord(Box<dyn std::error::Error + Send + Sync + 'static>),
+    WriteExportRecord(Box<dyn std::error::Error + Send + Sync + 'static>),
+    WriteArchive(io::Error),
+    OpenArchive { path: PathBuf, error: io::Error },
+    ReadManifest(Box<dyn std::error::Error + Send + Sync + 'static>),
+    Unsigned,
+    Signature(signing::Error),
+    ExtractArchive(io::Error),
+    UnexpectedFile(String),
+    MissingFile(String),
+    ChecksumMismatch(String),
+    UnsafePath(String),
+    NotAFile(String),
+    ApplyImport(io::Error),
+    Registry(dst_registry::Error),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::HashFiles(e) => {
+                write!(f, "error exporting mirror: {e}")
+            }
+            Error::ZeroPartSize => {
+                write!(
+                    f,
+                    "error exporting mirror: the maximum part size must be greater than zero"
+                )
+            }
+            Error::NoPreviousExport => {
+                write!(
+                    f,
+                    "error exporting mirror: no previous export of this mirror has been recorded"
+                )
+            }
+            Error::ReadExportRecord(e) => {
+                write!(
+                    f,
+                    "error exporting mirror: failed to read the record of the previous export: {e}"
+                )
+            }
+            Error::WriteExportRecord(e) => {
+                write!(
+                    f,
+                    "error exporting mirror: failed to record the export: {e}"
+                )
+            }
+            Error::WriteArchive(e) => {
+                write!(
+                    f,
+                    "error exporting mirror: failed to write the archive: {e}"
+                )
+            }
+            Error::OpenArchive { path, error } => {
+                write!(
+                    f,
+                    "error importing mirror: failed to open {}: {error}",
+                    path.to_string_lossy()
+                )
+            }
+            Error::ReadManifest(e) => {
+                write!(
+                    f,
+                    "error importing mirror: failed to read the export manifest: {e}"
+                )
+            }
+            Error::Unsigned => {
+                write!(
+                    f,
+                    "error importing mirror: a verifying key was given but the archive is not signed"
+                )
+            }
+            Error::Signature(e) => {
+                write!(f, "error importing mirror: {e}")
+            }
+            Error::ExtractArchive(e) => {
+                write!(
+                    f,
+                    "error importing mirror: failed to extract the archive: {e}"
+                )
+            }
+            Error::UnexpectedFile(path) => {
+                write!(
+                    f,
+                    "error importing mirror: {path} is in the archive but not in the export manifest"
+                )
+            }
+            Error::MissingFile(path) => {
+                write!(
+                    f,
+                    "error importing mirror: {path} is in the export manifest but not in the archive"
+                )
+            }
+            Error::ChecksumMismatch(path) => {
+                write!(
+                    f,
+                    "error importing mirror: the checksum of {path} does not match the export manifest"
+                )
+            }
+            Error::UnsafePath(path) => {
+                write!(
+                    f,
+                    "error importing mirror: the export manifest lists {path}, which isn't a relative path inside the mirror"
+                )
+            }
+            Error::NotAFile(path) => {
+                write!(
+                    f,
+                    "error importing mirror: {path} is not a regular file in the archive"
+                )
+            }
+            Error::ApplyImport(e) => {
+                write!(
+                    f,
+                    "error importing mirror: failed to move the verified files into the mirror: {e}"
+                )
+            }
+            Error::Registry(e) => {
+                write!(f, "error importing mirror: {e}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::HashFiles(e) => Some(e),
+            Error::ZeroPartSize => None,
+            Error::NoPreviousExport => None,
+            Error::ReadExportRecord(e) => Some(e.as_ref()),
+            Error::WriteExportRecord(e) => Some(e.as_ref()),
+            Error::WriteArchive(e) => Some(e),
+            Error::OpenArchive { error, .. } => Some(error),
+            Error::ReadManifest(e) => Some(e.as_ref()),
+            Error::Unsigned => None,
+            Error::Signature(e) => Some(e),
+            Error::ExtractArchive(e) => Some(e),
+            Error::UnexpectedFile(_) => None,
+            Error::MissingFile(_) => None,
+            Error::ChecksumMismatch(_) => None,
+            Error::UnsafePath(_) => None,
+            Error::NotAFile(_) => None,
+            Error::ApplyImport(e) => Some(e),
+            Error::Registry(e) => Some(e),
+        }
+    }
+}
+
+impl From<dst_registry::Error> for Error {
+    fn from(e: dst_registry::Error) -> Self {
+        Error::Registry(e)
+    }
+}
+
+impl From<signing::Error> for Error {
+    fn from(e: signing::Error) -> Self {
+        Error::Signature(e)
+    }
+}
+
+impl From<checksums::Error> for Error {
+    fn from(e: checksums::Error) -> Self {
+        Error::HashFiles(e)
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Name of the manifest stored as the first entry of every export archive.
+const MANIFEST_FILE: &str = "micrio-export.json";
+/// Name of the manifest signature, stored as the second entry of signed export archives.
+const SIGNATURE_FILE: &str = "micrio-export.json.sig";
+/// The magic number zstd frames start with, identifying compressed archives on import.
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+/// Name of the file in the mirror's state directory recording the files included in the last export.
+const EXPORT_RECORD_FILE: &str = "last-export.json";
+
+/// Describes the contents of an export archive.
+#[derive(Serialize, Deserialize)]
+struct ExportManifest {
+    /// Whether the archive only holds the changes since the previous export.
+    delta: bool,
+    /// SHA256 digest of each file in the archive, keyed by its path relative to the mirror directory.
+    files: BTreeMap<String, String>,
+    /// Files removed from the mirror since the previous export.
+    deleted: Vec<String>,
+}
+
+#[derive(Default)]
+pub struct ExportOptions {
+    /// Split the archive into numbered parts (`<archive>.001`, `<archive>.002`, ...)
+    /// no larger than this size.
+    pub max_part_size: Option<u64>,
+    /// Only include the files added or changed since the previous export.
+    pub since_last: bool,
+    /// Sign the export manifest with this key.
+    pub signing_key: Option<SigningKey>,
+    /// Leave file timestamps, owners, and permissions out of the archive, so mirrors built
+    /// reproducibly export to bit-identical archives.
+    pub reproducible: bool,
+    /// Compress the archive with zstd at this level, from 1 (fastest) to 22 (smallest).
+    /// Compressed archives are detected on import.
+    pub zstd_level: Option<i32>,
+}
+
+/// Packs the mirror directory into a tar archive, optionally zstd-compressed, whose first entry
+/// is a manifest holding the checksum of every file in the archive. Split archives are split
+/// after compression.
+///
+/// Returns the paths of the files written.
+pub fn export<P: AsRef<Path>, Q: AsRef<Path>>(
+    mirror_dir_path: P,
+    archive_path: Q,
+    options: &ExportOptions,
+) -> Result<Vec<PathBuf>> {
+    let mirror_dir_path = mirror_dir_path.as_ref();
+    let record_path = mirror_dir_path.join(STATE_DIR).join(EXPORT_RECORD_FILE);
+    if options.max_part_size == Some(0) {
+        return Err(Error::ZeroPartSize);
+    }
+
+    let mut files = BTreeMap::new();
+    for file in checksums::list_mirror_files(mirror_dir_path)? {
+        let digest = checksums::sha256_file(mirror_dir_path.join(&file)).map_err(|e| {
+            checksums::Error::HashFile {
+                path: file.clone(),
+                error: e,
+            }
+        })?;
+        files.insert(file.to_string_lossy().replace('\\', "/"), digest);
+    }
+
+    let manifest = if options.since_last {
+        let previous = read_export_record(&record_path)?;
+        ExportManifest {
+            delta: true,
+            files: files
+                .iter()
+                .filter(|(path, digest)| previous.get(*path) != Some(*digest))
+                .map(|(path, digest)| (path.clone(), digest.clone()))
+                .collect(),
+            deleted: previous
+                .keys()
+                .filter(|path| !files.contains_key(*path))
+                .cloned()
+                .collect(),
+        }
+    } else {
+        ExportManifest {
+            delta: false,
+            files: files.clone(),
+            deleted: Vec::new(),
+        }
+    };
+
+    let mut writer = SplitWriter::new(archive_path.as_ref(), options.max_part_size);
+    match options.zstd_level {
+        Some(level) => {
+            let mut encoder =
+                zstd::Encoder::new(&mut writer, level).map_err(Error::WriteArchive)?;
+            write_archive(&mut encoder, mirror_dir_path, &manifest, options)?;
+            encoder.finish().map_err(Error::WriteArchive)?;
+        }
+        None => write_archive(&mut writer, mirror_dir_path, &manifest, options)?,
+    }
+    writer.flush().map_err(Error::WriteArchive)?;
+
+    write_export_record(&record_path, &files)?;
+    Ok(writer.parts)
+}
+
+/// Writes the tar archive of the manifest, its signature if signing, and the files it lists.
+fn write_archive<W: Write>(
+    writer: W,
+    mirror_dir_path: &Path,
+    manifest: &ExportManifest,
+    options: &ExportOptions,
+) -> Result<()> {
+    let mut builder = tar::Builder::new(writer);
+    builder.follow_symlinks(false);
+    if options.reproducible {
+        builder.mode(tar::HeaderMode::Deterministic);
+    }
+
+    let manifest_json = serde_json::to_vec_pretty(manifest)
+        .map_err(|e| Error::WriteArchive(io::Error::other(e)))?;
+    append_bytes(&mut builder, MANIFEST_FILE, &manifest_json)?;
+    if let Some(signing_key) = &options.signing_key {
+        let signature = signing::sign(signing_key, &manifest_json);
+        append_bytes(&mut builder, SIGNATURE_FILE, signature.as_bytes())?;
+    }
+
+    for path in manifest.files.keys() {
+        builder
+            .append_path_with_name(mirror_dir_path.join(path), path)
+            .map_err(Error::WriteArchive)?;
+    }
+    builder.finish().map_err(Error::WriteArchive)
+}
+
+/// Unpacks an exported archive, reassembling split parts, then points the mirror's index
+/// at its new location.
+///
+/// The archive is extracted into a staging directory next to the mirror and every file is
+/// checked against the export manifest (and the manifest against its signature, if a verifying
+/// key is given) before anything in the mirror is touched.
+/// A full export replaces the contents of the mirror directory.
+/// A delta export is applied on top of the existing mirror.
+pub fn import<P: AsRef<Path>, Q: AsRef<Path>>(
+    archive_path: P,
+    mirror_dir_path: Q,
+    verifying_key: Option<&VerifyingKey>,
+) -> Result<()> {
+    let mirror_dir_path = mirror_dir_path.as_ref();
+    let mut staging_dir_path = mirror_dir_path.as_os_str().to_owned();
+    staging_dir_path.push(".import-staging");
+    let staging_dir_path = PathBuf::from(staging_dir_path);
+    if staging_dir_path.exists() {
+        fs::remove_dir_all(&staging_dir_path).map_err(Error::ExtractArchive)?;
+    }
+    fs::create_dir_all(&staging_dir_path).map_err(Error::ExtractArchive)?;
+
+    let result = stage_import(archive_path.as_ref(), &staging_dir_path, verifying_key)
+        .and_then(|manifest| apply_import(&staging_dir_path, mirror_dir_path, &manifest));
+    // The staging directory only holds leftovers at this point; failing to remove it is harmless.
+    let _ = fs::remove_dir_all(&staging_dir_path);
+    result
+}
+
+/// Extracts the archive into the staging directory, verifying it against its manifest.
+fn stage_import(
+    archive_path: &Path,
+    staging_dir_path: &Path,
+    verifying_key: Option<&VerifyingKey>,
+) -> Result<ExportManifest> {
+    let reader = open_archive(archive_path)?;
+    let mut archive = tar::Archive::new(reader);
+    let mut entries = archive.entries().map_err(Error::ExtractArchive)?;
+
+    let manifest_json = match entries.next() {
+        Some(entry) => {
+            let mut entry = entry.map_err(Error::ExtractArchive)?;
+            let path = entry.path().map_err(Error::ExtractArchive)?;
+            if path != Path::new(MANIFEST_FILE) {
+                return Err(Error::ReadManifest(
+                    "the archive was not created by micrio export".into(),
+                ));
+            }
+            let mut manifest_json = Vec::new();
+            entry
+                .read_to_end(&mut manifest_json)
+                .map_err(|e| Error::ReadManifest(Box::new(e)))?;
+            manifest_json
+        }
+        None => return Err(Error::ReadManifest("the archive is empty".into())),
+    };
+    let manifest: ExportManifest =
+        serde_json::from_slice(&manifest_json).map_err(|e| Error::ReadManifest(Box::new(e)))?;
+    // The paths are joined to the staging and mirror directories, so they mustn't escape them.
+    let unsafe_path = manifest
+        .files
+        .keys()
+        .chain(&manifest.deleted)
+        .find(|path| !is_confined(path));
+    if let Some(path) = unsafe_path {
+        return Err(Error::UnsafePath(path.clone()));
+    }
+
+    let mut signature_verified = false;
+    let mut staged = HashSet::new();
+    for entry in entries {
+        let mut entry = entry.map_err(Error::ExtractArchive)?;
+        let path = entry
+            .path()
+            .map_err(Error::ExtractArchive)?
+            .to_string_lossy()
+            .replace('\\', "/");
+        // Links would be hashed and moved into the mirror as the files they point to.
+        if entry.header().entry_type() != tar::EntryType::Regular {
+            return Err(Error::NotAFile(path));
+        }
+
+        if path == SIGNATURE_FILE {
+            if let Some(verifying_key) = verifying_key {
+                let mut signature = String::new();
+                entry
+                    .read_to_string(&mut signature)
+                    .map_err(Error::ExtractArchive)?;
+                signing::verify(verifying_key, &manifest_json, &signature)?;
+                signature_verified = true;
+            }
+            continue;
+        }
+        if verifying_key.is_some() && !signature_verified {
+            return Err(Error::Unsigned);
+        }
+
+        let expected_digest = manifest
+            .files
+            .get(&path)
+            .ok_or_else(|| Error::UnexpectedFile(path.clone()))?;
+        entry
+            .unpack_in(staging_dir_path)
+            .map_err(Error::ExtractArchive)?;
+        let digest =
+            checksums::sha256_file(staging_dir_path.join(&path)).map_err(Error::ExtractArchive)?;
+        if &digest != expected_digest {
+            return Err(Error::ChecksumMismatch(path));
+        }
+        staged.insert(path);
+    }
+
+    if verifying_key.is_some() && !signature_verified {
+        return Err(Error::Unsigned);
+    }
+    if let Some(missing) = manifest.files.keys().find(|path| !staged.contains(*path)) {
+        return Err(Error::MissingFile(missing.clone()));
+    }
+    Ok(manifest)
+}
+
+/// Moves the verified files from the staging directory into the mirror.
+fn apply_import(
+    staging_dir_path: &Path,
+    mirror_dir_path: &Path,
+    manifest: &ExportManifest,
+) -> Result<()> {
+    let dst_registry = if manifest.delta {
+        DstRegistry::open(mirror_dir_path)?
+    } else {
+        DstRegistry::new(mirror_dir_path)?
+    };
+
+    for path in manifest.files.keys() {
+        let dst_path = dst_registry.path().join(path);
+        fs::create_dir_all(dst_path.parent().unwrap()).map_err(Error::ApplyImport)?;
+        if dst_path.exists() {
+            fs::remove_file(&dst_path).map_err(Error::ApplyImport)?;
+        }
+        fs::rename(staging_dir_path.join(path), &dst_path).map_err(Error::ApplyImport)?;
+    }
+    for path in &manifest.deleted {
+        let path = dst_registry.path().join(path);
+        if path.is_file() {
+            fs::remove_file(path).map_err(Error::ApplyImport)?;
+        }
+    }
+
+    dst_registry.relocate()?;
+    Ok(())
+}
+
+/// Returns whether the path only has normal components, e.g. no `..`, root or prefix, so it
+/// stays inside the directory it is joined to.
+fn is_confined(path: &str) -> bool {
+    let path = Path::new(path);
+    path.components().next().is_some()
+        && path
+            .components()
+            .all(|component| matches!(component, Component::Normal(_)))
+}
+
+fn append_bytes<W: Write>(builder: &mut tar::Builder<W>, path: &str, bytes: &[u8]) -> Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_size(bytes.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder
+        .append_data(&mut header, path, bytes)
+        .map_err(Error::WriteArchive)
+}
+
+fn read_export_record(record_path: &Path) -> Result<BTreeMap<String, String>> {
+    if !record_path.exists() {
+        return Err(Error::NoPreviousExport);
+    }
+    let record = File::open(record_path).map_err(|e| Error::ReadExportRecord(Box::new(e)))?;
+    serde_json::from_reader(BufReader::new(record))
+        .map_err(|e| Error::ReadExportRecord(Box::new(e)))
+}
+
+fn write_export_record(record_path: &Path, files: &BTreeMap<String, String>) -> Result<()> {
+    fs::create_dir_all(record_path.parent().unwrap())
+        .map_err(|e| Error::WriteExportRecord(Box::new(e)))?;
+    let record = File::create(record_path).map_err(|e| Error::WriteExportRecord(Box::new(e)))?;
+    serde_json::to_writer_pretty(BufWriter::new(record), files)
+        .map_err(|e| Error::WriteExportRecord(Box::new(e)))
+}
+
+fn part_path(archive_path: &Path, part_num: usize) -> PathBuf {
+    let mut part_path = archive_path.as_os_str().to_owned();
+    part_path.push(format!(".{part_num:03}"));
+    PathBuf::from(part_path)
+}
+
+/// Opens the archive for reading, decompressing it if zstd-compressed.
+fn open_archive(archive_path: &Path) -> Result<Box<dyn Read>> {
+    let mut reader = BufReader::new(open_archive_parts(archive_path)?);
+    let is_zstd = reader
+        .fill_buf()
+        .map_err(Error::ExtractArchive)?
+        .starts_with(&ZSTD_MAGIC);
+    if is_zstd {
+        let decoder = zstd::Decoder::with_buffer(reader).map_err(Error::ExtractArchive)?;
+        return Ok(Box::new(decoder));
+    }
+    Ok(Box::new(reader))
+}
+
+/// Opens the archive file for reading. Either the archive itself or its first part may be
+/// specified; split archives are read back as a single stream.
+fn open_archive_parts(archive_path: &Path) -> Result<Box<dyn Read>> {
+    let open = |path: &Path| {
+        File::open(path).map_err(|e| Error::OpenArchive {
+            path: path.to_path_buf(),
+            error: e,
+        })
+    };
+
+    let base_path = match archive_path.extension() {
+        Some(ext) if ext == "001" => archive_path.with_extension(""),
+        _ => archive_path.to_path_buf(),
+    };
+    if base_path.is_file() {
+        return Ok(Box::new(BufReader::new(open(&base_path)?)));
+    }
+
+    let mut reader: Box<dyn Read> = Box::new(BufReader::new(open(&part_path(&base_path, 1))?));
+    let mut part_num = 2;
+    loop {
+        let path = part_path(&base_path, part_num);
+        if !path.is_file() {
+            break;
+        }
+        reader = Box::new(reader.chain(BufReader::new(open(&path)?)));
+        part_num += 1;
+    }
+    Ok(reader)
+}
+
+/// Writes to a single file, or to a sequence of numbered part files when a
+/// maximum part size is set.
+struct SplitWriter {
+    archive_path: PathBuf,
+    max_part_size: Option<u64>,
+    cur_part: Option<BufWriter<File>>,
+    cur_part_size: u64,
+    parts: Vec<PathBuf>,
+}
+
+impl SplitWriter {
+    fn new(archive_path: &Path, max_part_size: Option<u64>) -> Self {
+        SplitWriter {
+            archive_path: archive_path.to_path_buf(),
+            max_part_size,
+            cur_part: None,
+            cur_part_size: 0,
+            parts: Vec::new(),
+        }
+    }
+
+    fn start_next_part(&mut self) -> io::Result<()> {
+        if let Some(mut part) = self.cur_part.take() {
+            part.flush()?;
+        }
+        let path = match self.max_part_size {
+            Some(_) => part_path(&self.archive_path, self.parts.len() + 1),
+            None => self.archive_path.clone(),
+        };
+        self.cur_part = Some(BufWriter::new(File::create(&path)?));
+        self.cur_part_size = 0;
+        self.parts.push(path);
+        Ok(())
+    }
+}
+
+impl Write for SplitWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let part_is_full = match self.max_part_size {
+            Some(max_part_size) => self.cur_part_size >= max_part_size,
+            None => false,
+        };
+        if self.cur_part.is_none() || part_is_full {
+            self.start_next_part()?;
+        }
+
+        let len = match self.max_part_size {
+            Some(max_part_size) => {
+                (max_part_size - self.cur_part_size).min(buf.len() as u64) as usize
+            }
+            None => buf.len(),
+        };
+        let written = self.cur_part.as_mut().unwrap().write(&buf[..len])?;
+        self.cur_part_size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.cur_part.as_mut() {
+            Some(part) => part.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir_path =
+            std::env::temp_dir().join(format!("micrio-test-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir_path);
+        fs::create_dir_all(&dir_path).unwrap();
+        dir_path
+    }
+
+    /// Creates a mirror directory with an index file and a crate file to export.
+    fn test_mirror(dir_path: &Path) -> PathBuf {
+        let mirror_dir_path = dir_path.join("mirror");
+        fs::create_dir_all(mirror_dir_path.join("index/3/s")).unwrap();
+        fs::create_dir_all(mirror_dir_path.join("crates/syn")).unwrap();
+        fs::write(mirror_dir_path.join("index/3/s/syn"), "{}\n").unwrap();
+        fs::write(
+            mirror_dir_path.join("crates/syn/syn-1.0.0.crate"),
+            vec![7; 10_000],
+        )
+        .unwrap();
+        mirror_dir_path
+    }
+
+    /// Writes an archive of the entries, as export does, whatever they hold.
+    fn write_test_archive(archive_path: &Path, entries: &[(&str, &[u8])]) {
+        let mut builder = tar::Builder::new(File::create(archive_path).unwrap());
+        for (path, bytes) in entries {
+            append_bytes(&mut builder, path, bytes).unwrap();
+        }
+        builder.finish().unwrap();
+    }
+
+    fn manifest_json(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let manifest = ExportManifest {
+            delta: false,
+            files: files
+                .iter()
+                .map(|(path, bytes)| (path.to_string(), checksums::sha256(bytes)))
+                .collect(),
+            deleted: Vec::new(),
+        };
+        serde_json::to_vec_pretty(&manifest).unwrap()
+    }
+
+    fn stage(archive_path: &Path, verifying_key: Option<&VerifyingKey>) -> Result<ExportManifest> {
+        let staging_dir_path = archive_path.with_extension("staging");
+        let _ = fs::remove_dir_all(&staging_dir_path);
+        fs::create_dir_all(&staging_dir_path).unwrap();
+        stage_import(archive_path, &staging_dir_path, verifying_key)
+    }
+
+    #[test]
+    fn signed_exports_verify_with_the_key() {
+        let dir_path = test_dir("export-signed");
+        let mirror_dir_path = test_mirror(&dir_path);
+        let archive_path = dir_path.join("mirror.tar");
+        let signing_key = SigningKey::generate(&mut rand_core::OsRng);
+        let options = ExportOptions {
+            signing_key: Some(signing_key.clone()),
+            ..Default::default()
+        };
+        export(&mirror_dir_path, &archive_path, &options).unwrap();
+
+        let manifest = stage(&archive_path, Some(&signing_key.verifying_key())).unwrap();
+        assert_eq!(manifest.files.len(), 2);
+        let other_key = SigningKey::generate(&mut rand_core::OsRng).verifying_key();
+        assert!(matches!(
+            stage(&archive_path, Some(&other_key)),
+            Err(Error::Signature(_))
+        ));
+        fs::remove_dir_all(&dir_path).unwrap();
+    }
+
+    #[test]
+    fn unsigned_exports_are_refused_with_a_key() {
+        let dir_path = test_dir("export-unsigned");
+        let mirror_dir_path = test_mirror(&dir_path);
+        let archive_path = dir_path.join("mirror.tar");
+        export(&mirror_dir_path, &archive_path, &ExportOptions::default()).unwrap();
+
+        assert!(stage(&archive_path, None).is_ok());
+        let verifying_key = SigningKey::generate(&mut rand_core::OsRng).verifying_key();
+        assert!(matches!(
+            stage(&archive_path, Some(&verifying_key)),
+            Err(Error::Unsigned)
+        ));
+        fs::remove_dir_all(&dir_path).unwrap();
+    }
+
+    #[test]
+    fn tampered_manifests_are_refused() {
+        let dir_path = test_dir("export-tampered");
+        let archive_path = dir_path.join("mirror.tar");
+        let signing_key = SigningKey::generate(&mut rand_core::OsRng);
+        let signed = manifest_json(&[("index/3/s/syn", b"{}\n")]);
+        let signature = signing::sign(&signing_key, &signed);
+        let tampered = manifest_json(&[("index/3/s/syn", b"{\"evil\":1}\n")]);
+        write_test_archive(
+            &archive_path,
+            &[
+                (MANIFEST_FILE, &tampered),
+                (SIGNATURE_FILE, signature.as_bytes()),
+                ("index/3/s/syn", b"{\"evil\":1}\n"),
+            ],
+        );
+
+        assert!(matches!(
+            stage(&archive_path, Some(&signing_key.verifying_key())),
+            Err(Error::Signature(signing::Error::InvalidSignature(_)))
+        ));
+        fs::remove_dir_all(&dir_path).unwrap();
+    }
+
+    #[test]
+    fn changed_files_are_refused() {
+        let dir_path = test_dir("export-changed");
+        let archive_path = dir_path.join("mirror.tar");
+        let manifest = manifest_json(&[("index/3/s/syn", b"{}\n")]);
+        write_test_archive(
+            &archive_path,
+            &[
+                (MANIFEST_FILE, &manifest),
+                ("index/3/s/syn", b"{\"evil\":1}\n"),
+            ],
+        );
+
+        assert!(
+            matches!(stage(&archive_path, None), Err(Error::ChecksumMismatch(path)) if path == "index/3/s/syn")
+        );
+        fs::remove_dir_all(&dir_path).unwrap();
+    }
+
+    #[test]
+    fn links_are_refused() {
+        let dir_path = test_dir("export-links");
+        let archive_path = dir_path.join("mirror.tar");
+        let manifest = manifest_json(&[("index/3/s/syn", b"{}\n")]);
+        for entry_type in [tar::EntryType::Symlink, tar::EntryType::Link] {
+            let mut builder = tar::Builder::new(File::create(&archive_path).unwrap());
+            append_bytes(&mut builder, MANIFEST_FILE, &manifest).unwrap();
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(entry_type);
+            header.set_size(0);
+            header.set_mode(0o644);
+            builder
+                .append_link(&mut header, "index/3/s/syn", "/etc/passwd")
+                .unwrap();
+            builder.finish().unwrap();
+            drop(builder);
+
+            assert!(
+                matches!(stage(&archive_path, None), Err(Error::NotAFile(path)) if path == "index/3/s/syn")
+            );
+            assert!(fs::symlink_metadata(
+                archive_path.with_extension("staging").join("index/3/s/syn")
+            )
+            .is_err());
+        }
+        fs::remove_dir_all(&dir_path).unwrap();
+    }
+
+    #[test]
+    fn split_exports_are_reassembled() {
+        let dir_path = test_dir("export-split");
+        let mirror_dir_path = test_mirror(&dir_path);
+        let archive_path = dir_path.join("mirror.tar");
+        let options = ExportOptions {
+            max_part_size: Some(4096),
+            ..Default::default()
+        };
+        let parts = export(&mirror_dir_path, &archive_path, &options).unwrap();
+        assert!(parts.len() > 1);
+        for (i, part) in parts.iter().enumerate() {
+            assert_eq!(part, &part_path(&archive_path, i + 1));
+            assert!(fs::metadata(part).unwrap().len() <= 4096);
+        }
+        assert!(!archive_path.exists());
+
+        let manifest = stage(&parts[0], None).unwrap();
+        assert_eq!(manifest.files.len(), 2);
+        let staged_path = parts[0]
+            .with_extension("staging")
+            .join("crates/syn/syn-1.0.0.crate");
+        assert_eq!(fs::read(staged_path).unwrap(), vec![7; 10_000]);
+        fs::remove_dir_all(&dir_path).unwrap();
+    }
+
+    #[test]
+    fn zero_part_sizes_are_refused() {
+        let dir_path = test_dir("export-zero-parts");
+        let mirror_dir_path = test_mirror(&dir_path);
+        let options = ExportOptions {
+            max_part_size: Some(0),
+            ..Default::default()
+        };
+        assert!(matches!(
+            export(&mirror_dir_path, dir_path.join("mirror.tar"), &options),
+            Err(Error::ZeroPartSize)
+        ));
+        fs::remove_dir_all(&dir_path).unwrap();
+    }
+}
@@ -0,0 +1,165 @@
+//! Searches the sources of the mirrored crates, straight from their .crate files, e.g. to find
+//! which crate defines a symbol without unpacking the mirror.
+
+use crate::common::Version;
+use crate::dst_registry::{self, DstRegistry};
+use crate::selection::CrateSpec;
+use crate::state_db::{self, StateDb};
+use flate2::read::GzDecoder;
+use regex::Regex;
+use std::fmt::{self, Display};
+use std::io::{self, Read};
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum Error {
+    OpenMirror(dst_registry::Error),
+    ReadIndex(dst_registry::Error),
+    ReadStateDb(state_db::Error),
+    ReadCrateFile {
+        crate_name: String,
+        crate_version: String,
+        error: io::Error,
+    },
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::OpenMirror(e) => write!(f, "{e}"),
+            Error::ReadIndex(e) => write!(f, "{e}"),
+            Error::ReadStateDb(e) => write!(f, "{e}"),
+            Error::ReadCrateFile {
+                crate_name,
+                crate_version,
+                error,
+            } => {
+                write!(
+                    f,
+                    "failed to read the .crate file for {crate_name} version {crate_version}: {error}"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::OpenMirror(e) => Some(e),
+            Error::ReadIndex(e) => Some(e),
+            Error::ReadStateDb(e) => Some(e),
+            Error::ReadCrateFile { error, .. } => Some(error),
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// How many bytes at the start of a file are checked for a NUL byte to tell it's binary.
+const BINARY_CHECK_LEN: usize = 8 * 1024;
+
+/// What to search for, and in which crates.
+pub struct GrepOptions {
+    pub pattern: Regex,
+    /// Only search the crate versions matching one of the specs, or all if there are none.
+    pub crates: Vec<CrateSpec>,
+    /// Match the pattern against the paths of the files instead of their lines.
+    pub paths_only: bool,
+}
+
+/// A line, or a file path, matching the pattern.
+pub struct GrepMatch<'a> {
+    pub crat: &'a Version,
+    /// The path of the file in the package, without the `{name}-{version}` directory.
+    pub path: &'a str,
+    /// The number of the matching line, from 1, or `None` when matching paths.
+    pub line_number: Option<usize>,
+    pub line: Option<&'a str>,
+}
+
+/// Searches the files of every crate version in the mirror selected by the options, in name
+/// and version order, calling `on_match` with each match as it's found. Binary files are
+/// skipped. The crate versions are read from the state database of the mirror, if it has one.
+/// Returns the number of matches.
+pub fn grep<P: AsRef<Path>, F: FnMut(&GrepMatch)>(
+    mirror_dir_path: P,
+    options: &GrepOptions,
+    mut on_match: F,
+) -> Result<usize> {
+    let mirror_dir_path = mirror_dir_path.as_ref();
+    let dst_registry = DstRegistry::open(mirror_dir_path).map_err(Error::OpenMirror)?;
+    let crates = match StateDb::open_existing(mirror_dir_path).map_err(Error::ReadStateDb)? {
+        Some(state_db) => state_db.crates().map_err(Error::ReadStateDb)?,
+        None => dst_registry.read_index().map_err(Error::ReadIndex)?,
+    };
+    let mut crates = Vec::from_iter(crates.into_iter().filter(|crat| {
+        options.crates.is_empty() || options.crates.iter().any(|spec| spec.matches(crat))
+    }));
+    crates.sort_by(|a, b| (a.name(), a.version()).cmp(&(b.name(), b.version())));
+
+    let mut num_matches = 0;
+    for crat in &crates {
+        let read_error = |e| Error::ReadCrateFile {
+            crate_name: crat.name().to_string(),
+            crate_version: crat.version().to_string(),
+            error: e,
+        };
+        let crate_file = dst_registry
+            .read_crate_file(crat.name(), crat.version())
+            .map_err(read_error)?;
+        num_matches += grep_crate(crat, &crate_file, options, &mut on_match).map_err(read_error)?;
+    }
+    Ok(num_matches)
+}
+
+fn grep_crate<F: FnMut(&GrepMatch)>(
+    crat: &Version,
+    crate_file: &[u8],
+    options: &GrepOptions,
+    on_match: &mut F,
+) -> io::Result<usize> {
+    let root = format!("{}-{}", crat.name(), crat.version());
+    let mut num_matches = 0;
+    let mut archive = tar::Archive::new(GzDecoder::new(crate_file));
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let path = entry.path()?.into_owned();
+        let path = path.strip_prefix(&root).unwrap_or(&path);
+        let path = path.to_string_lossy().replace('\\', "/");
+        if options.paths_only {
+            if options.pattern.is_match(&path) {
+                num_matches += 1;
+                on_match(&GrepMatch {
+                    crat,
+                    path: &path,
+                    line_number: None,
+                    line: None,
+                });
+            }
+            continue;
+        }
+
+        let mut contents = Vec::new();
+        entry.read_to_end(&mut contents)?;
+        if contents[..contents.len().min(BINARY_CHECK_LEN)].contains(&0) {
+            continue;
+        }
+        let contents = String::from_utf8_lossy(&contents);
+        for (i, line) in contents.lines().enumerate() {
+            if options.pattern.is_match(line) {
+                num_matches += 1;
+                on_match(&GrepMatch {
+                    crat,
+                    path: &path,
+                    line_number: Some(i + 1),
+                    line: Some(line),
+                });
+            }
+        }
+    }
+    Ok(num_matches)
+}
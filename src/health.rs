@@ -0,0 +1,224 @@
+//! The health endpoint of `MirrorScheduler`, telling load balancers and monitoring whether the
+//! mirror it maintains is safe to serve from.
+
+use crate::cancel::CancellationToken;
+use crate::dst_registry::{DstRegistry, INDEX_DIR};
+use crate::top_level;
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::debug;
+
+/// The path the health report is served at.
+pub const HEALTH_PATH: &str = "/healthz";
+
+/// The maximum number of crate versions missing a .crate file listed in the report.
+const MAX_MISSING_LISTED: usize = 100;
+
+/// What the scheduler's refresh job is doing.
+#[derive(Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SyncStatus {
+    /// No refresh ran yet, or the last one succeeded.
+    Idle,
+    /// A refresh is rebuilding the mirror.
+    Syncing,
+    /// The last refresh failed.
+    Failed,
+}
+
+/// Whether every crate version in the mirror's index has its .crate file in the registry.
+#[derive(Clone, Serialize)]
+pub struct Consistency {
+    pub num_index_entries: usize,
+    pub num_missing: usize,
+    /// The crate versions without a .crate file, as `NAME@VERSION`, up to 100 of them.
+    pub missing: Vec<String>,
+    /// Why the index or registry couldn't be read, if it couldn't.
+    pub error: Option<String>,
+}
+
+impl Consistency {
+    pub fn is_consistent(&self) -> bool {
+        self.error.is_none() && self.num_missing == 0
+    }
+}
+
+/// The health report served at `/healthz`, as JSON.
+#[derive(Clone, Serialize)]
+pub struct Health {
+    /// Whether the mirror is safe to serve from: no refresh is rebuilding it, and its index and
+    /// registry are consistent. Served with status 200 if so, 503 otherwise.
+    pub healthy: bool,
+    pub status: SyncStatus,
+    /// When the last successful refresh finished, in seconds since the Unix epoch.
+    pub last_successful_sync: Option<u64>,
+    /// Why the last refresh failed, if it did.
+    pub last_error: Option<String>,
+    /// `None` until the mirror has been checked.
+    pub consistency: Option<Consistency>,
+}
+
+/// Checks that every crate version in the mirror's index has its .crate file in the registry.
+pub fn check_consistency<P: AsRef<Path>>(mirror_dir_path: P) -> Consistency {
+    let mirror_dir_path = mirror_dir_path.as_ref();
+    let mut consistency = Consistency {
+        num_index_entries: 0,
+        num_missing: 0,
+        missing: Vec::new(),
+        error: None,
+    };
+    let entries = match top_level::read_index_entries(&mirror_dir_path.join(INDEX_DIR)) {
+        Ok(entries) => entries,
+        Err(e) => {
+            consistency.error = Some(format!("failed to read the index: {e}"));
+            return consistency;
+        }
+    };
+    let dst_registry = match DstRegistry::open(mirror_dir_path) {
+        Ok(dst_registry) => dst_registry,
+        Err(e) => {
+            consistency.error = Some(e.to_string());
+            return consistency;
+        }
+    };
+    consistency.num_index_entries = entries.len();
+    for entry in &entries {
+        let link = dst_registry.crate_file_link(&entry.name, &entry.vers);
+        if !mirror_dir_path.join(link).is_file() {
+            consistency.num_missing += 1;
+            if consistency.missing.len() < MAX_MISSING_LISTED {
+                consistency
+                    .missing
+                    .push(format!("{}@{}", entry.name, entry.vers));
+            }
+        }
+    }
+    consistency
+}
+
+/// Tracks the health of the mirror as the scheduler's jobs run.
+pub(crate) struct HealthMonitor {
+    mirror_dir_path: PathBuf,
+    health: Mutex<Health>,
+}
+
+impl HealthMonitor {
+    pub fn new(mirror_dir_path: PathBuf) -> Self {
+        HealthMonitor {
+            mirror_dir_path,
+            health: Mutex::new(Health {
+                healthy: false,
+                status: SyncStatus::Idle,
+                last_successful_sync: None,
+                last_error: None,
+                consistency: None,
+            }),
+        }
+    }
+
+    fn health(&self) -> MutexGuard<'_, Health> {
+        // The report is replaced field by field, so it's still usable if a thread panicked.
+        self.health.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn sync_started(&self) {
+        let mut health = self.health();
+        health.status = SyncStatus::Syncing;
+        health.healthy = false;
+    }
+
+    pub fn sync_finished(&self, error: Option<String>) {
+        let mut health = self.health();
+        match error {
+            Some(error) => {
+                health.status = SyncStatus::Failed;
+                health.last_error = Some(error);
+            }
+            None => {
+                health.status = SyncStatus::Idle;
+                health.last_error = None;
+                health.last_successful_sync = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .ok();
+            }
+        }
+    }
+
+    /// Checks the consistency of the mirror, which reads the whole index, so it's done after
+    /// each job rather than on each request.
+    pub fn check(&self) {
+        let consistency = check_consistency(&self.mirror_dir_path);
+        let mut health = self.health();
+        health.healthy = health.status != SyncStatus::Syncing && consistency.is_consistent();
+        health.consistency = Some(consistency);
+    }
+
+    /// Answers the requests to the listener until the cancellation token is cancelled.
+    pub async fn serve(self: Arc<Self>, listener: TcpListener, cancel: CancellationToken) {
+        const POLL_INTERVAL: Duration = Duration::from_millis(500);
+        while !cancel.is_cancelled() {
+            let stream = match tokio::time::timeout(POLL_INTERVAL, listener.accept()).await {
+                Ok(Ok((stream, _))) => stream,
+                Ok(Err(e)) => {
+                    debug!("failed to accept a health check connection: {e}");
+                    continue;
+                }
+                // Timed out, to check the cancellation token.
+                Err(_) => continue,
+            };
+            let monitor = Arc::clone(&self);
+            tokio::spawn(async move {
+                if let Err(e) = monitor.respond(stream).await {
+                    debug!("failed to answer a health check: {e}");
+                }
+            });
+        }
+    }
+
+    /// Answers a single HTTP request with the health report, closing the connection after.
+    async fn respond(&self, mut stream: TcpStream) -> std::io::Result<()> {
+        const MAX_REQUEST_SIZE: usize = 8 * 1024;
+        let mut request = Vec::new();
+        let mut buf = [0; 1024];
+        while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < MAX_REQUEST_SIZE {
+            let n = stream.read(&mut buf).await?;
+            if n == 0 {
+                break;
+            }
+            request.extend_from_slice(&buf[..n]);
+        }
+        let request = String::from_utf8_lossy(&request);
+        let mut request_line = request.lines().next().unwrap_or("").split_whitespace();
+        let (method, target) = (request_line.next(), request_line.next());
+        let path = target.map(|t| t.split('?').next().unwrap_or(t));
+
+        let (status, body) = match (method, path) {
+            (Some("GET" | "HEAD"), Some(HEALTH_PATH)) => {
+                let health = self.health().clone();
+                let status = if health.healthy {
+                    "200 OK"
+                } else {
+                    "503 Service Unavailable"
+                };
+                let body = serde_json::to_string(&health).map_err(std::io::Error::from)?;
+                (status, body)
+            }
+            (Some("GET" | "HEAD"), _) => ("404 Not Found", String::new()),
+            _ => ("405 Method Not Allowed", String::new()),
+        };
+        let mut response = format!(
+            "HTTP/1.1 {status}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            body.len()
+        );
+        if method != Some("HEAD") {
+            response.push_str(&body);
+        }
+        stream.write_all(response.as_bytes()).await?;
+        stream.shutdown().await
+    }
+}
@@ -0,0 +1,202 @@
+use crate::common::Version;
+use std::fmt::{self, Display};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::{Command, ExitStatus, Output};
+
+#[derive(Debug)]
+pub enum Error {
+    Rejected {
+        crate_name: String,
+        crate_version: String,
+        reason: String,
+    },
+    WriteCrateFile(io::Error),
+    RunCommand {
+        command: String,
+        error: io::Error,
+    },
+    CommandFailed {
+        command: String,
+        status: ExitStatus,
+    },
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Rejected {
+                crate_name,
+                crate_version,
+                reason,
+            } => {
+                write!(
+                    f,
+                    "{crate_name} version {crate_version} was rejected by a hook: {reason}"
+                )
+            }
+            Error::WriteCrateFile(e) => {
+                write!(f, "failed to write the .crate file for the hook: {e}")
+            }
+            Error::RunCommand { command, error } => {
+                write!(f, "failed to run the hook command `{command}`: {error}")
+            }
+            Error::CommandFailed { command, status } => {
+                write!(f, "the hook command `{command}` failed: {status}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Rejected { .. } => None,
+            Error::WriteCrateFile(e) => Some(e),
+            Error::RunCommand { error, .. } => Some(error),
+            Error::CommandFailed { .. } => None,
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// Callbacks invoked around each crate download and at the end of a mirror run.
+///
+/// Every method has a default implementation that does nothing, so hooks only implement
+/// the points they care about. A download hook returning an error keeps the crate version
+/// out of the mirror; the error is reported as a download failure for that crate.
+pub trait Hook: Send + Sync {
+    /// Called before the .crate file is downloaded.
+    fn before_download(&self, _crat: &Version) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called once the .crate file is downloaded, before it is stored in the mirror,
+    /// e.g. to scan it for malware.
+    fn after_download(&self, _crat: &Version, _crate_file: &[u8]) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called when the run ends, whether or not it succeeded.
+    fn run_finished(&self, _mirror_dir_path: &Path, _succeeded: bool) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Runs external commands as hooks, through `sh -c`, or `cmd /C` on Windows.
+///
+/// The commands are given the details in environment variables:
+/// - `MICRIO_CRATE_NAME` and `MICRIO_CRATE_VERSION` for the download hooks.
+/// - `MICRIO_CRATE_FILE`, the path of a temporary copy of the .crate file,
+///   for the post-download hook.
+/// - `MICRIO_MIRROR_DIR` and `MICRIO_RUN_STATUS`, `succeeded` or `failed`, for the run-end hook.
+///
+/// A download hook exiting with a non-zero status rejects the crate version.
+#[derive(Clone, Debug, Default)]
+pub struct CommandHooks {
+    pub pre_download: Option<String>,
+    pub post_download: Option<String>,
+    pub run_end: Option<String>,
+}
+
+impl Hook for CommandHooks {
+    fn before_download(&self, crat: &Version) -> Result<()> {
+        let command = match &self.pre_download {
+            Some(command) => command,
+            None => return Ok(()),
+        };
+        let output = run(command, &crate_env(crat))?;
+        check_admitted(crat, command, &output)
+    }
+
+    fn after_download(&self, crat: &Version, crate_file: &[u8]) -> Result<()> {
+        let command = match &self.post_download {
+            Some(command) => command,
+            None => return Ok(()),
+        };
+        let crate_file_path = std::env::temp_dir().join(format!(
+            "micrio-{}-{}-{}.crate",
+            std::process::id(),
+            crat.name(),
+            crat.version()
+        ));
+        fs::write(&crate_file_path, crate_file).map_err(Error::WriteCrateFile)?;
+        let mut env = crate_env(crat);
+        env.push((
+            "MICRIO_CRATE_FILE",
+            crate_file_path.to_string_lossy().to_string(),
+        ));
+        let output = run(command, &env);
+        // Best effort: a leftover file in the temp directory is harmless.
+        let _ = fs::remove_file(&crate_file_path);
+        check_admitted(crat, command, &output?)
+    }
+
+    fn run_finished(&self, mirror_dir_path: &Path, succeeded: bool) -> Result<()> {
+        let command = match &self.run_end {
+            Some(command) => command,
+            None => return Ok(()),
+        };
+        let status = if succeeded { "succeeded" } else { "failed" };
+        let env = [
+            (
+                "MICRIO_MIRROR_DIR",
+                mirror_dir_path.to_string_lossy().to_string(),
+            ),
+            ("MICRIO_RUN_STATUS", status.to_string()),
+        ];
+        let output = run(command, &env)?;
+        if !output.status.success() {
+            return Err(Error::CommandFailed {
+                command: command.clone(),
+                status: output.status,
+            });
+        }
+        Ok(())
+    }
+}
+
+fn crate_env(crat: &Version) -> Vec<(&'static str, String)> {
+    vec![
+        ("MICRIO_CRATE_NAME", crat.name().to_string()),
+        ("MICRIO_CRATE_VERSION", crat.version().to_string()),
+    ]
+}
+
+/// Runs the command through the shell, capturing its output so it doesn't mix with progress.
+fn run(command: &str, env: &[(&str, String)]) -> Result<Output> {
+    let mut cmd = if cfg!(windows) {
+        let mut cmd = Command::new("cmd");
+        cmd.arg("/C");
+        cmd
+    } else {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c");
+        cmd
+    };
+    cmd.arg(command)
+        .envs(env.iter().map(|(k, v)| (k, v)))
+        .output()
+        .map_err(|e| Error::RunCommand {
+            command: command.to_string(),
+            error: e,
+        })
+}
+
+fn check_admitted(crat: &Version, command: &str, output: &Output) -> Result<()> {
+    if output.status.success() {
+        return Ok(());
+    }
+    let mut reason = format!("`{command}` exited with {}", output.status);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !stderr.trim().is_empty() {
+        reason += &format!(": {}", stderr.trim());
+    }
+    Err(Error::Rejected {
+        crate_name: crat.name().to_string(),
+        crate_version: crat.version().to_string(),
+        reason,
+    })
+}
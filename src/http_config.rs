@@ -0,0 +1,223 @@
+//! Reads the `[http]` settings of Cargo's configuration, so the HTTP requests of a mirror run go
+//! through the same proxy, trust the same certificates, and time out like `cargo fetch` does.
+
+use crate::credentials::{self, cargo_home};
+use std::env;
+use std::fmt::{self, Display};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+#[derive(Debug)]
+pub enum Error {
+    ReadConfig(credentials::Error),
+    InvalidSetting {
+        key: &'static str,
+        origin: String,
+    },
+    ReadCainfo {
+        path: PathBuf,
+        error: io::Error,
+    },
+    InvalidCainfo {
+        path: PathBuf,
+        error: reqwest::Error,
+    },
+    InvalidProxy {
+        proxy: String,
+        error: reqwest::Error,
+    },
+    CreateClient(reqwest::Error),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::ReadConfig(e) => write!(f, "error reading Cargo's HTTP settings: {e}"),
+            Error::InvalidSetting { key, origin } => {
+                write!(
+                    f,
+                    "error reading Cargo's HTTP settings: invalid http.{key} in {origin}"
+                )
+            }
+            Error::ReadCainfo { path, error } => {
+                write!(
+                    f,
+                    "error reading Cargo's HTTP settings: failed to read the http.cainfo file {}: {error}",
+                    path.to_string_lossy()
+                )
+            }
+            Error::InvalidCainfo { path, error } => {
+                write!(
+                    f,
+                    "error reading Cargo's HTTP settings: invalid certificate in the http.cainfo file {}: {error}",
+                    path.to_string_lossy()
+                )
+            }
+            Error::InvalidProxy { proxy, error } => {
+                write!(
+                    f,
+                    "error reading Cargo's HTTP settings: invalid http.proxy {proxy}: {error}"
+                )
+            }
+            Error::CreateClient(e) => write!(f, "failed to create the HTTP client: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::ReadConfig(e) => Some(e),
+            Error::InvalidSetting { .. } => None,
+            Error::ReadCainfo { error, .. } => Some(error),
+            Error::InvalidCainfo { error, .. } => Some(error),
+            Error::InvalidProxy { error, .. } => Some(error),
+            Error::CreateClient(e) => Some(e),
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// The names of Cargo's config file in a `.cargo` directory, highest precedence first.
+const CONFIG_FILES: [&str; 2] = ["config.toml", "config"];
+
+/// The HTTP settings applied to the clients of a mirror run. The default leaves reqwest's own
+/// defaults, which already honor the `HTTP_PROXY`, `HTTPS_PROXY` and `ALL_PROXY` environment
+/// variables.
+#[derive(Clone, Default)]
+pub struct HttpConfig {
+    proxy: Option<reqwest::Proxy>,
+    /// Whether `http.proxy` is set to an empty string, turning the proxy off.
+    no_proxy: bool,
+    cainfo: Option<reqwest::Certificate>,
+    timeout: Option<Duration>,
+}
+
+impl HttpConfig {
+    /// Reads `http.proxy`, `http.cainfo` and `http.timeout` the way Cargo does when run in the
+    /// current directory: from the `CARGO_HTTP_PROXY`, `CARGO_HTTP_CAINFO` and
+    /// `CARGO_HTTP_TIMEOUT` environment variables, else from the `.cargo/config.toml` files of
+    /// the current directory and its ancestors, the nearest first, else from the one in Cargo's
+    /// home directory. Without `http.proxy`, the standard proxy environment variables apply.
+    pub fn from_cargo_config() -> Result<Self> {
+        let mut files = Vec::new();
+        let mut dir_paths = Vec::new();
+        if let Ok(current_dir) = env::current_dir() {
+            dir_paths.extend(current_dir.ancestors().map(|p| p.join(".cargo")));
+        }
+        if let Some(cargo_home) = cargo_home() {
+            if !dir_paths.contains(&cargo_home) {
+                dir_paths.push(cargo_home);
+            }
+        }
+        for dir_path in dir_paths {
+            for file_name in CONFIG_FILES {
+                let path = dir_path.join(file_name);
+                if let Some(file) =
+                    credentials::read_cargo_file(&path).map_err(Error::ReadConfig)?
+                {
+                    files.push((path, file));
+                    break;
+                }
+            }
+        }
+
+        let mut config = HttpConfig::default();
+        if let Some((proxy, _)) = setting(&files, "proxy", "CARGO_HTTP_PROXY")? {
+            // Like Cargo, an empty proxy turns the proxy off, the environment's included.
+            if proxy.is_empty() {
+                config.no_proxy = true;
+            } else {
+                let proxy_url = if proxy.contains("://") {
+                    proxy.clone()
+                } else {
+                    format!("http://{proxy}")
+                };
+                let proxy = reqwest::Proxy::all(proxy_url)
+                    .map_err(|e| Error::InvalidProxy { proxy, error: e })?;
+                config.proxy = Some(proxy);
+            }
+        }
+        if let Some((cainfo, base_dir_path)) = setting(&files, "cainfo", "CARGO_HTTP_CAINFO")? {
+            let path = base_dir_path.join(cainfo);
+            let contents = fs::read(&path).map_err(|e| Error::ReadCainfo {
+                path: path.clone(),
+                error: e,
+            })?;
+            let cert = reqwest::Certificate::from_pem(&contents)
+                .map_err(|e| Error::InvalidCainfo { path, error: e })?;
+            config.cainfo = Some(cert);
+        }
+        if let Some((timeout, _)) = setting(&files, "timeout", "CARGO_HTTP_TIMEOUT")? {
+            let seconds = timeout.parse().map_err(|_| Error::InvalidSetting {
+                key: "timeout",
+                origin: format!("the value {timeout}"),
+            })?;
+            config.timeout = Some(Duration::from_secs(seconds));
+        }
+        Ok(config)
+    }
+
+    /// Returns a client builder with the settings applied, identifying as micrio, since
+    /// crates.io rejects requests without a user agent. As with Cargo, the timeout is how long
+    /// connecting may take, so large downloads over slow links aren't cut short.
+    pub fn client_builder(&self) -> reqwest::ClientBuilder {
+        let mut builder =
+            reqwest::Client::builder().user_agent(concat!("micrio/", env!("CARGO_PKG_VERSION")));
+        match &self.proxy {
+            Some(proxy) => builder = builder.proxy(proxy.clone()),
+            None if self.no_proxy => builder = builder.no_proxy(),
+            None => (),
+        }
+        if let Some(cainfo) = &self.cainfo {
+            builder = builder.add_root_certificate(cainfo.clone());
+        }
+        if let Some(timeout) = self.timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        builder
+    }
+
+    /// Returns a client with the settings applied.
+    pub fn client(&self) -> Result<reqwest::Client> {
+        self.client_builder().build().map_err(Error::CreateClient)
+    }
+}
+
+/// Returns the value of the setting from the environment variable, or else from the first
+/// config file setting it, with the directory a relative path in it is relative to: the current
+/// directory for the environment variable, the parent of the `.cargo` directory for a file.
+fn setting(
+    files: &[(PathBuf, toml::Value)],
+    key: &'static str,
+    env_name: &str,
+) -> Result<Option<(String, PathBuf)>> {
+    if let Ok(value) = env::var(env_name) {
+        return Ok(Some((value, PathBuf::new())));
+    }
+    for (path, file) in files {
+        let value = match file.get("http").and_then(|http| http.get(key)) {
+            Some(value) => value,
+            None => continue,
+        };
+        let value = match value {
+            toml::Value::String(s) => s.clone(),
+            toml::Value::Integer(n) => n.to_string(),
+            _ => {
+                return Err(Error::InvalidSetting {
+                    key,
+                    origin: path.to_string_lossy().into_owned(),
+                })
+            }
+        };
+        let base_dir_path = path
+            .parent()
+            .and_then(Path::parent)
+            .unwrap_or_else(|| Path::new(""));
+        return Ok(Some((value, base_dir_path.to_path_buf())));
+    }
+    Ok(None)
+}
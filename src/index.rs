@@ -0,0 +1,145 @@
+//! Reads a registry index straight from its git repository.
+//!
+//! crates_index parses index entries into versions that drop the fields it doesn't know about,
+//! such as `rust_version` and the `registry` of dependencies. The entries are read here from the
+//! repository crates_index fetched instead, at the same commit, keeping those fields.
+
+use crate::common::Crate;
+use crate::credentials;
+use std::path::{Path, PathBuf};
+
+/// A registry index fetched by crates_index, read at the commit last fetched unless another
+/// one is pinned.
+pub struct Index {
+    repo: git2::Repository,
+    commit: git2::Oid,
+}
+
+impl Index {
+    pub fn new(index: crates_index::Index) -> Result<Self, crates_index::Error> {
+        let repo = git2::Repository::open(index.path())?;
+        // As crates_index does: a fresh clone has no FETCH_HEAD.
+        let commit = repo
+            .refname_to_id("FETCH_HEAD")
+            .or_else(|_| repo.refname_to_id("HEAD"))?;
+        Ok(Index { repo, commit })
+    }
+
+    /// Reads the index at the commit the git revision, e.g. a commit hash or a tag, names
+    /// instead, so runs at different times resolve against the same index.
+    pub fn pin(&mut self, revision: &str) -> Result<(), crates_index::Error> {
+        self.commit = self.repo.revparse_single(revision)?.peel_to_commit()?.id();
+        Ok(())
+    }
+
+    /// Returns the hash of the commit the index is read at.
+    pub fn head_commit(&self) -> String {
+        self.commit.to_string()
+    }
+
+    /// Reads the crate's index file. Returns `None` if the index doesn't have the crate,
+    /// or its index file can't be read, as crates_index does.
+    pub fn crate_(&self, name: &str) -> Option<Crate> {
+        let contents = self.read_file(&crate_index_path(name)?).ok()?;
+        Crate::from_index_file(std::str::from_utf8(&contents).ok()?)
+            .ok()
+            .flatten()
+    }
+
+    /// Reads the index's config.json.
+    pub fn index_config(&self) -> Result<crates_index::IndexConfig, crates_index::Error> {
+        let contents = self.read_file("config.json")?;
+        serde_json::from_slice(&contents).map_err(crates_index::Error::Json)
+    }
+
+    fn read_file(&self, path: &str) -> Result<Vec<u8>, git2::Error> {
+        let tree = self.repo.find_commit(self.commit)?.tree()?;
+        let blob = tree.get_path(Path::new(path))?.to_object(&self.repo)?;
+        match blob.as_blob() {
+            Some(blob) => Ok(blob.content().to_vec()),
+            None => Err(git2::Error::from_str(&format!("{path} is not a file"))),
+        }
+    }
+}
+
+/// Returns the directory Cargo, and crates_index, clone the index of the registry with the URL
+/// into: `registry/index/{host}-{hash}` in Cargo's home directory. Returns `None` if the URL
+/// isn't valid or Cargo's home directory can't be found.
+pub fn cargo_index_path(index_url: &str) -> Option<PathBuf> {
+    let url = match index_url.split_once("://")?.0.split_once('+') {
+        Some(("registry", _)) => index_url.split_once('+')?.1,
+        Some(_) => return None,
+        None => index_url,
+    };
+    let host_and_path = url.split_once("://")?.1;
+    let host = host_and_path.split('/').next()?.split(':').next()?;
+    // Cargo special-cases GitHub, and hashes the URL without its query or fragment.
+    let mut canonical = if host == "github.com" {
+        url.to_lowercase()
+    } else {
+        url.to_string()
+    };
+    if let Some(i) = canonical.rfind('#') {
+        canonical.truncate(i);
+    }
+    if let Some(i) = canonical.rfind('?') {
+        canonical.truncate(i);
+    }
+    #[allow(deprecated)]
+    let mut hasher = std::hash::SipHasher::new_with_keys(0, 0);
+    // The kind of source, a registry.
+    std::hash::Hash::hash(&2usize, &mut hasher);
+    std::hash::Hash::hash(canonical.as_str(), &mut hasher);
+    let hash = std::hash::Hasher::finish(&hasher).to_le_bytes();
+    let dir_name = format!("{host}-{}", hex::encode(hash));
+    Some(
+        credentials::cargo_home()?
+            .join("registry")
+            .join("index")
+            .join(dir_name),
+    )
+}
+
+/// Returns the path of the crate's index file in the index, e.g. `3/s/syn` or `se/rd/serde`.
+fn crate_index_path(name: &str) -> Option<String> {
+    if !crate::common::is_valid_crate_name(name) {
+        return None;
+    }
+    let name = name.to_lowercase();
+    let prefix = match name.len() {
+        1 => "1".to_string(),
+        2 => "2".to_string(),
+        3 => format!("3/{}", &name[..1]),
+        _ => format!("{}/{}", &name[..2], &name[2..4]),
+    };
+    Some(format!("{prefix}/{name}"))
+}
+
+#[cfg(test)]
+impl Index {
+    /// Creates an index repository in the directory, holding the versions' index entries,
+    /// and reads it at its only commit.
+    pub(crate) fn from_entries(dir_path: &Path, entries: &[serde_json::Value]) -> Self {
+        let _ = std::fs::remove_dir_all(dir_path);
+        let repo = git2::Repository::init(dir_path).unwrap();
+        let mut files: std::collections::BTreeMap<String, String> = Default::default();
+        for entry in entries {
+            let path = crate_index_path(entry["name"].as_str().unwrap()).unwrap();
+            *files.entry(path).or_default() += &format!("{entry}\n");
+        }
+        let mut git_index = repo.index().unwrap();
+        for (path, contents) in &files {
+            let file_path = dir_path.join(path);
+            std::fs::create_dir_all(file_path.parent().unwrap()).unwrap();
+            std::fs::write(file_path, contents).unwrap();
+            git_index.add_path(Path::new(path)).unwrap();
+        }
+        let tree = repo.find_tree(git_index.write_tree().unwrap()).unwrap();
+        let signature = git2::Signature::now("micrio", "micrio@example.com").unwrap();
+        let commit = repo
+            .commit(Some("HEAD"), &signature, &signature, "index", &tree, &[])
+            .unwrap();
+        drop(tree);
+        Index { repo, commit }
+    }
+}
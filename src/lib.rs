@@ -0,0 +1,2677 @@
+//! Mirrors a subset of crates from crates.io to a local registry.
+//!
+//! The [`MirrorBuilder`] selects the top-level crates to mirror and the outputs to produce.
+//! The resulting [`Mirror`] resolves their dependencies and populates the registry:
+//!
+//! ```no_run
+//! let mirror = micrio::MirrorBuilder::new("mirror")
+//!     .most_downloaded(50)
+//!     .sha256sums(true)
+//!     .build()?;
+//! let selection = mirror.run()?;
+//! println!("{} crate versions mirrored", selection.crates.len());
+//! # Ok::<(), micrio::Error>(())
+//! ```
+
+pub mod advisory;
+pub mod cancel;
+pub mod cargo_manifest;
+pub mod checksums;
+pub mod common;
+pub mod credentials;
+pub mod downloader;
+pub mod dst_registry;
+pub mod endpoints;
+pub mod export;
+pub mod grep;
+pub mod health;
+pub mod hooks;
+pub mod http_config;
+pub mod index;
+pub mod licenses;
+pub mod metadata;
+pub mod notify;
+pub mod owners;
+pub mod picker;
+pub mod progress;
+pub mod provenance;
+pub mod publish;
+pub mod rate_limit;
+pub mod replicate;
+pub mod repos;
+pub mod report;
+pub mod resolvability;
+pub mod run_report;
+pub mod scheduler;
+pub mod search;
+pub mod selection;
+pub mod serve;
+pub mod signing;
+pub mod site;
+pub mod smoke_test;
+pub mod status;
+pub mod src_registry;
+pub mod state_db;
+pub mod storage;
+pub mod top_level;
+pub mod vendor;
+pub mod warnings;
+
+use advisory::{AdvisoryDb, Substitution};
+use cancel::CancellationToken;
+use cfg_expr::targets::{self as cfg_targets, TargetInfo};
+use common::Version;
+use crates_index::DependencyKind;
+use downloader::{CrateDownloader, HttpDownloader};
+use dst_registry::{CrateSource, DstRegistry, RegistryLayout};
+use endpoints::Endpoints;
+use hooks::Hook;
+use http_config::HttpConfig;
+use index::Index;
+use picker::{Candidate, TopLevelPicker};
+use progress::{NoProgress, Phase, ProgressObserver};
+use provenance::Provenance;
+use rate_limit::{RateLimitedDownloader, RateLimiter};
+use replicate::Replica;
+use report::{DependencyGraph, Inventory, ReportFormat};
+use run_report::{RunRecorder, SelectionRecord};
+use selection::{CrateSpec, SelectionSpec};
+use semver::VersionReq;
+use src_registry::{SrcRegistry, TopLevelOptions};
+use state_db::StateDb;
+use status::StatusWriter;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Display};
+use std::io;
+use std::path::PathBuf;
+use std::sync::{mpsc, Arc};
+use storage::StorageBackend;
+use top_level::{Origin, TopLevelBuilder, Unmirrorable};
+use tracing::{info, info_span, warn};
+use url::Url;
+
+/// What went wrong: the error of the module that failed, as that module reports it.
+/// The module errors are wrapped unchanged rather than merged into one enum, so matching on
+/// the cause of a failure means matching on the wrapped module error.
+#[derive(Debug)]
+pub enum ErrorKind {
+    NoCratesSelected,
+    Cancelled,
+    UnknownTarget(String),
+    TooManyTargets(usize),
+    Endpoints(endpoints::Error),
+    OpenIndex(crates_index::Error),
+    IndexNotFound(PathBuf),
+    Offline(String),
+    EstimateSize(io::Error),
+    Pick(io::Error),
+    Credentials(credentials::Error),
+    HttpConfig(http_config::Error),
+    SelectCrates(top_level::Error),
+    ResolveDependencies(src_registry::Error),
+    Audit(advisory::Error),
+    Registry(dst_registry::Error),
+    Resolvability(resolvability::Error),
+    Metadata(metadata::Error),
+    Licenses(licenses::Error),
+    Owners(owners::Error),
+    Repos(repos::Error),
+    HtmlIndex(site::Error),
+    Checksums(checksums::Error),
+    StateDb(state_db::Error),
+    Publish(publish::Error),
+    Replicate(replicate::Error),
+    Report(report::Error),
+    Hook(hooks::Error),
+    RunReport(run_report::Error),
+}
+
+impl Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ErrorKind::NoCratesSelected => write!(f, "no crates selected to mirror"),
+            ErrorKind::Cancelled => write!(f, "the mirror run was cancelled"),
+            ErrorKind::UnknownTarget(triple) => write!(f, "unknown target triple {triple}"),
+            ErrorKind::TooManyTargets(n) => write!(
+                f,
+                "{n} targets given, at most {} are supported",
+                src_registry::MAX_TARGETS
+            ),
+            ErrorKind::Endpoints(e) => write!(f, "{e}"),
+            ErrorKind::OpenIndex(e) => write!(f, "failed to open the registry index: {e}"),
+            ErrorKind::IndexNotFound(path) => {
+                write!(f, "no registry index found in {}", path.to_string_lossy())
+            }
+            ErrorKind::Offline(step) => {
+                write!(
+                    f,
+                    "{step} requires network access, which offline mode forbids"
+                )
+            }
+            ErrorKind::EstimateSize(e) => {
+                write!(
+                    f,
+                    "failed to create tokio runtime to estimate the download size: {e}"
+                )
+            }
+            ErrorKind::Pick(e) => write!(f, "failed to run the crate picker: {e}"),
+            ErrorKind::Credentials(e) => {
+                write!(f, "failed to look up the registry token: {e}")
+            }
+            ErrorKind::HttpConfig(e) => write!(f, "{e}"),
+            ErrorKind::SelectCrates(e) => {
+                write!(f, "failed to select the top-level crates: {e}")
+            }
+            ErrorKind::ResolveDependencies(e) => {
+                write!(f, "failed to resolve dependencies: {e}")
+            }
+            ErrorKind::Audit(e) => write!(f, "failed to scan for known vulnerabilities: {e}"),
+            ErrorKind::Registry(e) => write!(f, "{e}"),
+            ErrorKind::Resolvability(e) => write!(f, "{e}"),
+            ErrorKind::Metadata(e) => write!(f, "{e}"),
+            ErrorKind::Licenses(e) => write!(f, "{e}"),
+            ErrorKind::Owners(e) => write!(f, "{e}"),
+            ErrorKind::Repos(e) => write!(f, "{e}"),
+            ErrorKind::HtmlIndex(e) => write!(f, "{e}"),
+            ErrorKind::Checksums(e) => write!(f, "{e}"),
+            ErrorKind::StateDb(e) => write!(f, "{e}"),
+            ErrorKind::Publish(e) => write!(f, "{e}"),
+            ErrorKind::Replicate(e) => write!(f, "{e}"),
+            ErrorKind::Report(e) => write!(f, "{e}"),
+            ErrorKind::Hook(e) => write!(f, "{e}"),
+            ErrorKind::RunReport(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+/// An error from a mirror run: the module error that caused it, along with the phase that
+/// failed, the crate version involved, and whether retrying may help. The crate and the
+/// retryability are only known for the module errors that identify a crate or come from
+/// the network; the other errors have neither.
+#[derive(Debug)]
+pub struct Error {
+    kind: Box<ErrorKind>,
+    phase: Option<Phase>,
+    crate_name: Option<String>,
+    crate_version: Option<String>,
+    retryable: bool,
+}
+
+impl Error {
+    fn new(kind: ErrorKind) -> Self {
+        Error {
+            kind: Box::new(kind),
+            phase: None,
+            crate_name: None,
+            crate_version: None,
+            retryable: false,
+        }
+    }
+
+    fn with_crate(mut self, crate_name: &str, crate_version: Option<&str>) -> Self {
+        self.crate_name = Some(crate_name.to_string());
+        self.crate_version = crate_version.map(|v| v.to_string());
+        self
+    }
+
+    fn retryable(mut self) -> Self {
+        self.retryable = true;
+        self
+    }
+
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+
+    /// The phase of the run that failed, or `None` if the run failed before starting.
+    pub fn phase(&self) -> Option<Phase> {
+        self.phase
+    }
+
+    /// The name of the crate involved in the failure, if any.
+    pub fn crate_name(&self) -> Option<&str> {
+        self.crate_name.as_deref()
+    }
+
+    /// The version of the crate involved in the failure, if known.
+    pub fn crate_version(&self) -> Option<&str> {
+        self.crate_version.as_deref()
+    }
+
+    /// Whether the failure may be temporary, e.g. a network error, so retrying may succeed.
+    pub fn is_retryable(&self) -> bool {
+        self.retryable
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.kind)
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self.kind.as_ref() {
+            ErrorKind::NoCratesSelected => None,
+            ErrorKind::Cancelled => None,
+            ErrorKind::UnknownTarget(_) => None,
+            ErrorKind::TooManyTargets(_) => None,
+            ErrorKind::Endpoints(e) => Some(e),
+            ErrorKind::OpenIndex(e) => Some(e),
+            ErrorKind::IndexNotFound(_) => None,
+            ErrorKind::EstimateSize(e) => Some(e),
+            ErrorKind::Pick(e) => Some(e),
+            ErrorKind::Offline(_) => None,
+            ErrorKind::Credentials(e) => Some(e),
+            ErrorKind::HttpConfig(e) => Some(e),
+            ErrorKind::SelectCrates(e) => Some(e),
+            ErrorKind::ResolveDependencies(e) => Some(e),
+            ErrorKind::Audit(e) => Some(e),
+            ErrorKind::Registry(e) => Some(e),
+            ErrorKind::Resolvability(e) => Some(e),
+            ErrorKind::Metadata(e) => Some(e),
+            ErrorKind::Licenses(e) => Some(e),
+            ErrorKind::Owners(e) => Some(e),
+            ErrorKind::Repos(e) => Some(e),
+            ErrorKind::HtmlIndex(e) => Some(e),
+            ErrorKind::Checksums(e) => Some(e),
+            ErrorKind::StateDb(e) => Some(e),
+            ErrorKind::Publish(e) => Some(e),
+            ErrorKind::Replicate(e) => Some(e),
+            ErrorKind::Report(e) => Some(e),
+            ErrorKind::Hook(e) => Some(e),
+            ErrorKind::RunReport(e) => Some(e),
+        }
+    }
+}
+
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Self {
+        Error::new(kind)
+    }
+}
+
+impl From<crates_index::Error> for Error {
+    fn from(e: crates_index::Error) -> Self {
+        Error::new(ErrorKind::OpenIndex(e))
+    }
+}
+
+impl From<endpoints::Error> for Error {
+    fn from(e: endpoints::Error) -> Self {
+        Error::new(ErrorKind::Endpoints(e))
+    }
+}
+
+impl From<credentials::Error> for Error {
+    fn from(e: credentials::Error) -> Self {
+        Error::new(ErrorKind::Credentials(e))
+    }
+}
+
+impl From<http_config::Error> for Error {
+    fn from(e: http_config::Error) -> Self {
+        Error::new(ErrorKind::HttpConfig(e))
+    }
+}
+
+impl From<top_level::Error> for Error {
+    fn from(e: top_level::Error) -> Self {
+        match &e {
+            // Every crates.io query fails while the API is unavailable or rate limiting.
+            top_level::Error::QueryMostDownloadedCrates(_)
+            | top_level::Error::QueryCategory { .. }
+            | top_level::Error::QueryKeyword { .. }
+            | top_level::Error::QueryRecentlyUpdated(_)
+            | top_level::Error::QueryOwner { .. }
+            | top_level::Error::Search { .. } => Error::new(ErrorKind::SelectCrates(e)).retryable(),
+            top_level::Error::MostDownloadedCrateNotFound(common::Error::CrateNotFound {
+                crate_name,
+            })
+            | top_level::Error::CrateNotFound(common::Error::CrateNotFound { crate_name })
+            | top_level::Error::NoMatchingVersion { crate_name, .. }
+            | top_level::Error::NoVersions { crate_name, .. } => {
+                let crate_name = crate_name.clone();
+                Error::new(ErrorKind::SelectCrates(e)).with_crate(&crate_name, None)
+            }
+            _ => Error::new(ErrorKind::SelectCrates(e)),
+        }
+    }
+}
+
+impl From<src_registry::Error> for Error {
+    fn from(e: src_registry::Error) -> Self {
+        if let src_registry::Error::Cancelled = e {
+            return ErrorKind::Cancelled.into();
+        }
+        let (crate_name, crate_version) = match &e {
+            src_registry::Error::CrateNotFound(common::Error::CrateNotFound { crate_name }) => {
+                (Some(crate_name.clone()), None)
+            }
+            src_registry::Error::SemVerRequirement { crate_name, .. }
+            | src_registry::Error::UnknownRegistry { crate_name, .. } => {
+                (Some(crate_name.clone()), None)
+            }
+            src_registry::Error::SemVerVersion {
+                crate_name,
+                crate_version,
+                ..
+            }
+            | src_registry::Error::MissingDependency {
+                crate_name,
+                crate_version,
+                ..
+            } => (Some(crate_name.clone()), Some(crate_version.clone())),
+            _ => (None, None),
+        };
+        let error = Error::new(ErrorKind::ResolveDependencies(e));
+        match crate_name {
+            Some(crate_name) => error.with_crate(&crate_name, crate_version.as_deref()),
+            None => error,
+        }
+    }
+}
+
+impl From<advisory::Error> for Error {
+    fn from(e: advisory::Error) -> Self {
+        match &e {
+            advisory::Error::FetchDb(_) => Error::new(ErrorKind::Audit(e)).retryable(),
+            _ => Error::new(ErrorKind::Audit(e)),
+        }
+    }
+}
+
+impl From<dst_registry::Error> for Error {
+    fn from(e: dst_registry::Error) -> Self {
+        if let dst_registry::Error::Cancelled { .. } = e {
+            return ErrorKind::Cancelled.into();
+        }
+        let (crat, retryable) = match &e {
+            dst_registry::Error::AddCrateToIndex {
+                crate_name,
+                crate_version,
+                ..
+            }
+            | dst_registry::Error::WriteRegistryFile {
+                crate_name,
+                crate_version,
+                ..
+            }
+            | dst_registry::Error::NotCached {
+                crate_name,
+                crate_version,
+            }
+            | dst_registry::Error::InvalidCrateName {
+                crate_name,
+                crate_version,
+            }
+            | dst_registry::Error::CrateUrl {
+                crate_name,
+                crate_version,
+                ..
+            } => (Some((crate_name.clone(), crate_version.clone())), false),
+            dst_registry::Error::DownloadCrate {
+                crate_name,
+                crate_version,
+                ..
+            }
+            | dst_registry::Error::ChecksumMismatch {
+                crate_name,
+                crate_version,
+            } => (Some((crate_name.clone(), crate_version.clone())), true),
+            _ => (None, false),
+        };
+        let mut error = Error::new(ErrorKind::Registry(e));
+        if let Some((crate_name, crate_version)) = crat {
+            error = error.with_crate(&crate_name, Some(&crate_version));
+        }
+        if retryable {
+            error = error.retryable();
+        }
+        error
+    }
+}
+
+impl From<resolvability::Error> for Error {
+    fn from(e: resolvability::Error) -> Self {
+        Error::new(ErrorKind::Resolvability(e))
+    }
+}
+
+impl From<metadata::Error> for Error {
+    fn from(e: metadata::Error) -> Self {
+        let crat = match &e {
+            metadata::Error::ReadCrateFile {
+                crate_name,
+                crate_version,
+                ..
+            }
+            | metadata::Error::WriteMetadata {
+                crate_name,
+                crate_version,
+                ..
+            } => Some((crate_name.clone(), crate_version.clone())),
+            metadata::Error::WriteSearchIndex(_) => None,
+        };
+        let error = Error::new(ErrorKind::Metadata(e));
+        match crat {
+            Some((crate_name, crate_version)) => {
+                error.with_crate(&crate_name, Some(&crate_version))
+            }
+            None => error,
+        }
+    }
+}
+
+impl From<licenses::Error> for Error {
+    fn from(e: licenses::Error) -> Self {
+        let crat = match &e {
+            licenses::Error::ReadCrateFile {
+                crate_name,
+                crate_version,
+                ..
+            }
+            | licenses::Error::WriteLicense {
+                crate_name,
+                crate_version,
+                ..
+            } => (crate_name.clone(), crate_version.clone()),
+        };
+        Error::new(ErrorKind::Licenses(e)).with_crate(&crat.0, Some(&crat.1))
+    }
+}
+
+impl From<owners::Error> for Error {
+    fn from(e: owners::Error) -> Self {
+        if let owners::Error::Cancelled = e {
+            return ErrorKind::Cancelled.into();
+        }
+        // The registry's API may be unavailable for now.
+        let retryable = matches!(e, owners::Error::FetchOwners { .. });
+        let crate_name = match &e {
+            owners::Error::OwnersUrl { crate_name, .. }
+            | owners::Error::FetchOwners { crate_name, .. }
+            | owners::Error::ParseOwners { crate_name, .. }
+            | owners::Error::WriteOwners { crate_name, .. } => Some(crate_name.clone()),
+            _ => None,
+        };
+        let mut error = Error::new(ErrorKind::Owners(e));
+        if let Some(crate_name) = crate_name {
+            error = error.with_crate(&crate_name, None);
+        }
+        if retryable {
+            error.retryable()
+        } else {
+            error
+        }
+    }
+}
+
+impl From<repos::Error> for Error {
+    fn from(e: repos::Error) -> Self {
+        match e {
+            repos::Error::Cancelled => ErrorKind::Cancelled.into(),
+            repos::Error::ReadCrateFile {
+                ref crate_name,
+                ref crate_version,
+                ..
+            } => {
+                let crat = (crate_name.clone(), crate_version.clone());
+                Error::new(ErrorKind::Repos(e)).with_crate(&crat.0, Some(&crat.1))
+            }
+            _ => Error::new(ErrorKind::Repos(e)),
+        }
+    }
+}
+
+impl From<site::Error> for Error {
+    fn from(e: site::Error) -> Self {
+        let crat = match &e {
+            site::Error::ReadCrateFile {
+                crate_name,
+                crate_version,
+                ..
+            } => Some((crate_name.clone(), crate_version.clone())),
+            site::Error::WriteIndex(_) => None,
+        };
+        let error = Error::new(ErrorKind::HtmlIndex(e));
+        match crat {
+            Some((crate_name, crate_version)) => {
+                error.with_crate(&crate_name, Some(&crate_version))
+            }
+            None => error,
+        }
+    }
+}
+
+impl From<checksums::Error> for Error {
+    fn from(e: checksums::Error) -> Self {
+        Error::new(ErrorKind::Checksums(e))
+    }
+}
+
+impl From<state_db::Error> for Error {
+    fn from(e: state_db::Error) -> Self {
+        let crat = match &e {
+            state_db::Error::ParseEntry {
+                crate_name,
+                crate_version,
+                ..
+            } => Some((crate_name.clone(), crate_version.clone())),
+            _ => None,
+        };
+        let error = Error::new(ErrorKind::StateDb(e));
+        match crat {
+            Some((crate_name, crate_version)) => {
+                error.with_crate(&crate_name, Some(&crate_version))
+            }
+            None => error,
+        }
+    }
+}
+
+impl From<publish::Error> for Error {
+    fn from(e: publish::Error) -> Self {
+        if let publish::Error::Cancelled = e {
+            return ErrorKind::Cancelled.into();
+        }
+        let (crat, retryable) = match &e {
+            publish::Error::CreateRuntime(_) | publish::Error::Cancelled => (None, false),
+            publish::Error::ReadCrateFile {
+                crate_name,
+                crate_version,
+                ..
+            }
+            | publish::Error::SerializeMetadata {
+                crate_name,
+                crate_version,
+                ..
+            }
+            | publish::Error::Rejected {
+                crate_name,
+                crate_version,
+                ..
+            } => (Some((crate_name.clone(), crate_version.clone())), false),
+            publish::Error::Upload {
+                crate_name,
+                crate_version,
+                ..
+            } => (Some((crate_name.clone(), crate_version.clone())), true),
+        };
+        let mut error = Error::new(ErrorKind::Publish(e));
+        if let Some((crate_name, crate_version)) = crat {
+            error = error.with_crate(&crate_name, Some(&crate_version));
+        }
+        if retryable {
+            error = error.retryable();
+        }
+        error
+    }
+}
+
+impl From<replicate::Error> for Error {
+    fn from(e: replicate::Error) -> Self {
+        // The replica may be unreachable for now.
+        let retryable = matches!(e, replicate::Error::RsyncFailed { .. });
+        let error = Error::new(ErrorKind::Replicate(e));
+        if retryable {
+            error.retryable()
+        } else {
+            error
+        }
+    }
+}
+
+impl From<report::Error> for Error {
+    fn from(e: report::Error) -> Self {
+        let crat = match &e {
+            report::Error::ReadCrateFile {
+                crate_name,
+                crate_version,
+                ..
+            } => Some((crate_name.clone(), Some(crate_version.clone()))),
+            report::Error::ReadOwners { crate_name, .. } => Some((crate_name.clone(), None)),
+            report::Error::WriteReport(_) => None,
+        };
+        let error = Error::new(ErrorKind::Report(e));
+        match crat {
+            Some((crate_name, crate_version)) => {
+                error.with_crate(&crate_name, crate_version.as_deref())
+            }
+            None => error,
+        }
+    }
+}
+
+impl From<run_report::Error> for Error {
+    fn from(e: run_report::Error) -> Self {
+        Error::new(ErrorKind::RunReport(e))
+    }
+}
+
+impl From<hooks::Error> for Error {
+    fn from(e: hooks::Error) -> Self {
+        let crat = match &e {
+            hooks::Error::Rejected {
+                crate_name,
+                crate_version,
+                ..
+            } => Some((crate_name.clone(), crate_version.clone())),
+            _ => None,
+        };
+        let error = Error::new(ErrorKind::Hook(e));
+        match crat {
+            Some((crate_name, crate_version)) => {
+                error.with_crate(&crate_name, Some(&crate_version))
+            }
+            None => error,
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// The crates directly depending on each crate version.
+type Dependents = HashMap<Version, HashSet<Version>>;
+
+/// The number of resolved crate versions that can wait to be downloaded when streaming.
+const STREAMING_QUEUE_SIZE: usize = 1000;
+
+/// Configures which crates to mirror and which outputs to produce.
+pub struct MirrorBuilder {
+    mirror_dir_path: PathBuf,
+    storage: Option<Arc<dyn StorageBackend>>,
+    layout: RegistryLayout,
+    auth_required: bool,
+    downloader: Option<Arc<dyn CrateDownloader>>,
+    http_config: HttpConfig,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    progress: Arc<dyn ProgressObserver>,
+    cancel: CancellationToken,
+    selection: SelectionSpec,
+    hooks: Vec<Arc<dyn Hook>>,
+    picker: Option<Arc<dyn TopLevelPicker>>,
+    streaming: bool,
+    index_only: bool,
+    download_only: bool,
+    reproducible: bool,
+    deny_warnings: bool,
+    endpoints: Endpoints,
+    registry_token: Option<String>,
+    secondary_registries: Vec<(String, Option<String>)>,
+    cargo_credentials: bool,
+    offline: bool,
+    index_path: Option<PathBuf>,
+    index_commit: Option<String>,
+    targets: Vec<String>,
+    max_depth: Option<usize>,
+    overrides: HashMap<String, VersionReq>,
+    audit: bool,
+    advisory_db: Option<PathBuf>,
+    exclude_vulnerable: bool,
+    substitute_patched: bool,
+    check_resolvable: bool,
+    with_metadata: bool,
+    with_licenses: bool,
+    with_owners: bool,
+    with_repos: bool,
+    html_index: bool,
+    sha256sums: bool,
+    state_db: bool,
+    keep_run_reports: Option<usize>,
+    link_dirs: Vec<PathBuf>,
+    seed_dirs: Vec<PathBuf>,
+    publish_to: Option<String>,
+    publish_token: Option<String>,
+    replicas: Vec<Replica>,
+    report: Option<PathBuf>,
+    size_report: Option<PathBuf>,
+    publisher_report: Option<PathBuf>,
+    report_format: ReportFormat,
+    dependency_graph: Option<PathBuf>,
+}
+
+impl MirrorBuilder {
+    /// Starts configuring a mirror in the specified directory.
+    /// The directory is emptied when the mirror is populated.
+    pub fn new<P: Into<PathBuf>>(mirror_dir_path: P) -> Self {
+        MirrorBuilder {
+            mirror_dir_path: mirror_dir_path.into(),
+            storage: None,
+            layout: RegistryLayout::default(),
+            auth_required: false,
+            downloader: None,
+            http_config: HttpConfig::default(),
+            rate_limiter: None,
+            progress: Arc::new(NoProgress),
+            cancel: CancellationToken::new(),
+            selection: SelectionSpec::new(),
+            hooks: Vec::new(),
+            picker: None,
+            streaming: false,
+            index_only: false,
+            download_only: false,
+            reproducible: false,
+            deny_warnings: false,
+            endpoints: Endpoints::crates_io(),
+            registry_token: None,
+            secondary_registries: Vec::new(),
+            cargo_credentials: false,
+            offline: false,
+            index_path: None,
+            index_commit: None,
+            targets: Vec::new(),
+            max_depth: None,
+            overrides: HashMap::new(),
+            audit: false,
+            advisory_db: None,
+            exclude_vulnerable: false,
+            substitute_patched: false,
+            check_resolvable: false,
+            with_metadata: false,
+            with_licenses: false,
+            with_owners: false,
+            with_repos: false,
+            html_index: false,
+            sha256sums: false,
+            state_db: false,
+            keep_run_reports: None,
+            link_dirs: Vec::new(),
+            seed_dirs: Vec::new(),
+            publish_to: None,
+            publish_token: None,
+            replicas: Vec::new(),
+            report: None,
+            size_report: None,
+            publisher_report: None,
+            report_format: ReportFormat::Csv,
+            dependency_graph: None,
+        }
+    }
+
+    /// Stores the .crate files in the storage backend instead of the mirror directory.
+    pub fn storage(mut self, storage: Arc<dyn StorageBackend>) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
+    /// Stores the .crate files in the layout, e.g. flat `{name}-{version}.crate` files that
+    /// are simpler to host on static file servers. See `RegistryLayout`.
+    pub fn layout(mut self, layout: RegistryLayout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// Marks the mirror as requiring authentication in its config.json, for serving it with
+    /// `serve::MirrorServer` checking tokens. See `DstRegistry::auth_required`.
+    pub fn auth_required(mut self, auth_required: bool) -> Self {
+        self.auth_required = auth_required;
+        self
+    }
+
+    /// Fetches the .crate files with the downloader instead of over HTTP, e.g. to exercise
+    /// the mirror against canned responses.
+    pub fn downloader(mut self, downloader: Arc<dyn CrateDownloader>) -> Self {
+        self.downloader = Some(downloader);
+        self
+    }
+
+    /// Applies the HTTP settings, e.g. those of Cargo's configuration, to every HTTP request of
+    /// the run. See `http_config::HttpConfig::from_cargo_config`.
+    pub fn http_config(mut self, http_config: HttpConfig) -> Self {
+        self.http_config = http_config;
+        self
+    }
+
+    /// Makes the requests to the registry, the crates.io API queries and the .crate file
+    /// downloads, through the limiter. Share one limiter between mirrors, e.g. across the runs
+    /// of a scheduler, to keep to the limits globally. See `rate_limit::RateLimiter`.
+    pub fn rate_limiter(mut self, rate_limiter: Arc<RateLimiter>) -> Self {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
+
+    /// Reports progress to the observer. By default progress isn't reported.
+    pub fn progress(mut self, progress: Arc<dyn ProgressObserver>) -> Self {
+        self.progress = progress;
+        self
+    }
+
+    /// Stops the run early, with an `ErrorKind::Cancelled` error, once the token is cancelled.
+    pub fn cancellation_token(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Mirrors the crates described by the spec, replacing any crates selected so far.
+    pub fn selection(mut self, selection: SelectionSpec) -> Self {
+        self.selection = selection;
+        self
+    }
+
+    /// Also mirrors the crates listed in the file, one crate name per line. The file can also
+    /// be an http:// or https:// URL, or `-` for standard input. A .toml or .json file is a
+    /// structured crate list, see `selection::parse_crate_list`.
+    pub fn from_file<P: Into<PathBuf>>(mut self, file_path: P) -> Self {
+        self.selection.files.push(file_path.into());
+        self
+    }
+
+    /// Also mirrors the top N most downloaded crates on crates.io.
+    pub fn most_downloaded(mut self, n: u64) -> Self {
+        self.selection.most_downloaded = Some(n);
+        self
+    }
+
+    /// Adds a hook to invoke around each crate download and at the end of the run.
+    /// Hooks are invoked in the order they were added.
+    pub fn hook(mut self, hook: Arc<dyn Hook>) -> Self {
+        self.hooks.push(hook);
+        self
+    }
+
+    /// Lets the picker narrow the top-level crates down before their dependencies
+    /// are resolved. The candidates' sizes are looked up unless offline.
+    pub fn picker(mut self, picker: Arc<dyn TopLevelPicker>) -> Self {
+        self.picker = Some(picker);
+        self
+    }
+
+    /// Downloads crates while their dependencies are still being resolved, instead of resolving
+    /// the whole selection first, so downloads of very large selections start right away.
+    /// Resolved crates wait in a bounded queue, but the memory used still grows with the
+    /// selection: the resolved crates and their dependents are kept for the index and reports.
+    ///
+    /// Ignored when excluding vulnerable crates, since that needs the whole selection up front.
+    pub fn streaming(mut self, streaming: bool) -> Self {
+        self.streaming = streaming;
+        self
+    }
+
+    /// Only writes the index of the selection, without downloading the .crate files, e.g. on a
+    /// machine that can reach the registry's index but not its downloads. A download-only run
+    /// on the mirror directory then downloads them and produces the other outputs.
+    /// Implies not streaming.
+    pub fn index_only(mut self, index_only: bool) -> Self {
+        self.index_only = index_only;
+        self
+    }
+
+    /// Downloads the .crate files of the crate versions in the existing index of the mirror,
+    /// e.g. written by an index-only run on another machine, verifying each against its index
+    /// checksum. Nothing is selected or resolved: the index and its layout are kept as they are,
+    /// and every crate is downloaded from the registry configured, not a secondary one.
+    /// Takes precedence over `index_only`.
+    pub fn download_only(mut self, download_only: bool) -> Self {
+        self.download_only = download_only;
+        self
+    }
+
+    /// Builds the index so that runs with the same selection against the same crates.io index
+    /// commit produce bit-identical mirrors; see `DstRegistry::reproducible`. Implies not
+    /// streaming, since streaming adds crates in the order they happen to be resolved.
+    ///
+    /// Selections drawn from live crates.io data, e.g. the most downloaded crates or those in
+    /// a category, can still differ between runs.
+    pub fn reproducible(mut self, reproducible: bool) -> Self {
+        self.reproducible = reproducible;
+        self
+    }
+
+    /// Fails the run on conditions that are otherwise only logged and leave crates out of the
+    /// mirror: a dependency without a compatible version, a listed crate without versions,
+    /// an unreadable lockfile package, a crate that fails to download, or, when checked,
+    /// a top-level crate that doesn't resolve against the mirror.
+    pub fn deny_warnings(mut self, deny_warnings: bool) -> Self {
+        self.deny_warnings = deny_warnings;
+        self
+    }
+
+    /// Resolves and downloads the crates from the Cargo registry with the index URL instead of
+    /// crates.io, e.g. an organization's internal registry. The token, if any, is sent with
+    /// the downloads, for registries requiring authentication.
+    pub fn registry<S: Into<String>>(mut self, index_url: S, token: Option<String>) -> Self {
+        self.endpoints.index_url = Some(index_url.into());
+        self.registry_token = token;
+        self
+    }
+
+    /// Mirrors the registry with the endpoints instead of crates.io, and points the mirror's
+    /// config.json at their serve URL, if any. They are validated when the mirror is built.
+    /// The token given with `registry`, if any, is kept.
+    pub fn endpoints(mut self, endpoints: Endpoints) -> Self {
+        self.endpoints = endpoints;
+        self
+    }
+
+    /// Resolves the dependencies on the Cargo registry with the index URL, those naming it in
+    /// their `registry` field, against its index instead of leaving them out, and downloads
+    /// them from it. Cargo's alternate registries can depend on crates.io and each other, so
+    /// e.g. crates.io can be added when mirroring an internal registry. The crates are then
+    /// served from the mirror, like the others. The token is used as with `registry`.
+    pub fn secondary_registry<S: Into<String>>(
+        mut self,
+        index_url: S,
+        token: Option<String>,
+    ) -> Self {
+        self.secondary_registries.push((index_url.into(), token));
+        self
+    }
+
+    /// Downloads from the registries given without a token with the token Cargo has for them,
+    /// if any, from Cargo's credentials and config files or environment variables.
+    /// See `credentials::cargo_token`.
+    pub fn cargo_credentials(mut self, cargo_credentials: bool) -> Self {
+        self.cargo_credentials = cargo_credentials;
+        self
+    }
+
+    /// Only uses local data: the indexes already fetched, the advisory database given with
+    /// `advisory_db`, and the .crate files in Cargo's download cache. Building fails right away
+    /// if the configuration needs the network, e.g. to select the most downloaded crates,
+    /// and populating fails for the crates missing from the cache.
+    pub fn offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    /// Resolves the selection against the git clone of the index at the path, e.g. one fetched
+    /// beforehand for hermetic builds, instead of Cargo's or one micrio fetches. The clone is
+    /// of crates.io's index, or of the `registry`'s if set, and is used as is, without fetching.
+    pub fn index_path<P: Into<PathBuf>>(mut self, index_path: P) -> Self {
+        self.index_path = Some(index_path.into());
+        self
+    }
+
+    /// Resolves the selection against the specified commit of the local crates.io index
+    /// instead of the latest one fetched, pinning the crate versions chosen.
+    pub fn index_commit<S: Into<String>>(mut self, commit: S) -> Self {
+        self.index_commit = Some(commit.into());
+        self
+    }
+
+    /// Only mirrors the platform-specific dependencies needed to build for the target triple,
+    /// e.g. `x86_64-pc-windows-msvc`. Call repeatedly to mirror what each of several targets
+    /// needs. Every platform-specific dependency is mirrored if no target is given.
+    pub fn target<S: Into<String>>(mut self, triple: S) -> Self {
+        self.targets.push(triple.into());
+        self
+    }
+
+    /// Only mirrors the dependencies at most N dependency edges away from a top-level crate,
+    /// so 1 only mirrors their direct dependencies, to bound the size of exploratory mirrors.
+    /// The dependencies left out are reported, and listed in `Selection::beyond_max_depth`.
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Resolves every dependency on the named crate to the newest version matching the
+    /// requirement instead of the dependency's own, like Cargo's `[patch]`, e.g. to work around
+    /// a broken upstream release without editing every selection source. Call repeatedly to
+    /// override several crates.
+    pub fn override_version<S: Into<String>>(mut self, crate_name: S, req: VersionReq) -> Self {
+        self.overrides.insert(crate_name.into(), req);
+        self
+    }
+
+    /// Scans the selected crates for known vulnerabilities using the RustSec advisory database.
+    pub fn audit(mut self, audit: bool) -> Self {
+        self.audit = audit;
+        self
+    }
+
+    /// Uses a local clone of the RustSec advisory database instead of cloning a fresh copy.
+    pub fn advisory_db<P: Into<PathBuf>>(mut self, db_dir_path: P) -> Self {
+        self.advisory_db = Some(db_dir_path.into());
+        self
+    }
+
+    /// Excludes crate versions with known vulnerabilities. Only applies when auditing.
+    /// The crates that depend on an excluded version, with no other version left to resolve
+    /// the dependency to, are excluded too, and reported, since they couldn't be built from
+    /// the mirror.
+    pub fn exclude_vulnerable(mut self, exclude_vulnerable: bool) -> Self {
+        self.exclude_vulnerable = exclude_vulnerable;
+        self
+    }
+
+    /// Replaces crate versions with known vulnerabilities by the newest semver-compatible
+    /// version no vulnerability affects, if any, so the mirror doesn't distribute known
+    /// vulnerable code. The substitutions are reported, and listed in `Selection::substitutions`.
+    /// Only applies when auditing, and before excluding the vulnerable versions left.
+    pub fn substitute_patched(mut self, substitute_patched: bool) -> Self {
+        self.substitute_patched = substitute_patched;
+        self
+    }
+
+    /// Stores each crate version's README and metadata next to its .crate file.
+    pub fn with_metadata(mut self, with_metadata: bool) -> Self {
+        self.with_metadata = with_metadata;
+        self
+    }
+
+    /// Extracts the license files of each crate version into the mirror's licenses directory.
+    /// See `licenses::write_licenses`.
+    pub fn with_licenses(mut self, with_licenses: bool) -> Self {
+        self.with_licenses = with_licenses;
+        self
+    }
+
+    /// Fetches the owners of each crate from the registry's web API into the mirror's owners
+    /// directory, for the inventory report and for ownership queries to the mirror.
+    /// See `owners::write_owners`.
+    pub fn with_owners(mut self, with_owners: bool) -> Self {
+        self.with_owners = with_owners;
+        self
+    }
+
+    /// Clones the source repositories of the top-level crates into the mirror's repositories
+    /// directory, which is kept between runs and only fetched into after. Download-only runs
+    /// leave it as it is, and runs without it remove it. See `repos::mirror_repos`.
+    pub fn with_repos(mut self, with_repos: bool) -> Self {
+        self.with_repos = with_repos;
+        self
+    }
+
+    /// Writes an index.html file to the mirror directory for browsing the mirrored crates.
+    pub fn html_index(mut self, html_index: bool) -> Self {
+        self.html_index = html_index;
+        self
+    }
+
+    /// Checks that every top-level crate resolves against the mirror's index alone once it is
+    /// populated, logging each dependency without a compatible version in the mirror.
+    /// With `deny_warnings`, the run fails if any doesn't. See `resolvability::check_resolvable`.
+    pub fn check_resolvable(mut self, check_resolvable: bool) -> Self {
+        self.check_resolvable = check_resolvable;
+        self
+    }
+
+    /// Writes a SHA256SUMS file covering every file in the mirror.
+    pub fn sha256sums(mut self, sha256sums: bool) -> Self {
+        self.sha256sums = sha256sums;
+        self
+    }
+
+    /// Records the crate versions in the mirror, why each is there, and the digests of the
+    /// mirror's files in an SQLite database in its state directory, which download-only runs,
+    /// the scheduler's verify job, and the grep and list subcommands query instead of scanning
+    /// the index and the files. The SHA256SUMS file then takes the digests of the .crate files
+    /// from it instead of hashing them. See `state_db::StateDb`.
+    pub fn state_db(mut self, state_db: bool) -> Self {
+        self.state_db = state_db;
+        self
+    }
+
+    /// Only keeps the reports of the N most recent runs in the mirror's state directory,
+    /// removing the older ones after each run, instead of keeping every report.
+    /// See `run_report::RunReport`.
+    pub fn keep_run_reports(mut self, keep_run_reports: usize) -> Self {
+        self.keep_run_reports = Some(keep_run_reports);
+        self
+    }
+
+    /// Hard-links the .crate files identical to those in the directory, e.g. another mirror
+    /// on the same server, instead of downloading them. See `DstRegistry::link_from`.
+    pub fn link_from<P: Into<PathBuf>>(mut self, dir_path: P) -> Self {
+        self.link_dirs.push(dir_path.into());
+        self
+    }
+
+    /// Copies the .crate files identical to those in the directory, e.g. files downloaded
+    /// ahead of time, instead of downloading them. See `DstRegistry::seed_from`.
+    pub fn seed_from<P: Into<PathBuf>>(mut self, dir_path: P) -> Self {
+        self.seed_dirs.push(dir_path.into());
+        self
+    }
+
+    /// Also uploads the mirrored crates to the registry with the specified API base URL.
+    pub fn publish_to<S: Into<String>>(mut self, api_url: S, token: Option<String>) -> Self {
+        self.publish_to = Some(api_url.into());
+        self.publish_token = token;
+        self
+    }
+
+    /// Pushes the mirror to the replica at the end of each successful run. Can be called
+    /// several times to keep several replicas in sync. See `replicate::push`.
+    pub fn replicate_to(mut self, replica: Replica) -> Self {
+        self.replicas.push(replica);
+        self
+    }
+
+    /// Writes an inventory report listing every mirrored crate to the file.
+    pub fn report<P: Into<PathBuf>>(mut self, report_path: P) -> Self {
+        self.report = Some(report_path.into());
+        self
+    }
+
+    /// Writes a report attributing registry disk usage to each top-level crate to the file.
+    pub fn size_report<P: Into<PathBuf>>(mut self, size_report_path: P) -> Self {
+        self.size_report = Some(size_report_path.into());
+        self
+    }
+
+    /// Writes a report grouping the mirrored crates by owner to the file. Needs the owners,
+    /// see `with_owners`; crates without them are left out.
+    pub fn publisher_report<P: Into<PathBuf>>(mut self, publisher_report_path: P) -> Self {
+        self.publisher_report = Some(publisher_report_path.into());
+        self
+    }
+
+    /// Sets the format of the inventory, size, and publisher reports. Defaults to CSV.
+    pub fn report_format(mut self, report_format: ReportFormat) -> Self {
+        self.report_format = report_format;
+        self
+    }
+
+    /// Writes the dependency graph of the mirrored crates to the file as JSON.
+    pub fn dependency_graph<P: Into<PathBuf>>(mut self, graph_path: P) -> Self {
+        self.dependency_graph = Some(graph_path.into());
+        self
+    }
+
+    /// Opens the index used to resolve the selection, and those of the secondary registries.
+    pub fn build(mut self) -> Result<Mirror> {
+        if self.targets.len() > src_registry::MAX_TARGETS {
+            return Err(ErrorKind::TooManyTargets(self.targets.len()).into());
+        }
+        let targets = builtin_targets(&self.targets)?;
+        for spec in &self.selection.crates {
+            builtin_targets(&spec.targets)?;
+        }
+        self.endpoints.validate()?;
+        if self.offline {
+            self.check_offline()?;
+        }
+        let (mut index, source) = match (&self.index_path, &self.endpoints.index_url) {
+            (Some(index_path), registry) => {
+                let index_url = registry.as_deref().unwrap_or(common::CRATES_IO_INDEX);
+                // crates_index would clone the index into the path if it isn't one.
+                if git2::Repository::open(index_path).is_err() {
+                    return Err(ErrorKind::IndexNotFound(index_path.clone()).into());
+                }
+                let index = crates_index::Index::with_path(index_path, index_url)?;
+                let index = Index::new(index)?;
+                let source = match registry {
+                    Some(index_url) => {
+                        let token = self.token(index_url, &self.registry_token)?;
+                        registry_source(&index, index_url, token)?
+                    }
+                    None => CrateSource::crates_io(),
+                };
+                (index, source)
+            }
+            (None, Some(index_url)) => open_registry(
+                index_url,
+                self.token(index_url, &self.registry_token)?,
+                self.offline,
+            )?,
+            (None, None) => {
+                if self.offline && !has_cargo_index(common::CRATES_IO_INDEX) {
+                    let step = format!("fetching the index of {}", common::CRATES_IO_INDEX);
+                    return Err(ErrorKind::Offline(step).into());
+                }
+                let index = crates_index::Index::new_cargo_default()?;
+                (Index::new(index)?, CrateSource::crates_io())
+            }
+        };
+        let source = match &self.endpoints.dl {
+            // The registry's downloads are reached through another URL, e.g. a caching proxy.
+            Some(dl) => CrateSource::new(
+                source.index_url(),
+                dl.clone(),
+                source.token().map(|t| t.to_string()),
+            ),
+            None => source,
+        };
+        if let Some(commit) = &self.index_commit {
+            index.pin(commit)?;
+        }
+        let secondary = self
+            .secondary_registries
+            .iter()
+            .map(|(index_url, token)| {
+                open_registry(index_url, self.token(index_url, token)?, self.offline)
+            })
+            .collect::<Result<_>>()?;
+        let status = Arc::new(StatusWriter::new(
+            Arc::clone(&self.progress),
+            self.mirror_dir_path.clone(),
+        ));
+        let recorder = Arc::new(RunRecorder::new(status));
+        self.progress = recorder.clone();
+        let http_client = self.http_config.client()?;
+        Ok(Mirror {
+            options: self,
+            index,
+            source,
+            secondary,
+            targets,
+            http_client,
+            recorder,
+        })
+    }
+
+    /// Fails with the first configured step that needs the network.
+    fn check_offline(&self) -> Result<()> {
+        let spec = &self.selection;
+        let step = if spec.most_downloaded.is_some() {
+            Some("selecting the most downloaded crates".to_string())
+        } else if spec.recently_updated.is_some() {
+            Some("selecting the recently updated crates".to_string())
+        } else if let Some(category) = spec.categories.first() {
+            Some(format!(
+                "selecting the crates in category {}",
+                category.slug
+            ))
+        } else if let Some(keyword) = spec.keywords.first() {
+            Some(format!(
+                "selecting the crates with keyword {}",
+                keyword.keyword
+            ))
+        } else if let Some(owner) = spec.owners.first() {
+            Some(format!("selecting the crates owned by {owner}"))
+        } else if let Some(search) = spec.searches.first() {
+            Some(format!("searching crates.io for {}", search.query))
+        } else if let Some(url) = spec.files.iter().find_map(|f| selection::list_url(f)) {
+            Some(format!("fetching the crate list {url}"))
+        } else if let Some(mirror) = spec.mirrors.iter().find(|m| is_remote_url(m)) {
+            Some(format!("cloning the mirror {mirror}"))
+        } else if self.with_owners {
+            Some(format!(
+                "fetching crate owners from {}",
+                self.endpoints.api_url
+            ))
+        } else if self.with_repos {
+            Some("cloning the crates' source repositories".to_string())
+        } else if self.audit && self.advisory_db.is_none() {
+            Some("fetching the RustSec advisory database".to_string())
+        } else {
+            self.publish_to
+                .as_ref()
+                .map(|api_url| format!("publishing to {api_url}"))
+        };
+        match step {
+            Some(step) => Err(ErrorKind::Offline(step).into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the token to download from the registry with: the one given,
+    /// or Cargo's if enabled.
+    fn token(&self, index_url: &str, token: &Option<String>) -> Result<Option<String>> {
+        match token {
+            Some(token) => Ok(Some(token.clone())),
+            None if self.cargo_credentials => Ok(credentials::cargo_token(index_url)?),
+            None => Ok(None),
+        }
+    }
+}
+
+/// The crate versions chosen for the mirror.
+pub struct Selection {
+    /// The top-level crates along with every dependency they require.
+    pub crates: HashSet<Version>,
+    /// Why each crate version was selected.
+    pub provenance: Provenance,
+    pub num_top_level: usize,
+    /// The dependencies left out for being deeper than the maximum depth, if any,
+    /// each with the crates at the maximum depth depending on it.
+    pub beyond_max_depth: HashMap<Version, HashSet<Version>>,
+    /// The git and path packages of the lockfiles and vendor directories selected from, which
+    /// the mirror can't cover.
+    pub unmirrorable: Vec<Unmirrorable>,
+    /// The vulnerable crate versions replaced by patched versions, if any.
+    pub substitutions: Vec<Substitution>,
+    advisory_db: Option<AdvisoryDb>,
+}
+
+/// The expected size of the .crate files of a selection, from the sizes the registries tell
+/// without downloading the files.
+pub struct SizeEstimate {
+    pub num_crates: usize,
+    /// The total size of the crates whose size is known.
+    pub known_bytes: u64,
+    /// The number of crates whose size isn't known, e.g. offline.
+    pub num_unknown: usize,
+}
+
+impl SizeEstimate {
+    /// The expected total size, taking the crates whose size isn't known to be the average size
+    /// of the others.
+    pub fn estimated_bytes(&self) -> u64 {
+        let num_known = (self.num_crates - self.num_unknown) as u64;
+        if num_known == 0 {
+            return 0;
+        }
+        self.known_bytes + self.known_bytes / num_known * self.num_unknown as u64
+    }
+}
+
+/// The top-level crates selected, before their dependencies are resolved.
+struct TopLevel {
+    crates: HashSet<Version>,
+    provenance: Provenance,
+    /// The git and path packages of the lockfiles and vendor directories selected from.
+    unmirrorable: Vec<Unmirrorable>,
+    /// The entries of the structured crate lists, with their own resolution options.
+    listed: Vec<CrateSpec>,
+}
+
+/// A configured mirror, ready to be resolved and populated.
+pub struct Mirror {
+    options: MirrorBuilder,
+    index: Index,
+    source: CrateSource,
+    /// The indexes of the secondary registries, each with its source.
+    secondary: Vec<(Index, CrateSource)>,
+    targets: Vec<&'static TargetInfo>,
+    /// The client of the run's HTTP requests, with the configured HTTP settings.
+    http_client: reqwest::Client,
+    /// Records the run's progress for its report, passing it on to the configured observer.
+    recorder: Arc<RunRecorder>,
+}
+
+impl Mirror {
+    /// Selects the top-level crates and resolves every dependency they require,
+    /// without touching the mirror directory.
+    pub fn resolve(&self) -> Result<Selection> {
+        let options = &self.options;
+        let progress = self.options.progress.as_ref();
+
+        let TopLevel {
+            mut crates,
+            mut provenance,
+            unmirrorable,
+            listed,
+        } = self.phase(Phase::Selection, || self.select())?;
+        if crates.is_empty() {
+            return Err(ErrorKind::NoCratesSelected.into());
+        }
+        let num_top_level = crates.len();
+
+        let beyond_max_depth = self.phase(Phase::Resolution, || {
+            progress.resolution_started(num_top_level);
+            let unlocked = unlocked(&crates, &provenance);
+            let mut src_registry = self.src_registry(&unlocked, &listed)?;
+            let dependencies = src_registry.get_dependencies(&unlocked)?;
+            let num_deps = dependencies.len();
+            crates.extend(dependencies);
+            provenance.add_dependents(src_registry.dependents());
+            progress.resolution_finished(num_deps);
+            progress.phase_crates(Phase::Resolution, num_deps);
+            Ok(src_registry.beyond_max_depth().clone())
+        })?;
+        self.report_beyond_max_depth(&beyond_max_depth);
+
+        let (advisory_db, substitutions) = if options.audit {
+            let (advisory_db, substitutions) =
+                self.phase(Phase::Audit, || self.audit(&mut crates, &mut provenance))?;
+            (Some(advisory_db), substitutions)
+        } else {
+            (None, Vec::new())
+        };
+        progress.graph_analyzed(&provenance.graph_stats(&crates));
+
+        Ok(Selection {
+            crates,
+            provenance,
+            num_top_level,
+            beyond_max_depth,
+            unmirrorable,
+            substitutions,
+            advisory_db,
+        })
+    }
+
+    /// Estimates the size of the .crate files of the selection, without downloading them,
+    /// e.g. for a dry run. Offline, no size is known.
+    pub fn estimate_size(&self, selection: &Selection) -> Result<SizeEstimate> {
+        let num_crates = selection.crates.len();
+        let sizes = self.crate_sizes(Vec::from_iter(&selection.crates))?;
+        let known = Vec::from_iter(sizes.into_iter().flatten());
+        Ok(SizeEstimate {
+            num_crates,
+            known_bytes: known.iter().sum(),
+            num_unknown: num_crates - known.len(),
+        })
+    }
+
+    /// Looks up the sizes of the crates' .crate files from their registries, in the order of
+    /// the crates. Sizes that aren't known, or can't be looked up offline, are `None`.
+    fn crate_sizes(&self, crates: Vec<&Version>) -> Result<Vec<Option<u64>>> {
+        let mut sizes = vec![None; crates.len()];
+        if self.options.offline {
+            return Ok(sizes);
+        }
+        let downloader = self.downloader();
+        let mut indices = Vec::new();
+        let mut requests = Vec::new();
+        for (i, crat) in crates.into_iter().enumerate() {
+            let source = self.crate_source(crat);
+            if let Ok(url) = source.crate_url(crat) {
+                indices.push(i);
+                requests.push((url, source.token().map(|t| t.to_string())));
+            }
+        }
+        let found = downloader::sizes(&downloader, requests)
+            .map_err(|e| Error::new(ErrorKind::EstimateSize(e)))?;
+        for (i, size) in indices.into_iter().zip(found) {
+            sizes[i] = size;
+        }
+        Ok(sizes)
+    }
+
+    /// Returns the source of the crate's registry.
+    fn crate_source(&self, crat: &Version) -> &CrateSource {
+        let secondary = match crat.registry() {
+            Some(registry) => self
+                .secondary
+                .iter()
+                .map(|(_, source)| source)
+                .find(|source| source.index_url() == registry),
+            None => None,
+        };
+        secondary.unwrap_or(&self.source)
+    }
+
+    /// Resolves the selection, populates the mirror with it, and produces the configured outputs.
+    /// The hooks are told when the run ends, whether or not it succeeded. A report of the run
+    /// is then written to `.micrio/runs/` in the mirror directory, if it exists.
+    pub fn run(&self) -> Result<Selection> {
+        let mut result = self.populate();
+        let succeeded = result.is_ok();
+        for hook in &self.options.hooks {
+            if let Err(e) = hook.run_finished(&self.options.mirror_dir_path, succeeded) {
+                // Don't let a hook failure mask the error that failed the run.
+                if result.is_ok() {
+                    result = Err(e.into());
+                } else {
+                    warn!("{e}");
+                }
+            }
+        }
+        if let Err(e) = self.write_run_report(succeeded) {
+            if result.is_ok() {
+                result = Err(e);
+            } else {
+                warn!("{e}");
+            }
+        }
+        self.options.progress.run_finished(result.is_ok());
+        result
+    }
+
+    /// Records the crate versions in the freshly written index of the mirror in its state
+    /// database, if it keeps one.
+    fn record_state(&self, selection: &Selection) -> Result<()> {
+        if !self.options.state_db {
+            return Ok(());
+        }
+        let mut state_db = StateDb::open(&self.options.mirror_dir_path)?;
+        state_db.record_crates(&selection.crates, &selection.provenance)?;
+        Ok(())
+    }
+
+    fn write_run_report(&self, succeeded: bool) -> Result<()> {
+        let options = &self.options;
+        // Runs failing before the mirror directory is created leave nothing to audit.
+        if !options.mirror_dir_path.is_dir() {
+            return Ok(());
+        }
+        let report = self.recorder.report(
+            SelectionRecord::new(&options.selection, &options.targets),
+            self.source.index_url(),
+            Some(self.index.head_commit()),
+            succeeded,
+        );
+        let report_path = report.write(&options.mirror_dir_path)?;
+        info!(path = %report_path.to_string_lossy(), "run report written");
+        if let Some(keep) = options.keep_run_reports {
+            let num_removed = run_report::remove_old(&options.mirror_dir_path, keep)?;
+            info!(num_removed, "old run reports removed");
+        }
+        Ok(())
+    }
+
+    fn populate(&self) -> Result<Selection> {
+        let options = &self.options;
+        let progress = self.options.progress.as_ref();
+        if options.reproducible {
+            let commit = self.index.head_commit();
+            progress.info(&format!("Resolving against index commit {commit}."));
+            if options.selection.queries_crates_io() {
+                warn!(
+                    "the crates selected from live crates.io data, e.g. the most downloaded \
+                     ones, may differ between runs"
+                );
+            }
+        }
+        let streaming = options.streaming
+            && !options.reproducible
+            && !options.index_only
+            && !(options.audit && (options.exclude_vulnerable || options.substitute_patched));
+        let (selection, dst_registry) = if options.download_only {
+            self.download_only()?
+        } else if streaming {
+            let (selection, dst_registry) = self.stream()?;
+            self.record_state(&selection)?;
+            (selection, dst_registry)
+        } else {
+            let selection = self.resolve()?;
+            let dst_registry = self.phase(Phase::Index, || {
+                let dst_registry = self.dst_registry()?;
+                dst_registry.populate_index(&selection.crates)?;
+                self.record_state(&selection)?;
+                progress.phase_crates(Phase::Index, selection.crates.len());
+                Ok(dst_registry)
+            })?;
+            if options.index_only {
+                progress.info(&format!(
+                    "Index of {} crates written; the .crate files are left to a download-only run.",
+                    selection.crates.len()
+                ));
+                return Ok(selection);
+            }
+            self.phase(Phase::Download, || {
+                dst_registry.populate_registry(
+                    &selection.crates,
+                    &options.hooks,
+                    progress,
+                    &options.cancel,
+                )?;
+                progress.phase_crates(Phase::Download, selection.crates.len());
+                Ok(())
+            })?;
+            (selection, dst_registry)
+        };
+        let crates = &selection.crates;
+
+        if options.check_resolvable {
+            self.phase(Phase::Resolvability, || {
+                self.check_resolvable(&selection, &dst_registry)
+            })?;
+        }
+
+        if options.with_metadata {
+            self.phase(Phase::Metadata, || {
+                let counts = metadata::write_metadata(&dst_registry, crates)?;
+                progress.info(&format!(
+                    "{} READMEs found, {} crates indexed for search.",
+                    counts.num_readmes, counts.num_indexed
+                ));
+                Ok(())
+            })?;
+        }
+
+        if options.with_licenses {
+            self.phase(Phase::Licenses, || {
+                let counts = licenses::write_licenses(&dst_registry, crates)?;
+                progress.info(&format!(
+                    "{} license files extracted from {} crates; {} crates have none.",
+                    counts.num_files, counts.num_crates, counts.num_without
+                ));
+                Ok(())
+            })?;
+        }
+
+        if options.with_owners {
+            self.phase(Phase::Owners, || {
+                let num_crates = owners::write_owners(
+                    &dst_registry,
+                    crates,
+                    &options.endpoints.api_url,
+                    &self.http_client,
+                    options.rate_limiter.as_deref(),
+                    progress,
+                    &options.cancel,
+                )?;
+                progress.info(&format!("Owners of {num_crates} crates stored."));
+                Ok(())
+            })?;
+        }
+
+        if options.with_repos && !options.download_only {
+            self.phase(Phase::Repos, || {
+                let top_level = HashSet::from_iter(
+                    crates
+                        .iter()
+                        .filter(|crat| selection.provenance.is_top_level(crat)),
+                );
+                let counts =
+                    repos::mirror_repos(&dst_registry, &top_level, progress, &options.cancel)?;
+                progress.info(&format!(
+                    "{} repositories cloned, {} updated, {} failed; {} top-level crates without a repository.",
+                    counts.num_cloned, counts.num_updated, counts.num_failed, counts.num_without
+                ));
+                Ok(())
+            })?;
+        }
+
+        if options.html_index {
+            self.phase(Phase::HtmlIndex, || {
+                Ok(site::write_index_html(
+                    &dst_registry,
+                    crates,
+                    options.with_metadata,
+                )?)
+            })?;
+        }
+
+        if options.sha256sums {
+            self.phase(Phase::Checksums, || {
+                if !options.state_db {
+                    let num_files = checksums::write_sha256sums(dst_registry.path())?;
+                    progress.info(&format!("{num_files} files covered."));
+                    return Ok(());
+                }
+                let mut state_db = StateDb::open(dst_registry.path())?;
+                // The .crate files were checked against their index checksums when stored.
+                let known_digests =
+                    HashMap::from_iter(state_db.crate_checksums()?.into_iter().map(
+                        |(name, version, checksum)| {
+                            (
+                                PathBuf::from(dst_registry.crate_file_link(&name, &version)),
+                                checksum,
+                            )
+                        },
+                    ));
+                let digests =
+                    checksums::write_sha256sums_with(dst_registry.path(), &known_digests)?;
+                state_db.record_file_digests(&digests)?;
+                progress.info(&format!("{} files covered.", digests.len()));
+                Ok(())
+            })?;
+        }
+
+        if let Some(api_url) = &options.publish_to {
+            self.phase(Phase::Publish, || {
+                let summary = publish::publish(
+                    &dst_registry,
+                    crates,
+                    api_url,
+                    &self.http_client,
+                    options.publish_token.as_deref(),
+                    progress,
+                    &options.cancel,
+                )?;
+                progress.info(&format!(
+                    "{} published to {api_url}, {} already present.",
+                    summary.published, summary.already_published
+                ));
+                Ok(())
+            })?;
+        }
+
+        if options.report.is_some()
+            || options.size_report.is_some()
+            || options.publisher_report.is_some()
+            || options.dependency_graph.is_some()
+        {
+            self.phase(Phase::Reports, || {
+                self.write_reports(&dst_registry, &selection)
+            })?;
+        }
+
+        if !options.replicas.is_empty() {
+            self.phase(Phase::Replicate, || {
+                // An unreachable replica doesn't keep the others from being updated.
+                let mut first_error = None;
+                for replica in &options.replicas {
+                    match replicate::push(dst_registry.path(), replica) {
+                        Ok(()) => progress.info(&format!("Pushed to {replica}.")),
+                        Err(e) => {
+                            warn!("{e}");
+                            first_error.get_or_insert(e);
+                        }
+                    }
+                }
+                match first_error {
+                    Some(e) => Err(e.into()),
+                    None => Ok(()),
+                }
+            })?;
+        }
+
+        Ok(selection)
+    }
+
+    /// Downloads the crate versions in the existing index of the mirror, which stays as it is.
+    fn download_only(&self) -> Result<(Selection, DstRegistry)> {
+        let options = &self.options;
+        let progress = self.options.progress.as_ref();
+        let dst_registry = self.dst_registry()?;
+        let crates = self.phase(Phase::Download, || {
+            let crates = match StateDb::open_existing(dst_registry.path())? {
+                Some(state_db) => {
+                    let crates = state_db.crates()?;
+                    progress.info(&format!(
+                        "{} crates found in the state database.",
+                        crates.len()
+                    ));
+                    crates
+                }
+                None => {
+                    let crates = dst_registry.read_index()?;
+                    progress.info(&format!("{} crates found in the index.", crates.len()));
+                    crates
+                }
+            };
+            dst_registry.populate_registry(&crates, &options.hooks, progress, &options.cancel)?;
+            progress.phase_crates(Phase::Download, crates.len());
+            Ok(crates)
+        })?;
+        let selection = Selection {
+            crates,
+            provenance: Provenance::new(),
+            num_top_level: 0,
+            beyond_max_depth: HashMap::new(),
+            unmirrorable: Vec::new(),
+            substitutions: Vec::new(),
+            advisory_db: None,
+        };
+        Ok((selection, dst_registry))
+    }
+
+    /// Selects the top-level crates, then resolves their dependencies on another thread while
+    /// downloading the crates resolved so far.
+    fn stream(&self) -> Result<(Selection, DstRegistry)> {
+        let options = &self.options;
+        let progress = self.options.progress.as_ref();
+
+        let TopLevel {
+            crates: top_level,
+            mut provenance,
+            unmirrorable,
+            listed,
+        } = self.phase(Phase::Selection, || self.select())?;
+        if top_level.is_empty() {
+            return Err(ErrorKind::NoCratesSelected.into());
+        }
+        let num_top_level = top_level.len();
+
+        let dst_registry = self.phase(Phase::Index, || self.dst_registry())?;
+        let (crates, (dependents, beyond_max_depth)) = self.phase(Phase::Download, || {
+            let (sender, receiver) = mpsc::sync_channel(STREAMING_QUEUE_SIZE);
+            let dst_registry = &dst_registry;
+            std::thread::scope(|scope| {
+                // The downloads run on another thread so the index stays on this one.
+                let downloader = scope.spawn(move || {
+                    dst_registry.populate_streaming(
+                        receiver,
+                        &options.hooks,
+                        progress,
+                        &options.cancel,
+                    )
+                });
+                let unlocked = unlocked(&top_level, &provenance);
+                let dependents = self.resolve_streaming(&top_level, &unlocked, &listed, sender);
+                let crates = downloader.join().expect("download thread panicked");
+                // If the downloads failed, resolution stopped because of it.
+                let crates = crates?;
+                let dependents = dependents.map_err(|mut e| {
+                    e.phase = Some(Phase::Resolution);
+                    e
+                })?;
+                progress.phase_crates(Phase::Download, crates.len());
+                Ok((crates, dependents))
+            })
+        })?;
+        provenance.add_dependents(&dependents);
+        self.report_beyond_max_depth(&beyond_max_depth);
+
+        let mut selection = Selection {
+            crates,
+            provenance,
+            num_top_level,
+            beyond_max_depth,
+            unmirrorable,
+            substitutions: Vec::new(),
+            advisory_db: None,
+        };
+        if options.audit {
+            let (advisory_db, substitutions) = self.phase(Phase::Audit, || {
+                self.audit(&mut selection.crates, &mut selection.provenance)
+            })?;
+            selection.advisory_db = Some(advisory_db);
+            selection.substitutions = substitutions;
+        }
+        let graph_stats = selection.provenance.graph_stats(&selection.crates);
+        progress.graph_analyzed(&graph_stats);
+        Ok((selection, dst_registry))
+    }
+
+    /// Sends the top-level crates, then each dependency of the unlocked ones as soon as it is
+    /// resolved. Returns, for each dependency, the crates that directly depend on it, and the
+    /// dependencies left out for being deeper than the maximum depth.
+    fn resolve_streaming(
+        &self,
+        top_level: &HashSet<Version>,
+        unlocked: &HashSet<Version>,
+        listed: &[CrateSpec],
+        sender: mpsc::SyncSender<Version>,
+    ) -> Result<(Dependents, Dependents)> {
+        let options = &self.options;
+        let progress = options.progress.as_ref();
+        progress.resolution_started(top_level.len());
+        for crat in top_level {
+            if sender.send(crat.clone()).is_err() {
+                // The downloads failed.
+                return Err(ErrorKind::Cancelled.into());
+            }
+        }
+        let mut src_registry = self
+            .src_registry(unlocked, listed)?
+            .on_dependency_found(|crat| sender.send(crat.clone()).is_ok());
+        let num_deps = src_registry.get_dependencies(unlocked)?.len();
+        progress.resolution_finished(num_deps);
+        let beyond_max_depth = src_registry.beyond_max_depth().clone();
+        Ok((src_registry.into_dependents(), beyond_max_depth))
+    }
+
+    /// Lists the dependencies left out for being deeper than the maximum depth, for review.
+    fn report_beyond_max_depth(&self, beyond_max_depth: &Dependents) {
+        let max_depth = match self.options.max_depth {
+            Some(max_depth) if !beyond_max_depth.is_empty() => max_depth,
+            _ => return,
+        };
+        let progress = self.options.progress.as_ref();
+        progress.info(&format!(
+            "{} dependencies beyond the maximum depth of {max_depth} left out:",
+            beyond_max_depth.len()
+        ));
+        let mut left_out = Vec::from_iter(beyond_max_depth);
+        left_out.sort_by(|(a, _), (b, _)| (a.name(), a.version()).cmp(&(b.name(), b.version())));
+        for (crat, dependents) in left_out {
+            let mut dependents = Vec::from_iter(
+                dependents
+                    .iter()
+                    .map(|d| format!("{}@{}", d.name(), d.version())),
+            );
+            dependents.sort();
+            progress.info(&format!(
+                "  {}@{}, required by {}",
+                crat.name(),
+                crat.version(),
+                dependents.join(", ")
+            ));
+        }
+    }
+
+    /// Configures dependency resolution against the index and the secondary registries' indexes.
+    /// The crates listed in structured crate lists resolve with their entries' options.
+    fn src_registry(
+        &self,
+        top_level: &HashSet<Version>,
+        listed: &[CrateSpec],
+    ) -> Result<SrcRegistry<'_>> {
+        let options = &self.options;
+        let mut src_registry =
+            SrcRegistry::new(&self.index, options.progress.as_ref(), &options.cancel)
+                .registry_url(self.source.index_url())
+                .targets(self.targets.clone())
+                .features(options.selection.features.clone())
+                .top_level_options(self.top_level_options(top_level, listed)?)
+                .deny_warnings(options.deny_warnings)
+                .max_depth(options.max_depth)
+                .overrides(options.overrides.clone())
+                .exclusions(options.selection.exclusions.clone());
+        for (index, source) in &self.secondary {
+            src_registry = src_registry.secondary_registry(source.index_url(), index);
+        }
+        Ok(src_registry)
+    }
+
+    /// The resolution options of the top-level crates matching a crate spec that has its own,
+    /// given or listed in a structured crate list.
+    fn top_level_options(
+        &self,
+        top_level: &HashSet<Version>,
+        listed: &[CrateSpec],
+    ) -> Result<HashMap<Version, TopLevelOptions>> {
+        let mut top_level_options = HashMap::new();
+        for spec in self.options.selection.crates.iter().chain(listed) {
+            if spec.features.is_none()
+                && spec.targets.is_empty()
+                && !spec.dev_dependencies
+                && spec.exclusions.is_empty()
+            {
+                continue;
+            }
+            let options = TopLevelOptions {
+                features: spec.features.clone(),
+                targets: builtin_targets(&spec.targets)?,
+                dev_dependencies: spec.dev_dependencies,
+                exclusions: spec.exclusions.clone(),
+            };
+            for crat in top_level.iter().filter(|crat| spec.matches(crat)) {
+                top_level_options.insert(crat.clone(), options.clone());
+            }
+        }
+        Ok(top_level_options)
+    }
+
+    /// Checks the top-level crates resolve against the populated mirror's index.
+    fn check_resolvable(&self, selection: &Selection, dst_registry: &DstRegistry) -> Result<()> {
+        let top_level = Vec::from_iter(
+            selection
+                .crates
+                .iter()
+                .filter(|crat| selection.provenance.is_top_level(crat)),
+        );
+        let index_dir_path = dst_registry.path().join(dst_registry::INDEX_DIR);
+        let unresolvable =
+            resolvability::check_resolvable(index_dir_path, &top_level, &self.targets)?;
+        for u in &unresolvable {
+            warn!(
+                target: warnings::UNRESOLVABLE,
+                crate_name = %u.crate_name,
+                crate_version = %u.crate_version,
+                required_by = %u.required_by,
+                dependency = %u.dependency_name,
+                requirement = %u.requirement,
+                "top-level crate can't be resolved against the mirror"
+            );
+        }
+        let num_crates = HashSet::<_>::from_iter(
+            unresolvable
+                .iter()
+                .map(|u| (&u.crate_name, &u.crate_version)),
+        )
+        .len();
+        if num_crates > 0 && self.options.deny_warnings {
+            return Err(resolvability::Error::Unresolvable {
+                num_crates,
+                num_top_level: top_level.len(),
+            }
+            .into());
+        }
+        self.options.progress.info(&format!(
+            "{} of {} top-level crates resolve against the mirror.",
+            top_level.len() - num_crates,
+            top_level.len()
+        ));
+        Ok(())
+    }
+
+    /// Creates the destination registry, emptying the mirror directory.
+    fn dst_registry(&self) -> Result<DstRegistry> {
+        let options = &self.options;
+        let dst_registry = if options.download_only {
+            // The existing index is kept, along with its layout.
+            match &options.storage {
+                Some(storage) => {
+                    DstRegistry::open_with_storage(&options.mirror_dir_path, Arc::clone(storage))?
+                }
+                None => DstRegistry::open(&options.mirror_dir_path)?,
+            }
+        } else {
+            // The index is rewritten, so the state database would list the previous crates.
+            StateDb::remove(&options.mirror_dir_path)?;
+            if !options.with_repos {
+                repos::remove_repos(&options.mirror_dir_path)?;
+            }
+            let dst_registry = match &options.storage {
+                Some(storage) => {
+                    DstRegistry::with_storage(&options.mirror_dir_path, Arc::clone(storage))?
+                }
+                None => DstRegistry::new(&options.mirror_dir_path)?,
+            };
+            dst_registry
+                .layout(options.layout)
+                .auth_required(options.auth_required)
+        };
+        let mut dst_registry = dst_registry
+            .source(self.source.clone())
+            .reproducible(options.reproducible)
+            .require_all(options.deny_warnings)
+            .offline(options.offline);
+        for (_, source) in &self.secondary {
+            dst_registry = dst_registry.secondary_source(source.clone());
+        }
+        for dir_path in &options.link_dirs {
+            dst_registry = dst_registry.link_from(dir_path);
+        }
+        for dir_path in &options.seed_dirs {
+            dst_registry = dst_registry.seed_from(dir_path);
+        }
+        dst_registry = dst_registry.downloader(self.downloader());
+        if let Some(serve_url) = options.endpoints.serve_url()? {
+            dst_registry = dst_registry.serve_url(serve_url);
+        }
+        Ok(dst_registry)
+    }
+
+    /// Returns the downloader of the .crate files, going through the rate limiter, if any.
+    fn downloader(&self) -> Arc<dyn CrateDownloader> {
+        let downloader = match &self.options.downloader {
+            Some(downloader) => Arc::clone(downloader),
+            None => Arc::new(HttpDownloader::with_client(self.http_client.clone())),
+        };
+        match &self.options.rate_limiter {
+            Some(limiter) => Arc::new(RateLimitedDownloader::new(downloader, Arc::clone(limiter))),
+            None => downloader,
+        }
+    }
+
+    /// Runs a phase, reporting its start, end, and failure to the progress observer.
+    fn phase<T, F: FnOnce() -> Result<T>>(&self, phase: Phase, f: F) -> Result<T> {
+        let progress = self.options.progress.as_ref();
+        if self.options.cancel.is_cancelled() {
+            return Err(ErrorKind::Cancelled.into());
+        }
+        let _span = info_span!("phase", ?phase).entered();
+        progress.phase_started(phase);
+        match f() {
+            Ok(value) => {
+                progress.phase_finished(phase);
+                Ok(value)
+            }
+            Err(mut e) => {
+                e.phase.get_or_insert(phase);
+                progress.failure(phase, None, &e);
+                Err(e)
+            }
+        }
+    }
+
+    /// Selects the top-level crates described by the selection spec, minus its exclusions.
+    fn select(&self) -> Result<TopLevel> {
+        let spec = &self.options.selection;
+        if self.options.endpoints.index_url.is_some() && spec.queries_crates_io() {
+            warn!(
+                "the crates selected from live crates.io data, e.g. the most downloaded ones, \
+                 may not be in the registry"
+            );
+        }
+        let mut top_level_builder =
+            TopLevelBuilder::new(&self.index, self.options.progress.as_ref())?
+                .deny_warnings(self.options.deny_warnings)
+                .http_client(self.http_client.clone())
+                .min_downloads(spec.min_downloads);
+        if let Some(limiter) = &self.options.rate_limiter {
+            top_level_builder = top_level_builder.rate_limiter(Arc::clone(limiter));
+        }
+
+        let mut crates = HashSet::new();
+        let mut provenance = Provenance::new();
+        let mut add = |selected: Vec<Version>, source: &str| {
+            info!(source, num_crates = selected.len(), "crates selected");
+            for crat in selected {
+                if spec.is_excluded(&crat) {
+                    continue;
+                }
+                if crat.is_yanked() {
+                    info!(
+                        source,
+                        crate_name = crat.name(),
+                        crate_version = crat.version(),
+                        "yanked version selected since it is pinned"
+                    );
+                }
+                provenance.add_top_level(&crat, source);
+                crates.insert(crat);
+            }
+        };
+        for crate_spec in &spec.crates {
+            let crat = top_level_builder.get_crate(crate_spec)?;
+            add(vec![crat], &format!("crate {crate_spec}"));
+        }
+        let mut listed = Vec::new();
+        for file_path in &spec.files {
+            let source = format!("from-file {}", file_path.to_string_lossy());
+            let (crates, specs) = top_level_builder.from_file(file_path)?;
+            add(crates, &source);
+            listed.extend(specs);
+        }
+        if let Some(n) = spec.most_downloaded {
+            let source = format!("most-downloaded {n}");
+            add(top_level_builder.get_n_most_downloaded(n)?, &source);
+        }
+        if let Some(n) = spec.recently_updated {
+            let source = format!("recently-updated {n}");
+            add(top_level_builder.get_n_recently_updated(n)?, &source);
+        }
+        let mut unmirrorable = Vec::new();
+        let mut locked_crates = Vec::new();
+        for lockfile_path in &spec.lockfiles {
+            let source = format!("lockfile {}", lockfile_path.to_string_lossy());
+            let (locked, unlocked) = top_level_builder.from_lockfile(lockfile_path)?;
+            locked_crates.extend(locked.iter().cloned());
+            add(locked, &source);
+            unmirrorable.extend(unlocked);
+        }
+        for vendor_dir_path in &spec.vendor_dirs {
+            let source = format!("from-vendor {}", vendor_dir_path.to_string_lossy());
+            let (vendored, unvendored) = top_level_builder.from_vendor_dir(vendor_dir_path)?;
+            add(vendored, &source);
+            unmirrorable.extend(unvendored);
+        }
+        for mirror in &spec.mirrors {
+            let source = format!("from-mirror {mirror}");
+            add(top_level_builder.from_mirror(mirror)?, &source);
+        }
+        for category in &spec.categories {
+            let source = format!("category {}", category.slug);
+            add(top_level_builder.from_category(category)?, &source);
+        }
+        for keyword in &spec.keywords {
+            let source = format!("keyword {}", keyword.keyword);
+            add(top_level_builder.from_keyword(keyword)?, &source);
+        }
+        for owner in &spec.owners {
+            let source = format!("owned-by {owner}");
+            add(top_level_builder.from_owner(owner)?, &source);
+        }
+        for search in &spec.searches {
+            let source = format!("search {}", search.query);
+            add(top_level_builder.from_search(search)?, &source);
+        }
+        for crat in locked_crates.iter().filter(|crat| crates.contains(*crat)) {
+            provenance.lock(crat);
+        }
+        self.report_unmirrorable(&mut unmirrorable)?;
+        if let Some(picker) = &self.options.picker {
+            self.pick(picker.as_ref(), &mut crates, &mut provenance)?;
+        }
+        self.options
+            .progress
+            .phase_crates(Phase::Selection, crates.len());
+        Ok(TopLevel {
+            crates,
+            provenance,
+            unmirrorable,
+            listed,
+        })
+    }
+
+    /// Offers the top-level crates to the picker, keeping the ones picked.
+    fn pick(
+        &self,
+        picker: &dyn TopLevelPicker,
+        crates: &mut HashSet<Version>,
+        provenance: &mut Provenance,
+    ) -> Result<()> {
+        let mut sorted = Vec::from_iter(crates.iter());
+        sorted.sort_by(|a, b| (a.name(), a.version()).cmp(&(b.name(), b.version())));
+        let sizes = self.crate_sizes(sorted.clone())?;
+        let candidates = Vec::from_iter(sorted.into_iter().zip(sizes).map(|(crat, size)| {
+            let num_dependencies = crat
+                .dependencies()
+                .iter()
+                .filter(|d| d.kind() != DependencyKind::Dev)
+                .count();
+            Candidate {
+                crat: crat.clone(),
+                sources: provenance.sources(crat),
+                size,
+                num_dependencies,
+            }
+        }));
+        let picked = match picker.pick(candidates) {
+            Ok(Some(picked)) => picked,
+            Ok(None) => return Err(ErrorKind::Cancelled.into()),
+            Err(e) => return Err(Error::new(ErrorKind::Pick(e))),
+        };
+        let num_candidates = crates.len();
+        crates.retain(|crat| {
+            let keep = picked.contains(crat);
+            if !keep {
+                provenance.remove(crat);
+            }
+            keep
+        });
+        info!(
+            num_picked = crates.len(),
+            num_candidates, "top-level crates picked"
+        );
+        Ok(())
+    }
+
+    /// Lists the git and path packages the mirror can't cover, so offline builds needing them
+    /// don't come as a surprise. With `deny_warnings`, git packages fail the run.
+    fn report_unmirrorable(&self, unmirrorable: &mut Vec<Unmirrorable>) -> Result<()> {
+        if unmirrorable.is_empty() {
+            return Ok(());
+        }
+        unmirrorable.sort_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)));
+        unmirrorable.dedup_by(|a, b| (&a.name, &a.version) == (&b.name, &b.version));
+        let mut num_git = 0;
+        for package in unmirrorable.iter() {
+            if let Origin::Git(url) = &package.origin {
+                num_git += 1;
+                warn!(
+                    target: warnings::UNMIRRORABLE,
+                    crate_name = %package.name,
+                    crate_version = %package.version,
+                    repository = url.as_deref().unwrap_or("unknown"),
+                    "git dependency can't be mirrored, builds still need network access to it"
+                );
+            }
+        }
+        if num_git > 0 && self.options.deny_warnings {
+            return Err(top_level::Error::GitDependencies {
+                num_packages: num_git,
+            }
+            .into());
+        }
+
+        let progress = self.options.progress.as_ref();
+        progress.info(&format!(
+            "{} packages can't be mirrored, since they don't come from a registry:",
+            unmirrorable.len()
+        ));
+        for package in unmirrorable.iter() {
+            let origin = match &package.origin {
+                Origin::Git(Some(url)) => format!("git repository {url}"),
+                Origin::Git(None) => "git repository".to_string(),
+                Origin::Path => "local path".to_string(),
+            };
+            progress.info(&format!(
+                "  {}@{}, from a {origin}",
+                package.name, package.version
+            ));
+        }
+        Ok(())
+    }
+
+    /// Scans the crates for known vulnerabilities, substituting patched versions and excluding
+    /// the vulnerable versions if configured to.
+    fn audit(
+        &self,
+        crates: &mut HashSet<Version>,
+        provenance: &mut Provenance,
+    ) -> Result<(AdvisoryDb, Vec<Substitution>)> {
+        let progress = self.options.progress.as_ref();
+        let advisory_db = match &self.options.advisory_db {
+            Some(db_dir_path) => AdvisoryDb::open(db_dir_path)?,
+            None => AdvisoryDb::fetch(std::env::temp_dir().join("micrio-advisory-db"))?,
+        };
+        let substitutions = if self.options.substitute_patched {
+            self.substitute_patched(&advisory_db, crates, provenance)?
+        } else {
+            Vec::new()
+        };
+        let findings = advisory_db.scan(crates);
+        let mut vulnerable = Vec::new();
+        for (crat, advisories) in &findings {
+            for advisory in advisories {
+                progress.advisory_found(crat, advisory);
+            }
+            if advisories.iter().any(|a| a.is_vulnerability()) {
+                vulnerable.push((*crat).clone());
+            }
+        }
+        progress.info(&format!(
+            "{} affected crate versions found.",
+            findings.len()
+        ));
+        if self.options.exclude_vulnerable {
+            for crat in &vulnerable {
+                crates.remove(crat);
+            }
+            progress.info(&format!(
+                "{} vulnerable crate versions excluded.",
+                vulnerable.len()
+            ));
+            let unresolvable = exclude_unresolvable_dependents(crates, provenance, vulnerable);
+            if !unresolvable.is_empty() {
+                progress.info(&format!(
+                    "{} crate versions excluded, since a dependency only resolved to an excluded version:",
+                    unresolvable.len()
+                ));
+                for (crat, dependency) in &unresolvable {
+                    progress.info(&format!(
+                        "  {} {}, requiring {} {}",
+                        crat.name(),
+                        crat.version(),
+                        dependency.name(),
+                        dependency.version()
+                    ));
+                }
+            }
+        }
+        Ok((advisory_db, substitutions))
+    }
+
+    /// Replaces the vulnerable crate versions by patched versions, then resolves the
+    /// dependencies of the patched versions.
+    fn substitute_patched(
+        &self,
+        advisory_db: &AdvisoryDb,
+        crates: &mut HashSet<Version>,
+        provenance: &mut Provenance,
+    ) -> Result<Vec<Substitution>> {
+        let progress = self.options.progress.as_ref();
+        let mut substitutions = Vec::new();
+        for (crat, advisories) in advisory_db.scan(crates) {
+            let vulnerabilities = Vec::from_iter(
+                advisories
+                    .iter()
+                    .filter(|a| a.is_vulnerability())
+                    .map(|a| a.id.clone()),
+            );
+            if vulnerabilities.is_empty() {
+                continue;
+            }
+            match self.patched_version(crat, advisory_db) {
+                Some(patched) => substitutions.push(Substitution {
+                    vulnerable: crat.clone(),
+                    patched,
+                    advisory_ids: vulnerabilities,
+                }),
+                None => info!(
+                    crate_name = crat.name(),
+                    crate_version = crat.version(),
+                    "no patched semver-compatible version to substitute"
+                ),
+            }
+        }
+        if substitutions.is_empty() {
+            return Ok(substitutions);
+        }
+
+        let mut patched = HashSet::new();
+        for substitution in &substitutions {
+            crates.remove(&substitution.vulnerable);
+            provenance.replace(&substitution.vulnerable, &substitution.patched);
+            patched.insert(substitution.patched.clone());
+        }
+        let mut src_registry = self.src_registry(&patched, &[])?;
+        let dependencies = src_registry.get_dependencies(&patched)?;
+        provenance.add_dependents(src_registry.dependents());
+        crates.extend(patched);
+        crates.extend(dependencies);
+
+        progress.info(&format!(
+            "{} vulnerable crate versions substituted with patched versions:",
+            substitutions.len()
+        ));
+        for substitution in &substitutions {
+            progress.info(&format!(
+                "  {} {} -> {} ({})",
+                substitution.vulnerable.name(),
+                substitution.vulnerable.version(),
+                substitution.patched.version(),
+                substitution.advisory_ids.join(", ")
+            ));
+        }
+        Ok(substitutions)
+    }
+
+    /// Returns the newest version of the crate that is semver-compatible with the crate version,
+    /// newer, not yanked, and affected by no vulnerability, if any. Only crates of the registry
+    /// resolved against are looked up.
+    fn patched_version(&self, crat: &Version, advisory_db: &AdvisoryDb) -> Option<Version> {
+        if crat.registry().is_some() {
+            return None;
+        }
+        let current = semver::Version::parse(crat.version()).ok()?;
+        let compatible = VersionReq::parse(&format!("^{current}")).ok()?;
+        let versions = common::get_crate(&self.index, crat.name()).ok()?;
+        versions.versions().iter().rev().find_map(|candidate| {
+            let version = semver::Version::parse(candidate.version()).ok()?;
+            if candidate.is_yanked() || version <= current || !compatible.matches(&version) {
+                return None;
+            }
+            let candidate = candidate.clone();
+            let vulnerable = advisory_db
+                .advisories_for(&candidate)
+                .iter()
+                .any(|a| a.is_vulnerability());
+            if vulnerable {
+                None
+            } else {
+                Some(candidate)
+            }
+        })
+    }
+
+    fn write_reports(&self, dst_registry: &DstRegistry, selection: &Selection) -> Result<()> {
+        let options = &self.options;
+        if let Some(graph_path) = &options.dependency_graph {
+            let registry_url = common::canonical_registry_url(self.source.index_url());
+            DependencyGraph::new(&selection.crates, &selection.provenance, &registry_url)
+                .write(graph_path)?;
+        }
+        if options.report.is_none()
+            && options.size_report.is_none()
+            && options.publisher_report.is_none()
+        {
+            return Ok(());
+        }
+        let inventory = Inventory::new(
+            dst_registry,
+            &selection.crates,
+            &selection.provenance,
+            selection.advisory_db.as_ref(),
+        )?;
+        if let Some(report_path) = &options.report {
+            inventory.write(report_path, options.report_format)?;
+        }
+        if let Some(size_report_path) = &options.size_report {
+            inventory.write_size_attribution(size_report_path, options.report_format)?;
+        }
+        if let Some(publisher_report_path) = &options.publisher_report {
+            inventory.write_publishers(publisher_report_path, options.report_format)?;
+        }
+        let mut summary = String::from("Largest top-level crates by mirror size:");
+        for attribution in inventory.size_attribution().iter().take(10) {
+            summary += &format!(
+                "\n\t{} version {}: {} bytes total, {} bytes exclusive, {} dependencies",
+                attribution.name,
+                attribution.version,
+                attribution.total_size,
+                attribution.exclusive_size,
+                attribution.num_dependencies
+            );
+        }
+        options.progress.info(&summary);
+        Ok(())
+    }
+}
+
+/// Looks up the built-in targets for the triples.
+fn builtin_targets(triples: &[String]) -> Result<Vec<&'static TargetInfo>> {
+    let mut targets = Vec::new();
+    for triple in triples {
+        match cfg_targets::get_builtin_target_by_triple(triple) {
+            Some(target) => targets.push(target),
+            None => return Err(ErrorKind::UnknownTarget(triple.clone()).into()),
+        }
+    }
+    Ok(targets)
+}
+
+/// Returns the top-level crates whose dependencies need resolving: those that aren't locked,
+/// since a lockfile locks the exact version of every dependency along with them.
+fn unlocked(top_level: &HashSet<Version>, provenance: &Provenance) -> HashSet<Version> {
+    HashSet::from_iter(
+        top_level
+            .iter()
+            .filter(|crat| !provenance.is_locked(crat))
+            .cloned(),
+    )
+}
+
+/// Excludes the crates that depended on an excluded crate version, with no other version of
+/// the crate left matching their requirement, and in turn those that depended on them.
+/// Returns each excluded crate with the excluded dependency version it can no longer resolve.
+fn exclude_unresolvable_dependents(
+    crates: &mut HashSet<Version>,
+    provenance: &Provenance,
+    excluded: Vec<Version>,
+) -> Vec<(Version, Version)> {
+    let mut unresolvable = Vec::new();
+    let mut to_check = excluded;
+    while let Some(dependency) = to_check.pop() {
+        let dependency_version = match semver::Version::parse(dependency.version()) {
+            Ok(version) => version,
+            Err(_) => continue,
+        };
+        let dependents = Vec::from_iter(
+            provenance
+                .dependents_of(&dependency)
+                .filter(|crat| crates.contains(*crat))
+                .cloned(),
+        );
+        for crat in dependents {
+            let resolvable = crat
+                .dependencies()
+                .iter()
+                .filter(|d| {
+                    !matches!(d.kind(), DependencyKind::Dev)
+                        && d.crate_name().eq_ignore_ascii_case(dependency.name())
+                })
+                .filter_map(|d| VersionReq::parse(d.requirement()).ok())
+                .filter(|req| req.matches(&dependency_version))
+                .all(|req| {
+                    crates.iter().any(|other| {
+                        other.name().eq_ignore_ascii_case(dependency.name())
+                            && other.registry() == dependency.registry()
+                            && semver::Version::parse(other.version())
+                                .is_ok_and(|v| req.matches(&v))
+                    })
+                });
+            if !resolvable {
+                crates.remove(&crat);
+                unresolvable.push((crat.clone(), dependency.clone()));
+                to_check.push(crat);
+            }
+        }
+    }
+    unresolvable
+}
+
+/// Opens the index of the registry, fetching it unless offline, and the source of its
+/// .crate files.
+fn open_registry(
+    index_url: &str,
+    token: Option<String>,
+    offline: bool,
+) -> Result<(Index, CrateSource)> {
+    // crates_index clones the index if Cargo hasn't.
+    if offline && !has_cargo_index(index_url) {
+        let step = format!("fetching the index of {index_url}");
+        return Err(ErrorKind::Offline(step).into());
+    }
+    let mut index = crates_index::Index::from_url(index_url)?;
+    if !offline {
+        // Unlike the crates.io index, Cargo may never have fetched it.
+        index.update()?;
+    }
+    let index = Index::new(index)?;
+    let source = registry_source(&index, index_url, token)?;
+    Ok((index, source))
+}
+
+/// Whether Cargo's clone of the registry's index exists, so it can be opened without network
+/// access.
+fn has_cargo_index(index_url: &str) -> bool {
+    match index::cargo_index_path(index_url) {
+        Some(index_path) => git2::Repository::open(index_path).is_ok(),
+        None => false,
+    }
+}
+
+/// Whether the mirror is given by the URL of a remote git repo rather than a local path.
+fn is_remote_url(mirror: &str) -> bool {
+    // Single letter schemes are Windows drive letters.
+    match Url::parse(mirror) {
+        Ok(url) => url.scheme().len() > 1 && url.scheme() != "file",
+        Err(_) => false,
+    }
+}
+
+/// Returns the source of the registry's .crate files, from the config.json of its index.
+fn registry_source(index: &Index, index_url: &str, token: Option<String>) -> Result<CrateSource> {
+    let config = index.index_config()?;
+    Ok(CrateSource::new(index_url, config.dl, token))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    /// Returns a crate version with the dependencies, given as name, requirement and kind.
+    fn crate_version(name: &str, version: &str, deps: &[(&str, &str, &str)]) -> Version {
+        let deps = Vec::from_iter(deps.iter().map(|(name, req, kind)| {
+            json!({
+                "name": name,
+                "req": req,
+                "features": [],
+                "optional": false,
+                "default_features": true,
+                "target": null,
+                "kind": kind,
+            })
+        }));
+        let entry = json!({
+            "name": name,
+            "vers": version,
+            "deps": deps,
+            "cksum": "0".repeat(64),
+            "features": {},
+            "yanked": false,
+        });
+        Version::from_json(&entry.to_string()).unwrap()
+    }
+
+    #[test]
+    fn dependents_left_unresolvable_are_excluded_transitively() {
+        let vulnerable = crate_version("vuln", "1.0.0", &[]);
+        let patched = crate_version("vuln", "1.1.0", &[]);
+        // Only the vulnerable version matches, so it goes, and so does what depends on it.
+        let pinned = crate_version("pinned", "1.0.0", &[("vuln", "=1.0.0", "normal")]);
+        let app = crate_version("app", "1.0.0", &[("pinned", "^1", "normal")]);
+        // The patched version matches too, so it stays.
+        let lenient = crate_version("lenient", "1.0.0", &[("vuln", "^1", "normal")]);
+        // A dev-dependency isn't needed to build the crate.
+        let tested = crate_version("tested", "1.0.0", &[("vuln", "=1.0.0", "dev")]);
+        // Depending on each other, and one of them on the vulnerable version.
+        let cycle_a = crate_version(
+            "cycle-a",
+            "1.0.0",
+            &[("cycle-b", "^1", "normal"), ("vuln", "=1.0.0", "normal")],
+        );
+        let cycle_b = crate_version("cycle-b", "1.0.0", &[("cycle-a", "^1", "normal")]);
+
+        let mut provenance = Provenance::new();
+        provenance.add_dependents(&HashMap::from([
+            (
+                vulnerable.clone(),
+                HashSet::from([
+                    pinned.clone(),
+                    lenient.clone(),
+                    tested.clone(),
+                    cycle_a.clone(),
+                ]),
+            ),
+            (pinned.clone(), HashSet::from([app.clone()])),
+            (cycle_a.clone(), HashSet::from([cycle_b.clone()])),
+            (cycle_b.clone(), HashSet::from([cycle_a.clone()])),
+        ]));
+        let mut crates = HashSet::from([patched, pinned, app, lenient, tested, cycle_a, cycle_b]);
+
+        let unresolvable =
+            exclude_unresolvable_dependents(&mut crates, &provenance, vec![vulnerable]);
+        let mut kept = Vec::from_iter(crates.iter().map(|c| c.name()));
+        kept.sort();
+        assert_eq!(kept, ["lenient", "tested", "vuln"]);
+        let mut unresolvable =
+            Vec::from_iter(unresolvable.iter().map(|(c, d)| (c.name(), d.name())));
+        unresolvable.sort();
+        assert_eq!(
+            unresolvable,
+            [
+                ("app", "pinned"),
+                ("cycle-a", "vuln"),
+                ("cycle-b", "cycle-a"),
+                ("pinned", "vuln"),
+            ]
+        );
+    }
+}
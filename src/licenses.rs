@@ -0,0 +1,196 @@
+use crate::common::{self, Version};
+use crate::dst_registry::DstRegistry;
+use flate2::read::GzDecoder;
+use std::collections::HashSet;
+use std::fmt::{self, Display};
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+
+#[derive(Debug)]
+pub enum Error {
+    ReadCrateFile {
+        crate_name: String,
+        crate_version: String,
+        error: io::Error,
+    },
+    WriteLicense {
+        crate_name: String,
+        crate_version: String,
+        path: PathBuf,
+        error: io::Error,
+    },
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::ReadCrateFile {
+                crate_name,
+                crate_version,
+                error,
+            } => {
+                write!(f, "error extracting licenses: failed to read the .crate file for {crate_name} version {crate_version}: {error}")
+            }
+            Error::WriteLicense {
+                crate_name,
+                crate_version,
+                path,
+                error,
+            } => {
+                write!(
+                    f,
+                    "error extracting licenses: failed to write {} for {crate_name} version {crate_version}: {error}",
+                    path.to_string_lossy()
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::ReadCrateFile { error, .. } => Some(error),
+            Error::WriteLicense { error, .. } => Some(error),
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// The directory in the mirror the license files are extracted to, with a subdirectory
+/// per crate version named `{name}@{version}`.
+pub const LICENSES_DIR: &str = "licenses";
+
+/// The start of the names of the files at the package root holding license texts,
+/// compared case-insensitively.
+const LICENSE_FILE_PREFIXES: [&str; 6] = [
+    "LICENSE",
+    "LICENCE",
+    "COPYING",
+    "COPYRIGHT",
+    "NOTICE",
+    "UNLICENSE",
+];
+
+/// How many license files were extracted, from how many crate versions.
+pub struct LicenseCounts {
+    pub num_files: usize,
+    pub num_crates: usize,
+    /// The crate versions without any license file, e.g. with only an SPDX identifier.
+    pub num_without: usize,
+}
+
+/// Extracts the license files packaged in each crate version's .crate file into the licenses
+/// directory of the mirror, keeping their paths relative to the package root, since legal
+/// review of offline mirrors often requires the license texts, not only SPDX identifiers.
+///
+/// License files are the files at the package root named like `LICENSE-MIT` or `COPYING`,
+/// the files of a REUSE-style `LICENSES` directory, and the `license-file` of the packaged
+/// Cargo.toml.
+pub fn write_licenses(
+    dst_registry: &DstRegistry,
+    crates: &HashSet<Version>,
+) -> Result<LicenseCounts> {
+    let licenses_dir_path = dst_registry.path().join(LICENSES_DIR);
+    let mut counts = LicenseCounts {
+        num_files: 0,
+        num_crates: 0,
+        num_without: 0,
+    };
+    for crat in crates {
+        let read_error = |e| Error::ReadCrateFile {
+            crate_name: crat.name().to_string(),
+            crate_version: crat.version().to_string(),
+            error: e,
+        };
+        let crate_file = dst_registry
+            .read_crate_file(crat.name(), crat.version())
+            .map_err(read_error)?;
+        let files = read_license_files(&crate_file, crat).map_err(read_error)?;
+        if files.is_empty() {
+            counts.num_without += 1;
+            continue;
+        }
+
+        let crate_dir_path = licenses_dir_path.join(format!("{}@{}", crat.name(), crat.version()));
+        for (rel_path, contents) in &files {
+            let path = crate_dir_path.join(rel_path);
+            let result = match path.parent() {
+                Some(parent) => fs::create_dir_all(parent),
+                None => Ok(()),
+            };
+            result
+                .and_then(|()| fs::write(&path, contents))
+                .map_err(|e| Error::WriteLicense {
+                    crate_name: crat.name().to_string(),
+                    crate_version: crat.version().to_string(),
+                    path: path.clone(),
+                    error: e,
+                })?;
+        }
+        counts.num_files += files.len();
+        counts.num_crates += 1;
+    }
+    Ok(counts)
+}
+
+/// Reads the license files packaged inside the .crate file, with their paths relative to
+/// the package root.
+fn read_license_files(crate_file: &[u8], crat: &Version) -> io::Result<Vec<(PathBuf, Vec<u8>)>> {
+    let license_file = common::read_packaged_manifest(crate_file, crat)?.and_then(|manifest| {
+        let path = manifest.get("package")?.get("license-file")?.as_str()?;
+        Some(path.trim_start_matches("./").to_string())
+    });
+
+    let root = format!("{}-{}", crat.name(), crat.version());
+    let mut files = Vec::new();
+    let mut archive = tar::Archive::new(GzDecoder::new(crate_file));
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let path = entry.path()?.into_owned();
+        let rel_path = match path.strip_prefix(&root) {
+            Ok(rel_path) => rel_path.to_path_buf(),
+            Err(_) => continue,
+        };
+        // Never write outside the crate version's license directory.
+        if !rel_path
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)))
+        {
+            continue;
+        }
+        if !is_license_file(&rel_path, license_file.as_deref()) {
+            continue;
+        }
+        let mut contents = Vec::new();
+        entry.read_to_end(&mut contents)?;
+        files.push((rel_path, contents));
+    }
+    Ok(files)
+}
+
+/// Returns whether the file, given by its path relative to the package root, holds a license
+/// text. `license_file` is the `package.license-file` field of the packaged Cargo.toml, if any.
+fn is_license_file(rel_path: &Path, license_file: Option<&str>) -> bool {
+    let slash_path = rel_path.to_string_lossy().replace('\\', "/");
+    if license_file == Some(slash_path.as_str()) {
+        return true;
+    }
+    let names = Vec::from_iter(
+        rel_path
+            .iter()
+            .map(|name| name.to_string_lossy().to_uppercase()),
+    );
+    match names.as_slice() {
+        [file_name] => LICENSE_FILE_PREFIXES
+            .iter()
+            .any(|prefix| file_name.starts_with(prefix)),
+        [dir_name, _] => dir_name == "LICENSES",
+        _ => false,
+    }
+}
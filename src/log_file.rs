@@ -0,0 +1,105 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// A log file that is rotated once it would grow past a maximum size, or once it has been
+/// written to for longer than a maximum age.
+///
+/// Rotating renames `micrio.log` to `micrio.log.1`, `micrio.log.1` to `micrio.log.2`,
+/// and so on, deleting the oldest so only `keep` rotated files are left. Each log event
+/// is a single write, so events are never split across files.
+pub struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    opened: Instant,
+    max_size: Option<u64>,
+    max_age: Option<Duration>,
+    keep: usize,
+}
+
+impl RotatingFile {
+    /// Opens the log file, appending to it if it exists.
+    pub fn open<P: Into<PathBuf>>(
+        path: P,
+        max_size: Option<u64>,
+        max_age: Option<Duration>,
+        keep: usize,
+    ) -> io::Result<Self> {
+        let path = path.into();
+        let file = open_append(&path)?;
+        let size = file.metadata()?.len();
+        Ok(RotatingFile {
+            path,
+            file,
+            size,
+            opened: Instant::now(),
+            max_size,
+            max_age,
+            keep,
+        })
+    }
+
+    fn needs_rotation(&self, len: usize) -> bool {
+        // An event larger than the maximum size still goes to an empty file.
+        let too_big = match self.max_size {
+            Some(max_size) => self.size > 0 && self.size + len as u64 > max_size,
+            None => false,
+        };
+        let too_old = match self.max_age {
+            Some(max_age) => self.opened.elapsed() >= max_age,
+            None => false,
+        };
+        too_big || too_old
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        if self.keep == 0 {
+            fs::remove_file(&self.path)?;
+        } else {
+            let oldest = rotated_path(&self.path, self.keep);
+            if oldest.exists() {
+                fs::remove_file(&oldest)?;
+            }
+            for n in (1..self.keep).rev() {
+                let rotated = rotated_path(&self.path, n);
+                if rotated.exists() {
+                    fs::rename(&rotated, rotated_path(&self.path, n + 1))?;
+                }
+            }
+            fs::rename(&self.path, rotated_path(&self.path, 1))?;
+        }
+        self.file = open_append(&self.path)?;
+        self.size = 0;
+        self.opened = Instant::now();
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.needs_rotation(buf.len()) {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.size += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn open_append(path: &Path) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+/// The path of the `n`th most recently rotated file, e.g. `micrio.log.1`.
+fn rotated_path(path: &Path, n: usize) -> PathBuf {
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(format!(".{n}"));
+    PathBuf::from(rotated)
+}
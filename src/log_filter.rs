@@ -0,0 +1,41 @@
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+/// Writes log lines to a shared writer, dropping those that contain one of the patterns.
+///
+/// The fmt layer writes each event as a single line, so a line is dropped as a whole.
+pub struct SuppressingWriter<W> {
+    inner: Arc<Mutex<W>>,
+    patterns: Arc<[String]>,
+}
+
+/// Makes the writers for a fmt layer, each writing to `inner` and dropping the same lines.
+pub fn make_writer<W>(inner: W, patterns: &Arc<[String]>) -> impl Fn() -> SuppressingWriter<W> {
+    let inner = Arc::new(Mutex::new(inner));
+    let patterns = Arc::clone(patterns);
+    move || SuppressingWriter {
+        inner: Arc::clone(&inner),
+        patterns: Arc::clone(&patterns),
+    }
+}
+
+impl<W: Write> Write for SuppressingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let line = String::from_utf8_lossy(buf);
+        if self
+            .patterns
+            .iter()
+            .any(|pattern| line.contains(pattern.as_str()))
+        {
+            return Ok(buf.len());
+        }
+        // A panic while logging leaves the writer usable.
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        let mut inner = self.inner.lock().unwrap_or_else(|e| e.into_inner());
+        inner.flush()
+    }
+}
@@ -1,55 +1,646 @@
 mod cli;
-mod common;
-mod dst_registry;
-mod src_registry;
-mod top_level;
-
-use clap::{CommandFactory, Parser};
-use cli::Cli;
-use dst_registry::DstRegistry;
-use log::error;
-use src_registry::SrcRegistry;
-use std::collections::HashSet;
-use top_level::TopLevelBuilder;
+mod config;
+mod console;
+mod dashboard;
+mod log_file;
+mod log_filter;
+mod service;
+mod tui;
+mod tui_picker;
+
+use anyhow::Context;
+use cli::{Cli, Command, MirrorArgs, ProgressFormat};
+use config::Config;
+use console::{ConsoleProgress, JsonProgress};
+use ed25519_dalek::VerifyingKey;
+use log_file::RotatingFile;
+use micrio::cancel::CancellationToken;
+use micrio::checksums;
+use micrio::common;
+use micrio::dst_registry::DstRegistry;
+use micrio::endpoints::{self, Endpoints};
+use micrio::export::{self, ExportOptions};
+use micrio::grep::{self, GrepOptions};
+use micrio::hooks::CommandHooks;
+use micrio::http_config::HttpConfig;
+use micrio::notify::Notification;
+use micrio::progress::ProgressObserver;
+use micrio::rate_limit::RateLimiter;
+use micrio::search::SearchIndex;
+use micrio::serve::{self, MirrorServer};
+use micrio::site::format_size;
+use micrio::smoke_test::{self, SmokeTestOptions};
+use micrio::state_db::StateDb;
+use micrio::{signing, vendor, ErrorKind, Mirror, MirrorBuilder};
+use regex::RegexBuilder;
+use std::collections::HashMap;
+use std::io::{IsTerminal, Write};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use tracing::{error, warn};
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{fmt, EnvFilter};
+use tui_picker::TuiPicker;
 
 fn try_main() -> anyhow::Result<()> {
-    env_logger::init();
+    let cli = Cli::parse_with_env();
+    init_tracing(&cli)?;
+
+    match cli.command {
+        Some(Command::Mirror(args)) => mirror(*args),
+        Some(Command::Verify {
+            ref mirror_dir_path,
+        }) => verify_mirror(mirror_dir_path),
+        Some(Command::Export {
+            ref mirror_dir_path,
+            ref archive_path,
+            max_part_size,
+            since_last,
+            ref signing_key,
+            reproducible,
+            zstd,
+        }) => {
+            let options = ExportOptions {
+                max_part_size,
+                since_last,
+                signing_key: match signing_key {
+                    Some(signing_key) => Some(signing::load_signing_key(signing_key)?),
+                    None => None,
+                },
+                reproducible,
+                zstd_level: zstd,
+            };
+            export_mirror(mirror_dir_path, archive_path, &options)
+        }
+        Some(Command::Import {
+            ref archive_path,
+            ref mirror_dir_path,
+            ref verify_key,
+        }) => {
+            let verifying_key = match verify_key {
+                Some(verify_key) => Some(signing::load_verifying_key(verify_key)?),
+                None => None,
+            };
+            import_mirror(archive_path, mirror_dir_path, verifying_key.as_ref())
+        }
+        Some(Command::SmokeTest {
+            ref mirror_dir_path,
+            sample,
+            check,
+            keep_project,
+        }) => {
+            let options = SmokeTestOptions {
+                sample_size: sample,
+                check,
+                keep_project,
+            };
+            smoke_test_mirror(mirror_dir_path, &options)
+        }
+        Some(Command::Vendor {
+            ref mirror_dir_path,
+            ref vendor_dir_path,
+            versioned_dirs,
+        }) => vendor_mirror(mirror_dir_path, vendor_dir_path, versioned_dirs),
+        Some(Command::Dashboard {
+            ref mirror_dir_path,
+        }) => {
+            if !std::io::stdout().is_terminal() {
+                anyhow::bail!("the dashboard requires a terminal");
+            }
+            dashboard::show_dashboard(mirror_dir_path)
+        }
+        Some(Command::Grep {
+            ref mirror_dir_path,
+            ref pattern,
+            ref crates,
+            paths,
+            ignore_case,
+        }) => {
+            let options = GrepOptions {
+                pattern: RegexBuilder::new(pattern)
+                    .case_insensitive(ignore_case)
+                    .build()?,
+                crates: crates.clone(),
+                paths_only: paths,
+            };
+            grep_mirror(mirror_dir_path, &options)
+        }
+        Some(Command::List {
+            ref mirror_dir_path,
+        }) => list_mirror(mirror_dir_path),
+        Some(Command::Search {
+            ref mirror_dir_path,
+            ref query,
+            limit,
+        }) => search_mirror(mirror_dir_path, query, limit),
+        Some(Command::Serve {
+            ref mirror_dir_path,
+            addr,
+            ref tokens_file,
+        }) => {
+            let tokens = match tokens_file {
+                Some(tokens_file) => serve::read_tokens(tokens_file)?,
+                None => Vec::new(),
+            };
+            serve_mirror(mirror_dir_path, addr, tokens)
+        }
+        Some(Command::Keygen {
+            ref signing_key_path,
+            ref verify_key_path,
+        }) => {
+            signing::generate_keypair(signing_key_path, verify_key_path)?;
+            println!("Key pair generated.");
+            Ok(())
+        }
+        Some(Command::Completions { shell }) => {
+            let mut cmd = Cli::command_with_env();
+            let bin_name = cmd.get_name().to_string();
+            clap_complete::generate(shell, &mut cmd, bin_name, &mut std::io::stdout());
+            Ok(())
+        }
+        None => mirror(cli.mirror),
+    }
+}
+
+fn mirror(mut args: MirrorArgs) -> anyhow::Result<()> {
+    if args.print_systemd_unit {
+        print!("{}", service::systemd_unit()?);
+        return Ok(());
+    }
+
+    // The first Ctrl-C or SIGTERM stops the run once the crate in progress is done;
+    // a second one exits immediately. ctrlc only handles Ctrl-C: its termination feature
+    // would also take SIGHUP, which reloads the daemon's configuration, so SIGTERM is
+    // handled by the service module.
+    let cancel = CancellationToken::new();
+    let handler_cancel = cancel.clone();
+    ctrlc::set_handler(move || {
+        if handler_cancel.is_cancelled() {
+            std::process::exit(130);
+        }
+        eprintln!("Cancelling... press Ctrl-C again to exit immediately.");
+        handler_cancel.cancel();
+    })?;
 
-    let cli = Cli::parse();
+    let config = load_config(&args)?;
+    if args.mirror_dir_path.is_none() {
+        let profile = config.clone().take_profile(args.profile.as_deref())?;
+        let mirror_dir = profile.mirror_dir.context(
+            "no mirror directory given, on the command line or as mirror-dir in the configuration file",
+        )?;
+        args.mirror_dir_path = Some(mirror_dir.to_string_lossy().into_owned());
+    }
+    // Set above, so the daemon's runs and the builder can rely on it.
+    let mirror_dir_path = args.mirror_dir_path.clone().unwrap();
+    if let Some(interval) = args.daemon {
+        return service::run_daemon(args, config, interval, cancel);
+    }
+    #[cfg(unix)]
+    service::cancel_on_terminate_in_background(cancel.clone())?;
+
+    let builder = mirror_builder(&args, config.clone())?.cancellation_token(cancel);
+    let mirror = builder.build()?;
+    let result = if args.dry_run {
+        dry_run(&mirror)
+    } else {
+        let result = mirror.run().map(|_| ());
+        let notification = Notification {
+            job: "mirror".to_string(),
+            mirror_dir_path: Some(mirror_dir_path.into()),
+            error: result.as_ref().err().map(|e| e.to_string()),
+        };
+        for notifier in config.notifications.notifiers() {
+            // Don't let a notification failure mask how the run went.
+            if let Err(e) = notifier.notify(&notification) {
+                warn!("{e}");
+            }
+        }
+        result
+    };
+    match result {
+        Ok(()) => Ok(()),
+        Err(e) if matches!(e.kind(), ErrorKind::NoCratesSelected) => {
+            Cli::command_with_env().print_help()?;
+            Err(e.into())
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Reads the configuration file given with --config, or else micrio.toml from the current
+/// directory, which is only required for a profile.
+fn load_config(args: &MirrorArgs) -> anyhow::Result<Config> {
+    if let Some(config_path) = &args.config {
+        return config::load_config(config_path);
+    }
+    let config_path = Path::new(config::CONFIG_FILE_NAME);
+    if args.profile.is_some() || config_path.is_file() {
+        config::load_config(config_path)
+    } else {
+        Ok(Config::default())
+    }
+}
 
-    let index = crates_index::Index::new_cargo_default()?;
-    let top_level_builder = TopLevelBuilder::new(&index)?;
-    let mut src_registry = SrcRegistry::new(&index);
-    let dst_registry = DstRegistry::new(&cli.mirror_dir_path)?;
+/// Configures a mirror run from the command line and the configuration file.
+fn mirror_builder(args: &MirrorArgs, mut config: Config) -> anyhow::Result<MirrorBuilder> {
+    // Taken from the configuration file by `mirror` if not given.
+    let mirror_dir_path = args.mirror_dir_path.as_deref().unwrap();
+    let profile = config.take_profile(args.profile.as_deref())?;
+    let mut selection = args.selection_spec()?;
+    profile.add_to_selection(&mut selection)?;
 
-    let mut crates = HashSet::new();
-    match cli.from_file {
-        Some(file_path) => crates.extend(top_level_builder.from_file(file_path)?),
-        None => (),
+    let progress: Arc<dyn ProgressObserver> = match args.progress {
+        ProgressFormat::Human => Arc::new(ConsoleProgress::new(args.color.use_color())),
+        ProgressFormat::Json => Arc::new(JsonProgress),
     };
-    match cli.most_downloaded {
-        Some(n) => crates.extend(top_level_builder.get_n_most_downloaded(n)?),
-        None => (),
+    let http_config = HttpConfig::from_cargo_config()?;
+    let mut builder = MirrorBuilder::new(mirror_dir_path)
+        .http_config(http_config)
+        .progress(progress)
+        .selection(selection)
+        .streaming(args.streaming)
+        .index_only(args.index_only)
+        .download_only(args.download_only)
+        .reproducible(args.reproducible)
+        .deny_warnings(args.deny_warnings)
+        .cargo_credentials(args.cargo_credentials)
+        .offline(args.offline)
+        .audit(args.audit)
+        .exclude_vulnerable(args.exclude_vulnerable)
+        .substitute_patched(args.substitute_patched)
+        .check_resolvable(args.check_resolvable)
+        .with_metadata(args.with_metadata)
+        .with_licenses(args.with_licenses)
+        .with_owners(args.with_owners)
+        .with_repos(args.with_repos)
+        .auth_required(args.auth_required)
+        .html_index(args.html_index)
+        .sha256sums(args.sha256sums)
+        .state_db(args.state_db)
+        .report_format(args.report_format)
+        .layout(args.layout);
+    if args.pre_download_hook.is_some()
+        || args.post_download_hook.is_some()
+        || args.run_end_hook.is_some()
+    {
+        builder = builder.hook(Arc::new(CommandHooks {
+            pre_download: args.pre_download_hook.clone(),
+            post_download: args.post_download_hook.clone(),
+            run_end: args.run_end_hook.clone(),
+        }));
+    }
+    let targets = if args.target.is_empty() {
+        profile.targets
+    } else {
+        args.target.clone()
     };
+    for triple in targets {
+        builder = builder.target(triple);
+    }
+    if let Some(max_depth) = args.max_depth.or(profile.max_depth) {
+        builder = builder.max_depth(max_depth);
+    }
+    if let Some(keep) = args.keep_run_reports.or(profile.keep_run_reports) {
+        builder = builder.keep_run_reports(keep);
+    }
+    for (crate_name, req) in config.overrides {
+        let req = req.parse().with_context(|| {
+            format!("invalid version requirement {req} for the {crate_name} override")
+        })?;
+        builder = builder.override_version(crate_name, req);
+    }
+    let registry = args.registry.clone().or(profile.registry);
+    builder = builder.endpoints(Endpoints {
+        index_url: registry.clone(),
+        api_url: args
+            .registry_api
+            .clone()
+            .or(profile.registry_api)
+            .unwrap_or_else(|| endpoints::CRATES_IO_API.to_string()),
+        dl: args.registry_dl.clone().or(profile.registry_dl),
+        serve_url: args.serve_url.clone().or(profile.serve_url),
+    });
+    if let Some(index_url) = &registry {
+        builder = builder.registry(index_url.clone(), args.registry_token.clone());
+    }
+    if let Some(index_path) = &args.cargo_index_path {
+        builder = builder.index_path(index_path);
+    }
+    let mut secondary_tokens = HashMap::new();
+    for arg in &args.secondary_registry_token {
+        let (index_url, token) = arg.split_once('=').with_context(|| {
+            format!("invalid secondary registry token {arg}, expected INDEX-URL=TOKEN")
+        })?;
+        secondary_tokens.insert(common::canonical_registry_url(index_url), token.to_string());
+    }
+    for index_url in &args.secondary_registry {
+        let token = secondary_tokens
+            .get(&common::canonical_registry_url(index_url))
+            .cloned();
+        builder = builder.secondary_registry(index_url.clone(), token);
+    }
+    for dir_path in &args.link_from {
+        builder = builder.link_from(dir_path);
+    }
+    for dir_path in &args.seed_dir {
+        builder = builder.seed_from(dir_path);
+    }
+    if let Some(commit) = &args.index_commit {
+        builder = builder.index_commit(commit.clone());
+    }
+    if let Some(db_dir_path) = &args.advisory_db {
+        builder = builder.advisory_db(db_dir_path);
+    }
+    if let Some(api_url) = &args.publish_to {
+        builder = builder.publish_to(api_url.clone(), args.publish_token.clone());
+    }
+    for replica in &args.replicate_to {
+        builder = builder.replicate_to(replica.clone());
+    }
+    if args.interactive {
+        if !std::io::stdout().is_terminal() {
+            anyhow::bail!("--interactive requires a terminal");
+        }
+        builder = builder.picker(Arc::new(TuiPicker));
+    }
+    if let Some(report_path) = &args.report {
+        builder = builder.report(report_path);
+    }
+    if let Some(size_report_path) = &args.size_report {
+        builder = builder.size_report(size_report_path);
+    }
+    let max_requests_per_sec = args.max_requests_per_sec.or(profile.max_requests_per_sec);
+    let max_concurrent_requests = args
+        .max_concurrent_requests
+        .or(profile.max_concurrent_requests);
+    if max_requests_per_sec.is_some() || max_concurrent_requests.is_some() {
+        builder = builder.rate_limiter(Arc::new(RateLimiter::new(
+            max_requests_per_sec,
+            max_concurrent_requests,
+        )));
+    }
+    if let Some(publisher_report_path) = &args.publisher_report {
+        builder = builder.publisher_report(publisher_report_path);
+    }
+    if let Some(graph_path) = &args.dependency_graph {
+        builder = builder.dependency_graph(graph_path);
+    }
+    Ok(builder)
+}
 
-    if crates.is_empty() {
-        println!("ERROR: no crates selected to mirror\n");
-        Cli::command().print_help()?;
-        std::process::exit(1);
+/// Resolves the selection and reports what mirroring it would download.
+fn dry_run(mirror: &Mirror) -> micrio::Result<()> {
+    let selection = mirror.resolve()?;
+    let estimate = mirror.estimate_size(&selection)?;
+    println!(
+        "Dry run: {} crates would be mirrored, {} of them top-level.",
+        estimate.num_crates, selection.num_top_level
+    );
+    if estimate.num_unknown == estimate.num_crates {
+        println!("Their size isn't known.");
+    } else if estimate.num_unknown > 0 {
+        println!(
+            "Expected size: about {} ({} known, {} crates of unknown size).",
+            format_size(estimate.estimated_bytes()),
+            format_size(estimate.known_bytes),
+            estimate.num_unknown
+        );
+    } else {
+        println!("Expected size: {}.", format_size(estimate.known_bytes));
     }
+    Ok(())
+}
+
+/// Logs diagnostics to stderr, and to the log file if one is given. RUST_LOG takes precedence
+/// over the verbosity flag, e.g. RUST_LOG=micrio=debug, but not over suppressed warnings.
+fn init_tracing(cli: &Cli) -> anyhow::Result<()> {
+    const LEVELS: [&str; 5] = ["error", "warn", "info", "debug", "trace"];
+    let level = LEVELS[usize::from(cli.verbose).min(LEVELS.len() - 1)];
+    // The log file is read after the fact, so it always gets the selected and downloaded crates.
+    let file_level = LEVELS[usize::from(cli.verbose).clamp(2, LEVELS.len() - 1)];
+    let filter = |level: &str| {
+        let mut filter = EnvFilter::try_from_default_env()
+            .unwrap_or_else(|_| EnvFilter::new(format!("error,micrio={level}")));
+        for category in &cli.suppress {
+            let directive = format!("{}=off", category.target());
+            filter = filter.add_directive(directive.parse().expect("valid directive"));
+        }
+        filter
+    };
+    let patterns: Arc<[String]> = cli.suppress_warning.clone().into();
+
+    let log_file = match &cli.log_file {
+        Some(log_file_path) => {
+            let max_age = cli.log_rotate.map(|rotation| rotation.interval());
+            match RotatingFile::open(log_file_path, cli.log_max_size, max_age, cli.log_keep) {
+                Ok(log_file) => Some(log_file),
+                Err(e) => {
+                    // Still log to stderr, so the error is reported.
+                    init_stderr_tracing(filter(level));
+                    return Err(anyhow::Error::new(e).context(format!(
+                        "failed to open the log file {}",
+                        log_file_path.to_string_lossy()
+                    )));
+                }
+            }
+        }
+        None => None,
+    };
+    let file_layer = log_file.map(|log_file| {
+        fmt::layer()
+            .with_writer(log_filter::make_writer(log_file, &patterns))
+            .with_ansi(false)
+            .with_filter(filter(file_level))
+    });
+    tracing_subscriber::registry()
+        .with(
+            fmt::layer()
+                .with_writer(log_filter::make_writer(std::io::stderr(), &patterns))
+                .with_filter(filter(level)),
+        )
+        .with(file_layer)
+        .init();
+    Ok(())
+}
+
+fn init_stderr_tracing(filter: EnvFilter) {
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .init();
+}
+
+fn export_mirror(
+    mirror_dir_path: &Path,
+    archive_path: &Path,
+    options: &ExportOptions,
+) -> anyhow::Result<()> {
+    println!("Exporting mirror...");
+    let parts = export::export(mirror_dir_path, archive_path, options)?;
+    for part in &parts {
+        println!("\tWrote {}", part.to_string_lossy());
+    }
+    println!("Done exporting mirror.");
+    Ok(())
+}
+
+fn import_mirror(
+    archive_path: &Path,
+    mirror_dir_path: &Path,
+    verifying_key: Option<&VerifyingKey>,
+) -> anyhow::Result<()> {
+    println!("Importing mirror...");
+    export::import(archive_path, mirror_dir_path, verifying_key)?;
+    println!("Done importing mirror.");
+    Ok(())
+}
+
+fn vendor_mirror(
+    mirror_dir_path: &Path,
+    vendor_dir_path: &Path,
+    versioned_dirs: bool,
+) -> anyhow::Result<()> {
+    println!("Vendoring mirror...");
+    let num_packages = vendor::export_vendor_dir(mirror_dir_path, vendor_dir_path, versioned_dirs)?;
+    println!("Done vendoring mirror: {num_packages} packages written.");
+    println!("\nTo build from it, add this to .cargo/config.toml:\n");
+    println!("[source.crates-io]\nreplace-with = \"vendored-sources\"\n");
+    println!(
+        "[source.vendored-sources]\ndirectory = \"{}\"",
+        vendor_dir_path.to_string_lossy().replace('\\', "/")
+    );
+    Ok(())
+}
+
+/// Prints the matches in the mirror's crates, failing if there are none so the exit status
+/// is 1, as with grep.
+fn grep_mirror(mirror_dir_path: &Path, options: &GrepOptions) -> anyhow::Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    let mut write_error = None;
+    let num_matches = grep::grep(mirror_dir_path, options, |m| {
+        if write_error.is_some() {
+            return;
+        }
+        let location = format!("{}@{}:{}", m.crat.name(), m.crat.version(), m.path);
+        let result = match (m.line_number, m.line) {
+            (Some(line_number), Some(line)) => writeln!(stdout, "{location}:{line_number}: {line}"),
+            _ => writeln!(stdout, "{location}"),
+        };
+        write_error = result.err();
+    })?;
+    if let Some(e) = write_error {
+        return Err(e.into());
+    }
+    if num_matches == 0 {
+        anyhow::bail!("no matches found");
+    }
+    Ok(())
+}
+
+fn list_mirror(mirror_dir_path: &Path) -> anyhow::Result<()> {
+    let mut stdout = std::io::stdout().lock();
+    let state_db = match StateDb::open_existing(mirror_dir_path)? {
+        Some(state_db) => state_db,
+        None => {
+            let mut crates = Vec::from_iter(DstRegistry::open(mirror_dir_path)?.read_index()?);
+            crates.sort_by(|a, b| (a.name(), a.version()).cmp(&(b.name(), b.version())));
+            for crat in &crates {
+                writeln!(stdout, "{}@{}", crat.name(), crat.version())?;
+            }
+            return Ok(());
+        }
+    };
+    for record in state_db.list_crates()? {
+        let crat = format!("{}@{}", record.name, record.version);
+        if !record.sources.is_empty() {
+            writeln!(
+                stdout,
+                "{crat} (top-level, from {})",
+                record.sources.join(", ")
+            )?;
+        } else if !record.dependents.is_empty() {
+            writeln!(
+                stdout,
+                "{crat} (required by {})",
+                record.dependents.join(", ")
+            )?;
+        } else {
+            writeln!(stdout, "{crat}")?;
+        }
+    }
+    Ok(())
+}
+
+fn verify_mirror(mirror_dir_path: &Path) -> anyhow::Result<()> {
+    let digests = match StateDb::open_existing(mirror_dir_path)? {
+        Some(state_db) => state_db.file_digests()?,
+        None => Vec::new(),
+    };
+    let files = if digests.is_empty() {
+        checksums::verify_sha256sums(mirror_dir_path)?
+    } else {
+        checksums::verify_digests(mirror_dir_path, &digests)?
+    };
+    for file in &files {
+        println!("{}", file.to_string_lossy());
+    }
+    if !files.is_empty() {
+        anyhow::bail!("{} files are missing or changed", files.len());
+    }
+    println!("Every file matches its digest.");
+    Ok(())
+}
 
-    println!("{} top level crates selected.", crates.len());
-    println!("Getting required dependencies...");
-    let dependencies = src_registry.get_dependencies(&crates)?;
-    let num_deps = dependencies.len();
-    crates.extend(dependencies);
-    println!("Done getting required dependencies.");
-    println!("{num_deps} total dependencies identified.");
+fn serve_mirror(
+    mirror_dir_path: &Path,
+    addr: SocketAddr,
+    tokens: Vec<String>,
+) -> anyhow::Result<()> {
+    let server = Arc::new(MirrorServer::new(mirror_dir_path, tokens)?);
+    let cancel = CancellationToken::new();
+    let handler_cancel = cancel.clone();
+    ctrlc::set_handler(move || handler_cancel.cancel())?;
 
-    println!("Populating local registry...");
-    dst_registry.populate(&crates)?;
-    println!("Done populating local registry.");
+    let rt = tokio::runtime::Runtime::new()?;
+    rt.block_on(async {
+        let listener = tokio::net::TcpListener::bind(addr)
+            .await
+            .with_context(|| format!("failed to listen on {addr}"))?;
+        println!("Serving the mirror at sparse+http://{addr}/index/ until Ctrl-C.");
+        server.serve(listener, cancel).await;
+        Ok(())
+    })
+}
 
+fn search_mirror(mirror_dir_path: &Path, query: &str, limit: usize) -> anyhow::Result<()> {
+    let index = SearchIndex::read(mirror_dir_path)
+        .context("the mirror has no search index, populate it with --with-metadata")?;
+    let hits = index.search(query, limit);
+    if hits.is_empty() {
+        anyhow::bail!("no crates found");
+    }
+    for hit in &hits {
+        match &hit.doc.description {
+            Some(description) => {
+                println!("{}@{}: {description}", hit.doc.name, hit.doc.version)
+            }
+            None => println!("{}@{}", hit.doc.name, hit.doc.version),
+        }
+    }
+    Ok(())
+}
+
+fn smoke_test_mirror(mirror_dir_path: &Path, options: &SmokeTestOptions) -> anyhow::Result<()> {
+    println!("Smoke testing mirror...");
+    let tested = smoke_test::smoke_test(mirror_dir_path, options)?;
+    for (name, version) in &tested {
+        println!("\tFetched {name} version {version}");
+    }
+    println!(
+        "Done smoke testing mirror: {} crates fetched.",
+        tested.len()
+    );
     Ok(())
 }
 
@@ -59,6 +650,12 @@ fn main() {
         for cause in error.chain() {
             msg += &format!("\n\tCaused by: {}", cause);
         }
+        if let Some(error) = error.downcast_ref::<micrio::Error>() {
+            if error.is_retryable() {
+                msg += "\n\tThe failure may be temporary; retrying may succeed.";
+            }
+        }
         error!("{}", msg);
+        std::process::exit(1);
     }
 }
@@ -0,0 +1,214 @@
+use crate::common::{self, Version};
+use crate::dst_registry::DstRegistry;
+use crate::search::{self, SearchIndexBuilder};
+use serde::Serialize;
+use std::collections::HashSet;
+use std::fmt::{self, Display};
+use std::io;
+
+#[derive(Debug)]
+pub enum Error {
+    ReadCrateFile {
+        crate_name: String,
+        crate_version: String,
+        error: io::Error,
+    },
+    WriteMetadata {
+        crate_name: String,
+        crate_version: String,
+        error: Box<dyn std::error::Error + Send + Sync + 'static>,
+    },
+    WriteSearchIndex(search::Error),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::ReadCrateFile {
+                crate_name,
+                crate_version,
+                error,
+            } => {
+                write!(f, "error mirroring crate metadata: failed to read the .crate file for {crate_name} version {crate_version}: {error}")
+            }
+            Error::WriteMetadata {
+                crate_name,
+                crate_version,
+                error,
+            } => {
+                write!(f, "error mirroring crate metadata: failed to store the metadata for {crate_name} version {crate_version}: {error}")
+            }
+            Error::WriteSearchIndex(e) => write!(f, "error mirroring crate metadata: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::ReadCrateFile { error, .. } => Some(error),
+            Error::WriteMetadata { error, .. } => Some(error.as_ref()),
+            Error::WriteSearchIndex(e) => Some(e),
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Name of the file holding a crate version's metadata, stored next to its .crate file.
+pub const METADATA_FILE: &str = "metadata.json";
+/// Name of the file holding a crate version's README, stored next to its .crate file.
+pub const README_FILE: &str = "README.md";
+
+/// Descriptive information about a crate version from its packaged Cargo.toml.
+#[derive(Default, Serialize)]
+pub struct CrateMetadata {
+    pub description: Option<String>,
+    pub keywords: Vec<String>,
+    pub categories: Vec<String>,
+    pub license: Option<String>,
+    pub homepage: Option<String>,
+    pub documentation: Option<String>,
+    pub repository: Option<String>,
+    /// Whether the .crate file packages a README.
+    pub has_readme: bool,
+}
+
+impl CrateMetadata {
+    /// Reads the metadata and README packaged inside the .crate file.
+    pub fn read(crate_file: &[u8], crat: &Version) -> io::Result<(Self, Option<String>)> {
+        let manifest = match common::read_packaged_manifest(crate_file, crat)? {
+            Some(manifest) => manifest,
+            None => return Ok((CrateMetadata::default(), None)),
+        };
+        let package = manifest.get("package");
+        let string = |key: &str| {
+            package
+                .and_then(|p| p.get(key))
+                .and_then(|v| v.as_str())
+                .map(|v| v.split_whitespace().collect::<Vec<_>>().join(" "))
+        };
+        let strings = |key: &str| {
+            package
+                .and_then(|p| p.get(key))
+                .and_then(|v| v.as_array())
+                .map(|v| {
+                    v.iter()
+                        .filter_map(|v| v.as_str())
+                        .map(|v| v.to_string())
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+
+        let readme = read_readme(crate_file, crat, package.and_then(|p| p.get("readme")))?;
+        let metadata = CrateMetadata {
+            description: string("description"),
+            keywords: strings("keywords"),
+            categories: strings("categories"),
+            license: string("license"),
+            homepage: string("homepage"),
+            documentation: string("documentation"),
+            repository: string("repository"),
+            has_readme: readme.is_some(),
+        };
+        Ok((metadata, readme))
+    }
+}
+
+/// How many READMEs were stored, and how many crates were indexed for search.
+pub struct MetadataCounts {
+    pub num_readmes: usize,
+    pub num_indexed: usize,
+}
+
+/// Stores each crate version's metadata and README next to its .crate file in the registry,
+/// so they can be presented to developers browsing the mirror offline, and indexes the
+/// highest version of each crate for full-text search in the search index of the mirror.
+pub fn write_metadata(
+    dst_registry: &DstRegistry,
+    crates: &HashSet<Version>,
+) -> Result<MetadataCounts> {
+    let mut num_readmes = 0;
+    let mut search_index = SearchIndexBuilder::new();
+    for crat in crates {
+        let write_error =
+            |e: Box<dyn std::error::Error + Send + Sync + 'static>| Error::WriteMetadata {
+                crate_name: crat.name().to_string(),
+                crate_version: crat.version().to_string(),
+                error: e,
+            };
+
+        let crate_file = dst_registry
+            .read_crate_file(crat.name(), crat.version())
+            .map_err(|e| Error::ReadCrateFile {
+                crate_name: crat.name().to_string(),
+                crate_version: crat.version().to_string(),
+                error: e,
+            })?;
+        let (metadata, readme) =
+            CrateMetadata::read(&crate_file, crat).map_err(|e| Error::ReadCrateFile {
+                crate_name: crat.name().to_string(),
+                crate_version: crat.version().to_string(),
+                error: e,
+            })?;
+
+        let json = serde_json::to_vec_pretty(&metadata).map_err(|e| write_error(Box::new(e)))?;
+        dst_registry
+            .write_extra_file(crat.name(), crat.version(), METADATA_FILE, &json)
+            .map_err(|e| write_error(Box::new(e)))?;
+        if let Some(readme) = &readme {
+            dst_registry
+                .write_extra_file(crat.name(), crat.version(), README_FILE, readme.as_bytes())
+                .map_err(|e| write_error(Box::new(e)))?;
+            num_readmes += 1;
+        }
+        search_index.add(
+            crat.name(),
+            crat.version(),
+            metadata.description.as_deref(),
+            &metadata.keywords,
+            readme.as_deref(),
+        );
+    }
+
+    let search_index = search_index.build();
+    search_index
+        .write(dst_registry.path())
+        .map_err(Error::WriteSearchIndex)?;
+    Ok(MetadataCounts {
+        num_readmes,
+        num_indexed: search_index.len(),
+    })
+}
+
+/// Reads the README packaged inside the .crate file.
+///
+/// `readme` is the `package.readme` field of the packaged Cargo.toml: a path, `false`
+/// if the crate has no README, or missing, in which case cargo uses the conventional names.
+fn read_readme(
+    crate_file: &[u8],
+    crat: &Version,
+    readme: Option<&toml::Value>,
+) -> io::Result<Option<String>> {
+    let candidates = match readme {
+        Some(toml::Value::Boolean(false)) => return Ok(None),
+        Some(toml::Value::String(path)) => {
+            let path = path.trim_start_matches("./").to_string();
+            // Older versions of cargo packaged READMEs outside the package root at the root.
+            let file_name = path.rsplit('/').next().unwrap_or_default().to_string();
+            vec![path, file_name]
+        }
+        _ => vec![
+            "README.md".to_string(),
+            "README.txt".to_string(),
+            "README".to_string(),
+        ],
+    };
+    for candidate in candidates {
+        if let Some(contents) = common::read_packaged_file(crate_file, crat, &candidate)? {
+            return Ok(Some(contents));
+        }
+    }
+    Ok(None)
+}
@@ -0,0 +1,238 @@
+//! Sends the outcome of mirror runs and scheduled jobs by email or to Slack, for teams without
+//! a webhook receiver to run a hook command against.
+
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Message, SmtpTransport, Transport};
+use serde::Deserialize;
+use std::env;
+use std::fmt::{self, Display};
+use std::io;
+use std::path::PathBuf;
+
+#[derive(Debug)]
+pub enum Error {
+    InvalidAddress {
+        address: String,
+        error: lettre::address::AddressError,
+    },
+    MissingPassword {
+        env_var: String,
+    },
+    BuildEmail(lettre::error::Error),
+    SendEmail(lettre::transport::smtp::Error),
+    CreateRuntime(io::Error),
+    PostSlack(reqwest::Error),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidAddress { address, error } => {
+                write!(
+                    f,
+                    "error sending notification: invalid email address {address}: {error}"
+                )
+            }
+            Error::MissingPassword { env_var } => {
+                write!(f, "error sending notification: the SMTP password environment variable {env_var} isn't set")
+            }
+            Error::BuildEmail(e) => {
+                write!(
+                    f,
+                    "error sending notification: failed to build the email: {e}"
+                )
+            }
+            Error::SendEmail(e) => {
+                write!(
+                    f,
+                    "error sending notification: failed to send the email: {e}"
+                )
+            }
+            Error::CreateRuntime(e) => {
+                write!(
+                    f,
+                    "error sending notification: failed to create tokio runtime: {e}"
+                )
+            }
+            Error::PostSlack(e) => {
+                write!(
+                    f,
+                    "error sending notification: failed to post to Slack: {e}"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::InvalidAddress { error, .. } => Some(error),
+            Error::MissingPassword { .. } => None,
+            Error::BuildEmail(e) => Some(e),
+            Error::SendEmail(e) => Some(e),
+            Error::CreateRuntime(e) => Some(e),
+            Error::PostSlack(e) => Some(e),
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// How a mirror run or scheduled job went.
+#[derive(Clone, Debug)]
+pub struct Notification {
+    /// The name of the job, e.g. `refresh`, or `mirror` for a run outside the scheduler.
+    pub job: String,
+    /// The mirror the job works on, if known.
+    pub mirror_dir_path: Option<PathBuf>,
+    /// Why the job failed, or `None` if it succeeded.
+    pub error: Option<String>,
+}
+
+impl Notification {
+    pub fn succeeded(&self) -> bool {
+        self.error.is_none()
+    }
+
+    /// A one-line summary, e.g. `micrio refresh failed for /srv/mirror`.
+    pub fn summary(&self) -> String {
+        let outcome = if self.succeeded() {
+            "succeeded"
+        } else {
+            "failed"
+        };
+        let mut summary = format!("micrio {} {outcome}", self.job);
+        if let Some(mirror_dir_path) = &self.mirror_dir_path {
+            summary += &format!(" for {}", mirror_dir_path.to_string_lossy());
+        }
+        summary
+    }
+
+    /// The summary followed by the error, if any.
+    pub fn text(&self) -> String {
+        match &self.error {
+            Some(error) => format!("{}: {error}", self.summary()),
+            None => self.summary(),
+        }
+    }
+}
+
+/// Something told how mirror runs and scheduled jobs went.
+pub trait Notifier: Send + Sync {
+    fn notify(&self, notification: &Notification) -> Result<()>;
+}
+
+/// How the connection to the SMTP server is secured.
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpSecurity {
+    /// TLS from the start, on port 465 by default.
+    #[default]
+    Tls,
+    /// Plain text upgraded with STARTTLS, on port 587 by default.
+    Starttls,
+    /// Plain text, on port 25 by default, e.g. for a relay on the same host.
+    None,
+}
+
+/// Sends notifications by email through an SMTP server.
+///
+/// The password is read from the environment variable named by `password_env` when sending,
+/// so it doesn't have to be written to a configuration file.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct EmailNotifier {
+    pub host: String,
+    /// Defaults to the port of `security`.
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub security: SmtpSecurity,
+    pub username: Option<String>,
+    pub password_env: Option<String>,
+    pub from: String,
+    pub to: Vec<String>,
+    /// Only send notifications of failures.
+    #[serde(default)]
+    pub only_failures: bool,
+}
+
+impl Notifier for EmailNotifier {
+    fn notify(&self, notification: &Notification) -> Result<()> {
+        if self.only_failures && notification.succeeded() {
+            return Ok(());
+        }
+        let mut email = Message::builder()
+            .from(parse_address(&self.from)?)
+            .subject(notification.summary());
+        for to in &self.to {
+            email = email.to(parse_address(to)?);
+        }
+        let email = email.body(notification.text()).map_err(Error::BuildEmail)?;
+
+        let mut transport = match self.security {
+            SmtpSecurity::Tls => SmtpTransport::relay(&self.host),
+            SmtpSecurity::Starttls => SmtpTransport::starttls_relay(&self.host),
+            SmtpSecurity::None => Ok(SmtpTransport::builder_dangerous(&self.host)),
+        }
+        .map_err(Error::SendEmail)?;
+        if let Some(port) = self.port {
+            transport = transport.port(port);
+        }
+        if let Some(username) = &self.username {
+            let password = match &self.password_env {
+                Some(env_var) => env::var(env_var).map_err(|_| Error::MissingPassword {
+                    env_var: env_var.clone(),
+                })?,
+                None => String::new(),
+            };
+            transport = transport.credentials(Credentials::new(username.clone(), password));
+        }
+        transport.build().send(&email).map_err(Error::SendEmail)?;
+        Ok(())
+    }
+}
+
+/// Posts notifications to a Slack channel through an incoming webhook.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct SlackNotifier {
+    /// The URL of the incoming webhook, `https://hooks.slack.com/services/...`.
+    pub webhook_url: String,
+    /// Only send notifications of failures.
+    #[serde(default)]
+    pub only_failures: bool,
+}
+
+impl Notifier for SlackNotifier {
+    fn notify(&self, notification: &Notification) -> Result<()> {
+        if self.only_failures && notification.succeeded() {
+            return Ok(());
+        }
+        let icon = if notification.succeeded() {
+            ":white_check_mark:"
+        } else {
+            ":x:"
+        };
+        let message = serde_json::json!({ "text": format!("{icon} {}", notification.text()) });
+        let rt = tokio::runtime::Runtime::new().map_err(Error::CreateRuntime)?;
+        rt.block_on(async {
+            reqwest::Client::new()
+                .post(&self.webhook_url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(message.to_string())
+                .send()
+                .await?
+                .error_for_status()
+        })
+        .map_err(Error::PostSlack)?;
+        Ok(())
+    }
+}
+
+fn parse_address(address: &str) -> Result<lettre::message::Mailbox> {
+    address.parse().map_err(|e| Error::InvalidAddress {
+        address: address.to_string(),
+        error: e,
+    })
+}
@@ -0,0 +1,219 @@
+use crate::cancel::CancellationToken;
+use crate::common::Version;
+use crate::dst_registry::DstRegistry;
+use crate::progress::ProgressObserver;
+use crate::rate_limit::RateLimiter;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashSet};
+use std::fmt::{self, Display};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use url::Url;
+
+#[derive(Debug)]
+pub enum Error {
+    CreateRuntime(io::Error),
+    OwnersUrl {
+        crate_name: String,
+        error: url::ParseError,
+    },
+    FetchOwners {
+        crate_name: String,
+        error: reqwest::Error,
+    },
+    ParseOwners {
+        crate_name: String,
+        error: serde_json::Error,
+    },
+    WriteOwners {
+        crate_name: String,
+        path: PathBuf,
+        error: io::Error,
+    },
+    Cancelled,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::CreateRuntime(e) => {
+                write!(
+                    f,
+                    "error fetching crate owners: failed to create tokio runtime: {e}"
+                )
+            }
+            Error::OwnersUrl { crate_name, error } => {
+                write!(f, "error fetching crate owners: failed to build the owners URL of the {crate_name} crate: {error}")
+            }
+            Error::FetchOwners { crate_name, error } => {
+                write!(f, "error fetching crate owners: failed to fetch the owners of the {crate_name} crate: {error}")
+            }
+            Error::ParseOwners { crate_name, error } => {
+                write!(f, "error fetching crate owners: failed to parse the owners of the {crate_name} crate: {error}")
+            }
+            Error::WriteOwners {
+                crate_name,
+                path,
+                error,
+            } => {
+                write!(
+                    f,
+                    "error fetching crate owners: failed to write {} for the {crate_name} crate: {error}",
+                    path.to_string_lossy()
+                )
+            }
+            Error::Cancelled => write!(f, "fetching crate owners was cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::CreateRuntime(e) => Some(e),
+            Error::OwnersUrl { error, .. } => Some(error),
+            Error::FetchOwners { error, .. } => Some(error),
+            Error::ParseOwners { error, .. } => Some(error),
+            Error::WriteOwners { error, .. } => Some(error),
+            Error::Cancelled => None,
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// The directory in the mirror the owners are stored in, at the paths of the crates.io API,
+/// `api/v1/crates/{name}/owners`, so a web server serving the mirror answers ownership
+/// queries like crates.io does.
+pub const OWNERS_DIR: &str = "api/v1/crates";
+
+/// The name of the file holding a crate's owners in its directory of the owners directory.
+const OWNERS_FILE: &str = "owners";
+
+/// The time between two requests to the registry's API. crates.io asks crawlers for at most
+/// one request per second.
+const REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A user or team owning a crate, as the crates.io API describes them.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Owner {
+    pub id: Option<u64>,
+    /// The user's login, or `github:{org}:{team}` for teams.
+    pub login: String,
+    pub kind: Option<String>,
+    pub name: Option<String>,
+    pub url: Option<String>,
+    pub avatar: Option<String>,
+}
+
+/// The response of the crates.io API to `GET /api/v1/crates/{name}/owners`.
+#[derive(Serialize, Deserialize)]
+struct OwnersResponse {
+    users: Vec<Owner>,
+}
+
+/// Fetches the owners of each crate from the registry's web API and stores them in the owners
+/// directory of the mirror. Owners belong to crates, not versions, so each crate is fetched
+/// once. Crates the API doesn't know are skipped. Returns the number of crates whose owners
+/// were stored. The requests go through the rate limiter, if any, besides being paced to one
+/// per second.
+pub fn write_owners(
+    dst_registry: &DstRegistry,
+    crates: &HashSet<Version>,
+    api_url: &str,
+    client: &reqwest::Client,
+    rate_limiter: Option<&RateLimiter>,
+    progress: &dyn ProgressObserver,
+    cancel: &CancellationToken,
+) -> Result<usize> {
+    let names = BTreeSet::from_iter(crates.iter().map(|crat| crat.name()));
+    let owners_dir_path = dst_registry.path().join(OWNERS_DIR);
+    let rt = tokio::runtime::Runtime::new().map_err(Error::CreateRuntime)?;
+    let mut interval = rt.block_on(async { tokio::time::interval(REQUEST_INTERVAL) });
+    let mut num_stored = 0;
+    for (i, name) in names.iter().enumerate() {
+        if cancel.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+        let url = owners_url(api_url, name).map_err(|e| Error::OwnersUrl {
+            crate_name: name.to_string(),
+            error: e,
+        })?;
+        let body: reqwest::Result<Option<_>> = rt.block_on(async {
+            interval.tick().await;
+            let _permit = match rate_limiter {
+                Some(limiter) => Some(limiter.acquire().await),
+                None => None,
+            };
+            let response = client.get(url).send().await?;
+            if response.status() == reqwest::StatusCode::NOT_FOUND {
+                return Ok(None);
+            }
+            Ok(Some(response.error_for_status()?.bytes().await?))
+        });
+        let body = match body {
+            Ok(Some(body)) => body,
+            Ok(None) => continue,
+            Err(e) => {
+                return Err(Error::FetchOwners {
+                    crate_name: name.to_string(),
+                    error: e,
+                })
+            }
+        };
+        // Checked before storing, since the mirror serves the response as it is.
+        serde_json::from_slice::<OwnersResponse>(&body).map_err(|e| Error::ParseOwners {
+            crate_name: name.to_string(),
+            error: e,
+        })?;
+        let crate_dir_path = owners_dir_path.join(name);
+        let path = crate_dir_path.join(OWNERS_FILE);
+        fs::create_dir_all(&crate_dir_path)
+            .and_then(|_| fs::write(&path, &body))
+            .map_err(|e| Error::WriteOwners {
+                crate_name: name.to_string(),
+                path: path.clone(),
+                error: e,
+            })?;
+        num_stored += 1;
+        if (i + 1) % 100 == 0 {
+            progress.info(&format!(
+                "Owners of {} of {} crates fetched.",
+                i + 1,
+                names.len()
+            ));
+        }
+    }
+    Ok(num_stored)
+}
+
+/// Reads the owners of the crate stored in the mirror, if they were fetched.
+pub fn read_owners<P: AsRef<Path>>(
+    mirror_dir_path: P,
+    crate_name: &str,
+) -> io::Result<Option<Vec<Owner>>> {
+    let path = mirror_dir_path
+        .as_ref()
+        .join(OWNERS_DIR)
+        .join(crate_name)
+        .join(OWNERS_FILE);
+    let contents = match fs::read(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let response: OwnersResponse = serde_json::from_slice(&contents)?;
+    Ok(Some(response.users))
+}
+
+/// Returns `{api_url}/crates/{name}/owners`.
+fn owners_url(api_url: &str, crate_name: &str) -> std::result::Result<Url, url::ParseError> {
+    let mut url = Url::parse(api_url)?;
+    url.path_segments_mut()
+        .map_err(|_| url::ParseError::RelativeUrlWithCannotBeABaseBase)?
+        .pop_if_empty()
+        .extend(["crates", crate_name, OWNERS_FILE]);
+    Ok(url)
+}
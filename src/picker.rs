@@ -0,0 +1,22 @@
+use crate::common::Version;
+use std::collections::HashSet;
+use std::io;
+
+/// A top-level crate offered to the picker.
+pub struct Candidate {
+    pub crat: Version,
+    /// The selection sources the crate came from, e.g. `most-downloaded 100`, sorted.
+    pub sources: Vec<String>,
+    /// The size of the .crate file, if the registry tells.
+    pub size: Option<u64>,
+    /// The number of direct dependencies, leaving out dev-dependencies, which aren't mirrored.
+    pub num_dependencies: usize,
+}
+
+/// Lets the user review the top-level crates once they are selected, before their
+/// dependencies are resolved, e.g. to drop some of the most downloaded crates.
+pub trait TopLevelPicker: Send + Sync {
+    /// Returns the crates to mirror among the candidates, which are sorted by name,
+    /// or `None` to cancel the run.
+    fn pick(&self, candidates: Vec<Candidate>) -> io::Result<Option<HashSet<Version>>>;
+}
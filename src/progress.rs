@@ -0,0 +1,195 @@
+use crate::advisory::Advisory;
+use crate::common::Version;
+use crate::provenance::GraphStats;
+use std::fmt::{self, Display};
+use std::time::{Duration, Instant};
+
+/// The phases of a mirror run, in the order they run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    Selection,
+    Resolution,
+    Audit,
+    Index,
+    Download,
+    Resolvability,
+    Metadata,
+    Licenses,
+    Owners,
+    Repos,
+    HtmlIndex,
+    Checksums,
+    Publish,
+    Reports,
+    Replicate,
+}
+
+impl Phase {
+    /// A short, stable identifier for the phase, e.g. for machine-readable output.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Phase::Selection => "selection",
+            Phase::Resolution => "resolution",
+            Phase::Audit => "audit",
+            Phase::Index => "index",
+            Phase::Download => "download",
+            Phase::Resolvability => "resolvability",
+            Phase::Metadata => "metadata",
+            Phase::Licenses => "licenses",
+            Phase::Owners => "owners",
+            Phase::Repos => "repos",
+            Phase::HtmlIndex => "html_index",
+            Phase::Checksums => "checksums",
+            Phase::Publish => "publish",
+            Phase::Reports => "reports",
+            Phase::Replicate => "replicate",
+        }
+    }
+}
+
+impl Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = match self {
+            Phase::Selection => "selecting top-level crates",
+            Phase::Resolution => "getting required dependencies",
+            Phase::Audit => "scanning for known vulnerabilities",
+            Phase::Index => "populating local index",
+            Phase::Download => "downloading crates",
+            Phase::Resolvability => "checking the crates resolve against the mirror",
+            Phase::Metadata => "mirroring crate metadata",
+            Phase::Licenses => "extracting license files",
+            Phase::Owners => "fetching crate owners",
+            Phase::Repos => "mirroring source repositories",
+            Phase::HtmlIndex => "writing HTML index",
+            Phase::Checksums => "writing checksum manifest",
+            Phase::Publish => "publishing crates",
+            Phase::Reports => "writing reports",
+            Phase::Replicate => "pushing to replicas",
+        };
+        write!(f, "{description}")
+    }
+}
+
+/// Receives progress events during a mirror run.
+///
+/// Every method has an empty default implementation, so observers only implement the events
+/// they care about.
+pub trait ProgressObserver: Send + Sync {
+    fn phase_started(&self, _phase: Phase) {}
+
+    fn phase_finished(&self, _phase: Phase) {}
+
+    /// The phase in progress handled `num_crates` crate versions, e.g. selected, resolved,
+    /// indexed, or downloaded. Reported before `phase_finished`.
+    fn phase_crates(&self, _phase: Phase, _num_crates: usize) {}
+
+    /// A page of the most downloaded crates is being retrieved from crates.io.
+    fn fetching_most_downloaded_page(&self, _page: u64, _num_pages: u64) {}
+
+    fn resolution_started(&self, _num_top_level: usize) {}
+
+    /// The dependencies of the `completed`th of `total` top-level crates are being resolved.
+    fn top_level_analyzed(&self, _crat: &Version, _completed: usize, _total: usize) {}
+
+    /// The dependencies of a newly found dependency are being resolved.
+    fn dependency_analyzed(&self, _crat: &Version) {}
+
+    fn resolution_finished(&self, _num_dependencies: usize) {}
+
+    /// The dependency graph of the selected crates was analyzed, once resolved.
+    fn graph_analyzed(&self, _stats: &GraphStats) {}
+
+    fn advisory_found(&self, _crat: &Version, _advisory: &Advisory) {}
+
+    /// The `completed`th of `total` crates was downloaded.
+    /// `total` is 0 when it isn't known yet, i.e. when streaming.
+    fn crate_downloaded(&self, _crat: &Version, _completed: usize, _total: usize) {}
+
+    /// The .crate file, `size` bytes, was stored in the mirror. Reported before `crate_downloaded`.
+    fn crate_stored(&self, _crat: &Version, _size: u64) {}
+
+    fn crate_published(&self, _crat: &Version, _completed: usize, _total: usize) {}
+
+    /// Something failed. `crat` is set if the failure is specific to a crate version.
+    fn failure(
+        &self,
+        _phase: Phase,
+        _crat: Option<&Version>,
+        _error: &(dyn std::error::Error + 'static),
+    ) {
+    }
+
+    /// An informational message, e.g. a summary of the phase that just finished.
+    fn info(&self, _msg: &str) {}
+
+    /// The run ended, whether or not it succeeded.
+    fn run_finished(&self, _succeeded: bool) {}
+}
+
+/// Ignores every progress event.
+pub struct NoProgress;
+
+impl ProgressObserver for NoProgress {}
+
+/// How long a phase of the run took.
+#[derive(Clone, Copy, Debug)]
+pub struct PhaseTime {
+    pub phase: Phase,
+    pub elapsed: Duration,
+    /// The number of crate versions the phase handled, if it reported one.
+    pub num_crates: Option<usize>,
+    pub succeeded: bool,
+}
+
+/// Times the phases of a run from its progress events, for the observers summarizing it.
+#[derive(Default)]
+pub struct PhaseTimer {
+    started: Option<Instant>,
+    /// The phase in progress and when it started.
+    current: Option<(Phase, Instant)>,
+    /// The number of crate versions the phase in progress handled, if reported.
+    current_num_crates: Option<usize>,
+    times: Vec<PhaseTime>,
+}
+
+impl PhaseTimer {
+    /// Starts timing the phase, ending the one in progress, if any, as succeeded.
+    pub fn start(&mut self, phase: Phase) {
+        let now = Instant::now();
+        self.started.get_or_insert(now);
+        self.end(true);
+        self.current = Some((phase, now));
+    }
+
+    /// Records the number of crate versions the phase in progress handled.
+    pub fn set_num_crates(&mut self, num_crates: usize) {
+        self.current_num_crates = Some(num_crates);
+    }
+
+    /// Ends the phase in progress, if any.
+    pub fn end(&mut self, succeeded: bool) {
+        if let Some((phase, started)) = self.current.take() {
+            self.times.push(PhaseTime {
+                phase,
+                elapsed: started.elapsed(),
+                num_crates: self.current_num_crates.take(),
+                succeeded,
+            });
+        }
+    }
+
+    /// Returns the phase in progress and when it started, if any.
+    pub fn current(&self) -> Option<(Phase, Instant)> {
+        self.current
+    }
+
+    /// Returns how long the phases ended so far took, in the order they ran.
+    pub fn times(&self) -> &[PhaseTime] {
+        &self.times
+    }
+
+    /// Returns the time since the first phase started, if any did.
+    pub fn elapsed(&self) -> Option<Duration> {
+        self.started.map(|started| started.elapsed())
+    }
+}
@@ -0,0 +1,256 @@
+use crate::common::Version;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+/// The number of crates listed in `GraphStats::most_depended_upon`.
+const NUM_MOST_DEPENDED_UPON: usize = 10;
+
+/// Metrics of the dependency graph of the mirrored crates.
+#[derive(Clone)]
+pub struct GraphStats {
+    /// The most dependency edges between a crate and the nearest top-level crate requiring it.
+    pub max_depth: usize,
+    /// The average number of mirrored crates each mirrored crate directly depends on.
+    pub average_fan_out: f64,
+    /// The crates the most mirrored crates directly depend on, with how many, most first.
+    pub most_depended_upon: Vec<(Version, usize)>,
+    /// The longest chain of dependencies, from a top-level crate down to a crate without
+    /// dependencies. Dependency cycles are broken where they are entered.
+    pub longest_chain: Vec<Version>,
+}
+
+/// Records why each crate ended up in the mirror.
+///
+/// Top-level crates remember the selection source(s) they came from.
+/// Dependencies remember the crates that directly depend on them, which is
+/// enough to walk back up to the top-level crates that required them.
+/// Top-level crates from a lockfile are locked: their dependencies are locked along with
+/// them, so they aren't resolved.
+#[derive(Default)]
+pub struct Provenance {
+    sources: HashMap<Version, HashSet<String>>,
+    dependents: HashMap<Version, HashSet<Version>>,
+    locked: HashSet<Version>,
+}
+
+impl Provenance {
+    pub fn new() -> Self {
+        Provenance::default()
+    }
+
+    pub fn add_top_level(&mut self, crat: &Version, source: &str) {
+        self.sources
+            .entry(crat.clone())
+            .or_default()
+            .insert(source.to_string());
+    }
+
+    /// Marks the top-level crate as locked, e.g. selected from a lockfile.
+    pub fn lock(&mut self, crat: &Version) {
+        self.locked.insert(crat.clone());
+    }
+
+    /// Returns whether the crate is a locked top-level crate, whose dependencies are selected
+    /// along with it instead of resolved.
+    pub fn is_locked(&self, crat: &Version) -> bool {
+        self.locked.contains(crat)
+    }
+
+    pub fn add_dependents(&mut self, dependents: &HashMap<Version, HashSet<Version>>) {
+        for (dependency, crates) in dependents {
+            self.dependents
+                .entry(dependency.clone())
+                .or_default()
+                .extend(crates.iter().cloned());
+        }
+    }
+
+    /// Gives the replacement the selection sources and dependents of the replaced crate,
+    /// e.g. a patched version substituted for a vulnerable one.
+    pub fn replace(&mut self, replaced: &Version, replacement: &Version) {
+        if let Some(sources) = self.sources.remove(replaced) {
+            self.sources
+                .entry(replacement.clone())
+                .or_default()
+                .extend(sources);
+        }
+        if let Some(dependents) = self.dependents.remove(replaced) {
+            self.dependents
+                .entry(replacement.clone())
+                .or_default()
+                .extend(dependents);
+        }
+        if self.locked.remove(replaced) {
+            self.locked.insert(replacement.clone());
+        }
+    }
+
+    /// Forgets the crate, e.g. a top-level crate left out of the selection.
+    pub fn remove(&mut self, crat: &Version) {
+        self.sources.remove(crat);
+        self.dependents.remove(crat);
+        self.locked.remove(crat);
+    }
+
+    /// Returns the selection sources the crate came from, sorted, if it is top-level.
+    pub fn sources(&self, crat: &Version) -> Vec<String> {
+        let mut sources = match self.sources.get(crat) {
+            Some(sources) => Vec::from_iter(sources.iter().cloned()),
+            None => Vec::new(),
+        };
+        sources.sort();
+        sources
+    }
+
+    pub fn is_top_level(&self, crat: &Version) -> bool {
+        self.sources.contains_key(crat)
+    }
+
+    /// Returns the crates that directly depend on the specified crate.
+    pub fn dependents_of(&self, crat: &Version) -> impl Iterator<Item = &Version> {
+        self.dependents.get(crat).into_iter().flatten()
+    }
+
+    /// Returns the top-level crates that transitively require the specified crate,
+    /// sorted by name then version.
+    pub fn required_by(&self, crat: &Version) -> Vec<&Version> {
+        let mut top_level = Vec::new();
+        let mut visited = HashSet::new();
+        let mut to_visit = VecDeque::new();
+        to_visit.push_back(crat);
+        while let Some(cur) = to_visit.pop_front() {
+            let dependents = match self.dependents.get(cur) {
+                Some(dependents) => dependents,
+                None => continue,
+            };
+            for dependent in dependents {
+                if !visited.insert(dependent) {
+                    continue;
+                }
+                if let Some((dependent, _)) = self.sources.get_key_value(dependent) {
+                    top_level.push(dependent);
+                }
+                to_visit.push_back(dependent);
+            }
+        }
+        top_level.sort_by(|a, b| (a.name(), a.version()).cmp(&(b.name(), b.version())));
+        top_level
+    }
+
+    /// Returns a human readable description of why the specified crate was mirrored.
+    pub fn describe(&self, crat: &Version) -> String {
+        let mut reasons = Vec::new();
+        if let Some(sources) = self.sources.get(crat) {
+            let mut sources = Vec::from_iter(sources.iter().map(|s| s.as_str()));
+            sources.sort();
+            reasons.push(format!("top-level ({})", sources.join(", ")));
+        }
+        let required_by = self.required_by(crat);
+        if !required_by.is_empty() {
+            let required_by = required_by
+                .iter()
+                .map(|v| format!("{}@{}", v.name(), v.version()))
+                .collect::<Vec<_>>();
+            reasons.push(format!("dependency of {}", required_by.join(", ")));
+        }
+        reasons.join("; ")
+    }
+
+    /// Computes metrics of the dependency graph between the crates, leaving out the
+    /// dependency edges to and from other crates, e.g. excluded ones.
+    pub fn graph_stats(&self, crates: &HashSet<Version>) -> GraphStats {
+        let mut dependencies: HashMap<&Version, Vec<&Version>> = HashMap::new();
+        let mut most_depended_upon = Vec::new();
+        let mut num_edges = 0;
+        for (dependency, dependents) in &self.dependents {
+            if !crates.contains(dependency) {
+                continue;
+            }
+            let dependents = Vec::from_iter(dependents.iter().filter(|d| crates.contains(*d)));
+            for dependent in &dependents {
+                dependencies.entry(*dependent).or_default().push(dependency);
+            }
+            num_edges += dependents.len();
+            most_depended_upon.push((dependency.clone(), dependents.len()));
+        }
+        for dependencies in dependencies.values_mut() {
+            dependencies.sort_by(|a, b| (a.name(), a.version()).cmp(&(b.name(), b.version())));
+        }
+        most_depended_upon.sort_by(|(a, a_count), (b, b_count)| {
+            (b_count, a.name(), a.version()).cmp(&(a_count, b.name(), b.version()))
+        });
+        most_depended_upon.truncate(NUM_MOST_DEPENDED_UPON);
+
+        let mut top_level = Vec::from_iter(self.sources.keys().filter(|c| crates.contains(*c)));
+        top_level.sort_by(|a, b| (a.name(), a.version()).cmp(&(b.name(), b.version())));
+
+        // Breadth-first, so each crate is first reached by its shortest path.
+        let mut max_depth = 0;
+        let mut visited: HashSet<&Version> = HashSet::from_iter(top_level.iter().copied());
+        let mut to_visit = VecDeque::from_iter(top_level.iter().map(|crat| (*crat, 0)));
+        while let Some((cur, depth)) = to_visit.pop_front() {
+            max_depth = max_depth.max(depth);
+            for dependency in dependencies.get(cur).into_iter().flatten() {
+                if visited.insert(*dependency) {
+                    to_visit.push_back((*dependency, depth + 1));
+                }
+            }
+        }
+
+        let mut chains = HashMap::new();
+        let mut on_path = HashSet::new();
+        let mut longest: Option<(usize, &Version)> = None;
+        for crat in &top_level {
+            let length = longest_chain(crat, &dependencies, &mut chains, &mut on_path);
+            let is_longer = match longest {
+                Some((longest, _)) => length > longest,
+                None => true,
+            };
+            if is_longer {
+                longest = Some((length, crat));
+            }
+        }
+        let mut longest_chain = Vec::new();
+        let mut next = longest.map(|(_, crat)| crat);
+        while let Some(crat) = next {
+            longest_chain.push(crat.clone());
+            next = chains.get(crat).and_then(|(_, next)| *next);
+        }
+
+        GraphStats {
+            max_depth,
+            average_fan_out: match crates.len() {
+                0 => 0.0,
+                n => num_edges as f64 / n as f64,
+            },
+            most_depended_upon,
+            longest_chain,
+        }
+    }
+}
+
+/// Returns the number of crates in the longest chain of dependencies starting at the crate,
+/// recording the length and next crate of the chains found in `chains`.
+fn longest_chain<'a>(
+    crat: &'a Version,
+    dependencies: &HashMap<&'a Version, Vec<&'a Version>>,
+    chains: &mut HashMap<&'a Version, (usize, Option<&'a Version>)>,
+    on_path: &mut HashSet<&'a Version>,
+) -> usize {
+    if let Some((length, _)) = chains.get(crat) {
+        return *length;
+    }
+    on_path.insert(crat);
+    let mut longest = (1, None);
+    for dependency in dependencies.get(crat).into_iter().flatten() {
+        if on_path.contains(dependency) {
+            continue;
+        }
+        let length = 1 + longest_chain(dependency, dependencies, chains, on_path);
+        if length > longest.0 {
+            longest = (length, Some(*dependency));
+        }
+    }
+    on_path.remove(crat);
+    chains.insert(crat, longest);
+    longest.0
+}
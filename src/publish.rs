@@ -0,0 +1,349 @@
+use crate::cancel::CancellationToken;
+use crate::common::{self, Version};
+use crate::dst_registry::DstRegistry;
+use crate::progress::ProgressObserver;
+use crates_index::DependencyKind;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::{self, Display};
+use std::io;
+
+#[derive(Debug)]
+pub enum Error {
+    CreateRuntime(io::Error),
+    ReadCrateFile {
+        crate_name: String,
+        crate_version: String,
+        error: io::Error,
+    },
+    SerializeMetadata {
+        crate_name: String,
+        crate_version: String,
+        error: serde_json::Error,
+    },
+    Upload {
+        crate_name: String,
+        crate_version: String,
+        error: reqwest::Error,
+    },
+    Rejected {
+        crate_name: String,
+        crate_version: String,
+        msg: String,
+    },
+    Cancelled,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::CreateRuntime(e) => {
+                write!(
+                    f,
+                    "error publishing crates: failed to create tokio runtime to upload crates: {e}"
+                )
+            }
+            Error::ReadCrateFile {
+                crate_name,
+                crate_version,
+                error,
+            } => {
+                write!(f, "error publishing crates: failed to read the .crate file for {crate_name} version {crate_version}: {error}")
+            }
+            Error::SerializeMetadata {
+                crate_name,
+                crate_version,
+                error,
+            } => {
+                write!(f, "error publishing crates: failed to serialize the metadata for {crate_name} version {crate_version}: {error}")
+            }
+            Error::Upload {
+                crate_name,
+                crate_version,
+                error,
+            } => {
+                write!(f, "error publishing crates: failed to upload {crate_name} version {crate_version}: {error}")
+            }
+            Error::Rejected {
+                crate_name,
+                crate_version,
+                msg,
+            } => {
+                write!(f, "error publishing crates: the registry rejected {crate_name} version {crate_version}: {msg}")
+            }
+            Error::Cancelled => {
+                write!(f, "publishing crates was cancelled")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::CreateRuntime(e) => Some(e),
+            Error::ReadCrateFile { error, .. } => Some(error),
+            Error::SerializeMetadata { error, .. } => Some(error),
+            Error::Upload { error, .. } => Some(error),
+            Error::Rejected { .. } => None,
+            Error::Cancelled => None,
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// The metadata cargo sends alongside the .crate file when publishing.
+/// See https://doc.rust-lang.org/cargo/reference/registry-web-api.html#publish
+#[derive(Serialize)]
+struct PublishMetadata<'a> {
+    name: &'a str,
+    vers: &'a str,
+    deps: Vec<PublishDependency<'a>>,
+    features: BTreeMap<&'a str, &'a [String]>,
+    authors: Vec<String>,
+    description: Option<String>,
+    documentation: Option<String>,
+    homepage: Option<String>,
+    readme: Option<String>,
+    readme_file: Option<String>,
+    keywords: Vec<String>,
+    categories: Vec<String>,
+    license: Option<String>,
+    license_file: Option<String>,
+    repository: Option<String>,
+    badges: BTreeMap<String, String>,
+    links: Option<&'a str>,
+}
+
+#[derive(Serialize)]
+struct PublishDependency<'a> {
+    name: &'a str,
+    version_req: &'a str,
+    features: &'a [String],
+    optional: bool,
+    default_features: bool,
+    target: Option<&'a str>,
+    kind: &'static str,
+    registry: Option<&'a str>,
+    explicit_name_in_toml: Option<&'a str>,
+}
+
+#[derive(Deserialize)]
+struct PublishResponse {
+    #[serde(default)]
+    errors: Vec<PublishResponseError>,
+}
+
+#[derive(Deserialize)]
+struct PublishResponseError {
+    detail: String,
+}
+
+/// The outcome of publishing the mirrored crates to a registry.
+pub struct PublishSummary {
+    pub published: usize,
+    /// Crate versions the registry already had.
+    pub already_published: usize,
+}
+
+/// Uploads every crate version in the set to a registry implementing cargo's publish API,
+/// such as an Artifactory or Nexus Cargo repository.
+///
+/// `api_url` is the registry's API base URL, i.e. the `api` field of its config.json.
+pub fn publish(
+    dst_registry: &DstRegistry,
+    crates: &HashSet<Version>,
+    api_url: &str,
+    client: &reqwest::Client,
+    token: Option<&str>,
+    progress: &dyn ProgressObserver,
+    cancel: &CancellationToken,
+) -> Result<PublishSummary> {
+    let mut crates = Vec::from_iter(crates.iter());
+    crates.sort_by(|a, b| (a.name(), a.version()).cmp(&(b.name(), b.version())));
+
+    let rt = tokio::runtime::Runtime::new().map_err(Error::CreateRuntime)?;
+    let publish_url = format!("{}/api/v1/crates/new", api_url.trim_end_matches('/'));
+
+    let mut summary = PublishSummary {
+        published: 0,
+        already_published: 0,
+    };
+    for (i, crat) in crates.iter().enumerate() {
+        if cancel.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+        let body = publish_body(dst_registry, crat)?;
+        let newly_published = rt.block_on(upload_crate(client, &publish_url, token, crat, body))?;
+        if newly_published {
+            summary.published += 1;
+        } else {
+            summary.already_published += 1;
+        }
+        progress.crate_published(crat, i + 1, crates.len());
+    }
+    Ok(summary)
+}
+
+/// Builds the request body of the publish API: the length-prefixed JSON metadata
+/// followed by the length-prefixed .crate file.
+fn publish_body(dst_registry: &DstRegistry, crat: &Version) -> Result<Vec<u8>> {
+    let read_error = |e| Error::ReadCrateFile {
+        crate_name: crat.name().to_string(),
+        crate_version: crat.version().to_string(),
+        error: e,
+    };
+    let crate_file = dst_registry
+        .read_crate_file(crat.name(), crat.version())
+        .map_err(read_error)?;
+    // The packaged Cargo.toml is normalized: fields inherited from a workspace are filled in,
+    // and the readme path is relative to the package root.
+    let manifest = common::read_packaged_manifest(&crate_file, crat).map_err(read_error)?;
+    let package = manifest.as_ref().and_then(|m| m.get("package"));
+    let readme = match package
+        .and_then(|p| p.get("readme"))
+        .and_then(|r| r.as_str())
+    {
+        Some(readme_file) => {
+            common::read_packaged_file(&crate_file, crat, readme_file).map_err(read_error)?
+        }
+        None => None,
+    };
+    let metadata = serde_json::to_vec(&publish_metadata(crat, package, readme)).map_err(|e| {
+        Error::SerializeMetadata {
+            crate_name: crat.name().to_string(),
+            crate_version: crat.version().to_string(),
+            error: e,
+        }
+    })?;
+
+    let mut body = Vec::with_capacity(8 + metadata.len() + crate_file.len());
+    body.extend_from_slice(&(metadata.len() as u32).to_le_bytes());
+    body.extend_from_slice(&metadata);
+    body.extend_from_slice(&(crate_file.len() as u32).to_le_bytes());
+    body.extend_from_slice(&crate_file);
+    Ok(body)
+}
+
+/// Builds the metadata of the crate version from its index entry, and from the `[package]`
+/// table of its packaged Cargo.toml for what the index doesn't hold, e.g. its description.
+fn publish_metadata<'a>(
+    crat: &'a Version,
+    package: Option<&toml::Value>,
+    readme: Option<String>,
+) -> PublishMetadata<'a> {
+    let string = |key: &str| {
+        package
+            .and_then(|p| p.get(key))
+            .and_then(|v| v.as_str())
+            .map(|v| v.to_string())
+    };
+    let strings = |key: &str| {
+        package
+            .and_then(|p| p.get(key))
+            .and_then(|v| v.as_array())
+            .map(|a| Vec::from_iter(a.iter().filter_map(|v| v.as_str()).map(|v| v.to_string())))
+            .unwrap_or_default()
+    };
+    let deps = crat
+        .dependencies()
+        .iter()
+        .map(|dep| PublishDependency {
+            name: dep.crate_name(),
+            version_req: dep.requirement(),
+            features: dep.features(),
+            optional: dep.is_optional(),
+            default_features: dep.has_default_features(),
+            target: dep.target(),
+            kind: match dep.kind() {
+                DependencyKind::Normal => "normal",
+                DependencyKind::Dev => "dev",
+                DependencyKind::Build => "build",
+            },
+            registry: crat.dependency_registry(dep),
+            explicit_name_in_toml: dep.package().map(|_| dep.name()),
+        })
+        .collect();
+    let features = crat
+        .0
+        .features()
+        .iter()
+        .map(|(name, values)| (name.as_str(), values.as_slice()))
+        .collect();
+
+    PublishMetadata {
+        name: crat.name(),
+        vers: crat.version(),
+        deps,
+        features,
+        authors: strings("authors"),
+        description: string("description"),
+        documentation: string("documentation"),
+        homepage: string("homepage"),
+        readme,
+        readme_file: string("readme"),
+        keywords: strings("keywords"),
+        categories: strings("categories"),
+        license: string("license"),
+        license_file: string("license-file"),
+        repository: string("repository"),
+        badges: BTreeMap::new(),
+        links: crat.0.links(),
+    }
+}
+
+/// Uploads the crate, returning false if the registry already had it.
+async fn upload_crate(
+    client: &reqwest::Client,
+    publish_url: &str,
+    token: Option<&str>,
+    crat: &Version,
+    body: Vec<u8>,
+) -> Result<bool> {
+    let upload_error = |e| Error::Upload {
+        crate_name: crat.name().to_string(),
+        crate_version: crat.version().to_string(),
+        error: e,
+    };
+
+    let mut request = client.put(publish_url).body(body);
+    if let Some(token) = token {
+        request = request.header(reqwest::header::AUTHORIZATION, token);
+    }
+    let response = request.send().await.map_err(upload_error)?;
+    let status = response.status();
+    let text = response.text().await.map_err(upload_error)?;
+
+    // Registries report errors in the body, sometimes with a 200 status.
+    let errors = serde_json::from_str::<PublishResponse>(&text)
+        .map(|r| r.errors.into_iter().map(|e| e.detail).collect::<Vec<_>>())
+        .unwrap_or_default();
+    if status.is_success() && errors.is_empty() {
+        return Ok(true);
+    }
+    if is_already_published(status, &errors) {
+        return Ok(false);
+    }
+    let msg = if errors.is_empty() {
+        format!("{status}: {}", text.trim())
+    } else {
+        errors.join("; ")
+    };
+    Err(Error::Rejected {
+        crate_name: crat.name().to_string(),
+        crate_version: crat.version().to_string(),
+        msg,
+    })
+}
+
+/// Whether the registry rejected the crate version because it already has it: with a
+/// 409 Conflict status, or with the error crates.io and registries mimicking it report, e.g.
+/// ``crate version `1.0.0` is already uploaded``.
+fn is_already_published(status: reqwest::StatusCode, errors: &[String]) -> bool {
+    status == reqwest::StatusCode::CONFLICT
+        || errors.iter().any(|detail| {
+            detail.starts_with("crate version")
+                && (detail.ends_with("is already uploaded") || detail.ends_with("already exists"))
+        })
+}
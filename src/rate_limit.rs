@@ -0,0 +1,122 @@
+//! Limits the requests micrio makes to the registry it mirrors from, across the crates.io API
+//! queries and the .crate file downloads, to comply with the registry's crawler policy.
+
+use crate::downloader::{CrateDownloader, DownloadFuture, SizeFuture};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use url::Url;
+
+/// Paces requests to at most a number per second and caps how many are in flight at once.
+///
+/// One limiter is meant to be shared by everything making requests, e.g. by the runs of a
+/// scheduler's refresh job, so the limits hold globally.
+///
+/// ```
+/// use micrio::rate_limit::RateLimiter;
+///
+/// // At most 10 requests per second, 4 at a time.
+/// let limiter = RateLimiter::new(Some(10.0), Some(4));
+/// let _permit = limiter.acquire_blocking();
+/// ```
+pub struct RateLimiter {
+    /// The time between two requests starting, if requests are paced.
+    interval: Option<Duration>,
+    /// When the next request may start.
+    next_start: Mutex<Instant>,
+    permits: Option<Arc<Semaphore>>,
+}
+
+/// Allows a request to be in flight until it is dropped.
+pub struct RatePermit {
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+impl RateLimiter {
+    /// Creates a limiter letting `requests_per_sec` requests start per second and
+    /// `max_concurrent` be in flight at once. `None` leaves the respective limit off.
+    pub fn new(requests_per_sec: Option<f64>, max_concurrent: Option<usize>) -> Self {
+        RateLimiter {
+            interval: requests_per_sec
+                .filter(|n| *n > 0.0)
+                .map(|n| Duration::from_secs_f64(1.0 / n)),
+            next_start: Mutex::new(Instant::now()),
+            permits: max_concurrent.map(|n| Arc::new(Semaphore::new(n.max(1)))),
+        }
+    }
+
+    /// Waits until a request may start.
+    pub async fn acquire(&self) -> RatePermit {
+        let permit = match &self.permits {
+            Some(permits) => Some(
+                Arc::clone(permits)
+                    .acquire_owned()
+                    .await
+                    .expect("the semaphore is never closed"),
+            ),
+            None => None,
+        };
+        if let Some(start) = self.reserve_start() {
+            tokio::time::sleep_until(start.into()).await;
+        }
+        RatePermit { _permit: permit }
+    }
+
+    /// Waits until a request may start, blocking the thread. Must not be called from within
+    /// a tokio runtime.
+    pub fn acquire_blocking(&self) -> RatePermit {
+        let permit = self.permits.as_ref().map(|permits| {
+            // Waiting on the semaphore needs no runtime driver, only something to poll it.
+            let rt = tokio::runtime::Builder::new_current_thread()
+                .build()
+                .expect("create tokio runtime");
+            rt.block_on(Arc::clone(permits).acquire_owned())
+                .expect("the semaphore is never closed")
+        });
+        if let Some(start) = self.reserve_start() {
+            std::thread::sleep(start.saturating_duration_since(Instant::now()));
+        }
+        RatePermit { _permit: permit }
+    }
+
+    /// Reserves the next start time, if requests are paced, and returns it.
+    fn reserve_start(&self) -> Option<Instant> {
+        let interval = self.interval?;
+        // The instant is replaced whole, so it's still usable if a thread panicked.
+        let mut next_start = self.next_start.lock().unwrap_or_else(|e| e.into_inner());
+        let start = (*next_start).max(Instant::now());
+        *next_start = start + interval;
+        Some(start)
+    }
+}
+
+/// Makes the downloader's requests through the limiter.
+pub struct RateLimitedDownloader {
+    downloader: Arc<dyn CrateDownloader>,
+    limiter: Arc<RateLimiter>,
+}
+
+impl RateLimitedDownloader {
+    pub fn new(downloader: Arc<dyn CrateDownloader>, limiter: Arc<RateLimiter>) -> Self {
+        RateLimitedDownloader {
+            downloader,
+            limiter,
+        }
+    }
+}
+
+impl CrateDownloader for RateLimitedDownloader {
+    fn download<'a>(&'a self, url: &'a Url, token: Option<&'a str>) -> DownloadFuture<'a> {
+        Box::pin(async move {
+            let _permit = self.limiter.acquire().await;
+            self.downloader.download(url, token).await
+        })
+    }
+
+    fn size<'a>(&'a self, url: &'a Url, token: Option<&'a str>) -> SizeFuture<'a> {
+        Box::pin(async move {
+            let _permit = self.limiter.acquire().await;
+            self.downloader.size(url, token).await
+        })
+    }
+}
@@ -0,0 +1,238 @@
+//! Pushes the mirror to downstream replicas after each successful run, so mirrors in several
+//! locations are kept in sync from one primary.
+
+use crate::checksums;
+use crate::dst_registry::{INDEX_DIR, STATE_DIR};
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::fmt::{self, Display};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus};
+use std::str::FromStr;
+
+#[derive(Debug)]
+pub enum Error {
+    ParseReplica(String),
+    ListFiles {
+        replica: String,
+        error: checksums::Error,
+    },
+    Copy {
+        replica: String,
+        path: PathBuf,
+        error: io::Error,
+    },
+    RunRsync {
+        replica: String,
+        error: io::Error,
+    },
+    RsyncFailed {
+        replica: String,
+        status: ExitStatus,
+        stderr: String,
+    },
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::ParseReplica(replica) => {
+                write!(f, "invalid replica {replica:?}: expected a directory path, HOST:PATH, or an rsync:// URL")
+            }
+            Error::ListFiles { replica, error } => {
+                write!(f, "error pushing to {replica}: {error}")
+            }
+            Error::Copy {
+                replica,
+                path,
+                error,
+            } => {
+                write!(
+                    f,
+                    "error pushing to {replica}: failed to copy {}: {error}",
+                    path.to_string_lossy()
+                )
+            }
+            Error::RunRsync { replica, error } => {
+                write!(
+                    f,
+                    "error pushing to {replica}: failed to run rsync: {error}"
+                )
+            }
+            Error::RsyncFailed {
+                replica,
+                status,
+                stderr,
+            } => {
+                write!(f, "error pushing to {replica}: rsync failed: {status}")?;
+                if !stderr.trim().is_empty() {
+                    write!(f, ": {}", stderr.trim())?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::ParseReplica(_) => None,
+            Error::ListFiles { error, .. } => Some(error),
+            Error::Copy { error, .. } => Some(error),
+            Error::RunRsync { error, .. } => Some(error),
+            Error::RsyncFailed { .. } => None,
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// A downstream copy of the mirror.
+///
+/// Other micrio mirrors are replicas like any other: their mirror directory is pushed to,
+/// e.g. over SSH, and served as is.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Replica {
+    /// A directory on this machine, e.g. on a network share.
+    Dir(PathBuf),
+    /// An rsync destination, `[USER@]HOST:PATH` over SSH or `rsync://HOST/MODULE/PATH`.
+    Rsync(String),
+}
+
+impl FromStr for Replica {
+    type Err = Error;
+
+    fn from_str(replica: &str) -> std::result::Result<Self, Self::Err> {
+        let replica = replica.trim();
+        if replica.is_empty() {
+            return Err(Error::ParseReplica(replica.to_string()));
+        }
+        if replica.starts_with("rsync://") {
+            return Ok(Replica::Rsync(replica.to_string()));
+        }
+        match replica.split_once(':') {
+            // Not a Windows drive letter, nor a path containing a colon.
+            Some((host, _)) if host.len() > 1 && !host.contains(['/', '\\']) => {
+                Ok(Replica::Rsync(replica.to_string()))
+            }
+            _ => Ok(Replica::Dir(PathBuf::from(replica))),
+        }
+    }
+}
+
+impl Display for Replica {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Replica::Dir(dir_path) => write!(f, "{}", dir_path.to_string_lossy()),
+            Replica::Rsync(dest) => write!(f, "{dest}"),
+        }
+    }
+}
+
+/// Makes the replica an exact copy of the mirror, leaving out micrio's own state.
+///
+/// The .crate files are transferred before the index, and stale files are deleted last,
+/// so clients of the replica never see index entries for .crate files it doesn't have yet.
+pub fn push<P: AsRef<Path>>(mirror_dir_path: P, replica: &Replica) -> Result<()> {
+    let mirror_dir_path = mirror_dir_path.as_ref();
+    match replica {
+        Replica::Dir(dir_path) => push_to_dir(mirror_dir_path, dir_path, replica),
+        Replica::Rsync(dest) => push_with_rsync(mirror_dir_path, dest, replica),
+    }
+}
+
+/// Copies the files that are new or changed since the last push, then deletes the files
+/// no longer in the mirror.
+fn push_to_dir(mirror_dir_path: &Path, replica_dir_path: &Path, replica: &Replica) -> Result<()> {
+    let list_error = |e| Error::ListFiles {
+        replica: replica.to_string(),
+        error: e,
+    };
+    let mut files = checksums::list_mirror_files(mirror_dir_path).map_err(list_error)?;
+    // Stable, so the index files keep their order among themselves.
+    files.sort_by_key(|f| f.starts_with(INDEX_DIR));
+
+    for file in &files {
+        let (src_path, dst_path) = (mirror_dir_path.join(file), replica_dir_path.join(file));
+        copy_if_changed(&src_path, &dst_path).map_err(|e| Error::Copy {
+            replica: replica.to_string(),
+            path: file.clone(),
+            error: e,
+        })?;
+    }
+
+    if !replica_dir_path.is_dir() {
+        return Ok(());
+    }
+    let files = HashSet::<&PathBuf>::from_iter(&files);
+    for file in checksums::list_mirror_files(replica_dir_path).map_err(list_error)? {
+        if !files.contains(&file) {
+            fs::remove_file(replica_dir_path.join(&file)).map_err(|e| Error::Copy {
+                replica: replica.to_string(),
+                path: file.clone(),
+                error: e,
+            })?;
+        }
+    }
+    Ok(())
+}
+
+/// Copies the file unless the destination is the same. Modification times can't tell,
+/// since the mirror directory is rebuilt by each run, so .crate files, which never change
+/// once published, are compared by size, and the other files by contents.
+/// The copy is written next to the destination first, then renamed over it,
+/// so the destination is never seen half-written.
+fn copy_if_changed(src_path: &Path, dst_path: &Path) -> io::Result<()> {
+    if let Ok(dst_metadata) = fs::metadata(dst_path) {
+        let src_metadata = fs::metadata(src_path)?;
+        let unchanged = if src_metadata.len() != dst_metadata.len() {
+            false
+        } else if src_path.extension() == Some(OsStr::new("crate")) {
+            true
+        } else {
+            fs::read(src_path)? == fs::read(dst_path)?
+        };
+        if unchanged {
+            return Ok(());
+        }
+    }
+    if let Some(parent) = dst_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut tmp_file_name = dst_path.file_name().unwrap_or_default().to_os_string();
+    tmp_file_name.push(".micrio-tmp");
+    let tmp_path = dst_path.with_file_name(tmp_file_name);
+    fs::copy(src_path, &tmp_path)?;
+    fs::rename(&tmp_path, dst_path)
+}
+
+/// Runs rsync, which only transfers what changed. `--delay-updates` puts the updated files
+/// in place together at the end of the transfer.
+fn push_with_rsync(mirror_dir_path: &Path, dest: &str, replica: &Replica) -> Result<()> {
+    // The trailing slash makes rsync copy the contents of the directory, not the directory.
+    let mut src = mirror_dir_path.to_string_lossy().into_owned();
+    if !src.ends_with('/') {
+        src.push('/');
+    }
+    let output = Command::new("rsync")
+        .args(["--archive", "--delete", "--delay-updates", "--exclude"])
+        .arg(format!("/{STATE_DIR}/"))
+        .arg(src)
+        .arg(dest)
+        .output()
+        .map_err(|e| Error::RunRsync {
+            replica: replica.to_string(),
+            error: e,
+        })?;
+    if !output.status.success() {
+        return Err(Error::RsyncFailed {
+            replica: replica.to_string(),
+            status: output.status,
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        });
+    }
+    Ok(())
+}
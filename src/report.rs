@@ -0,0 +1,432 @@
+use crate::advisory::AdvisoryDb;
+use crate::common::{self, Version};
+use crate::dst_registry::DstRegistry;
+use crate::owners::{self, Owner};
+use crate::provenance::Provenance;
+use clap::ValueEnum;
+use crates_index::DependencyKind;
+use semver::VersionReq;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::fmt::{self, Display};
+use std::fs::File;
+use std::io;
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum Error {
+    ReadCrateFile {
+        crate_name: String,
+        crate_version: String,
+        error: io::Error,
+    },
+    ReadOwners {
+        crate_name: String,
+        error: io::Error,
+    },
+    WriteReport(Box<dyn std::error::Error + Send + Sync + 'static>),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::ReadCrateFile {
+                crate_name,
+                crate_version,
+                error,
+            } => {
+                write!(
+                    f,
+                    "error building report: failed to read the .crate file for {crate_name} version {crate_version}: {error}"
+                )
+            }
+            Error::ReadOwners { crate_name, error } => {
+                write!(
+                    f,
+                    "error building report: failed to read the owners of the {crate_name} crate: {error}"
+                )
+            }
+            Error::WriteReport(e) => {
+                write!(
+                    f,
+                    "error building report: failed to write the report file: {e}"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::ReadCrateFile { error, .. } => Some(error),
+            Error::ReadOwners { error, .. } => Some(error),
+            Error::WriteReport(e) => Some(e.as_ref()),
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ReportFormat {
+    Csv,
+    Json,
+}
+
+#[derive(Serialize)]
+pub struct InventoryEntry {
+    pub name: String,
+    pub version: String,
+    pub size: u64,
+    pub license: Option<String>,
+    pub checksum: String,
+    pub provenance: String,
+    pub advisories: String,
+    /// The logins of the crate's owners, empty if they weren't fetched.
+    pub owners: String,
+}
+
+/// Registry disk usage attributed to a top-level crate.
+#[derive(Serialize)]
+pub struct SizeAttribution {
+    pub name: String,
+    pub version: String,
+    /// Size of the top-level crate's own .crate file.
+    pub own_size: u64,
+    /// Size of the crate plus every dependency it transitively requires.
+    pub total_size: u64,
+    /// Size of the crate plus the dependencies no other top-level crate requires,
+    /// i.e. the space that would be reclaimed by removing it from the selection.
+    pub exclusive_size: u64,
+    pub num_dependencies: u64,
+}
+
+/// The mirrored crates an owner publishes.
+#[derive(Serialize)]
+pub struct PublisherEntry {
+    /// The user's login, or `github:{org}:{team}` for teams.
+    pub owner: String,
+    /// `user` or `team`.
+    pub kind: Option<String>,
+    pub num_crates: u64,
+    /// The names of the crates, comma-separated.
+    pub crates: String,
+}
+
+/// A crate version in the dependency graph.
+#[derive(Serialize)]
+pub struct GraphNode {
+    /// The node's position in the list of nodes, by which edges refer to it.
+    pub id: usize,
+    pub name: String,
+    pub version: String,
+    /// The canonical index URL of the registry the crate comes from.
+    pub source: String,
+    pub top_level: bool,
+}
+
+/// A dependency of one crate version on another, as declared by the dependent.
+#[derive(Serialize)]
+pub struct GraphEdge {
+    /// The id of the dependent.
+    pub from: usize,
+    /// The id of the dependency.
+    pub to: usize,
+    pub requirement: String,
+    /// `normal`, `build`, or `dev`.
+    pub kind: &'static str,
+    pub optional: bool,
+    /// The platform the dependency is limited to, if any, e.g. `cfg(windows)`.
+    pub target: Option<String>,
+}
+
+/// The dependencies between the mirrored crates, for analysis by other tools.
+#[derive(Serialize)]
+pub struct DependencyGraph {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+}
+
+impl DependencyGraph {
+    /// Builds the graph of the dependencies between the crates. Each dependency declaration
+    /// the dependency's version satisfies is an edge, so a crate that is both a normal and
+    /// a build dependency of another has two edges. `registry_url` is the source of the
+    /// crates not from a secondary registry.
+    pub fn new(crates: &HashSet<Version>, provenance: &Provenance, registry_url: &str) -> Self {
+        let mut sorted = Vec::from_iter(crates);
+        sorted.sort_by(|a, b| (a.name(), a.version()).cmp(&(b.name(), b.version())));
+        let ids: HashMap<&Version, usize> =
+            HashMap::from_iter(sorted.iter().enumerate().map(|(id, crat)| (*crat, id)));
+
+        let nodes = Vec::from_iter(sorted.iter().enumerate().map(|(id, crat)| GraphNode {
+            id,
+            name: crat.name().to_string(),
+            version: crat.version().to_string(),
+            source: crat.registry().unwrap_or(registry_url).to_string(),
+            top_level: provenance.is_top_level(crat),
+        }));
+
+        let mut edges = Vec::new();
+        for (to, dependency) in sorted.iter().enumerate() {
+            let version = semver::Version::parse(dependency.version()).ok();
+            let mut dependents = Vec::from_iter(
+                provenance
+                    .dependents_of(dependency)
+                    .filter_map(|dependent| Some((*ids.get(dependent)?, dependent))),
+            );
+            dependents.sort_by_key(|(from, _)| *from);
+            for (from, dependent) in dependents {
+                let declarations = dependent.dependencies().iter().filter(|d| {
+                    if d.crate_name() != dependency.name() {
+                        return false;
+                    }
+                    match (VersionReq::parse(d.requirement()), &version) {
+                        (Ok(req), Some(version)) => req.matches(version),
+                        _ => false,
+                    }
+                });
+                for declaration in declarations {
+                    edges.push(GraphEdge {
+                        from,
+                        to,
+                        requirement: declaration.requirement().to_string(),
+                        kind: match declaration.kind() {
+                            DependencyKind::Normal => "normal",
+                            DependencyKind::Build => "build",
+                            DependencyKind::Dev => "dev",
+                        },
+                        optional: declaration.is_optional(),
+                        target: declaration.target().map(|t| t.to_string()),
+                    });
+                }
+            }
+        }
+        edges.sort_by_key(|edge| (edge.from, edge.to));
+        DependencyGraph { nodes, edges }
+    }
+
+    /// Writes the graph to the file as JSON.
+    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let file = File::create(path).map_err(|e| Error::WriteReport(Box::new(e)))?;
+        serde_json::to_writer_pretty(file, self).map_err(|e| Error::WriteReport(Box::new(e)))
+    }
+}
+
+/// Lists every crate in the mirror for import into asset-management and compliance systems.
+pub struct Inventory {
+    entries: Vec<InventoryEntry>,
+    size_attribution: Vec<SizeAttribution>,
+    publishers: Vec<PublisherEntry>,
+}
+
+impl Inventory {
+    pub fn new(
+        dst_registry: &DstRegistry,
+        crates: &HashSet<Version>,
+        provenance: &Provenance,
+        advisory_db: Option<&AdvisoryDb>,
+    ) -> Result<Self> {
+        let mut entries = Vec::new();
+        let mut sizes = HashMap::new();
+        // Owners belong to crates, so they're read once for all of a crate's versions.
+        let mut crate_owners: HashMap<&str, Vec<Owner>> = HashMap::new();
+        for crat in crates {
+            let crate_file = dst_registry
+                .read_crate_file(crat.name(), crat.version())
+                .map_err(|e| Error::ReadCrateFile {
+                    crate_name: crat.name().to_string(),
+                    crate_version: crat.version().to_string(),
+                    error: e,
+                })?;
+            let size = crate_file.len() as u64;
+            sizes.insert(crat, size);
+            let license = read_license(&crate_file, crat).map_err(|e| Error::ReadCrateFile {
+                crate_name: crat.name().to_string(),
+                crate_version: crat.version().to_string(),
+                error: e,
+            })?;
+            let advisories = match advisory_db {
+                Some(advisory_db) => advisory_db
+                    .advisories_for(crat)
+                    .iter()
+                    .map(|a| a.id.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                None => String::new(),
+            };
+            if !crate_owners.contains_key(crat.name()) {
+                let owners = owners::read_owners(dst_registry.path(), crat.name())
+                    .map_err(|e| Error::ReadOwners {
+                        crate_name: crat.name().to_string(),
+                        error: e,
+                    })?
+                    .unwrap_or_default();
+                crate_owners.insert(crat.name(), owners);
+            }
+            let owners = Vec::from_iter(
+                crate_owners[crat.name()]
+                    .iter()
+                    .map(|owner| owner.login.as_str()),
+            )
+            .join(", ");
+            entries.push(InventoryEntry {
+                name: crat.name().to_string(),
+                version: crat.version().to_string(),
+                size,
+                license,
+                checksum: crat.checksum(),
+                provenance: provenance.describe(crat),
+                advisories,
+                owners,
+            });
+        }
+        entries.sort_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)));
+        let size_attribution = attribute_sizes(&sizes, provenance);
+        let publishers = group_by_publisher(&crate_owners);
+        Ok(Inventory {
+            entries,
+            size_attribution,
+            publishers,
+        })
+    }
+
+    /// Returns the registry disk usage attributed to each top-level crate, largest first.
+    pub fn size_attribution(&self) -> &[SizeAttribution] {
+        &self.size_attribution
+    }
+
+    pub fn write<P: AsRef<Path>>(&self, path: P, format: ReportFormat) -> Result<()> {
+        write_rows(path, format, &self.entries)
+    }
+
+    pub fn write_size_attribution<P: AsRef<Path>>(
+        &self,
+        path: P,
+        format: ReportFormat,
+    ) -> Result<()> {
+        write_rows(path, format, &self.size_attribution)
+    }
+
+    /// Writes the mirrored crates grouped by owner, the owners of the most crates first.
+    /// A crate with several owners is listed under each.
+    pub fn write_publishers<P: AsRef<Path>>(&self, path: P, format: ReportFormat) -> Result<()> {
+        write_rows(path, format, &self.publishers)
+    }
+}
+
+fn write_rows<P: AsRef<Path>, T: Serialize>(
+    path: P,
+    format: ReportFormat,
+    rows: &[T],
+) -> Result<()> {
+    match format {
+        ReportFormat::Csv => {
+            let mut writer =
+                csv::Writer::from_path(path).map_err(|e| Error::WriteReport(Box::new(e)))?;
+            for row in rows {
+                writer
+                    .serialize(row)
+                    .map_err(|e| Error::WriteReport(Box::new(e)))?;
+            }
+            writer
+                .flush()
+                .map_err(|e| Error::WriteReport(Box::new(e)))?;
+        }
+        ReportFormat::Json => {
+            let file = File::create(path).map_err(|e| Error::WriteReport(Box::new(e)))?;
+            serde_json::to_writer_pretty(file, rows)
+                .map_err(|e| Error::WriteReport(Box::new(e)))?;
+        }
+    }
+    Ok(())
+}
+
+fn group_by_publisher(crate_owners: &HashMap<&str, Vec<Owner>>) -> Vec<PublisherEntry> {
+    let mut publishers: BTreeMap<&str, (Option<&str>, BTreeSet<&str>)> = BTreeMap::new();
+    for (crate_name, owners) in crate_owners {
+        for owner in owners {
+            let (_, crates) = publishers
+                .entry(owner.login.as_str())
+                .or_insert_with(|| (owner.kind.as_deref(), BTreeSet::new()));
+            crates.insert(*crate_name);
+        }
+    }
+    let mut entries =
+        Vec::from_iter(
+            publishers
+                .into_iter()
+                .map(|(owner, (kind, crates))| PublisherEntry {
+                    owner: owner.to_string(),
+                    kind: kind.map(str::to_string),
+                    num_crates: crates.len() as u64,
+                    crates: Vec::from_iter(crates).join(", "),
+                }),
+        );
+    entries.sort_by(|a, b| b.num_crates.cmp(&a.num_crates).then(a.owner.cmp(&b.owner)));
+    entries
+}
+
+fn attribute_sizes(
+    sizes: &HashMap<&Version, u64>,
+    provenance: &Provenance,
+) -> Vec<SizeAttribution> {
+    let mut attribution = HashMap::new();
+    for (crat, size) in sizes {
+        if provenance.is_top_level(crat) {
+            attribution.insert(
+                *crat,
+                SizeAttribution {
+                    name: crat.name().to_string(),
+                    version: crat.version().to_string(),
+                    own_size: *size,
+                    total_size: *size,
+                    exclusive_size: *size,
+                    num_dependencies: 0,
+                },
+            );
+        }
+    }
+
+    for (crat, size) in sizes {
+        let required_by = provenance.required_by(crat);
+        let exclusive = required_by.len() == 1 && !provenance.is_top_level(crat);
+        for top_level in required_by {
+            if top_level == *crat {
+                continue;
+            }
+            // The top-level crate may have been excluded from the mirror.
+            let top_level = match attribution.get_mut(top_level) {
+                Some(top_level) => top_level,
+                None => continue,
+            };
+            top_level.total_size += size;
+            top_level.num_dependencies += 1;
+            if exclusive {
+                top_level.exclusive_size += size;
+            }
+        }
+    }
+
+    let mut attribution = Vec::from_iter(attribution.into_values());
+    attribution.sort_by(|a, b| b.total_size.cmp(&a.total_size).then(a.name.cmp(&b.name)));
+    attribution
+}
+
+/// Reads the license from the Cargo.toml packaged inside the .crate file.
+fn read_license(crate_file: &[u8], crat: &Version) -> io::Result<Option<String>> {
+    let manifest = match common::read_packaged_manifest(crate_file, crat)? {
+        Some(manifest) => manifest,
+        None => return Ok(None),
+    };
+    let package = manifest.get("package");
+    let license = package
+        .and_then(|p| p.get("license"))
+        .and_then(|l| l.as_str())
+        .map(|l| l.to_string());
+    let license_file = package
+        .and_then(|p| p.get("license-file"))
+        .and_then(|l| l.as_str())
+        .map(|l| format!("see {l}"));
+    Ok(license.or(license_file))
+}
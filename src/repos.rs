@@ -0,0 +1,291 @@
+//! Clones the source repositories of the top-level crates into the mirror, so developers
+//! without network access have their history, examples, and templates besides the packaged
+//! sources.
+
+use crate::cancel::CancellationToken;
+use crate::common::{self, Version};
+use crate::dst_registry::DstRegistry;
+use crate::progress::ProgressObserver;
+use crate::warnings;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::{self, Display};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+use url::Url;
+use walkdir::WalkDir;
+
+#[derive(Debug)]
+pub enum Error {
+    ReadCrateFile {
+        crate_name: String,
+        crate_version: String,
+        error: io::Error,
+    },
+    CloneRepo {
+        url: String,
+        error: git2::Error,
+    },
+    CreateRepoDir {
+        path: PathBuf,
+        error: io::Error,
+    },
+    RemoveRepos(io::Error),
+    Cancelled,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::ReadCrateFile {
+                crate_name,
+                crate_version,
+                error,
+            } => {
+                write!(f, "error mirroring repositories: failed to read the .crate file for {crate_name} version {crate_version}: {error}")
+            }
+            Error::CloneRepo { url, error } => {
+                write!(
+                    f,
+                    "error mirroring repositories: failed to fetch {url}: {error}"
+                )
+            }
+            Error::CreateRepoDir { path, error } => {
+                write!(
+                    f,
+                    "error mirroring repositories: failed to create {}: {error}",
+                    path.to_string_lossy()
+                )
+            }
+            Error::RemoveRepos(e) => {
+                write!(
+                    f,
+                    "error mirroring repositories: failed to remove the repositories no longer mirrored: {e}"
+                )
+            }
+            Error::Cancelled => write!(f, "mirroring repositories was cancelled"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::ReadCrateFile { error, .. } => Some(error),
+            Error::CloneRepo { error, .. } => Some(error),
+            Error::CreateRepoDir { error, .. } => Some(error),
+            Error::RemoveRepos(e) => Some(e),
+            Error::Cancelled => None,
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// The directory in the mirror the repositories are cloned into, as bare repositories at
+/// `{host}/{path}.git`. Unlike the rest of the mirror, it's kept between runs, so repositories
+/// already cloned are only fetched into.
+pub const REPOS_DIR: &str = "repos";
+
+/// Hosts whose repository URLs often point into a repository, e.g. `/tree/master/tokio`,
+/// so only their first two path segments name the repository.
+const FORGES: [&str; 4] = ["github.com", "gitlab.com", "codeberg.org", "bitbucket.org"];
+
+/// How many repositories were cloned, fetched into, or couldn't be.
+pub struct RepoCounts {
+    pub num_cloned: usize,
+    pub num_updated: usize,
+    pub num_failed: usize,
+    /// The top-level crates whose packaged Cargo.toml has no usable `repository` URL.
+    pub num_without: usize,
+}
+
+/// Clones the repository given by the `repository` field of each top-level crate's packaged
+/// Cargo.toml into the repositories directory of the mirror, or fetches into it if it was
+/// cloned by a previous run, mirroring every branch and tag. Crates of the same repository,
+/// e.g. the members of a workspace, share its clone. Repositories that can't be fetched are
+/// logged and skipped, since they often move or disappear. Repositories of crates no longer
+/// mirrored are removed.
+pub fn mirror_repos(
+    dst_registry: &DstRegistry,
+    top_level: &HashSet<&Version>,
+    progress: &dyn ProgressObserver,
+    cancel: &CancellationToken,
+) -> Result<RepoCounts> {
+    let repos_dir_path = dst_registry.path().join(REPOS_DIR);
+    let mut counts = RepoCounts {
+        num_cloned: 0,
+        num_updated: 0,
+        num_failed: 0,
+        num_without: 0,
+    };
+
+    // The URL of each repository by the path of its clone, with a crate it was found for.
+    let mut repos = BTreeMap::new();
+    for crat in top_level {
+        let read_error = |e| Error::ReadCrateFile {
+            crate_name: crat.name().to_string(),
+            crate_version: crat.version().to_string(),
+            error: e,
+        };
+        let crate_file = dst_registry
+            .read_crate_file(crat.name(), crat.version())
+            .map_err(read_error)?;
+        let manifest = common::read_packaged_manifest(&crate_file, crat).map_err(read_error)?;
+        let repo = manifest
+            .as_ref()
+            .and_then(|m| m.get("package"))
+            .and_then(|p| p.get("repository"))
+            .and_then(|r| r.as_str())
+            .and_then(repo_location);
+        match repo {
+            Some((url, rel_path)) => {
+                repos.entry(rel_path).or_insert((url, *crat));
+            }
+            None => counts.num_without += 1,
+        }
+    }
+
+    for (i, (rel_path, (url, crat))) in repos.iter().enumerate() {
+        if cancel.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
+        let repo_path = repos_dir_path.join(rel_path);
+        let existed = repo_path.join("HEAD").is_file();
+        match fetch_repo(url, &repo_path) {
+            Ok(()) if existed => counts.num_updated += 1,
+            Ok(()) => counts.num_cloned += 1,
+            Err(e) => {
+                warn!(
+                    target: warnings::REPOSITORY,
+                    crate_name = crat.name(),
+                    "{e}"
+                );
+                counts.num_failed += 1;
+            }
+        }
+        if (i + 1) % 10 == 0 {
+            progress.info(&format!(
+                "{} of {} repositories fetched.",
+                i + 1,
+                repos.len()
+            ));
+        }
+    }
+
+    let kept = HashSet::from_iter(repos.into_keys());
+    prune_repos(&repos_dir_path, &kept).map_err(Error::RemoveRepos)?;
+    Ok(counts)
+}
+
+/// Removes the repositories directory of the mirror, e.g. once repositories are no longer
+/// mirrored.
+pub fn remove_repos<P: AsRef<Path>>(mirror_dir_path: P) -> Result<()> {
+    match fs::remove_dir_all(mirror_dir_path.as_ref().join(REPOS_DIR)) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(Error::RemoveRepos(e)),
+    }
+}
+
+/// Returns the URL to clone and the path of the clone in the repositories directory for the
+/// `repository` URL of a crate, or `None` if it isn't an HTTP(S) URL of a repository.
+fn repo_location(repository: &str) -> Option<(String, PathBuf)> {
+    let url = Url::parse(repository.trim()).ok()?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return None;
+    }
+    let host = url.host_str()?.to_lowercase();
+    let mut segments = Vec::from_iter(url.path_segments()?.filter(|s| !s.is_empty()));
+    if FORGES.contains(&host.as_str()) {
+        segments.truncate(2);
+    }
+    let last = segments.pop()?;
+    let last = last.strip_suffix(".git").unwrap_or(last);
+    segments.push(last);
+    // The path is built from the URL, so it must not leave the repositories directory.
+    if segments
+        .iter()
+        .any(|s| s.is_empty() || *s == "." || *s == ".." || s.contains('\\'))
+    {
+        return None;
+    }
+
+    let mut clone_url = url.clone();
+    clone_url.set_query(None);
+    clone_url.set_fragment(None);
+    clone_url.set_path(&segments.join("/"));
+    let mut rel_path = PathBuf::from(&host);
+    for segment in &segments[..segments.len() - 1] {
+        rel_path.push(segment);
+    }
+    rel_path.push(format!("{last}.git"));
+    Some((clone_url.to_string(), rel_path))
+}
+
+/// Fetches every branch and tag of the repository into the bare repository at the path,
+/// creating it if needed, and points its HEAD at the repository's default branch.
+fn fetch_repo(url: &str, repo_path: &Path) -> Result<()> {
+    let clone_error = |e| Error::CloneRepo {
+        url: url.to_string(),
+        error: e,
+    };
+    fs::create_dir_all(repo_path).map_err(|e| Error::CreateRepoDir {
+        path: repo_path.to_path_buf(),
+        error: e,
+    })?;
+    let repo = git2::Repository::init_bare(repo_path).map_err(clone_error)?;
+    let mut remote = repo.remote_anonymous(url).map_err(clone_error)?;
+    let mut connection = remote
+        .connect_auth(git2::Direction::Fetch, None, None)
+        .map_err(clone_error)?;
+    let default_branch = connection.default_branch().map_err(clone_error)?;
+    let default_branch = default_branch.as_str().map(|b| b.to_string());
+    let mut options = git2::FetchOptions::new();
+    options.prune(git2::FetchPrune::On);
+    connection
+        .remote()
+        .fetch(
+            &["+refs/heads/*:refs/heads/*", "+refs/tags/*:refs/tags/*"],
+            Some(&mut options),
+            None,
+        )
+        .map_err(clone_error)?;
+    if let Some(default_branch) = default_branch {
+        repo.set_head(&default_branch).map_err(clone_error)?;
+    }
+    Ok(())
+}
+
+/// Removes the bare repositories under the directory that aren't at one of the kept paths,
+/// relative to it, along with the directories left empty.
+fn prune_repos(repos_dir_path: &Path, kept: &HashSet<PathBuf>) -> io::Result<()> {
+    if !repos_dir_path.is_dir() {
+        return Ok(());
+    }
+    let mut removed = Vec::new();
+    let mut walker = WalkDir::new(repos_dir_path).min_depth(1).into_iter();
+    while let Some(entry) = walker.next() {
+        let entry = entry.map_err(io::Error::from)?;
+        if !entry.file_type().is_dir() || !entry.file_name().to_string_lossy().ends_with(".git") {
+            continue;
+        }
+        // Repositories aren't nested, so there's no need to look inside.
+        walker.skip_current_dir();
+        let rel_path = entry.path().strip_prefix(repos_dir_path).unwrap();
+        if !kept.contains(rel_path) {
+            fs::remove_dir_all(entry.path())?;
+            removed.push(entry.path().to_path_buf());
+        }
+    }
+    // Remove the host and owner directories emptied, deepest first.
+    for path in removed {
+        for dir_path in path.ancestors().skip(1) {
+            if dir_path == repos_dir_path || fs::remove_dir(dir_path).is_err() {
+                break;
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,219 @@
+//! Checks that Cargo can resolve the dependencies of crates using only a mirror's index,
+//! finding the crates that would fail to build against the mirror because a transitive
+//! dependency has no compatible version in it.
+
+use crate::common::Version;
+use crate::src_registry::{self, RequestedFeatures};
+use crate::top_level;
+use cfg_expr::targets::TargetInfo;
+use crates_index::DependencyKind;
+use semver::VersionReq;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Display};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum Error {
+    ReadIndex {
+        path: PathBuf,
+        error: Box<dyn std::error::Error + Send + Sync + 'static>,
+    },
+    Unresolvable {
+        num_crates: usize,
+        num_top_level: usize,
+    },
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::ReadIndex { path, error } => {
+                write!(
+                    f,
+                    "failed to read the mirror's index {}: {error}",
+                    path.to_string_lossy()
+                )
+            }
+            Error::Unresolvable {
+                num_crates,
+                num_top_level,
+            } => {
+                write!(
+                    f,
+                    "{num_crates} of {num_top_level} top-level crates can't be resolved against the mirror"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::ReadIndex { error, .. } => Some(error.as_ref()),
+            Error::Unresolvable { .. } => None,
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// A dependency without a compatible version in the mirror, making a top-level crate fail to
+/// build against it.
+#[derive(Clone, Debug)]
+pub struct Unresolvable {
+    /// The top-level crate that fails to resolve.
+    pub crate_name: String,
+    pub crate_version: String,
+    /// The crate version requiring the dependency, as `name@version`: the top-level crate
+    /// itself or one of its transitive dependencies.
+    pub required_by: String,
+    pub dependency_name: String,
+    pub requirement: String,
+}
+
+/// The versions of each crate in the mirror's index, by lowercase name.
+type MirrorIndex = HashMap<String, Vec<(semver::Version, Version)>>;
+
+/// Simulates Cargo resolving each top-level crate with its default features against the
+/// mirror's index directory alone, returning the dependencies that can't be resolved.
+///
+/// Like Cargo without a lockfile, the newest non-yanked version matching each requirement is
+/// picked, and the optional dependencies the enabled features require are followed.
+/// Platform-specific dependencies are only followed if they build for one of the targets,
+/// or always if there are none. Dependencies on other registries are left to Cargo's
+/// configuration for them.
+pub fn check_resolvable<P: AsRef<Path>>(
+    index_dir_path: P,
+    top_level: &[&Version],
+    targets: &[&'static TargetInfo],
+) -> Result<Vec<Unresolvable>> {
+    let index = read_mirror_index(index_dir_path.as_ref())?;
+    // Without targets, dependencies are followed for any platform.
+    let platforms = if targets.is_empty() {
+        vec![None]
+    } else {
+        Vec::from_iter(targets.iter().copied().map(Some))
+    };
+    let mut unresolvable = Vec::new();
+    for crat in top_level {
+        // The top-level crate may be a pinned yanked version.
+        let entry = find_version(&index, crat.name(), |v| v.version() == crat.version());
+        if let Some(entry) = entry {
+            unresolvable.extend(resolve(&index, entry, &platforms).into_iter().map(
+                |(required_by, dependency_name, requirement)| Unresolvable {
+                    crate_name: crat.name().to_string(),
+                    crate_version: crat.version().to_string(),
+                    required_by,
+                    dependency_name,
+                    requirement,
+                },
+            ));
+        }
+    }
+    Ok(unresolvable)
+}
+
+/// Resolves the crate version's dependency graph, returning the requiring crate version,
+/// name, and requirement of each dependency without a compatible version.
+fn resolve<'x>(
+    index: &'x MirrorIndex,
+    top_level: &'x Version,
+    platforms: &[Option<&'static TargetInfo>],
+) -> Vec<(String, String, String)> {
+    let mut enabled: HashMap<(&str, &str), RequestedFeatures> = HashMap::new();
+    let mut missing = Vec::new();
+    let mut reported = HashSet::new();
+    let mut to_resolve = vec![(top_level, Vec::new(), true)];
+    while let Some((crat, features, default)) = to_resolve.pop() {
+        let key = (crat.name(), crat.version());
+        let is_new = !enabled.contains_key(&key);
+        let state = enabled.entry(key).or_default();
+        let num_features = state.features.len();
+        state.features.extend(features);
+        let has_new_features = state.features.len() > num_features || (default && !state.default);
+        state.default |= default;
+        if !is_new && !has_new_features {
+            continue;
+        }
+
+        let activation = src_registry::activate(crat, state);
+        for dependency in crat.dependencies() {
+            if matches!(dependency.kind(), DependencyKind::Dev)
+                || (dependency.is_optional()
+                    && !activation.dependencies.contains(dependency.name()))
+                || crat.dependency_registry(dependency).is_some()
+                || src_registry::targets_building(dependency, platforms, crat.name()) == 0
+            {
+                continue;
+            }
+            let req = match VersionReq::parse(dependency.requirement()) {
+                Ok(req) => req,
+                Err(_) => continue,
+            };
+            let found =
+                find_version(
+                    index,
+                    dependency.crate_name(),
+                    |v| match semver::Version::parse(v.version()) {
+                        Ok(version) => !v.is_yanked() && req.matches(&version),
+                        Err(_) => false,
+                    },
+                );
+            let found = match found {
+                Some(found) => found,
+                None => {
+                    let required_by = format!("{}@{}", crat.name(), crat.version());
+                    if reported.insert((required_by.clone(), dependency.name().to_string())) {
+                        missing.push((
+                            required_by,
+                            dependency.crate_name().to_string(),
+                            dependency.requirement().to_string(),
+                        ));
+                    }
+                    continue;
+                }
+            };
+            let mut features = dependency.features().to_vec();
+            if let Some(extra) = activation.dependency_features.get(dependency.name()) {
+                features.extend(extra.iter().cloned());
+            }
+            to_resolve.push((found, features, dependency.has_default_features()));
+        }
+    }
+    missing
+}
+
+/// Returns the newest version of the crate in the mirror accepted by the filter.
+fn find_version<'x, F: Fn(&Version) -> bool>(
+    index: &'x MirrorIndex,
+    crate_name: &str,
+    accept: F,
+) -> Option<&'x Version> {
+    index
+        .get(&crate_name.to_lowercase())?
+        .iter()
+        .filter(|(_, version)| accept(version))
+        .max_by(|(a, _), (b, _)| a.cmp(b))
+        .map(|(_, version)| version)
+}
+
+/// Reads every crate version in the mirror's index directory.
+/// Versions that aren't valid semver are left out, since no requirement can match them.
+fn read_mirror_index(index_dir_path: &Path) -> Result<MirrorIndex> {
+    let entries =
+        top_level::read_index::<Version>(index_dir_path).map_err(|e| Error::ReadIndex {
+            path: index_dir_path.to_path_buf(),
+            error: e,
+        })?;
+    let mut index = MirrorIndex::new();
+    for version in entries {
+        if let Ok(semver) = semver::Version::parse(version.version()) {
+            index
+                .entry(version.name().to_lowercase())
+                .or_default()
+                .push((semver, version));
+        }
+    }
+    Ok(index)
+}
@@ -0,0 +1,398 @@
+use crate::advisory::Advisory;
+use crate::common::Version;
+use crate::dst_registry::STATE_DIR;
+use crate::progress::{Phase, PhaseTimer, ProgressObserver};
+use crate::provenance::GraphStats;
+use crate::selection::SelectionSpec;
+use serde::{Deserialize, Serialize};
+use std::fmt::{self, Display};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// The directory in the mirror's state directory the run reports are written to.
+pub const RUNS_DIR: &str = "runs";
+
+#[derive(Debug)]
+pub enum Error {
+    WriteReport { path: PathBuf, error: io::Error },
+    RemoveReport { path: PathBuf, error: io::Error },
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::WriteReport { path, error } => {
+                write!(
+                    f,
+                    "failed to write the run report {}: {error}",
+                    path.to_string_lossy()
+                )
+            }
+            Error::RemoveReport { path, error } => {
+                write!(
+                    f,
+                    "failed to remove the old run report {}: {error}",
+                    path.to_string_lossy()
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::WriteReport { error, .. } => Some(error),
+            Error::RemoveReport { error, .. } => Some(error),
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// A machine-readable record of a mirror run, written to `.micrio/runs/` in the mirror
+/// directory after each run as an audit trail across updates.
+#[derive(Serialize, Deserialize)]
+pub struct RunReport {
+    pub micrio_version: String,
+    /// When the run started, in seconds since the Unix epoch.
+    pub started_at: u64,
+    /// Identifies the run, also among those started in the same second: when it started,
+    /// with nanoseconds, and the process it ran in. The report's file is named after it.
+    #[serde(default)]
+    pub run_id: String,
+    pub duration_secs: f64,
+    pub succeeded: bool,
+    pub selection: SelectionRecord,
+    /// The index URL of the registry the crates were mirrored from.
+    pub registry: String,
+    /// The commit of the index the selection was resolved against, if known.
+    pub index_commit: Option<String>,
+    pub num_top_level: usize,
+    pub num_dependencies: usize,
+    pub num_downloaded: usize,
+    pub num_failed: usize,
+    pub bytes_downloaded: u64,
+    pub phases: Vec<PhaseRecord>,
+    pub failures: Vec<FailureRecord>,
+}
+
+/// The selection the run was given.
+#[derive(Serialize, Deserialize)]
+pub struct SelectionRecord {
+    /// Crates given as `NAME` or `NAME@REQ`.
+    pub crates: Vec<String>,
+    pub files: Vec<PathBuf>,
+    pub most_downloaded: Option<u64>,
+    #[serde(default)]
+    pub recently_updated: Option<u64>,
+    pub lockfiles: Vec<PathBuf>,
+    pub vendor_dirs: Vec<PathBuf>,
+    pub mirrors: Vec<String>,
+    /// Category slugs, with the maximum number of crates selected from each, if any.
+    pub categories: Vec<(String, Option<u64>)>,
+    /// Keywords, with the maximum number of crates selected with each, if any.
+    #[serde(default)]
+    pub keywords: Vec<(String, Option<u64>)>,
+    #[serde(default)]
+    pub owners: Vec<String>,
+    /// Search queries, with the maximum number of crates selected from each, if any.
+    #[serde(default)]
+    pub searches: Vec<(String, Option<u64>)>,
+    #[serde(default)]
+    pub min_downloads: Option<u64>,
+    pub exclusions: Vec<String>,
+    pub targets: Vec<String>,
+}
+
+impl SelectionRecord {
+    pub fn new(spec: &SelectionSpec, targets: &[String]) -> Self {
+        SelectionRecord {
+            crates: Vec::from_iter(spec.crates.iter().map(|c| c.to_string())),
+            files: spec.files.clone(),
+            most_downloaded: spec.most_downloaded,
+            recently_updated: spec.recently_updated,
+            lockfiles: spec.lockfiles.clone(),
+            vendor_dirs: spec.vendor_dirs.clone(),
+            mirrors: spec.mirrors.clone(),
+            categories: Vec::from_iter(spec.categories.iter().map(|c| (c.slug.clone(), c.limit))),
+            keywords: Vec::from_iter(spec.keywords.iter().map(|k| (k.keyword.clone(), k.limit))),
+            owners: spec.owners.clone(),
+            searches: Vec::from_iter(spec.searches.iter().map(|s| (s.query.clone(), s.limit))),
+            min_downloads: spec.min_downloads,
+            exclusions: Vec::from_iter(spec.exclusions.iter().map(|c| c.to_string())),
+            targets: targets.to_vec(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct PhaseRecord {
+    /// The phase's identifier, e.g. `download`.
+    pub phase: String,
+    pub duration_secs: f64,
+    /// The number of crate versions the phase handled, if it reported one.
+    pub num_crates: Option<usize>,
+    pub succeeded: bool,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct FailureRecord {
+    pub phase: String,
+    /// The crate version the failure is specific to, if any.
+    pub crate_name: Option<String>,
+    pub crate_version: Option<String>,
+    pub error: String,
+}
+
+impl RunReport {
+    /// Writes the report to the mirror's state directory, in a file named after the run's ID,
+    /// and returns its path.
+    pub fn write<P: AsRef<Path>>(&self, mirror_dir_path: P) -> Result<PathBuf> {
+        let runs_dir_path = mirror_dir_path.as_ref().join(STATE_DIR).join(RUNS_DIR);
+        let path = runs_dir_path.join(format!("run-{}.json", self.run_id));
+        let write_error = |e| Error::WriteReport {
+            path: path.clone(),
+            error: e,
+        };
+        fs::create_dir_all(&runs_dir_path).map_err(write_error)?;
+        let contents = serde_json::to_vec_pretty(self).map_err(|e| write_error(e.into()))?;
+        fs::write(&path, contents).map_err(write_error)?;
+        Ok(path)
+    }
+}
+
+/// Reads the reports of the `n` most recent runs of the mirror, newest first.
+/// Reports that can't be read are skipped.
+pub fn read_recent<P: AsRef<Path>>(mirror_dir_path: P, n: usize) -> io::Result<Vec<RunReport>> {
+    let runs_dir_path = mirror_dir_path.as_ref().join(STATE_DIR).join(RUNS_DIR);
+    let mut reports = Vec::new();
+    for run_id in list_runs(&runs_dir_path)?.into_iter().take(n) {
+        let path = runs_dir_path.join(format!("run-{run_id}.json"));
+        let report = fs::read(&path)
+            .ok()
+            .and_then(|contents| serde_json::from_slice(&contents).ok());
+        if let Some(report) = report {
+            reports.push(report);
+        }
+    }
+    Ok(reports)
+}
+
+/// Removes the reports of all but the `keep` most recent runs of the mirror.
+/// Returns the number of reports removed.
+pub fn remove_old<P: AsRef<Path>>(mirror_dir_path: P, keep: usize) -> Result<usize> {
+    let runs_dir_path = mirror_dir_path.as_ref().join(STATE_DIR).join(RUNS_DIR);
+    let run_ids = list_runs(&runs_dir_path).map_err(|e| Error::RemoveReport {
+        path: runs_dir_path.clone(),
+        error: e,
+    })?;
+    let mut num_removed = 0;
+    for run_id in run_ids.into_iter().skip(keep) {
+        let path = runs_dir_path.join(format!("run-{run_id}.json"));
+        fs::remove_file(&path).map_err(|e| Error::RemoveReport { path, error: e })?;
+        num_removed += 1;
+    }
+    Ok(num_removed)
+}
+
+/// Returns the IDs of the runs with a report in the directory, most recent first.
+fn list_runs(runs_dir_path: &Path) -> io::Result<Vec<String>> {
+    let entries = match fs::read_dir(runs_dir_path) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    // By when the run started, then its ID, which starts with the nanoseconds.
+    let mut runs = Vec::new();
+    for entry in entries {
+        let file_name = entry?.file_name();
+        let file_name = file_name.to_string_lossy();
+        let run_id = match file_name
+            .strip_prefix("run-")
+            .and_then(|f| f.strip_suffix(".json"))
+        {
+            Some(run_id) => run_id,
+            None => continue,
+        };
+        let started_at = run_id.split('-').next().and_then(|t| t.parse::<u64>().ok());
+        if let Some(started_at) = started_at {
+            runs.push((started_at, run_id.to_string()));
+        }
+    }
+    runs.sort_unstable_by(|a, b| b.cmp(a));
+    Ok(Vec::from_iter(runs.into_iter().map(|(_, run_id)| run_id)))
+}
+
+/// What the run did so far, for the report.
+#[derive(Default)]
+struct RunRecord {
+    num_top_level: usize,
+    num_dependencies: usize,
+    num_downloaded: usize,
+    num_failed: usize,
+    bytes_downloaded: u64,
+    phases: PhaseTimer,
+    failures: Vec<FailureRecord>,
+}
+
+/// Records the progress events of a run for its report, passing them on to another observer.
+pub(crate) struct RunRecorder {
+    inner: Arc<dyn ProgressObserver>,
+    started_at: SystemTime,
+    started: Instant,
+    record: Mutex<RunRecord>,
+}
+
+impl RunRecorder {
+    pub fn new(inner: Arc<dyn ProgressObserver>) -> Self {
+        RunRecorder {
+            inner,
+            started_at: SystemTime::now(),
+            started: Instant::now(),
+            record: Mutex::new(RunRecord::default()),
+        }
+    }
+
+    fn record(&self) -> MutexGuard<'_, RunRecord> {
+        // The record is only counters and lists, so it's still usable if a thread panicked.
+        self.record.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Reports what the run did so far.
+    pub fn report(
+        &self,
+        selection: SelectionRecord,
+        registry: &str,
+        index_commit: Option<String>,
+        succeeded: bool,
+    ) -> RunReport {
+        let mut record = self.record();
+        let started_at = self
+            .started_at
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        RunReport {
+            micrio_version: env!("CARGO_PKG_VERSION").to_string(),
+            started_at: started_at.as_secs(),
+            run_id: format!(
+                "{}-{:09}-{}",
+                started_at.as_secs(),
+                started_at.subsec_nanos(),
+                std::process::id()
+            ),
+            duration_secs: self.started.elapsed().as_secs_f64(),
+            succeeded,
+            selection,
+            registry: registry.to_string(),
+            index_commit,
+            num_top_level: record.num_top_level,
+            num_dependencies: record.num_dependencies,
+            num_downloaded: record.num_downloaded,
+            num_failed: record.num_failed,
+            bytes_downloaded: record.bytes_downloaded,
+            phases: Vec::from_iter(record.phases.times().iter().map(|time| PhaseRecord {
+                phase: time.phase.as_str().to_string(),
+                duration_secs: time.elapsed.as_secs_f64(),
+                num_crates: time.num_crates,
+                succeeded: time.succeeded,
+            })),
+            failures: std::mem::take(&mut record.failures),
+        }
+    }
+}
+
+impl ProgressObserver for RunRecorder {
+    fn phase_started(&self, phase: Phase) {
+        self.record().phases.start(phase);
+        self.inner.phase_started(phase);
+    }
+
+    fn phase_finished(&self, phase: Phase) {
+        self.record().phases.end(true);
+        self.inner.phase_finished(phase);
+    }
+
+    fn phase_crates(&self, phase: Phase, num_crates: usize) {
+        self.record().phases.set_num_crates(num_crates);
+        self.inner.phase_crates(phase, num_crates);
+    }
+
+    fn fetching_most_downloaded_page(&self, page: u64, num_pages: u64) {
+        self.inner.fetching_most_downloaded_page(page, num_pages);
+    }
+
+    fn resolution_started(&self, num_top_level: usize) {
+        self.record().num_top_level = num_top_level;
+        self.inner.resolution_started(num_top_level);
+    }
+
+    fn top_level_analyzed(&self, crat: &Version, completed: usize, total: usize) {
+        self.inner.top_level_analyzed(crat, completed, total);
+    }
+
+    fn dependency_analyzed(&self, crat: &Version) {
+        self.inner.dependency_analyzed(crat);
+    }
+
+    fn resolution_finished(&self, num_dependencies: usize) {
+        self.record().num_dependencies = num_dependencies;
+        self.inner.resolution_finished(num_dependencies);
+    }
+
+    fn graph_analyzed(&self, stats: &GraphStats) {
+        self.inner.graph_analyzed(stats);
+    }
+
+    fn advisory_found(&self, crat: &Version, advisory: &Advisory) {
+        self.inner.advisory_found(crat, advisory);
+    }
+
+    fn crate_downloaded(&self, crat: &Version, completed: usize, total: usize) {
+        self.record().num_downloaded += 1;
+        self.inner.crate_downloaded(crat, completed, total);
+    }
+
+    fn crate_stored(&self, crat: &Version, size: u64) {
+        self.record().bytes_downloaded += size;
+        self.inner.crate_stored(crat, size);
+    }
+
+    fn crate_published(&self, crat: &Version, completed: usize, total: usize) {
+        self.inner.crate_published(crat, completed, total);
+    }
+
+    fn failure(
+        &self,
+        phase: Phase,
+        crat: Option<&Version>,
+        error: &(dyn std::error::Error + 'static),
+    ) {
+        {
+            let mut record = self.record();
+            match crat {
+                Some(_) if phase == Phase::Download => record.num_failed += 1,
+                Some(_) => {}
+                None => record.phases.end(false),
+            }
+            record.failures.push(FailureRecord {
+                phase: phase.as_str().to_string(),
+                crate_name: crat.map(|c| c.name().to_string()),
+                crate_version: crat.map(|c| c.version().to_string()),
+                error: error.to_string(),
+            });
+        }
+        self.inner.failure(phase, crat, error);
+    }
+
+    fn info(&self, msg: &str) {
+        self.inner.info(msg);
+    }
+
+    fn run_finished(&self, succeeded: bool) {
+        self.inner.run_finished(succeeded);
+    }
+}
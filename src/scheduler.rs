@@ -0,0 +1,451 @@
+use crate::cancel::CancellationToken;
+use crate::checksums;
+use crate::health::HealthMonitor;
+use crate::notify::{Notification, Notifier};
+use crate::state_db::StateDb;
+use crate::status::{self, JobStatus, ScheduleStatus, SCHEDULE_FILE};
+use crate::MirrorBuilder;
+use std::fmt::{self, Display};
+use std::fs;
+use std::io;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::{oneshot, Mutex};
+use tracing::{debug, error, info, info_span, Instrument};
+
+#[derive(Debug)]
+pub enum Error {
+    CreateRuntime(io::Error),
+    ChecksumMismatch { files: Vec<PathBuf> },
+    Retain { dir_path: PathBuf, error: io::Error },
+    BindHealthEndpoint { addr: SocketAddr, error: io::Error },
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::CreateRuntime(e) => {
+                write!(
+                    f,
+                    "failed to create tokio runtime to run the scheduled jobs: {e}"
+                )
+            }
+            Error::ChecksumMismatch { files } => {
+                let files = files
+                    .iter()
+                    .map(|f| f.to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                write!(f, "mirror files missing or changed: {files}")
+            }
+            Error::Retain { dir_path, error } => {
+                write!(
+                    f,
+                    "failed to prune old files in {}: {error}",
+                    dir_path.to_string_lossy()
+                )
+            }
+            Error::BindHealthEndpoint { addr, error } => {
+                write!(f, "failed to listen on {addr} for health checks: {error}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::CreateRuntime(e) => Some(e),
+            Error::ChecksumMismatch { .. } => None,
+            Error::Retain { error, .. } => Some(error),
+            Error::BindHealthEndpoint { error, .. } => Some(error),
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+type JobResult<T = ()> = std::result::Result<T, Box<dyn std::error::Error + Send + Sync + 'static>>;
+type JobFn = Arc<dyn Fn(&CancellationToken) -> JobResult + Send + Sync>;
+
+struct Job {
+    name: String,
+    interval: Duration,
+    /// Whether the job rebuilds the mirror, which isn't safe to serve from meanwhile.
+    is_sync: bool,
+    run: JobFn,
+}
+
+/// Runs mirror maintenance jobs periodically on a tokio runtime, for services that keep a
+/// mirror up to date without reimplementing scheduling.
+///
+/// Each job runs once when the scheduler starts, then again `interval` after it last finished.
+/// Jobs never overlap: a job that comes due while another is running waits for it to finish,
+/// so a refresh never rebuilds the mirror while it is being verified. Outcomes are reported as
+/// tracing events, and to the notifiers.
+///
+/// ```no_run
+/// use micrio::scheduler::MirrorScheduler;
+/// use std::time::Duration;
+///
+/// const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+/// MirrorScheduler::new()
+///     .refresh(DAY, || {
+///         micrio::MirrorBuilder::new("mirror")
+///             .most_downloaded(500)
+///             .sha256sums(true)
+///     })
+///     .verify(DAY / 4, "mirror")
+///     .health_endpoint(([0, 0, 0, 0], 8080).into(), "mirror")
+///     .run_blocking()?;
+/// # Ok::<(), micrio::scheduler::Error>(())
+/// ```
+#[derive(Default)]
+pub struct MirrorScheduler {
+    jobs: Vec<Job>,
+    cancel: CancellationToken,
+    /// The address to serve the health endpoint on, and the mirror it reports on.
+    health: Option<(SocketAddr, PathBuf)>,
+    /// The mirror to record the status of the jobs in.
+    schedule_mirror_dir: Option<PathBuf>,
+    notifiers: Vec<Arc<dyn Notifier>>,
+}
+
+impl MirrorScheduler {
+    pub fn new() -> Self {
+        MirrorScheduler::default()
+    }
+
+    /// Stops the scheduler once the token is cancelled. A refresh in progress is cancelled too.
+    pub fn cancellation_token(mut self, cancel: CancellationToken) -> Self {
+        self.cancel = cancel;
+        self
+    }
+
+    /// Adds a job running the closure. The closure runs on its own thread, outside the tokio
+    /// runtime since mirror runs create their own, and is given the scheduler's cancellation token.
+    pub fn job<S, F>(mut self, name: S, interval: Duration, f: F) -> Self
+    where
+        S: Into<String>,
+        F: Fn(&CancellationToken) -> JobResult + Send + Sync + 'static,
+    {
+        self.jobs.push(Job {
+            name: name.into(),
+            interval,
+            is_sync: false,
+            run: Arc::new(f),
+        });
+        self
+    }
+
+    /// Serves `/healthz` on the address, reporting the status of the refresh job, when it last
+    /// succeeded, and whether every crate version in the mirror's index has its .crate file,
+    /// with status 200 if the mirror is safe to serve from and 503 otherwise.
+    /// See `health::Health`.
+    pub fn health_endpoint<P: Into<PathBuf>>(
+        mut self,
+        addr: SocketAddr,
+        mirror_dir_path: P,
+    ) -> Self {
+        self.health = Some((addr, mirror_dir_path.into()));
+        self
+    }
+
+    /// Records when each job last ran, how it went, and when it is due next, in the state
+    /// directory of the mirror, for dashboards watching the scheduler from another process.
+    /// See `status::read_schedule_status`.
+    pub fn record_schedule<P: Into<PathBuf>>(mut self, mirror_dir_path: P) -> Self {
+        self.schedule_mirror_dir = Some(mirror_dir_path.into());
+        self
+    }
+
+    /// Tells the notifier how each job went when it finishes. A notifier failing to send is
+    /// logged without failing the job.
+    pub fn notify(mut self, notifier: Arc<dyn Notifier>) -> Self {
+        self.notifiers.push(notifier);
+        self
+    }
+
+    /// Rebuilds the mirror. `builder` is called before each run to configure it,
+    /// so it can pick up changes, e.g. to a crate list file.
+    pub fn refresh<F>(self, interval: Duration, builder: F) -> Self
+    where
+        F: Fn() -> MirrorBuilder + Send + Sync + 'static,
+    {
+        self.try_refresh(interval, move || Ok(builder()))
+    }
+
+    /// Rebuilds the mirror like `refresh`, with a configuration that can fail to load,
+    /// e.g. from a file edited since the last run. A failure to configure fails the run.
+    pub fn try_refresh<F>(self, interval: Duration, builder: F) -> Self
+    where
+        F: Fn() -> JobResult<MirrorBuilder> + Send + Sync + 'static,
+    {
+        let mut scheduler = self.job("refresh", interval, move |cancel| {
+            builder()?
+                .cancellation_token(cancel.clone())
+                .build()?
+                .run()?;
+            Ok(())
+        });
+        if let Some(job) = scheduler.jobs.last_mut() {
+            job.is_sync = true;
+        }
+        scheduler
+    }
+
+    /// Checks the files in the mirror against the digests recorded in its state database, or
+    /// else its SHA256SUMS file, failing if any are missing or changed.
+    pub fn verify<P: Into<PathBuf>>(self, interval: Duration, mirror_dir_path: P) -> Self {
+        let mirror_dir_path = mirror_dir_path.into();
+        self.job("verify", interval, move |_| {
+            let digests = match StateDb::open_existing(&mirror_dir_path)? {
+                Some(state_db) => state_db.file_digests()?,
+                None => Vec::new(),
+            };
+            let files = if digests.is_empty() {
+                checksums::verify_sha256sums(&mirror_dir_path)?
+            } else {
+                checksums::verify_digests(&mirror_dir_path, &digests)?
+            };
+            if !files.is_empty() {
+                return Err(Box::new(Error::ChecksumMismatch { files }));
+            }
+            Ok(())
+        })
+    }
+
+    /// Deletes all but the `keep` most recently modified files in the directory,
+    /// e.g. old export archives.
+    pub fn retain<P: Into<PathBuf>>(self, interval: Duration, dir_path: P, keep: usize) -> Self {
+        let dir_path = dir_path.into();
+        self.job("retain", interval, move |_| {
+            let num_removed = retain_newest(&dir_path, keep)?;
+            info!(num_removed, "old files pruned");
+            Ok(())
+        })
+    }
+
+    /// Runs the jobs until the cancellation token is cancelled.
+    pub async fn run(self) -> Result<()> {
+        let running = Arc::new(Mutex::new(()));
+        let mut tasks = Vec::new();
+        let mut monitor = None;
+        let notified_mirror_dir = match (&self.schedule_mirror_dir, &self.health) {
+            (Some(mirror_dir_path), _) | (None, Some((_, mirror_dir_path))) => {
+                Some(mirror_dir_path.clone())
+            }
+            (None, None) => None,
+        };
+        if let Some((addr, mirror_dir_path)) = self.health {
+            let listener = tokio::net::TcpListener::bind(addr)
+                .await
+                .map_err(|e| Error::BindHealthEndpoint { addr, error: e })?;
+            info!(%addr, "serving health checks");
+            let health = Arc::new(HealthMonitor::new(mirror_dir_path));
+            let check = Arc::clone(&health);
+            // The mirror may be served before the first refresh finishes.
+            let _ = tokio::task::spawn_blocking(move || check.check()).await;
+            tasks.push(tokio::spawn(
+                Arc::clone(&health).serve(listener, self.cancel.clone()),
+            ));
+            monitor = Some(health);
+        }
+        let notifiers: Arc<[Arc<dyn Notifier>]> = self.notifiers.into();
+        let schedule = self
+            .schedule_mirror_dir
+            .map(|mirror_dir_path| Arc::new(ScheduleRecorder::new(mirror_dir_path, &self.jobs)));
+        for (i, job) in self.jobs.into_iter().enumerate() {
+            let running = Arc::clone(&running);
+            let monitor = monitor.clone();
+            let schedule = schedule.clone();
+            let notifiers = Arc::clone(&notifiers);
+            let notified_mirror_dir = notified_mirror_dir.clone();
+            let cancel = self.cancel.clone();
+            let span = info_span!("job", name = %job.name);
+            tasks.push(tokio::spawn(
+                async move {
+                    loop {
+                        {
+                            let _running = running.lock().await;
+                            if cancel.is_cancelled() {
+                                break;
+                            }
+                            let run = Arc::clone(&job.run);
+                            let job_cancel = cancel.clone();
+                            let span = tracing::Span::current();
+                            let (tx, rx) = oneshot::channel();
+                            if let Some(schedule) = &schedule {
+                                schedule.job_started(i);
+                            }
+                            let job_monitor = monitor.clone();
+                            let job_notifiers = Arc::clone(&notifiers);
+                            let mut notification = Notification {
+                                job: job.name.clone(),
+                                mirror_dir_path: notified_mirror_dir.clone(),
+                                error: None,
+                            };
+                            let is_sync = job.is_sync;
+                            std::thread::spawn(move || {
+                                if let (Some(monitor), true) = (&job_monitor, is_sync) {
+                                    monitor.sync_started();
+                                }
+                                let result = span.in_scope(|| run(&job_cancel));
+                                if let Some(monitor) = &job_monitor {
+                                    if is_sync {
+                                        let error = result.as_ref().err().map(|e| e.to_string());
+                                        monitor.sync_finished(error);
+                                    }
+                                    monitor.check();
+                                }
+                                // Sent from the job's thread, since notifiers may block.
+                                notification.error = result.as_ref().err().map(|e| e.to_string());
+                                for notifier in job_notifiers.iter() {
+                                    if let Err(e) = notifier.notify(&notification) {
+                                        error!("{e}");
+                                    }
+                                }
+                                let _ = tx.send(result);
+                            });
+                            let error = match rx.await {
+                                Ok(Ok(())) => {
+                                    info!("job finished");
+                                    None
+                                }
+                                Ok(Err(e)) => {
+                                    error!("job failed: {e}");
+                                    Some(e.to_string())
+                                }
+                                // The thread dropped the sender without sending.
+                                Err(_) => {
+                                    error!("job panicked");
+                                    if let (Some(monitor), true) = (&monitor, job.is_sync) {
+                                        monitor.sync_finished(Some("job panicked".to_string()));
+                                    }
+                                    Some("job panicked".to_string())
+                                }
+                            };
+                            if let Some(schedule) = &schedule {
+                                schedule.job_finished(i, error, job.interval);
+                            }
+                        }
+                        if !sleep_unless_cancelled(job.interval, &cancel).await {
+                            break;
+                        }
+                    }
+                }
+                .instrument(span),
+            ));
+        }
+        for task in tasks {
+            // Jobs run on their own threads, so their panics can't reach these tasks.
+            let _ = task.await;
+        }
+        Ok(())
+    }
+
+    /// Runs the jobs on a new tokio runtime, blocking until the cancellation token is cancelled.
+    pub fn run_blocking(self) -> Result<()> {
+        let rt = tokio::runtime::Runtime::new().map_err(Error::CreateRuntime)?;
+        rt.block_on(self.run())
+    }
+}
+
+/// Keeps the schedule file of the mirror up to date as the jobs run.
+struct ScheduleRecorder {
+    mirror_dir_path: PathBuf,
+    status: std::sync::Mutex<ScheduleStatus>,
+}
+
+impl ScheduleRecorder {
+    fn new(mirror_dir_path: PathBuf, jobs: &[Job]) -> Self {
+        // Every job runs once when the scheduler starts.
+        let now = status::unix_time(SystemTime::now());
+        let jobs = Vec::from_iter(jobs.iter().map(|job| JobStatus {
+            name: job.name.clone(),
+            interval_secs: job.interval.as_secs(),
+            running: false,
+            last_started: None,
+            last_finished: None,
+            last_error: None,
+            next_run: Some(now),
+        }));
+        let recorder = ScheduleRecorder {
+            mirror_dir_path,
+            status: std::sync::Mutex::new(ScheduleStatus {
+                pid: std::process::id(),
+                jobs,
+            }),
+        };
+        recorder.update(|_| {});
+        recorder
+    }
+
+    fn job_started(&self, i: usize) {
+        self.update(|status| {
+            let job = &mut status.jobs[i];
+            job.running = true;
+            job.last_started = Some(status::unix_time(SystemTime::now()));
+            job.next_run = None;
+        });
+    }
+
+    fn job_finished(&self, i: usize, error: Option<String>, interval: Duration) {
+        self.update(|status| {
+            let now = SystemTime::now();
+            let job = &mut status.jobs[i];
+            job.running = false;
+            job.last_finished = Some(status::unix_time(now));
+            job.last_error = error;
+            job.next_run = Some(status::unix_time(now + interval));
+        });
+    }
+
+    fn update(&self, f: impl FnOnce(&mut ScheduleStatus)) {
+        // The status is replaced field by field, so it's still usable if a thread panicked.
+        let mut status = self.status.lock().unwrap_or_else(|e| e.into_inner());
+        f(&mut status);
+        // The schedule is informational, so failing to write it doesn't fail the job.
+        if let Err(e) = status::write_state_file(&self.mirror_dir_path, SCHEDULE_FILE, &*status) {
+            debug!("failed to write the schedule: {e}");
+        }
+    }
+}
+
+/// Sleeps for the duration, waking early if cancelled. Returns false if cancelled.
+async fn sleep_unless_cancelled(duration: Duration, cancel: &CancellationToken) -> bool {
+    const POLL_INTERVAL: Duration = Duration::from_millis(500);
+    let deadline = Instant::now() + duration;
+    while !cancel.is_cancelled() {
+        let now = Instant::now();
+        if now >= deadline {
+            return true;
+        }
+        tokio::time::sleep((deadline - now).min(POLL_INTERVAL)).await;
+    }
+    false
+}
+
+/// Deletes all but the `keep` most recently modified files in the directory.
+/// Returns the number of files deleted.
+fn retain_newest(dir_path: &Path, keep: usize) -> Result<usize> {
+    let retain_error = |e| Error::Retain {
+        dir_path: dir_path.to_path_buf(),
+        error: e,
+    };
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir_path).map_err(retain_error)? {
+        let entry = entry.map_err(retain_error)?;
+        let metadata = entry.metadata().map_err(retain_error)?;
+        if metadata.is_file() {
+            files.push((metadata.modified().map_err(retain_error)?, entry.path()));
+        }
+    }
+    files.sort_by_key(|(modified, _)| std::cmp::Reverse(*modified));
+    for (_, path) in files.iter().skip(keep) {
+        fs::remove_file(path).map_err(retain_error)?;
+    }
+    Ok(files.len().saturating_sub(keep))
+}
@@ -0,0 +1,241 @@
+//! A full-text index of the mirrored crates' names, keywords, descriptions, and READMEs, built
+//! along with their metadata, for ranked searches of the mirror offline.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Display};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum Error {
+    WriteIndex(Box<dyn std::error::Error + Send + Sync + 'static>),
+    ReadIndex {
+        path: PathBuf,
+        error: Box<dyn std::error::Error + Send + Sync + 'static>,
+    },
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::WriteIndex(e) => {
+                write!(f, "failed to write the {SEARCH_INDEX_FILE} file: {e}")
+            }
+            Error::ReadIndex { path, error } => {
+                write!(
+                    f,
+                    "failed to read the search index {}: {error}",
+                    path.to_string_lossy()
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::WriteIndex(e) => Some(e.as_ref()),
+            Error::ReadIndex { error, .. } => Some(error.as_ref()),
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// The file in the mirror directory holding the search index.
+pub const SEARCH_INDEX_FILE: &str = "search-index.json";
+
+/// How much more a term counts in each field than in the README.
+const NAME_WEIGHT: f32 = 8.0;
+const KEYWORD_WEIGHT: f32 = 4.0;
+const DESCRIPTION_WEIGHT: f32 = 2.0;
+const README_WEIGHT: f32 = 1.0;
+
+/// The BM25 parameters: how quickly repeating a term stops raising the score, and how much
+/// long documents are penalized.
+const K1: f32 = 1.2;
+const B: f32 = 0.75;
+
+/// Words too common in READMEs to tell crates apart.
+const STOP_WORDS: [&str; 16] = [
+    "an", "and", "are", "as", "be", "by", "for", "in", "is", "it", "of", "on", "or", "the", "to",
+    "with",
+];
+
+/// A crate in the index, described by its highest mirrored version.
+#[derive(Serialize, Deserialize)]
+pub struct SearchDoc {
+    pub name: String,
+    pub version: String,
+    pub description: Option<String>,
+    /// The sum of the weights of the crate's terms.
+    length: f32,
+}
+
+/// A crate matching a search, with its score; higher is more relevant.
+pub struct SearchHit<'a> {
+    pub doc: &'a SearchDoc,
+    pub score: f32,
+}
+
+/// The terms of every crate in the mirror, with the crates each appears in.
+#[derive(Default, Serialize, Deserialize)]
+pub struct SearchIndex {
+    docs: Vec<SearchDoc>,
+    /// The crates each term appears in, by their position in `docs`, with the weighted number
+    /// of times it appears.
+    postings: BTreeMap<String, Vec<(u32, f32)>>,
+}
+
+/// Collects the crates to index, keeping the highest version of each.
+#[derive(Default)]
+pub struct SearchIndexBuilder {
+    crates: HashMap<String, (semver::Version, SearchDoc, HashMap<String, f32>)>,
+}
+
+impl SearchIndexBuilder {
+    pub fn new() -> Self {
+        SearchIndexBuilder::default()
+    }
+
+    /// Adds the crate version, unless a higher version of the crate was already added.
+    /// Versions that aren't valid semver are indexed only if the crate has no other.
+    pub fn add(
+        &mut self,
+        name: &str,
+        version: &str,
+        description: Option<&str>,
+        keywords: &[String],
+        readme: Option<&str>,
+    ) {
+        let semver = semver::Version::parse(version).unwrap_or(semver::Version::new(0, 0, 0));
+        if let Some((indexed, _, _)) = self.crates.get(name) {
+            if *indexed >= semver {
+                return;
+            }
+        }
+        // The whole name too, so searching for a crate by name finds it first.
+        let mut terms = HashMap::from([(name.to_lowercase(), NAME_WEIGHT)]);
+        let mut add_terms = |text: &str, weight: f32| {
+            for term in tokenize(text) {
+                *terms.entry(term).or_insert(0.0) += weight;
+            }
+        };
+        add_terms(name, NAME_WEIGHT);
+        for keyword in keywords {
+            add_terms(keyword, KEYWORD_WEIGHT);
+        }
+        if let Some(description) = description {
+            add_terms(description, DESCRIPTION_WEIGHT);
+        }
+        if let Some(readme) = readme {
+            add_terms(readme, README_WEIGHT);
+        }
+        let doc = SearchDoc {
+            name: name.to_string(),
+            version: version.to_string(),
+            description: description.map(|d| d.to_string()),
+            length: terms.values().sum(),
+        };
+        self.crates.insert(name.to_string(), (semver, doc, terms));
+    }
+
+    pub fn build(self) -> SearchIndex {
+        let mut crates = Vec::from_iter(self.crates.into_values());
+        crates.sort_by(|a, b| a.1.name.cmp(&b.1.name));
+        let mut index = SearchIndex::default();
+        for (id, (_, doc, terms)) in crates.into_iter().enumerate() {
+            for (term, frequency) in terms {
+                index
+                    .postings
+                    .entry(term)
+                    .or_default()
+                    .push((id as u32, frequency));
+            }
+            index.docs.push(doc);
+        }
+        index
+    }
+}
+
+impl SearchIndex {
+    /// Reads the search index of the mirror.
+    pub fn read<P: AsRef<Path>>(mirror_dir_path: P) -> Result<Self> {
+        let path = mirror_dir_path.as_ref().join(SEARCH_INDEX_FILE);
+        let read_error = |e: Box<dyn std::error::Error + Send + Sync + 'static>| Error::ReadIndex {
+            path: path.clone(),
+            error: e,
+        };
+        let contents = fs::read(&path).map_err(|e| read_error(Box::new(e)))?;
+        serde_json::from_slice(&contents).map_err(|e| read_error(Box::new(e)))
+    }
+
+    /// Writes the index to the mirror directory.
+    pub fn write<P: AsRef<Path>>(&self, mirror_dir_path: P) -> Result<()> {
+        let json = serde_json::to_vec(self).map_err(|e| Error::WriteIndex(Box::new(e)))?;
+        fs::write(mirror_dir_path.as_ref().join(SEARCH_INDEX_FILE), json)
+            .map_err(|e| Error::WriteIndex(Box::new(e)))
+    }
+
+    /// The number of crates in the index.
+    pub fn len(&self) -> usize {
+        self.docs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.docs.is_empty()
+    }
+
+    /// Returns up to `limit` crates matching any of the query's words, the most relevant first,
+    /// ranked by BM25: words found in few crates count more, and words in a crate's name or
+    /// keywords more than in its README.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<SearchHit<'_>> {
+        if self.docs.is_empty() {
+            return Vec::new();
+        }
+        let num_docs = self.docs.len() as f32;
+        let avg_length = self.docs.iter().map(|doc| doc.length).sum::<f32>() / num_docs;
+        let mut scores: HashMap<u32, f32> = HashMap::new();
+        let mut terms = tokenize(query);
+        terms.push(query.trim().to_lowercase());
+        terms.sort();
+        terms.dedup();
+        for term in terms {
+            let postings = match self.postings.get(&term) {
+                Some(postings) => postings,
+                None => continue,
+            };
+            let num_containing = postings.len() as f32;
+            let idf = ((num_docs - num_containing + 0.5) / (num_containing + 0.5) + 1.0).ln();
+            for (id, frequency) in postings {
+                let length = self.docs[*id as usize].length;
+                let norm = K1 * (1.0 - B + B * length / avg_length);
+                *scores.entry(*id).or_insert(0.0) +=
+                    idf * frequency * (K1 + 1.0) / (frequency + norm);
+            }
+        }
+        let mut hits = Vec::from_iter(scores.into_iter().map(|(id, score)| SearchHit {
+            doc: &self.docs[id as usize],
+            score,
+        }));
+        hits.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then_with(|| a.doc.name.cmp(&b.doc.name))
+        });
+        hits.truncate(limit);
+        hits
+    }
+}
+
+/// Splits the text into lowercase words of two or more letters or digits, without stop words.
+fn tokenize(text: &str) -> Vec<String> {
+    Vec::from_iter(
+        text.split(|c: char| !c.is_alphanumeric())
+            .filter(|word| word.chars().count() >= 2)
+            .map(|word| word.to_lowercase())
+            .filter(|word| !STOP_WORDS.contains(&word.as_str())),
+    )
+}
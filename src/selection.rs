@@ -0,0 +1,579 @@
+use crate::cargo_manifest;
+use crate::common::Version;
+use semver::VersionReq;
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::fmt::{self, Display};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use walkdir::WalkDir;
+
+#[derive(Debug)]
+pub enum Error {
+    ParseCrateSpec {
+        spec: String,
+        error: semver::Error,
+    },
+    ParseCategorySpec {
+        spec: String,
+        error: std::num::ParseIntError,
+    },
+    ParseKeywordSpec {
+        spec: String,
+        error: std::num::ParseIntError,
+    },
+    ReadManifest {
+        path: PathBuf,
+        error: io::Error,
+    },
+    ParseManifest {
+        path: PathBuf,
+        error: toml::de::Error,
+    },
+    CargoManifest(cargo_manifest::Error),
+    ScanProjects {
+        path: PathBuf,
+        error: walkdir::Error,
+    },
+    ParseTomlList(toml::de::Error),
+    ParseJsonList(serde_json::Error),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::ParseCrateSpec { spec, error } => {
+                write!(f, "invalid crate specification {spec}: {error}")
+            }
+            Error::ParseCategorySpec { spec, error } => {
+                write!(f, "invalid category specification {spec}: {error}")
+            }
+            Error::ParseKeywordSpec { spec, error } => {
+                write!(f, "invalid keyword specification {spec}: {error}")
+            }
+            Error::ReadManifest { path, error } => {
+                write!(
+                    f,
+                    "failed to read the selection manifest {}: {error}",
+                    path.to_string_lossy()
+                )
+            }
+            Error::ParseManifest { path, error } => {
+                write!(
+                    f,
+                    "invalid selection manifest {}: {error}",
+                    path.to_string_lossy()
+                )
+            }
+            Error::CargoManifest(e) => write!(f, "{e}"),
+            Error::ScanProjects { path, error } => {
+                write!(
+                    f,
+                    "failed to scan the projects in {}: {error}",
+                    path.to_string_lossy()
+                )
+            }
+            Error::ParseTomlList(e) => write!(f, "invalid TOML crate list: {e}"),
+            Error::ParseJsonList(e) => write!(f, "invalid JSON crate list: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::ParseCrateSpec { error, .. } => Some(error),
+            Error::ParseCategorySpec { error, .. } => Some(error),
+            Error::ParseKeywordSpec { error, .. } => Some(error),
+            Error::ReadManifest { error, .. } => Some(error),
+            Error::ParseManifest { error, .. } => Some(error),
+            Error::CargoManifest(e) => Some(e),
+            Error::ScanProjects { error, .. } => Some(error),
+            Error::ParseTomlList(e) => Some(e),
+            Error::ParseJsonList(e) => Some(e),
+        }
+    }
+}
+
+/// A crate requested by name, optionally restricted to the versions matching a requirement.
+///
+/// Parses from `NAME` or `NAME@REQ`, e.g. `serde`, `tokio@1`, or `rand@=0.8.5`.
+/// The resolution options only apply when the crate is selected, not when it is excluded.
+#[derive(Clone, Debug)]
+pub struct CrateSpec {
+    pub name: String,
+    pub req: Option<VersionReq>,
+    /// The features to enable on the crate, instead of those for the whole selection.
+    pub features: Option<FeatureSpec>,
+    /// The target triples to resolve the crate's platform-specific dependencies for,
+    /// instead of those for the whole selection.
+    pub targets: Vec<String>,
+    /// Also mirror the crate's dev-dependencies, e.g. to run its tests offline.
+    pub dev_dependencies: bool,
+    /// Crate versions left out of the crate's dependency tree, along with the dependencies
+    /// only they require there. They are still mirrored if another selected crate requires them.
+    pub exclusions: Vec<CrateSpec>,
+}
+
+impl CrateSpec {
+    pub fn new<S: Into<String>>(name: S) -> Self {
+        CrateSpec {
+            name: name.into(),
+            req: None,
+            features: None,
+            targets: Vec::new(),
+            dev_dependencies: false,
+            exclusions: Vec::new(),
+        }
+    }
+
+    pub fn with_req<S: Into<String>>(name: S, req: VersionReq) -> Self {
+        CrateSpec {
+            req: Some(req),
+            ..CrateSpec::new(name)
+        }
+    }
+
+    /// Whether the crate version is named by this spec and satisfies its requirement, if any.
+    /// Versions that aren't valid semver only match a spec without a requirement.
+    pub fn matches(&self, crat: &Version) -> bool {
+        if crat.name() != self.name {
+            return false;
+        }
+        match &self.req {
+            Some(req) => match semver::Version::parse(crat.version()) {
+                Ok(version) => req.matches(&version),
+                Err(_) => false,
+            },
+            None => true,
+        }
+    }
+}
+
+impl FromStr for CrateSpec {
+    type Err = Error;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let spec = spec.trim();
+        match spec.split_once('@') {
+            Some((name, req)) => {
+                let req = VersionReq::parse(req).map_err(|e| Error::ParseCrateSpec {
+                    spec: spec.to_string(),
+                    error: e,
+                })?;
+                Ok(CrateSpec::with_req(name, req))
+            }
+            None => Ok(CrateSpec::new(spec)),
+        }
+    }
+}
+
+impl Display for CrateSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.req {
+            Some(req) => write!(f, "{}@{req}", self.name),
+            None => write!(f, "{}", self.name),
+        }
+    }
+}
+
+/// A crates.io category to select crates from, most downloaded first.
+#[derive(Clone, Debug)]
+pub struct CategorySpec {
+    /// The category's slug, e.g. `command-line-utilities` or `web-programming::http-client`.
+    pub slug: String,
+    /// The maximum number of crates to select from the category. All of them if `None`.
+    pub limit: Option<u64>,
+}
+
+impl FromStr for CategorySpec {
+    type Err = Error;
+
+    /// Parses `SLUG` or `SLUG=LIMIT`, e.g. `embedded` or `wasm=100`.
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let (slug, limit) = split_limit(spec).map_err(|e| Error::ParseCategorySpec {
+            spec: spec.trim().to_string(),
+            error: e,
+        })?;
+        Ok(CategorySpec { slug, limit })
+    }
+}
+
+/// A crates.io keyword to select the crates tagged with, most downloaded first.
+#[derive(Clone, Debug)]
+pub struct KeywordSpec {
+    /// The keyword, e.g. `async` or `parser`.
+    pub keyword: String,
+    /// The maximum number of crates to select with the keyword. All of them if `None`.
+    pub limit: Option<u64>,
+}
+
+impl FromStr for KeywordSpec {
+    type Err = Error;
+
+    /// Parses `KEYWORD` or `KEYWORD=LIMIT`, e.g. `async` or `parser=20`.
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let (keyword, limit) = split_limit(spec).map_err(|e| Error::ParseKeywordSpec {
+            spec: spec.trim().to_string(),
+            error: e,
+        })?;
+        Ok(KeywordSpec { keyword, limit })
+    }
+}
+
+/// A crates.io search to select the crates found by, most relevant first.
+#[derive(Clone, Debug)]
+pub struct SearchSpec {
+    /// The search query, as typed in the search box on crates.io.
+    pub query: String,
+    /// The maximum number of crates to select from the results. All of them if `None`.
+    pub limit: Option<u64>,
+}
+
+/// Splits `NAME=LIMIT` into the name and the limit, if any.
+fn split_limit(spec: &str) -> Result<(String, Option<u64>), std::num::ParseIntError> {
+    let spec = spec.trim();
+    match spec.split_once('=') {
+        Some((name, limit)) => Ok((name.to_string(), Some(limit.parse()?))),
+        None => Ok((spec.to_string(), None)),
+    }
+}
+
+/// The features to enable on the top-level crates, as with Cargo's feature flags.
+///
+/// Features a top-level crate doesn't have are ignored, since the same features apply to every
+/// top-level crate.
+#[derive(Clone, Debug, Default)]
+pub struct FeatureSpec {
+    /// Enable every feature.
+    pub all_features: bool,
+    /// Don't enable the `default` feature.
+    pub no_default_features: bool,
+    /// Features to enable.
+    pub features: Vec<String>,
+}
+
+/// Describes which top-level crates to mirror, independent of how the description was given.
+///
+/// The selection is the union of every source; the exclusions are then removed from it and
+/// from the dependencies it resolves to. The CLI builds one from its flags, and library users
+/// can construct one directly:
+///
+/// ```
+/// use micrio::selection::{CrateSpec, SelectionSpec};
+///
+/// let spec = SelectionSpec {
+///     crates: vec!["tokio@1".parse()?, CrateSpec::new("serde")],
+///     most_downloaded: Some(50),
+///     exclusions: vec!["openssl-sys".parse()?],
+///     ..SelectionSpec::default()
+/// };
+/// # Ok::<(), micrio::selection::Error>(())
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct SelectionSpec {
+    /// Crates given by name. The highest normal version matching the requirement is selected.
+    /// Yanked versions are only selected when the requirement pins them, e.g. `=1.2.3`.
+    pub crates: Vec<CrateSpec>,
+    /// Files listing crates, one crate name per line. They can also be http:// or https://
+    /// URLs.
+    pub files: Vec<PathBuf>,
+    /// The top N most downloaded crates on crates.io.
+    pub most_downloaded: Option<u64>,
+    /// The N crates most recently updated on crates.io.
+    pub recently_updated: Option<u64>,
+    /// Cargo.lock files. The exact version of every crates.io package they lock is selected.
+    pub lockfiles: Vec<PathBuf>,
+    /// `cargo vendor` directories. The exact version of every registry package they contain
+    /// is selected.
+    pub vendor_dirs: Vec<PathBuf>,
+    /// Other micrio mirrors, given by the path of their directory or the URL of their index
+    /// git repo. The exact version of every crate they contain is selected, to rebuild them.
+    pub mirrors: Vec<String>,
+    /// crates.io categories.
+    pub categories: Vec<CategorySpec>,
+    /// crates.io keywords.
+    pub keywords: Vec<KeywordSpec>,
+    /// crates.io users, by login, and teams, by `github:ORG:TEAM` name, whose crates are
+    /// all selected.
+    pub owners: Vec<String>,
+    /// crates.io searches.
+    pub searches: Vec<SearchSpec>,
+    /// The minimum number of downloads of the crates selected from crates.io queries, i.e. the
+    /// most downloaded and recently updated crates and those of categories, keywords, owners,
+    /// and searches.
+    pub min_downloads: Option<u64>,
+    /// Crate versions never to mirror, even when required by a selected crate. The dependencies
+    /// only they require aren't mirrored either.
+    pub exclusions: Vec<CrateSpec>,
+    /// The features to enable on the top-level crates. Only the optional dependencies the
+    /// enabled features require are then mirrored, all the way down. If `None`, every optional
+    /// dependency is mirrored, unless features are given for some of the `crates`.
+    pub features: Option<FeatureSpec>,
+}
+
+impl SelectionSpec {
+    pub fn new() -> Self {
+        SelectionSpec::default()
+    }
+
+    /// Adds the crates and exclusions listed in a TOML selection manifest, where each crate can
+    /// have its own version requirement, features, targets, dev-dependencies, and exclusions
+    /// from its dependency tree:
+    ///
+    /// ```toml
+    /// exclude = ["openssl-sys", "time@<0.2"]
+    ///
+    /// [[crate]]
+    /// name = "tokio"
+    /// version = "1.28"
+    /// features = ["full"]
+    ///
+    /// [[crate]]
+    /// name = "rusqlite"
+    /// default-features = false
+    /// targets = ["x86_64-unknown-linux-gnu", "x86_64-pc-windows-msvc"]
+    /// dev-dependencies = true
+    /// exclude = ["cc"]
+    /// ```
+    ///
+    /// Specifying features for any crate makes resolution feature-aware for the whole selection:
+    /// crates without features get the selection's, or their default features.
+    ///
+    /// A file named Cargo.toml is read as a project or workspace manifest instead, adding the
+    /// crates.io dependencies it and its members declare, see `cargo_manifest::dependencies`.
+    pub fn add_manifest<P: AsRef<Path>>(&mut self, manifest_path: P) -> Result<(), Error> {
+        let path = manifest_path.as_ref();
+        if cargo_manifest::is_cargo_manifest(path) {
+            let dependencies = cargo_manifest::dependencies(path).map_err(Error::CargoManifest)?;
+            self.crates.extend(dependencies);
+            return Ok(());
+        }
+        let contents = fs::read_to_string(path).map_err(|e| Error::ReadManifest {
+            path: path.to_path_buf(),
+            error: e,
+        })?;
+        let manifest: Manifest = toml::from_str(&contents).map_err(|e| Error::ParseManifest {
+            path: path.to_path_buf(),
+            error: e,
+        })?;
+        for entry in manifest.crates {
+            self.crates.push(entry.into_crate_spec()?);
+        }
+        for exclusion in manifest.exclude {
+            self.exclusions.push(exclusion.parse()?);
+        }
+        Ok(())
+    }
+
+    /// Adds the crates.io dependencies of every Rust project found under the directory, e.g. a
+    /// monorepo or a folder of services. Projects with a Cargo.lock file, workspace members
+    /// included, add the exact versions it locks. The others add the dependencies their
+    /// Cargo.toml files declare. Hidden and target directories aren't searched.
+    pub fn add_projects<P: AsRef<Path>>(&mut self, dir_path: P) -> Result<(), Error> {
+        let dir_path = dir_path.as_ref();
+        let mut lockfiles = Vec::new();
+        let mut manifests = Vec::new();
+        let walker = WalkDir::new(dir_path)
+            .sort_by_file_name()
+            .into_iter()
+            .filter_entry(|e| {
+                let name = e.file_name().to_string_lossy();
+                e.depth() == 0 || !(name.starts_with('.') || name == "target")
+            });
+        for entry in walker {
+            let entry = entry.map_err(|e| Error::ScanProjects {
+                path: dir_path.to_path_buf(),
+                error: e,
+            })?;
+            if !entry.file_type().is_file() {
+                continue;
+            }
+            if entry.file_name() == "Cargo.lock" {
+                lockfiles.push(entry.into_path());
+            } else if cargo_manifest::is_cargo_manifest(entry.path()) {
+                manifests.push(entry.into_path());
+            }
+        }
+
+        let mut added = HashSet::new();
+        for manifest_path in manifests {
+            // The lockfile of the project, or of the workspace it's a member of, locks its
+            // dependencies.
+            let locked = manifest_path
+                .ancestors()
+                .skip(1)
+                .take_while(|ancestor| ancestor.starts_with(dir_path))
+                .any(|ancestor| lockfiles.contains(&ancestor.join("Cargo.lock")));
+            if locked {
+                continue;
+            }
+            let dependencies =
+                cargo_manifest::dependencies(&manifest_path).map_err(Error::CargoManifest)?;
+            for spec in dependencies {
+                if added.insert((spec.name.clone(), spec.req.clone())) {
+                    self.crates.push(spec);
+                }
+            }
+        }
+        self.lockfiles.extend(lockfiles);
+        Ok(())
+    }
+
+    /// Whether crates are selected from live crates.io data, e.g. the most downloaded crates or
+    /// those in a category, rather than from the index alone.
+    pub fn queries_crates_io(&self) -> bool {
+        self.most_downloaded.is_some()
+            || self.recently_updated.is_some()
+            || !self.categories.is_empty()
+            || !self.keywords.is_empty()
+            || !self.owners.is_empty()
+            || !self.searches.is_empty()
+    }
+
+    pub fn is_excluded(&self, crat: &Version) -> bool {
+        self.exclusions.iter().any(|spec| spec.matches(crat))
+    }
+}
+
+/// The TOML selection manifest read by `SelectionSpec::add_manifest`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Manifest {
+    #[serde(default, rename = "crate")]
+    crates: Vec<ManifestCrate>,
+    #[serde(default)]
+    exclude: Vec<String>,
+}
+
+/// A structured crate list in TOML, with the `[[crate]]` entries of a selection manifest.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TomlList {
+    #[serde(default, rename = "crate")]
+    crates: Vec<ManifestCrate>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct ManifestCrate {
+    name: String,
+    version: Option<String>,
+    features: Option<Vec<String>>,
+    default_features: Option<bool>,
+    all_features: Option<bool>,
+    #[serde(default)]
+    targets: Vec<String>,
+    #[serde(default)]
+    dev_dependencies: bool,
+    #[serde(default)]
+    exclude: Vec<String>,
+}
+
+impl ManifestCrate {
+    fn into_crate_spec(self) -> Result<CrateSpec, Error> {
+        let mut spec = match &self.version {
+            Some(req) => {
+                let req = VersionReq::parse(req).map_err(|e| Error::ParseCrateSpec {
+                    spec: format!("{}@{req}", self.name),
+                    error: e,
+                })?;
+                CrateSpec::with_req(self.name, req)
+            }
+            None => CrateSpec::new(self.name),
+        };
+        if self.features.is_some() || self.default_features.is_some() || self.all_features.is_some()
+        {
+            spec.features = Some(FeatureSpec {
+                all_features: self.all_features.unwrap_or(false),
+                no_default_features: !self.default_features.unwrap_or(true),
+                features: self.features.unwrap_or_default(),
+            });
+        }
+        spec.targets = self.targets;
+        spec.dev_dependencies = self.dev_dependencies;
+        for exclusion in self.exclude {
+            spec.exclusions.push(exclusion.parse()?);
+        }
+        Ok(spec)
+    }
+}
+
+/// The format of a crate list file, told by its extension.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ListFormat {
+    /// One crate name per line.
+    Plain,
+    /// `[[crate]]` entries, like those of a selection manifest.
+    Toml,
+    /// An array of entries with the fields of a selection manifest's `[[crate]]` entries.
+    Json,
+}
+
+impl ListFormat {
+    /// Returns the format of the crate list file or URL: TOML for a .toml extension, JSON for
+    /// a .json extension, plain text otherwise.
+    pub fn of(file_path: &Path) -> Self {
+        match file_path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => ListFormat::Toml,
+            Some("json") => ListFormat::Json,
+            _ => ListFormat::Plain,
+        }
+    }
+}
+
+/// Parses a structured crate list, where each crate can have its own version requirement,
+/// features, targets, and dev-dependencies:
+///
+/// ```json
+/// [
+///     { "name": "tokio", "version": "1.28", "features": ["full"] },
+///     { "name": "rusqlite", "default-features": false, "dev-dependencies": true },
+///     { "name": "serde" }
+/// ]
+/// ```
+///
+/// A plain list has no such options: each line is parsed as a crate name.
+pub fn parse_crate_list(contents: &str, format: ListFormat) -> Result<Vec<CrateSpec>, Error> {
+    let entries = match format {
+        ListFormat::Plain => {
+            return Ok(Vec::from_iter(
+                contents
+                    .lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty())
+                    .map(CrateSpec::new),
+            ))
+        }
+        ListFormat::Toml => {
+            let list: TomlList = toml::from_str(contents).map_err(Error::ParseTomlList)?;
+            list.crates
+        }
+        ListFormat::Json => serde_json::from_str(contents).map_err(Error::ParseJsonList)?,
+    };
+    entries
+        .into_iter()
+        .map(ManifestCrate::into_crate_spec)
+        .collect()
+}
+
+/// The crate list path standing for standard input, e.g. to pipe in the output of other tools.
+pub const STDIN_LIST: &str = "-";
+
+/// Returns whether the crate list file stands for standard input.
+pub fn is_stdin_list(file_path: &Path) -> bool {
+    file_path == Path::new(STDIN_LIST)
+}
+
+/// Returns the URL the crate list file stands for, if it's an http:// or https:// URL
+/// rather than a path.
+pub fn list_url(file_path: &Path) -> Option<&str> {
+    let path = file_path.to_str()?;
+    if path.starts_with("http://") || path.starts_with("https://") {
+        Some(path)
+    } else {
+        None
+    }
+}
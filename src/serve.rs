@@ -0,0 +1,342 @@
+//! Serves a mirror over HTTP as a sparse registry, requiring a token on every request when the
+//! mirror's config.json has `auth-required` set, as Cargo's registry authentication expects.
+
+use crate::cancel::CancellationToken;
+use crate::dst_registry::INDEX_DIR;
+use percent_encoding::percent_decode_str;
+use std::fmt::{self, Display};
+use std::fs;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tracing::debug;
+
+#[derive(Debug)]
+pub enum Error {
+    ReadConfigJson {
+        path: PathBuf,
+        error: Box<dyn std::error::Error + Send + Sync + 'static>,
+    },
+    ReadTokens {
+        path: PathBuf,
+        error: io::Error,
+    },
+    TokensRequired,
+    NotAuthRequired,
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::ReadConfigJson { path, error } => {
+                write!(
+                    f,
+                    "error serving mirror: failed to read {}: {error}",
+                    path.to_string_lossy()
+                )
+            }
+            Error::ReadTokens { path, error } => {
+                write!(
+                    f,
+                    "error serving mirror: failed to read the tokens file {}: {error}",
+                    path.to_string_lossy()
+                )
+            }
+            Error::TokensRequired => {
+                write!(f, "error serving mirror: the mirror requires authentication, but no tokens were given to check")
+            }
+            Error::NotAuthRequired => {
+                write!(f, "error serving mirror: tokens were given, but the mirror's config.json doesn't set auth-required, so Cargo wouldn't send them; populate the mirror with --auth-required")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::ReadConfigJson { error, .. } => Some(error.as_ref()),
+            Error::ReadTokens { error, .. } => Some(error),
+            Error::TokensRequired => None,
+            Error::NotAuthRequired => None,
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Reads the tokens accepted by the server from the file, one per line. Empty lines and lines
+/// starting with `#` are skipped.
+pub fn read_tokens<P: AsRef<Path>>(path: P) -> Result<Vec<String>> {
+    let path = path.as_ref();
+    let contents = fs::read_to_string(path).map_err(|e| Error::ReadTokens {
+        path: path.to_path_buf(),
+        error: e,
+    })?;
+    Ok(Vec::from_iter(
+        contents
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| line.to_string()),
+    ))
+}
+
+/// Serves the files of a mirror: its index, as a sparse registry at `/index/`, its .crate
+/// files, and whatever else it holds, e.g. its HTML index. The index's git repository and the
+/// mirror's state aren't served.
+pub struct MirrorServer {
+    mirror_dir_path: PathBuf,
+    /// The tokens accepted in the `Authorization` header, or none if the mirror doesn't
+    /// require authentication.
+    tokens: Vec<String>,
+}
+
+impl MirrorServer {
+    /// Creates a server for the mirror, checking every request for one of the tokens if the
+    /// mirror requires authentication. Fails if tokens are given for a mirror that doesn't
+    /// require authentication, or none for one that does, since Cargo wouldn't be able to use
+    /// the mirror either way.
+    pub fn new<P: Into<PathBuf>>(mirror_dir_path: P, tokens: Vec<String>) -> Result<Self> {
+        let mirror_dir_path = mirror_dir_path.into();
+        let config_json_path = mirror_dir_path.join(INDEX_DIR).join("config.json");
+        let read_error =
+            |e: Box<dyn std::error::Error + Send + Sync + 'static>| Error::ReadConfigJson {
+                path: config_json_path.clone(),
+                error: e,
+            };
+        let contents = fs::read(&config_json_path).map_err(|e| read_error(Box::new(e)))?;
+        let config: serde_json::Value =
+            serde_json::from_slice(&contents).map_err(|e| read_error(Box::new(e)))?;
+        let auth_required = matches!(
+            config.get("auth-required"),
+            Some(serde_json::Value::Bool(true))
+        );
+        match (auth_required, tokens.is_empty()) {
+            (true, true) => return Err(Error::TokensRequired),
+            (false, false) => return Err(Error::NotAuthRequired),
+            _ => (),
+        }
+        Ok(MirrorServer {
+            mirror_dir_path,
+            tokens,
+        })
+    }
+
+    /// Answers the requests to the listener until the cancellation token is cancelled.
+    pub async fn serve(self: Arc<Self>, listener: TcpListener, cancel: CancellationToken) {
+        const POLL_INTERVAL: Duration = Duration::from_millis(500);
+        while !cancel.is_cancelled() {
+            let stream = match tokio::time::timeout(POLL_INTERVAL, listener.accept()).await {
+                Ok(Ok((stream, _))) => stream,
+                Ok(Err(e)) => {
+                    debug!("failed to accept a connection: {e}");
+                    continue;
+                }
+                // Timed out, to check the cancellation token.
+                Err(_) => continue,
+            };
+            let server = Arc::clone(&self);
+            tokio::spawn(async move {
+                if let Err(e) = server.respond(stream).await {
+                    debug!("failed to answer a request: {e}");
+                }
+            });
+        }
+    }
+
+    /// Answers a single HTTP request, closing the connection after.
+    async fn respond(&self, mut stream: TcpStream) -> io::Result<()> {
+        const MAX_REQUEST_SIZE: usize = 8 * 1024;
+        let mut request = Vec::new();
+        let mut buf = [0; 1024];
+        while !request.windows(4).any(|w| w == b"\r\n\r\n") && request.len() < MAX_REQUEST_SIZE {
+            let n = stream.read(&mut buf).await?;
+            if n == 0 {
+                break;
+            }
+            request.extend_from_slice(&buf[..n]);
+        }
+        let request = String::from_utf8_lossy(&request);
+        let mut lines = request.lines();
+        let mut request_line = lines.next().unwrap_or("").split_whitespace();
+        let (method, target) = (request_line.next(), request_line.next());
+        let authorization = lines
+            .take_while(|line| !line.is_empty())
+            .filter_map(|line| line.split_once(':'))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("authorization"))
+            .map(|(_, value)| value.trim());
+
+        let (status, content_type, body) = if !matches!(method, Some("GET" | "HEAD")) {
+            ("405 Method Not Allowed", None, Vec::new())
+        } else if !self.is_authorized(authorization) {
+            ("401 Unauthorized", None, Vec::new())
+        } else {
+            match target.and_then(|t| self.file_path(t)) {
+                Some(path) => match tokio::fs::read(&path).await {
+                    Ok(contents) => ("200 OK", Some(content_type(&path)), contents),
+                    Err(_) => ("404 Not Found", None, Vec::new()),
+                },
+                None => ("404 Not Found", None, Vec::new()),
+            }
+        };
+        let mut response = format!("HTTP/1.1 {status}\r\n");
+        if let Some(content_type) = content_type {
+            response.push_str(&format!("Content-Type: {content_type}\r\n"));
+        }
+        if status.starts_with("401") {
+            // Tells Cargo the registry wants its token.
+            response.push_str("WWW-Authenticate: Cargo\r\n");
+        }
+        response.push_str(&format!(
+            "Content-Length: {}\r\nConnection: close\r\n\r\n",
+            body.len()
+        ));
+        stream.write_all(response.as_bytes()).await?;
+        if method != Some("HEAD") {
+            stream.write_all(&body).await?;
+        }
+        stream.shutdown().await
+    }
+
+    /// Whether the request may be answered: always if the mirror doesn't require
+    /// authentication, else if it carries one of the tokens, which Cargo sends as is.
+    fn is_authorized(&self, authorization: Option<&str>) -> bool {
+        if self.tokens.is_empty() {
+            return true;
+        }
+        let authorization = match authorization {
+            Some(authorization) => authorization,
+            None => return false,
+        };
+        self.tokens
+            .iter()
+            .any(|token| constant_time_eq(token.as_bytes(), authorization.as_bytes()))
+    }
+
+    /// Returns the path of the file the request target names, or `None` if it names something
+    /// not served. Directories are served by their index.html.
+    fn file_path(&self, target: &str) -> Option<PathBuf> {
+        let path = target.split(['?', '#']).next().unwrap_or(target);
+        let mut file_path = self.mirror_dir_path.clone();
+        for segment in path.split('/').filter(|s| !s.is_empty()) {
+            let segment = percent_decode_str(segment).decode_utf8().ok()?;
+            // Hides the index's .git directory and the mirror's .micrio state, and keeps
+            // requests inside the mirror directory: a segment must be a single file name, not
+            // e.g. `..`, a root, or a Windows drive prefix such as `C:`.
+            if segment.starts_with('.') || !is_file_name(&segment) {
+                return None;
+            }
+            file_path.push(segment.as_ref());
+        }
+        if file_path.is_dir() {
+            file_path.push("index.html");
+        }
+        Some(file_path)
+    }
+}
+
+/// Whether the URL path segment is a single file name on this platform.
+fn is_file_name(segment: &str) -> bool {
+    let mut components = Path::new(segment).components();
+    matches!(
+        (components.next(), components.next()),
+        (Some(Component::Normal(_)), None)
+    ) && !segment.contains(['/', '\\'])
+}
+
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("json") => "application/json",
+        Some("html") => "text/html; charset=utf-8",
+        _ => "application/octet-stream",
+    }
+}
+
+/// Compares the bytes in time independent of where they differ, so timing doesn't reveal how
+/// much of a guessed token is right.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Creates a mirror directory with just a config.json, and an index file to serve.
+    fn test_mirror(name: &str, auth_required: bool) -> PathBuf {
+        let dir_path =
+            std::env::temp_dir().join(format!("micrio-test-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir_path);
+        let index_dir_path = dir_path.join(INDEX_DIR);
+        fs::create_dir_all(index_dir_path.join("3/s")).unwrap();
+        let config = serde_json::json!({
+            "dl": "http://localhost/registry",
+            "auth-required": auth_required,
+        });
+        fs::write(index_dir_path.join("config.json"), config.to_string()).unwrap();
+        fs::write(index_dir_path.join("3/s/syn"), "{}\n").unwrap();
+        dir_path
+    }
+
+    #[test]
+    fn tokens_are_checked_if_auth_is_required() {
+        let dir_path = test_mirror("tokens", true);
+        assert!(matches!(
+            MirrorServer::new(&dir_path, Vec::new()),
+            Err(Error::TokensRequired)
+        ));
+        let server = MirrorServer::new(&dir_path, vec!["secret".to_string()]).unwrap();
+        assert!(server.is_authorized(Some("secret")));
+        assert!(!server.is_authorized(Some("secreT")));
+        assert!(!server.is_authorized(Some("secret2")));
+        assert!(!server.is_authorized(Some("")));
+        assert!(!server.is_authorized(None));
+        fs::remove_dir_all(&dir_path).unwrap();
+    }
+
+    #[test]
+    fn tokens_are_refused_if_auth_is_not_required() {
+        let dir_path = test_mirror("no-tokens", false);
+        assert!(matches!(
+            MirrorServer::new(&dir_path, vec!["secret".to_string()]),
+            Err(Error::NotAuthRequired)
+        ));
+        let server = MirrorServer::new(&dir_path, Vec::new()).unwrap();
+        assert!(server.is_authorized(None));
+        fs::remove_dir_all(&dir_path).unwrap();
+    }
+
+    #[test]
+    fn file_paths_are_confined_to_the_mirror() {
+        let dir_path = test_mirror("paths", false);
+        let server = MirrorServer::new(&dir_path, Vec::new()).unwrap();
+        assert_eq!(
+            server.file_path("/index/3/s/syn?x=1"),
+            Some(dir_path.join(INDEX_DIR).join("3/s/syn"))
+        );
+        assert_eq!(
+            server.file_path("/index/"),
+            Some(dir_path.join(INDEX_DIR).join("index.html"))
+        );
+        for target in [
+            "/../secret",
+            "/index/../../secret",
+            "/%2e%2e/secret",
+            "/index/%2E%2E%2Fsecret",
+            "/index%2F..%2F..%2Fsecret",
+            "/index%5C..%5Csecret",
+            "/%2Fetc/passwd",
+            "/index/.git/config",
+            "/.micrio/state.db",
+        ] {
+            assert_eq!(server.file_path(target), None, "{target}");
+        }
+        #[cfg(windows)]
+        assert_eq!(server.file_path("/C:/Windows/win.ini"), None);
+        fs::remove_dir_all(&dir_path).unwrap();
+    }
+}
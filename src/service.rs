@@ -0,0 +1,173 @@
+use crate::cli::MirrorArgs;
+use crate::config::Config;
+use anyhow::anyhow;
+use micrio::cancel::CancellationToken;
+use micrio::scheduler::MirrorScheduler;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tracing::{error, info};
+
+/// Keeps the mirror up to date, mirroring again `interval` after each run finishes, until the
+/// token is cancelled. The configuration file is reloaded on SIGHUP and applies from the next
+/// run on; the notifiers are those configured at startup.
+pub fn run_daemon(
+    args: MirrorArgs,
+    config: Config,
+    interval: Duration,
+    cancel: CancellationToken,
+) -> anyhow::Result<()> {
+    // Taken from the configuration file by `mirror` if not given.
+    let mirror_dir_path = PathBuf::from(args.mirror_dir_path.clone().unwrap());
+    let notifiers = config.notifications.notifiers();
+    let config = Arc::new(Mutex::new(config));
+    let args = Arc::new(args);
+
+    let run_args = Arc::clone(&args);
+    let run_config = Arc::clone(&config);
+    let mut scheduler = MirrorScheduler::new()
+        .cancellation_token(cancel.clone())
+        .record_schedule(&mirror_dir_path)
+        .try_refresh(interval, move || {
+            let config = lock(&run_config).clone();
+            Ok(crate::mirror_builder(&run_args, config)?)
+        });
+    if let Some(addr) = args.health_addr {
+        scheduler = scheduler.health_endpoint(addr, &mirror_dir_path);
+    }
+    for notifier in notifiers {
+        scheduler = scheduler.notify(notifier);
+    }
+
+    let rt = tokio::runtime::Runtime::new()?;
+    rt.block_on(async {
+        #[cfg(unix)]
+        {
+            tokio::spawn(reload_on_hangup(Arc::clone(&args), Arc::clone(&config)));
+            tokio::spawn(cancel_on_terminate(cancel));
+        }
+        scheduler.run().await
+    })?;
+    Ok(())
+}
+
+/// Reloads the configuration file whenever the process receives SIGHUP, keeping the previous
+/// configuration if the file is invalid.
+#[cfg(unix)]
+async fn reload_on_hangup(args: Arc<MirrorArgs>, config: Arc<Mutex<Config>>) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(e) => {
+            error!("failed to listen for SIGHUP, the configuration won't be reloaded: {e}");
+            return;
+        }
+    };
+    while hangups.recv().await.is_some() {
+        match crate::load_config(&args) {
+            Ok(reloaded) => {
+                *lock(&config) = reloaded;
+                info!("configuration reloaded");
+            }
+            Err(e) => error!("failed to reload the configuration, keeping the previous one: {e:#}"),
+        }
+    }
+}
+
+/// Cancels the token when the process receives SIGTERM, as service managers send to stop it,
+/// so the run in progress stops once the crate in progress is done. A second SIGTERM exits
+/// immediately.
+#[cfg(unix)]
+async fn cancel_on_terminate(cancel: CancellationToken) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    match signal(SignalKind::terminate()) {
+        Ok(terminations) => cancel_on(terminations, cancel).await,
+        Err(e) => error!("failed to listen for SIGTERM: {e}"),
+    }
+}
+
+/// Cancels the token when the process receives SIGTERM, as `cancel_on_terminate` does, for
+/// single runs outside the daemon, from a thread of its own. Listens before returning, so a
+/// SIGTERM right after can't kill the run midway.
+#[cfg(unix)]
+pub fn cancel_on_terminate_in_background(cancel: CancellationToken) -> std::io::Result<()> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    let terminations = rt.block_on(async { signal(SignalKind::terminate()) })?;
+    std::thread::spawn(move || rt.block_on(cancel_on(terminations, cancel)));
+    Ok(())
+}
+
+#[cfg(unix)]
+async fn cancel_on(mut terminations: tokio::signal::unix::Signal, cancel: CancellationToken) {
+    while terminations.recv().await.is_some() {
+        if cancel.is_cancelled() {
+            std::process::exit(143);
+        }
+        info!("stopping once the crate in progress is done");
+        cancel.cancel();
+    }
+}
+
+fn lock(config: &Mutex<Config>) -> std::sync::MutexGuard<'_, Config> {
+    // The configuration is only replaced whole, so it's still usable if a thread panicked.
+    config.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Returns a systemd unit running micrio with the arguments it was given, minus
+/// --print-systemd-unit, from the current directory, where micrio.toml is looked up.
+pub fn systemd_unit() -> anyhow::Result<String> {
+    let exe_path = std::env::current_exe()?;
+    let working_dir = std::env::current_dir()?;
+    let mut exec_start = vec![quote_systemd_arg(path_str(&exe_path)?)];
+    for arg in std::env::args_os().skip(1) {
+        let arg = arg
+            .into_string()
+            .map_err(|arg| anyhow!("argument {} isn't valid UTF-8", arg.to_string_lossy()))?;
+        if arg != "--print-systemd-unit" {
+            exec_start.push(quote_systemd_arg(&arg));
+        }
+    }
+    Ok(format!(
+        "[Unit]
+Description=micrio crates.io mirror
+Wants=network-online.target
+After=network-online.target
+
+[Service]
+Type=exec
+WorkingDirectory={}
+ExecStart={}
+ExecReload=/bin/kill -HUP $MAINPID
+# The run in progress finishes the crate it is downloading before exiting.
+TimeoutStopSec=5min
+Restart=on-failure
+
+[Install]
+WantedBy=multi-user.target
+",
+        path_str(&working_dir)?.replace('%', "%%"),
+        exec_start.join(" ")
+    ))
+}
+
+fn path_str(path: &std::path::Path) -> anyhow::Result<&str> {
+    path.to_str()
+        .ok_or_else(|| anyhow!("path {} isn't valid UTF-8", path.to_string_lossy()))
+}
+
+/// Quotes the argument for a systemd unit, escaping its specifiers and variables so it's
+/// passed as is.
+fn quote_systemd_arg(arg: &str) -> String {
+    let escaped = arg
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('%', "%%")
+        .replace('$', "$$");
+    format!("\"{escaped}\"")
+}
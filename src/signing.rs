@@ -0,0 +1,231 @@
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey};
+use std::fmt::{self, Display};
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum Error {
+    ReadKey { path: PathBuf, error: io::Error },
+    InvalidKey { path: PathBuf, msg: String },
+    WriteKey { path: PathBuf, error: io::Error },
+    KeyExists(PathBuf),
+    InvalidSignature(String),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::ReadKey { path, error } => {
+                write!(
+                    f,
+                    "failed to read the key file {}: {error}",
+                    path.to_string_lossy()
+                )
+            }
+            Error::InvalidKey { path, msg } => {
+                write!(
+                    f,
+                    "the key file {} is not valid: {msg}",
+                    path.to_string_lossy()
+                )
+            }
+            Error::WriteKey { path, error } => {
+                write!(
+                    f,
+                    "failed to write the key file {}: {error}",
+                    path.to_string_lossy()
+                )
+            }
+            Error::KeyExists(path) => {
+                write!(
+                    f,
+                    "the key file {} already exists, not overwriting it",
+                    path.to_string_lossy()
+                )
+            }
+            Error::InvalidSignature(msg) => {
+                write!(f, "signature verification failed: {msg}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::ReadKey { error, .. } => Some(error),
+            Error::InvalidKey { .. } => None,
+            Error::WriteKey { error, .. } => Some(error),
+            Error::KeyExists(_) => None,
+            Error::InvalidSignature(_) => None,
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// Generates an ed25519 key pair, writing each key as hex to its file. Existing key files
+/// aren't overwritten. On unix, the signing key's file is only readable by its owner.
+pub fn generate_keypair<P: AsRef<Path>, Q: AsRef<Path>>(
+    signing_key_path: P,
+    verifying_key_path: Q,
+) -> Result<()> {
+    let (signing_key_path, verifying_key_path) =
+        (signing_key_path.as_ref(), verifying_key_path.as_ref());
+    for path in [signing_key_path, verifying_key_path] {
+        if path.exists() {
+            return Err(Error::KeyExists(path.to_path_buf()));
+        }
+    }
+    let signing_key = SigningKey::generate(&mut rand_core::OsRng);
+    write_key(signing_key_path, signing_key.as_bytes(), true)?;
+    write_key(
+        verifying_key_path,
+        signing_key.verifying_key().as_bytes(),
+        false,
+    )
+}
+
+pub fn load_signing_key<P: AsRef<Path>>(path: P) -> Result<SigningKey> {
+    let path = path.as_ref();
+    Ok(SigningKey::from_bytes(&read_key(path)?))
+}
+
+pub fn load_verifying_key<P: AsRef<Path>>(path: P) -> Result<VerifyingKey> {
+    let path = path.as_ref();
+    VerifyingKey::from_bytes(&read_key(path)?).map_err(|e| Error::InvalidKey {
+        path: path.to_path_buf(),
+        msg: e.to_string(),
+    })
+}
+
+/// Signs the message, returning the signature as hex.
+pub fn sign(signing_key: &SigningKey, message: &[u8]) -> String {
+    hex::encode(signing_key.sign(message).to_bytes())
+}
+
+/// Verifies a hex signature produced by `sign`, strictly, rejecting malleable signatures and
+/// small-order keys.
+pub fn verify(verifying_key: &VerifyingKey, message: &[u8], signature: &str) -> Result<()> {
+    let signature =
+        hex::decode(signature.trim()).map_err(|e| Error::InvalidSignature(e.to_string()))?;
+    let signature =
+        Signature::from_slice(&signature).map_err(|e| Error::InvalidSignature(e.to_string()))?;
+    verifying_key
+        .verify_strict(message, &signature)
+        .map_err(|e| Error::InvalidSignature(e.to_string()))
+}
+
+fn read_key(path: &Path) -> Result<[u8; 32]> {
+    let contents = fs::read_to_string(path).map_err(|e| Error::ReadKey {
+        path: path.to_path_buf(),
+        error: e,
+    })?;
+    let bytes = hex::decode(contents.trim()).map_err(|e| Error::InvalidKey {
+        path: path.to_path_buf(),
+        msg: e.to_string(),
+    })?;
+    bytes.try_into().map_err(|_| Error::InvalidKey {
+        path: path.to_path_buf(),
+        msg: "expected 32 hex-encoded bytes".to_string(),
+    })
+}
+
+/// Writes the key to a new file, readable only by its owner on unix if it is private.
+fn write_key(path: &Path, key: &[u8; 32], private: bool) -> Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    if private {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    #[cfg(not(unix))]
+    let _ = private;
+    let write_error = |e: io::Error| {
+        if e.kind() == io::ErrorKind::AlreadyExists {
+            Error::KeyExists(path.to_path_buf())
+        } else {
+            Error::WriteKey {
+                path: path.to_path_buf(),
+                error: e,
+            }
+        }
+    };
+    let mut file = options.open(path).map_err(write_error)?;
+    file.write_all((hex::encode(key) + "\n").as_bytes())
+        .map_err(write_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir_path =
+            std::env::temp_dir().join(format!("micrio-test-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir_path);
+        fs::create_dir_all(&dir_path).unwrap();
+        dir_path
+    }
+
+    #[test]
+    fn signatures_verify_only_the_signed_message() {
+        let signing_key = SigningKey::generate(&mut rand_core::OsRng);
+        let verifying_key = signing_key.verifying_key();
+        let signature = sign(&signing_key, b"manifest");
+        assert!(verify(&verifying_key, b"manifest", &signature).is_ok());
+        assert!(matches!(
+            verify(&verifying_key, b"manifest2", &signature),
+            Err(Error::InvalidSignature(_))
+        ));
+        let other_key = SigningKey::generate(&mut rand_core::OsRng).verifying_key();
+        assert!(matches!(
+            verify(&other_key, b"manifest", &signature),
+            Err(Error::InvalidSignature(_))
+        ));
+        assert!(matches!(
+            verify(&verifying_key, b"manifest", "not hex"),
+            Err(Error::InvalidSignature(_))
+        ));
+    }
+
+    #[test]
+    fn generated_keys_round_trip_and_are_not_overwritten() {
+        let dir_path = test_dir("keypair");
+        let signing_key_path = dir_path.join("signing.key");
+        let verifying_key_path = dir_path.join("verifying.key");
+        generate_keypair(&signing_key_path, &verifying_key_path).unwrap();
+
+        let signing_key = load_signing_key(&signing_key_path).unwrap();
+        let verifying_key = load_verifying_key(&verifying_key_path).unwrap();
+        let signature = sign(&signing_key, b"manifest");
+        assert!(verify(&verifying_key, b"manifest", &signature).is_ok());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(&signing_key_path)
+                .unwrap()
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let signing_key_hex = fs::read_to_string(&signing_key_path).unwrap();
+        assert!(matches!(
+            generate_keypair(&signing_key_path, dir_path.join("other.key")),
+            Err(Error::KeyExists(_))
+        ));
+        assert!(matches!(
+            generate_keypair(dir_path.join("other.key"), &verifying_key_path),
+            Err(Error::KeyExists(_))
+        ));
+        assert_eq!(
+            fs::read_to_string(&signing_key_path).unwrap(),
+            signing_key_hex
+        );
+        assert!(!dir_path.join("other.key").exists());
+        fs::remove_dir_all(&dir_path).unwrap();
+    }
+}
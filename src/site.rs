@@ -0,0 +1,255 @@
+use crate::common::{self, Version};
+use crate::dst_registry::DstRegistry;
+use crate::metadata::{CrateMetadata, README_FILE};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::{self, Display, Write};
+use std::fs;
+use std::io;
+
+#[derive(Debug)]
+pub enum Error {
+    ReadCrateFile {
+        crate_name: String,
+        crate_version: String,
+        error: io::Error,
+    },
+    WriteIndex(io::Error),
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::ReadCrateFile {
+                crate_name,
+                crate_version,
+                error,
+            } => {
+                write!(f, "error writing HTML index: failed to read the .crate file for {crate_name} version {crate_version}: {error}")
+            }
+            Error::WriteIndex(e) => {
+                write!(
+                    f,
+                    "error writing HTML index: failed to write the {INDEX_HTML_FILE} file: {e}"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::ReadCrateFile { error, .. } => Some(error),
+            Error::WriteIndex(e) => Some(e),
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+pub const INDEX_HTML_FILE: &str = "index.html";
+
+struct CrateVersionEntry {
+    version: String,
+    size: u64,
+    link: String,
+}
+
+struct CrateEntry {
+    metadata: CrateMetadata,
+    /// Set if the README of the newest version was mirrored.
+    readme_link: Option<String>,
+    /// Sorted newest first.
+    versions: Vec<CrateVersionEntry>,
+}
+
+/// Writes an index.html file in the mirror directory listing every mirrored crate
+/// with its versions, sizes, and description, linking to the .crate files,
+/// so the mirror can be browsed without any tooling.
+///
+/// If `readmes_mirrored` is set, the page links to the READMEs stored next to the .crate files.
+pub fn write_index_html(
+    dst_registry: &DstRegistry,
+    crates: &HashSet<Version>,
+    readmes_mirrored: bool,
+) -> Result<()> {
+    let mut by_name: BTreeMap<&str, Vec<&Version>> = BTreeMap::new();
+    for crat in crates {
+        by_name.entry(crat.name()).or_default().push(crat);
+    }
+
+    let mut entries = BTreeMap::new();
+    for (name, mut versions) in by_name {
+        versions.sort_by(|a, b| compare_versions(b.version(), a.version()));
+        let mut entry = CrateEntry {
+            metadata: CrateMetadata::default(),
+            readme_link: None,
+            versions: Vec::new(),
+        };
+        for crat in versions {
+            let crate_file = dst_registry
+                .read_crate_file(crat.name(), crat.version())
+                .map_err(|e| Error::ReadCrateFile {
+                    crate_name: crat.name().to_string(),
+                    crate_version: crat.version().to_string(),
+                    error: e,
+                })?;
+            // Describe the crate using its newest mirrored version.
+            if entry.versions.is_empty() {
+                let (metadata, _) =
+                    CrateMetadata::read(&crate_file, crat).map_err(|e| Error::ReadCrateFile {
+                        crate_name: crat.name().to_string(),
+                        crate_version: crat.version().to_string(),
+                        error: e,
+                    })?;
+                if readmes_mirrored && metadata.has_readme {
+                    entry.readme_link = Some(dst_registry.extra_file_link(
+                        crat.name(),
+                        &common::url_path_version(crat.version()),
+                        README_FILE,
+                    ));
+                }
+                entry.metadata = metadata;
+            }
+            entry.versions.push(CrateVersionEntry {
+                version: crat.version().to_string(),
+                size: crate_file.len() as u64,
+                link: dst_registry
+                    .crate_file_link(crat.name(), &common::url_path_version(crat.version())),
+            });
+        }
+        entries.insert(name, entry);
+    }
+
+    let html = render(&entries).expect("write to String");
+    fs::write(dst_registry.path().join(INDEX_HTML_FILE), html).map_err(Error::WriteIndex)
+}
+
+fn render(entries: &BTreeMap<&str, CrateEntry>) -> std::result::Result<String, fmt::Error> {
+    let num_versions: usize = entries.values().map(|e| e.versions.len()).sum();
+    let mut html = String::new();
+    writeln!(html, "<!DOCTYPE html>")?;
+    writeln!(html, "<html>")?;
+    writeln!(html, "<head>")?;
+    writeln!(html, "<meta charset=\"utf-8\">")?;
+    writeln!(html, "<title>Crate mirror</title>")?;
+    writeln!(
+        html,
+        "<style>body {{ font-family: sans-serif; margin: 2em; }} \
+         table {{ border-collapse: collapse; }} \
+         td, th {{ padding: 0.2em 1em; text-align: left; }} \
+         .size {{ text-align: right; }}</style>"
+    )?;
+    writeln!(html, "</head>")?;
+    writeln!(html, "<body>")?;
+    writeln!(html, "<h1>Crate mirror</h1>")?;
+    writeln!(
+        html,
+        "<p>{} crates, {num_versions} crate versions.</p>",
+        entries.len()
+    )?;
+
+    writeln!(html, "<table>")?;
+    writeln!(
+        html,
+        "<tr><th>Crate</th><th>Latest</th><th>Versions</th><th>Description</th></tr>"
+    )?;
+    for (name, entry) in entries {
+        writeln!(
+            html,
+            "<tr><td><a href=\"#{name}\">{name}</a></td><td>{}</td><td>{}</td><td>{}</td></tr>",
+            escape(&entry.versions[0].version),
+            entry.versions.len(),
+            escape(entry.metadata.description.as_deref().unwrap_or_default()),
+            name = escape(name)
+        )?;
+    }
+    writeln!(html, "</table>")?;
+
+    for (name, entry) in entries {
+        writeln!(html, "<h2 id=\"{name}\">{name}</h2>", name = escape(name))?;
+        let metadata = &entry.metadata;
+        if let Some(description) = &metadata.description {
+            writeln!(html, "<p>{}</p>", escape(description))?;
+        }
+        if !metadata.keywords.is_empty() {
+            writeln!(
+                html,
+                "<p>Keywords: {}</p>",
+                escape(&metadata.keywords.join(", "))
+            )?;
+        }
+        let mut links = Vec::new();
+        if let Some(readme_link) = &entry.readme_link {
+            links.push(("README", readme_link));
+        }
+        for (label, url) in [
+            ("Homepage", &metadata.homepage),
+            ("Documentation", &metadata.documentation),
+            ("Repository", &metadata.repository),
+        ] {
+            if let Some(url) = url {
+                links.push((label, url));
+            }
+        }
+        if !links.is_empty() {
+            let links = links
+                .iter()
+                .map(|(label, url)| format!("<a href=\"{}\">{label}</a>", escape(url)))
+                .collect::<Vec<_>>();
+            writeln!(html, "<p>{}</p>", links.join(" | "))?;
+        }
+        writeln!(html, "<table>")?;
+        writeln!(
+            html,
+            "<tr><th>Version</th><th class=\"size\">Size</th></tr>"
+        )?;
+        for version in &entry.versions {
+            writeln!(
+                html,
+                "<tr><td><a href=\"{}\" download=\"{}-{}.crate\">{}</a></td><td class=\"size\">{}</td></tr>",
+                escape(&version.link),
+                escape(name),
+                escape(&version.version),
+                escape(&version.version),
+                format_size(version.size)
+            )?;
+        }
+        writeln!(html, "</table>")?;
+    }
+
+    writeln!(html, "</body>")?;
+    writeln!(html, "</html>")?;
+    Ok(html)
+}
+
+/// Orders versions by semver precedence, falling back to string order for invalid versions.
+fn compare_versions(a: &str, b: &str) -> std::cmp::Ordering {
+    match (semver::Version::parse(a), semver::Version::parse(b)) {
+        (Ok(a), Ok(b)) => a.cmp(&b),
+        _ => a.cmp(b),
+    }
+}
+
+/// Formats a size in bytes with a binary unit, e.g. `1.5 MiB`.
+pub fn format_size(size: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut size = size as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{size} {}", UNITS[unit])
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
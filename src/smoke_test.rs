@@ -0,0 +1,249 @@
+//! Proves a mirror works end to end by having Cargo build a project against it.
+
+use crate::dst_registry::INDEX_DIR;
+use crate::top_level::{self, IndexEntry};
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus};
+use url::Url;
+
+#[derive(Debug)]
+pub enum Error {
+    ReadIndex(Box<dyn std::error::Error + Send + Sync + 'static>),
+    NoCrates,
+    CreateProject {
+        path: PathBuf,
+        error: io::Error,
+    },
+    RunCargo {
+        command: String,
+        error: io::Error,
+    },
+    CargoFailed {
+        command: String,
+        status: ExitStatus,
+        stderr: String,
+    },
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::ReadIndex(e) => write!(f, "failed to read the mirror's index: {e}"),
+            Error::NoCrates => write!(f, "the mirror has no crates to test"),
+            Error::CreateProject { path, error } => {
+                write!(
+                    f,
+                    "failed to create the test project in {}: {error}",
+                    path.to_string_lossy()
+                )
+            }
+            Error::RunCargo { command, error } => {
+                write!(f, "failed to run {command}: {error}")
+            }
+            Error::CargoFailed {
+                command,
+                status,
+                stderr,
+            } => {
+                write!(f, "{command} failed with {status}:\n{stderr}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::ReadIndex(e) => Some(e.as_ref()),
+            Error::NoCrates => None,
+            Error::CreateProject { error, .. } => Some(error),
+            Error::RunCargo { error, .. } => Some(error),
+            Error::CargoFailed { .. } => None,
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+pub struct SmokeTestOptions {
+    /// The number of crates to depend on, spread evenly over the mirrored crates by name.
+    pub sample_size: usize,
+    /// Also run `cargo check`, compiling the crates. Crates with build scripts needing system
+    /// libraries can fail it on the machine running the test, whatever the mirror holds.
+    pub check: bool,
+    /// Keep the test project instead of deleting it, e.g. to investigate a failure.
+    pub keep_project: bool,
+}
+
+impl Default for SmokeTestOptions {
+    fn default() -> Self {
+        SmokeTestOptions {
+            sample_size: 20,
+            check: false,
+            keep_project: false,
+        }
+    }
+}
+
+/// Generates a Cargo project depending on the newest non-yanked version of a sample of the
+/// mirrored crates, replaces crates.io with the mirror in its Cargo config, then runs
+/// `cargo fetch` with an empty Cargo home so nothing comes from elsewhere, and, if enabled,
+/// `cargo check --offline`. The `CARGO` environment variable selects the Cargo to run.
+///
+/// Returns the crate versions tested, by name.
+pub fn smoke_test<P: AsRef<Path>>(
+    mirror_dir_path: P,
+    options: &SmokeTestOptions,
+) -> Result<BTreeMap<String, String>> {
+    let index_dir_path = mirror_dir_path.as_ref().join(INDEX_DIR);
+    let entries = top_level::read_index_entries(&index_dir_path).map_err(Error::ReadIndex)?;
+    let sample = sample_crates(entries, options.sample_size);
+    if sample.is_empty() {
+        return Err(Error::NoCrates);
+    }
+
+    let project_dir_path =
+        std::env::temp_dir().join(format!("micrio-smoke-test-{}", std::process::id()));
+    let result = create_project(&project_dir_path, &index_dir_path, &sample)
+        .and_then(|_| run_cargo(&project_dir_path, &["fetch"]))
+        .and_then(|_| {
+            if options.check {
+                run_cargo(&project_dir_path, &["check", "--offline"])
+            } else {
+                Ok(())
+            }
+        });
+    if !options.keep_project {
+        // Best effort: a leftover project in the temp directory is harmless.
+        let _ = fs::remove_dir_all(&project_dir_path);
+    }
+    result.map(|_| sample)
+}
+
+/// Picks the newest non-yanked version of up to `sample_size` crates, spread evenly over
+/// the crates sorted by name so the same mirror always gets the same sample.
+fn sample_crates(entries: Vec<IndexEntry>, sample_size: usize) -> BTreeMap<String, String> {
+    let mut newest: BTreeMap<String, (semver::Version, String)> = BTreeMap::new();
+    for entry in entries.into_iter().filter(|e| !e.yanked) {
+        let version = match semver::Version::parse(&entry.vers) {
+            Ok(version) => version,
+            Err(_) => continue,
+        };
+        let is_newer = match newest.get(&entry.name) {
+            Some((newest_version, _)) => version > *newest_version,
+            None => true,
+        };
+        if is_newer {
+            newest.insert(entry.name, (version, entry.vers));
+        }
+    }
+    let step = (newest.len() / sample_size.max(1)).max(1);
+    newest
+        .into_iter()
+        .step_by(step)
+        .take(sample_size)
+        .map(|(name, (_, vers))| (name, vers))
+        .collect()
+}
+
+/// Writes the project's manifest, source, and Cargo config using the mirror's index.
+fn create_project(
+    project_dir_path: &Path,
+    index_dir_path: &Path,
+    sample: &BTreeMap<String, String>,
+) -> Result<()> {
+    let create_error = |e| Error::CreateProject {
+        path: project_dir_path.to_path_buf(),
+        error: e,
+    };
+    if project_dir_path.exists() {
+        fs::remove_dir_all(project_dir_path).map_err(create_error)?;
+    }
+    fs::create_dir_all(project_dir_path.join("src")).map_err(create_error)?;
+    fs::create_dir_all(project_dir_path.join(".cargo")).map_err(create_error)?;
+    fs::create_dir_all(project_dir_path.join("cargo-home")).map_err(create_error)?;
+
+    let mut manifest = String::from(
+        "[package]\nname = \"micrio-smoke-test\"\nversion = \"0.0.0\"\nedition = \"2021\"\n\n\
+         [dependencies]\n",
+    );
+    for (name, version) in sample {
+        manifest += &format!("{name} = \"={version}\"\n");
+    }
+    fs::write(project_dir_path.join("Cargo.toml"), manifest).map_err(create_error)?;
+    fs::write(project_dir_path.join("src").join("lib.rs"), "").map_err(create_error)?;
+
+    let index_dir_path = fs::canonicalize(index_dir_path).map_err(create_error)?;
+    let index_url = Url::from_directory_path(&index_dir_path).map_err(|_| {
+        create_error(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "the index path can't be made a file URL",
+        ))
+    })?;
+    let config = format!(
+        "[source.crates-io]\nreplace-with = \"micrio\"\n\n\
+         [source.micrio]\nregistry = \"{index_url}\"\n"
+    );
+    fs::write(project_dir_path.join(".cargo").join("config.toml"), config).map_err(create_error)?;
+    Ok(())
+}
+
+/// Runs Cargo in the project with its own, empty Cargo home.
+fn run_cargo(project_dir_path: &Path, args: &[&str]) -> Result<()> {
+    let cargo = std::env::var_os("CARGO").unwrap_or_else(|| "cargo".into());
+    let command = format!("cargo {}", args.join(" "));
+    let output = Command::new(cargo)
+        .args(args)
+        .current_dir(project_dir_path)
+        .env("CARGO_HOME", project_dir_path.join("cargo-home"))
+        .output()
+        .map_err(|e| Error::RunCargo {
+            command: command.clone(),
+            error: e,
+        })?;
+    if !output.status.success() {
+        return Err(Error::CargoFailed {
+            command,
+            status: output.status,
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn failing_cargo_fails_the_smoke_test() {
+        let dir_path =
+            std::env::temp_dir().join(format!("micrio-test-{}-smoke-test", std::process::id()));
+        let _ = fs::remove_dir_all(&dir_path);
+        let index_dir_path = dir_path.join(INDEX_DIR);
+        fs::create_dir_all(index_dir_path.join("3/s")).unwrap();
+        // The crate isn't downloadable from the mirror, so `cargo fetch` must fail.
+        let config = serde_json::json!({ "dl": "http://127.0.0.1:9/crates" });
+        fs::write(index_dir_path.join("config.json"), config.to_string()).unwrap();
+        let entry = serde_json::json!({
+            "name": "syn",
+            "vers": "1.0.0",
+            "deps": [],
+            "cksum": "0".repeat(64),
+            "features": {},
+            "yanked": false,
+        });
+        fs::write(index_dir_path.join("3/s/syn"), format!("{entry}\n")).unwrap();
+
+        let result = smoke_test(&dir_path, &SmokeTestOptions::default());
+        assert!(
+            matches!(&result, Err(Error::CargoFailed { command, .. }) if command == "cargo fetch"),
+            "{result:?}"
+        );
+        fs::remove_dir_all(&dir_path).unwrap();
+    }
+}
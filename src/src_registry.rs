@@ -1,9 +1,17 @@
+use crate::cancel::CancellationToken;
 use crate::common::{self, Version};
+use crate::index::Index;
+use crate::progress::ProgressObserver;
+use crate::selection::{CrateSpec, FeatureSpec};
+use crate::warnings;
+use cfg_expr::targets::TargetInfo;
+use cfg_expr::{Expression, Predicate};
 use crates_index::DependencyKind;
-use log::warn;
 use semver::VersionReq;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::fmt::{self, Display};
+use std::sync::Arc;
+use tracing::{debug, debug_span, warn};
 
 #[derive(Debug)]
 pub enum Error {
@@ -18,6 +26,18 @@ pub enum Error {
         crate_version: String,
         error: semver::Error,
     },
+    TooManyTargets(usize),
+    MissingDependency {
+        crate_name: String,
+        crate_version: String,
+        dependency_name: String,
+    },
+    UnknownRegistry {
+        crate_name: String,
+        dependency_name: String,
+        registry: String,
+    },
+    Cancelled,
 }
 
 impl Display for Error {
@@ -48,6 +68,32 @@ impl Display for Error {
                     crate_name, crate_version, error
                 )
             }
+            Error::TooManyTargets(n) => {
+                write!(f, "{n} targets given, at most {MAX_TARGETS} are supported")
+            }
+            Error::MissingDependency {
+                crate_name,
+                crate_version,
+                dependency_name,
+            } => {
+                write!(
+                    f,
+                    "no compatible version of the {dependency_name} dependency of {crate_name} version {crate_version} found"
+                )
+            }
+            Error::UnknownRegistry {
+                crate_name,
+                dependency_name,
+                registry,
+            } => {
+                write!(
+                    f,
+                    "the {dependency_name} dependency of {crate_name} is on the registry {registry}, which isn't configured"
+                )
+            }
+            Error::Cancelled => {
+                write!(f, "dependency resolution was cancelled")
+            }
         }
     }
 }
@@ -58,136 +104,909 @@ impl std::error::Error for Error {
             Error::CrateNotFound(e) => Some(e),
             Error::SemVerRequirement { error, .. } => Some(error),
             Error::SemVerVersion { error, .. } => Some(error),
+            Error::TooManyTargets(_) => None,
+            Error::MissingDependency { .. } => None,
+            Error::UnknownRegistry { .. } => None,
+            Error::Cancelled => None,
         }
     }
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// Called with each newly found dependency. Returns false to stop resolution.
+type DependencyListener<'i> = Box<dyn FnMut(&Version) -> bool + 'i>;
+
+/// A crate's non-yanked versions, newest first, each with its parsed semver version,
+/// or `None` if the version isn't valid semver.
+type CachedVersions = Vec<(Option<semver::Version>, Version)>;
+
+/// A set of the targets resolved for, bit i standing for the i-th target.
+pub(crate) type TargetSet = u64;
+
+/// The maximum number of targets dependencies can be resolved for at once.
+pub const MAX_TARGETS: usize = TargetSet::BITS as usize;
+
+/// Resolution options for a single top-level crate, overriding the registry's.
+#[derive(Clone, Default)]
+pub struct TopLevelOptions {
+    /// The features to enable on the crate.
+    pub features: Option<FeatureSpec>,
+    /// The targets to resolve the crate's dependencies for. If empty, the registry's targets.
+    pub targets: Vec<&'static TargetInfo>,
+    /// Also resolve the crate's dev-dependencies.
+    pub dev_dependencies: bool,
+    /// The crates left out of the crate's dependency tree, in addition to the registry's.
+    pub exclusions: Vec<CrateSpec>,
+}
+
+/// The features requested of a crate version by the crates depending on it.
+#[derive(Default)]
+pub(crate) struct RequestedFeatures {
+    pub default: bool,
+    pub features: HashSet<String>,
+}
+
+/// What a crate version's requested features enable.
+#[derive(Default)]
+pub(crate) struct Activation {
+    /// The optional dependencies enabled, by the name the crate gives them.
+    pub dependencies: HashSet<String>,
+    /// The features enabled on each dependency, by the name the crate gives it.
+    pub dependency_features: HashMap<String, Vec<String>>,
+}
+
 pub struct SrcRegistry<'i> {
-    index: &'i crates_index::Index,
+    index: &'i Index,
+    /// The canonical URL of `index`.
+    registry_url: String,
+    /// The indexes of the other registries dependencies can be on, by canonical URL.
+    secondary: HashMap<Arc<str>, &'i Index>,
+    /// Index lookups, by registry and crate name. Popular crates are depended on thousands of
+    /// times, and reading and parsing their index entries dominates resolution otherwise.
+    crates: HashMap<(Option<Arc<str>>, String), CachedVersions>,
     dependencies: HashSet<Version>,
+    dependents: HashMap<Version, HashSet<Version>>,
+    /// The targets each dependency has been resolved for so far, by exclusion context: 0 for
+    /// the top-level crates without exclusions of their own, and one for each crate with some,
+    /// since a dependency tree they prune must be resolved again for the other crates.
+    resolved_for: HashMap<(Version, usize), TargetSet>,
+    targets: Vec<&'static TargetInfo>,
+    /// The platforms bit i of a `TargetSet` stands for: the registry's targets, or any platform
+    /// if there are none, then the top-level crates' own targets.
+    platforms: Vec<Option<&'static TargetInfo>>,
+    /// The features requested of each crate version so far. Only used when resolving features.
+    requested: HashMap<Version, RequestedFeatures>,
+    top_level_features: Option<FeatureSpec>,
+    resolves_features: bool,
+    top_level: HashMap<Version, TopLevelOptions>,
+    deny_warnings: bool,
+    max_depth: Option<usize>,
+    /// The requirements replacing those of the dependencies on each crate, by crate name.
+    overrides: HashMap<String, VersionReq>,
+    /// The crates left out along with the dependencies only they require.
+    exclusions: Vec<CrateSpec>,
+    /// The exclusions of the top-level crate being resolved, and its exclusion context.
+    cur_exclusions: Vec<CrateSpec>,
+    cur_context: usize,
+    /// The fewest dependency edges from a top-level crate to each crate version found so far.
+    /// Only tracked with a maximum depth.
+    depths: HashMap<Version, usize>,
+    /// The dependencies deeper than the maximum depth, each with the crates depending on it.
+    beyond_max_depth: HashMap<Version, HashSet<Version>>,
     cur_crate_name: String,
+    progress: &'i dyn ProgressObserver,
+    cancel: &'i CancellationToken,
+    on_found: Option<DependencyListener<'i>>,
 }
 
 impl<'i> SrcRegistry<'i> {
-    pub fn new(index: &'i crates_index::Index) -> Self {
+    pub fn new(
+        index: &'i Index,
+        progress: &'i dyn ProgressObserver,
+        cancel: &'i CancellationToken,
+    ) -> Self {
         SrcRegistry {
             index,
+            registry_url: common::CRATES_IO_INDEX.to_string(),
+            secondary: HashMap::new(),
+            crates: HashMap::new(),
             dependencies: HashSet::new(),
+            dependents: HashMap::new(),
+            resolved_for: HashMap::new(),
+            targets: Vec::new(),
+            platforms: Vec::new(),
+            requested: HashMap::new(),
+            top_level_features: None,
+            resolves_features: false,
+            top_level: HashMap::new(),
+            deny_warnings: false,
+            max_depth: None,
+            overrides: HashMap::new(),
+            exclusions: Vec::new(),
+            cur_exclusions: Vec::new(),
+            cur_context: 0,
+            depths: HashMap::new(),
+            beyond_max_depth: HashMap::new(),
             cur_crate_name: String::from(""),
+            progress,
+            cancel,
+            on_found: None,
         }
     }
 
+    /// Calls `on_found` with each dependency as soon as it is found, e.g. to start downloading
+    /// it while resolution continues. Resolution stops, as if cancelled, if it returns false.
+    pub fn on_dependency_found<F: FnMut(&Version) -> bool + 'i>(mut self, on_found: F) -> Self {
+        self.on_found = Some(Box::new(on_found));
+        self
+    }
+
+    /// Sets the URL of the index, crates.io's by default, so dependencies of secondary registry
+    /// crates naming it are resolved against it.
+    pub fn registry_url(mut self, index_url: &str) -> Self {
+        self.registry_url = common::canonical_registry_url(index_url);
+        self
+    }
+
+    /// Resolves the dependencies on the registry with the index URL, given by their `registry`
+    /// field, against its index. Dependencies on other registries are left out.
+    pub fn secondary_registry(mut self, index_url: &str, index: &'i Index) -> Self {
+        let url = common::canonical_registry_url(index_url);
+        self.secondary.insert(Arc::from(url), index);
+        self
+    }
+
+    /// Only resolves the dependencies needed to build for one of the targets, following each
+    /// target separately, instead of every platform-specific dependency.
+    /// At most `MAX_TARGETS` targets are supported, counting the top-level crates' own.
+    pub fn targets(mut self, targets: Vec<&'static TargetInfo>) -> Self {
+        self.targets = targets;
+        self
+    }
+
+    /// Enables the features on the top-level crates, then only resolves the optional dependencies
+    /// enabled features require, following the features each crate enables on its dependencies.
+    /// If `None`, the default, every optional dependency is resolved.
+    pub fn features(mut self, features: Option<FeatureSpec>) -> Self {
+        self.top_level_features = features;
+        self
+    }
+
+    /// Sets the options of individual top-level crates.
+    /// Features given for any of them make resolution feature-aware for every crate.
+    pub fn top_level_options(mut self, options: HashMap<Version, TopLevelOptions>) -> Self {
+        self.top_level = options;
+        self
+    }
+
+    /// Fails resolution when a dependency has no compatible version, instead of leaving it out.
+    pub fn deny_warnings(mut self, deny_warnings: bool) -> Self {
+        self.deny_warnings = deny_warnings;
+        self
+    }
+
+    /// Only resolves dependencies at most `max_depth` dependency edges away from a top-level
+    /// crate, so 1 only resolves their direct dependencies. The dependencies left out are
+    /// returned by `beyond_max_depth`.
+    pub fn max_depth(mut self, max_depth: Option<usize>) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Resolves the dependencies on each named crate of the registry to the newest version
+    /// matching its requirement instead of their own, like Cargo's `[patch]`, e.g. to work
+    /// around a broken release. Dependencies on secondary registries are left as is.
+    pub fn overrides(mut self, overrides: HashMap<String, VersionReq>) -> Self {
+        self.overrides = overrides;
+        self
+    }
+
+    /// Leaves out the dependencies matching any of the crate specs, without resolving their
+    /// own dependencies, so those only they require are left out too.
+    pub fn exclusions(mut self, exclusions: Vec<CrateSpec>) -> Self {
+        self.exclusions = exclusions;
+        self
+    }
+
+    /// Returns every crate version the crate versions transitively require.
+    /// The resolved set is moved out rather than copied, so call this once per registry.
     pub fn get_dependencies(&mut self, crate_versions: &HashSet<Version>) -> Result<HashSet<Version>> {
+        self.init_platforms()?;
+        self.resolves_features = self.top_level_features.is_some()
+            || self.top_level.values().any(|o| o.features.is_some());
         for (i, crate_version) in crate_versions.iter().enumerate() {
-            println!(
-                "Analyzing {:>4} of {}: {} version {}",
-                i + 1,
-                crate_versions.len(),
-                crate_version.name(),
-                crate_version.version()
-            );
-            // Cache the name of the current crate for use in error messages.
-            self.cur_crate_name = crate_version.name().to_string();
-            let mut deps_to_analyze = Vec::new();
-            for dependency in crate_version
-                .dependencies()
-                .iter()
-                .filter(|d| d.kind() == DependencyKind::Normal || d.kind() == DependencyKind::Build)
-            {
-                let dep_version = match self.get_compatible_version(dependency)? {
-                    Some(version) => version,
-                    None => {
-                        warn!(
-                            "{} version {}: compatible version for {} dependency not found",
-                            crate_version.name(),
-                            crate_version.version(),
-                            dependency.name()
-                        );
-                        continue;
-                    }
-                };
-                if self.dependencies.insert(dep_version.clone()) {
-                    deps_to_analyze.push(dep_version);
+            if self.cancel.is_cancelled() {
+                return Err(Error::Cancelled);
+            }
+            let _span = debug_span!(
+                "resolve",
+                crate_name = crate_version.name(),
+                crate_version = crate_version.version()
+            )
+            .entered();
+            self.progress.top_level_analyzed(crate_version, i + 1, crate_versions.len());
+            let options = self
+                .top_level
+                .get(crate_version)
+                .cloned()
+                .unwrap_or_default();
+            let spec = options
+                .features
+                .clone()
+                .or_else(|| self.top_level_features.clone())
+                .or_else(|| self.resolves_features.then(FeatureSpec::default));
+            if let Some(spec) = spec {
+                let requested = self.requested.entry(crate_version.clone()).or_default();
+                requested.default |= !spec.no_default_features;
+                requested.features.extend(spec.features.iter().cloned());
+                if spec.all_features {
+                    requested
+                        .features
+                        .extend(crate_version.features().keys().cloned());
+                    // Optional dependencies are features too, unless only enabled with `dep:`.
+                    requested.features.extend(
+                        crate_version
+                            .dependencies()
+                            .iter()
+                            .filter(|d| d.is_optional())
+                            .map(|d| d.name().to_string()),
+                    );
                 }
             }
+            let targets = self.top_level_targets(&options);
+            self.cur_context = if options.exclusions.is_empty() {
+                0
+            } else {
+                i + 1
+            };
+            self.cur_exclusions = options.exclusions;
+            if self.max_depth.is_some() {
+                self.depths.insert(crate_version.clone(), 0);
+            }
+            self.process_dependency(crate_version.clone(), targets, options.dev_dependencies, 0)?;
+        }
+        // Dependencies first found too deep may be within the maximum depth by another path.
+        self.beyond_max_depth
+            .retain(|crat, _| !self.dependencies.contains(crat) && !crate_versions.contains(crat));
+        Ok(std::mem::take(&mut self.dependencies))
+    }
 
-            for dep_version in deps_to_analyze {
-                println!(
-                    "\tAnalyzing dependency {} version {}",
-                    dep_version.name(),
-                    dep_version.version()
-                );
-                self.process_dependency(dep_version)?;
+    /// Returns the dependencies left out for being deeper than the maximum depth, each with
+    /// the crates at the maximum depth depending on it.
+    pub fn beyond_max_depth(&self) -> &HashMap<Version, HashSet<Version>> {
+        &self.beyond_max_depth
+    }
+
+    /// Returns, for each dependency found so far, the crates that directly depend on it.
+    pub fn dependents(&self) -> &HashMap<Version, HashSet<Version>> {
+        &self.dependents
+    }
+
+    /// Returns, for each dependency found, the crates that directly depend on it,
+    /// consuming the registry to avoid a copy.
+    pub fn into_dependents(self) -> HashMap<Version, HashSet<Version>> {
+        self.dependents
+    }
+
+    /// Passes a newly found dependency to the listener, if any.
+    fn found(&mut self, crat: &Version) -> Result<()> {
+        if let Some(on_found) = &mut self.on_found {
+            if !on_found(crat) {
+                return Err(Error::Cancelled);
             }
         }
-        Ok(self.dependencies.clone())
+        Ok(())
     }
 
-    fn process_dependency(&mut self, dep_version: common::Version) -> Result<()> {
-        let crate_version = dep_version;
+    /// Resolves the crate version's dependencies for the targets, skipping the targets
+    /// each dependency has already been resolved for with the same features.
+    /// `depth` is the number of dependency edges from the top-level crate to the crate version.
+    fn process_dependency(
+        &mut self,
+        crate_version: common::Version,
+        targets: TargetSet,
+        with_dev: bool,
+        depth: usize,
+    ) -> Result<()> {
+        if self.cancel.is_cancelled() {
+            return Err(Error::Cancelled);
+        }
         // Cache the name of the current crate for use in error messages.
         self.cur_crate_name = crate_version.name().to_string();
+        let activation = self.activation(&crate_version);
         let mut deps_to_analyze = Vec::new();
         for dependency in crate_version
             .dependencies()
             .iter()
-            .filter(|d| d.kind() == DependencyKind::Normal || d.kind() == DependencyKind::Build)
+            .filter(|d| match d.kind() {
+                DependencyKind::Normal | DependencyKind::Build => true,
+                DependencyKind::Dev => with_dev,
+            })
         {
-            let dep_version = match self.get_compatible_version(dependency)? {
+            if let Some(activation) = &activation {
+                if dependency.is_optional() && !activation.dependencies.contains(dependency.name())
+                {
+                    continue;
+                }
+            }
+            let dep_targets = targets & self.targets_building(dependency);
+            if dep_targets == 0 {
+                continue;
+            }
+            let registry = match self.dependency_registry(&crate_version, dependency) {
+                Ok(registry) => registry,
+                Err(e) if !self.deny_warnings => {
+                    warn!(
+                        target: warnings::MISSING_DEPENDENCY,
+                        crate_name = crate_version.name(),
+                        crate_version = crate_version.version(),
+                        dependency = dependency.name(),
+                        "{e}"
+                    );
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+            let dep_version = match self.get_compatible_version(dependency, registry)? {
                 Some(version) => version,
+                None if self.deny_warnings => {
+                    return Err(Error::MissingDependency {
+                        crate_name: crate_version.name().to_string(),
+                        crate_version: crate_version.version().to_string(),
+                        dependency_name: dependency.name().to_string(),
+                    });
+                }
                 None => {
                     warn!(
-                        "{} version {}: compatible version for {} dependency not found",
-                        crate_version.name(),
-                        crate_version.version(),
-                        dependency.name()
+                        target: warnings::MISSING_DEPENDENCY,
+                        crate_name = crate_version.name(),
+                        crate_version = crate_version.version(),
+                        dependency = dependency.name(),
+                        "compatible version for dependency not found"
                     );
                     continue;
                 }
             };
-            if self.dependencies.insert(dep_version.clone()) {
-                deps_to_analyze.push(dep_version);
+            let excluded = self
+                .exclusions
+                .iter()
+                .chain(&self.cur_exclusions)
+                .any(|spec| spec.matches(&dep_version));
+            if excluded {
+                debug!(
+                    dependency = dep_version.name(),
+                    dependency_version = dep_version.version(),
+                    required_by = crate_version.name(),
+                    "dependency excluded"
+                );
+                continue;
+            }
+            if let Some(max_depth) = self.max_depth {
+                if depth >= max_depth {
+                    self.beyond_max_depth
+                        .entry(dep_version)
+                        .or_default()
+                        .insert(crate_version.clone());
+                    continue;
+                }
+            }
+            self.dependents
+                .entry(dep_version.clone())
+                .or_default()
+                .insert(crate_version.clone());
+            let resolved_for = self
+                .resolved_for
+                .entry((dep_version.clone(), self.cur_context))
+                .or_default();
+            let mut new_targets = dep_targets & !*resolved_for;
+            *resolved_for |= new_targets;
+            let resolved_for = *resolved_for;
+            if let Some(activation) = &activation {
+                // Newly enabled features may enable more dependencies on every target.
+                if self.request_features(&dep_version, dependency, activation) {
+                    new_targets = resolved_for;
+                }
+            }
+            if self.max_depth.is_some() {
+                let dep_depth = self.depths.entry(dep_version.clone()).or_insert(usize::MAX);
+                if depth + 1 < *dep_depth {
+                    *dep_depth = depth + 1;
+                    // More of its dependencies are within the maximum depth by this path.
+                    new_targets = resolved_for;
+                }
+            }
+            if new_targets == 0 {
+                continue;
             }
+            let is_new = self.dependencies.insert(dep_version.clone());
+            if is_new {
+                self.found(&dep_version)?;
+            }
+            deps_to_analyze.push((dep_version, new_targets, is_new));
         }
 
-        for dep_version in deps_to_analyze {
-            println!(
-                "\tAnalyzing dependency {} version {}",
-                dep_version.name(),
-                dep_version.version()
-            );
-            self.process_dependency(dep_version)?;
+        for (dep_version, new_targets, is_new) in deps_to_analyze {
+            // Resolved at the fewest edges found, which may be fewer than by this crate.
+            let dep_depth = self.depths.get(&dep_version).copied().unwrap_or(depth + 1);
+            if is_new {
+                debug!(
+                    dependency = dep_version.name(),
+                    dependency_version = dep_version.version(),
+                    required_by = crate_version.name(),
+                    "dependency found"
+                );
+                self.progress.dependency_analyzed(&dep_version);
+            }
+            self.process_dependency(dep_version, new_targets, false, dep_depth)?;
         }
 
         Ok(())
     }
 
-    fn get_compatible_version(
+    /// Returns what the features requested of the crate version enable,
+    /// or `None` if features aren't being resolved.
+    fn activation(&self, crat: &Version) -> Option<Activation> {
+        if !self.resolves_features {
+            return None;
+        }
+        match self.requested.get(crat) {
+            Some(requested) => Some(activate(crat, requested)),
+            None => Some(Activation::default()),
+        }
+    }
+
+    /// Records the features the dependency declaration and the depending crate's enabled
+    /// features request of the dependency's version. Returns whether any weren't requested before.
+    fn request_features(
+        &mut self,
+        dep_version: &Version,
+        dependency: &crates_index::Dependency,
+        activation: &Activation,
+    ) -> bool {
+        let requested = self.requested.entry(dep_version.clone()).or_default();
+        let mut changed = false;
+        if dependency.has_default_features() && !requested.default {
+            requested.default = true;
+            changed = true;
+        }
+        let features = dependency.features().iter().chain(
+            activation
+                .dependency_features
+                .get(dependency.name())
+                .into_iter()
+                .flatten(),
+        );
+        for feature in features {
+            changed |= requested.features.insert(feature.clone());
+        }
+        changed
+    }
+
+    /// Lists the platforms resolved for. Without specific targets, every platform
+    /// is resolved for at once, as a single target.
+    fn init_platforms(&mut self) -> Result<()> {
+        let mut platforms = Vec::new();
+        if self.targets.is_empty() {
+            platforms.push(None);
+        }
+        let top_level_targets = self.top_level.values().flat_map(|o| o.targets.iter());
+        for target in self.targets.iter().chain(top_level_targets) {
+            if !platforms.contains(&Some(*target)) {
+                platforms.push(Some(*target));
+            }
+        }
+        if platforms.len() > MAX_TARGETS {
+            return Err(Error::TooManyTargets(platforms.len()));
+        }
+        self.platforms = platforms;
+        Ok(())
+    }
+
+    /// The platforms to resolve a top-level crate's dependencies for.
+    fn top_level_targets(&self, options: &TopLevelOptions) -> TargetSet {
+        if options.targets.is_empty() {
+            // The registry's targets come first.
+            return TargetSet::MAX >> (MAX_TARGETS - self.targets.len().max(1));
+        }
+        let mut targets = 0;
+        for (i, platform) in self.platforms.iter().enumerate() {
+            if matches!(platform, Some(target) if options.targets.contains(target)) {
+                targets |= 1 << i;
+            }
+        }
+        targets
+    }
+
+    /// Returns the targets that build the dependency: all of them unless it's platform-specific.
+    fn targets_building(&self, dependency: &crates_index::Dependency) -> TargetSet {
+        targets_building(dependency, &self.platforms, &self.cur_crate_name)
+    }
+
+    /// Returns the registry the crate version's dependency is on, as `Version::registry` gives it.
+    /// A dependency without a `registry` field is on the crate's own registry.
+    fn dependency_registry(
         &self,
+        crat: &Version,
         dependency: &crates_index::Dependency,
-    ) -> Result<Option<common::Version>> {
-        let version_req =
-            VersionReq::parse(dependency.requirement()).map_err(|e| Error::SemVerRequirement {
-                crate_name: self.cur_crate_name.clone(),
+    ) -> Result<Option<Arc<str>>> {
+        let url = match crat.dependency_registry(dependency) {
+            Some(url) => common::canonical_registry_url(url),
+            None => return Ok(crat.registry().map(Arc::from)),
+        };
+        if url == self.registry_url {
+            return Ok(None);
+        }
+        match self.secondary.get_key_value(url.as_str()) {
+            Some((url, _)) => Ok(Some(Arc::clone(url))),
+            None => Err(Error::UnknownRegistry {
+                crate_name: crat.name().to_string(),
                 dependency_name: dependency.name().to_string(),
-                error: e,
-            })?;
-        let crat = common::get_crate(self.index, dependency.crate_name())
-            .map_err(|e| Error::CrateNotFound(e))?;
-        for crate_version in crat.versions().iter().rev().filter(|c| !c.is_yanked()) {
-            let version = semver::Version::parse(crate_version.version()).map_err(|e| {
-                Error::SemVerVersion {
-                    crate_name: crat.name().to_string(),
-                    crate_version: crate_version.version().to_string(),
+                registry: url,
+            }),
+        }
+    }
+
+    fn get_compatible_version(
+        &mut self,
+        dependency: &crates_index::Dependency,
+        registry: Option<Arc<str>>,
+    ) -> Result<Option<common::Version>> {
+        let version_req = match self.overrides.get(dependency.crate_name()) {
+            Some(version_req) if registry.is_none() => {
+                debug!(
+                    dependency = dependency.crate_name(),
+                    requirement = dependency.requirement(),
+                    %version_req,
+                    "dependency requirement overridden"
+                );
+                version_req.clone()
+            }
+            _ => VersionReq::parse(dependency.requirement()).map_err(|e| {
+                Error::SemVerRequirement {
+                    crate_name: self.cur_crate_name.clone(),
+                    dependency_name: dependency.name().to_string(),
                     error: e,
                 }
-            })?;
-            if version_req.matches(&version) {
-                return Ok(Some(common::Version(crate_version.clone())));
+            })?,
+        };
+        for (version, crate_version) in self.get_versions(registry, dependency.crate_name())? {
+            let version = match version {
+                Some(version) => version,
+                None => {
+                    // Parse again for the error; semver errors can't be cached.
+                    let error = semver::Version::parse(crate_version.version()).unwrap_err();
+                    return Err(Error::SemVerVersion {
+                        crate_name: crate_version.name().to_string(),
+                        crate_version: crate_version.version().to_string(),
+                        error,
+                    });
+                }
+            };
+            if version_req.matches(version) {
+                return Ok(Some(crate_version.clone()));
             }
         }
         Ok(None)
     }
+
+    /// Returns the crate's versions in the registry, `None` standing for the one resolved
+    /// against, looking it up in its index the first time only.
+    fn get_versions(
+        &mut self,
+        registry: Option<Arc<str>>,
+        crate_name: &str,
+    ) -> Result<&CachedVersions> {
+        let key = (registry, crate_name.to_string());
+        if !self.crates.contains_key(&key) {
+            let index = match &key.0 {
+                Some(url) => self.secondary[url],
+                None => self.index,
+            };
+            let crat = common::get_crate(index, crate_name).map_err(Error::CrateNotFound)?;
+            let versions = crat
+                .versions()
+                .iter()
+                .rev()
+                .filter(|c| !c.is_yanked())
+                .map(|c| {
+                    (
+                        semver::Version::parse(c.version()).ok(),
+                        c.clone().with_registry(key.0.clone()),
+                    )
+                })
+                .collect();
+            self.crates.insert(key.clone(), versions);
+        }
+        Ok(&self.crates[&key])
+    }
+}
+
+/// Returns what the features requested of the crate version enable: the optional dependencies
+/// they enable and the features they enable on each dependency. Weak dependency features,
+/// e.g. `serde?/std`, only apply to dependencies something else enables.
+pub(crate) fn activate(crat: &Version, requested: &RequestedFeatures) -> Activation {
+    let mut activation = Activation::default();
+    let features = crat.features();
+    let is_optional_dependency = |name: &str| {
+        crat.dependencies()
+            .iter()
+            .any(|d| d.is_optional() && d.name() == name)
+    };
+    let mut enabled = HashSet::new();
+    let mut to_enable = Vec::from_iter(requested.features.iter().cloned());
+    if requested.default {
+        to_enable.push("default".to_string());
+    }
+    let mut weak = Vec::new();
+    while let Some(feature) = to_enable.pop() {
+        if let Some(name) = feature.strip_prefix("dep:") {
+            activation.dependencies.insert(name.to_string());
+        } else if let Some((name, dep_feature)) = feature.split_once('/') {
+            match name.strip_suffix('?') {
+                // Only enabled on the dependency if something else enables it.
+                Some(name) => weak.push((name.to_string(), dep_feature.to_string())),
+                None => {
+                    activation.dependencies.insert(name.to_string());
+                    activation
+                        .dependency_features
+                        .entry(name.to_string())
+                        .or_default()
+                        .push(dep_feature.to_string());
+                }
+            }
+        } else if enabled.insert(feature.clone()) {
+            match features.get(&feature) {
+                Some(enables) => to_enable.extend(enables.iter().cloned()),
+                // An optional dependency's implicit feature.
+                None if is_optional_dependency(&feature) => {
+                    activation.dependencies.insert(feature);
+                }
+                None => (),
+            }
+        }
+    }
+    for (name, dep_feature) in weak {
+        if activation.dependencies.contains(&name) || !is_optional_dependency(&name) {
+            activation
+                .dependency_features
+                .entry(name)
+                .or_default()
+                .push(dep_feature);
+        }
+    }
+    activation
+}
+
+/// Returns the platforms that build the dependency of the crate, as a set of their positions:
+/// all of them unless it's platform-specific. A `None` platform stands for any platform.
+pub(crate) fn targets_building(
+    dependency: &crates_index::Dependency,
+    platforms: &[Option<&'static TargetInfo>],
+    crate_name: &str,
+) -> TargetSet {
+    let all_targets = TargetSet::MAX >> (MAX_TARGETS - platforms.len());
+    let platform = match dependency.target() {
+        Some(platform) if platforms.iter().any(Option::is_some) => platform,
+        _ => return all_targets,
+    };
+    // The platform is either a cfg() expression or a target triple.
+    let expression = if platform.starts_with("cfg(") {
+        match Expression::parse(platform) {
+            Ok(expression) => Some(expression),
+            Err(e) => {
+                warn!(
+                    target: warnings::PLATFORM,
+                    crate_name,
+                    dependency = dependency.name(),
+                    platform,
+                    "failed to parse the dependency's platform, keeping it: {e}"
+                );
+                return all_targets;
+            }
+        }
+    } else {
+        None
+    };
+    let mut targets = 0;
+    for (i, resolved_platform) in platforms.iter().enumerate() {
+        let target = match resolved_platform {
+            Some(target) => target,
+            None => {
+                targets |= 1 << i;
+                continue;
+            }
+        };
+        let builds = match &expression {
+            // Other predicates, e.g. features, can't be evaluated here. They are left
+            // unknown, so the dependency is kept unless the target alone rules it out.
+            Some(expression) => {
+                expression.eval(|predicate| match predicate {
+                    Predicate::Target(predicate) => Some(predicate.matches(*target)),
+                    _ => None,
+                }) != Some(false)
+            }
+            None => target.triple.as_str() == platform,
+        };
+        if builds {
+            targets |= 1 << i;
+        }
+    }
+    targets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::progress::NoProgress;
+    use serde_json::json;
+
+    fn entry(
+        name: &str,
+        deps: serde_json::Value,
+        features: serde_json::Value,
+    ) -> serde_json::Value {
+        json!({
+            "name": name,
+            "vers": "1.0.0",
+            "deps": deps,
+            "cksum": "0".repeat(64),
+            "features": features,
+            "yanked": false,
+        })
+    }
+
+    fn dep(name: &str, optional: bool, target: Option<&str>) -> serde_json::Value {
+        json!({
+            "name": name,
+            "req": "^1",
+            "features": [],
+            "optional": optional,
+            "default_features": true,
+            "target": target,
+            "kind": "normal",
+        })
+    }
+
+    /// Resolves the dependencies of the first entry against an index of the entries, returning
+    /// their names.
+    fn resolve(
+        name: &str,
+        entries: &[serde_json::Value],
+        configure: impl FnOnce(SrcRegistry) -> SrcRegistry,
+    ) -> Result<Vec<String>> {
+        let dir_path =
+            std::env::temp_dir().join(format!("micrio-test-{}-{name}", std::process::id()));
+        let index = Index::from_entries(&dir_path, entries);
+        let top_level = Version::from_json(&entries[0].to_string()).unwrap();
+        let cancel = CancellationToken::new();
+        let mut src_registry = configure(SrcRegistry::new(&index, &NoProgress, &cancel));
+        let result = src_registry.get_dependencies(&HashSet::from([top_level]));
+        std::fs::remove_dir_all(&dir_path).unwrap();
+        let mut names = Vec::from_iter(result?.iter().map(|v| v.name().to_string()));
+        names.sort();
+        Ok(names)
+    }
+
+    fn target(triple: &str) -> &'static TargetInfo {
+        cfg_expr::targets::get_builtin_target_by_triple(triple).unwrap()
+    }
+
+    #[test]
+    fn platform_specific_dependencies_follow_the_targets() {
+        let entries = [
+            entry(
+                "app",
+                json!([
+                    dep("mio", false, None),
+                    dep("winapi", false, Some("cfg(windows)")),
+                    dep("wasm-bindgen", false, Some("wasm32-unknown-unknown")),
+                ]),
+                json!({}),
+            ),
+            entry(
+                "mio",
+                json!([
+                    dep("libc", false, Some("cfg(unix)")),
+                    dep("windows-sys", false, Some("cfg(windows)")),
+                ]),
+                json!({}),
+            ),
+            entry("winapi", json!([]), json!({})),
+            entry("wasm-bindgen", json!([]), json!({})),
+            entry("libc", json!([]), json!({})),
+            entry("windows-sys", json!([]), json!({})),
+        ];
+        let linux = target("x86_64-unknown-linux-gnu");
+        let windows = target("x86_64-pc-windows-msvc");
+        let wasm = target("wasm32-unknown-unknown");
+
+        assert_eq!(
+            resolve("targets-any", &entries, |r| r).unwrap(),
+            ["libc", "mio", "wasm-bindgen", "winapi", "windows-sys"]
+        );
+        assert_eq!(
+            resolve("targets-linux", &entries, |r| r.targets(vec![linux])).unwrap(),
+            ["libc", "mio"]
+        );
+        assert_eq!(
+            resolve("targets-windows-wasm", &entries, |r| r
+                .targets(vec![windows, wasm]))
+            .unwrap(),
+            ["mio", "wasm-bindgen", "winapi", "windows-sys"]
+        );
+        let top_level = Version::from_json(&entries[0].to_string()).unwrap();
+        let options = TopLevelOptions {
+            targets: vec![windows],
+            ..Default::default()
+        };
+        assert_eq!(
+            resolve("targets-top-level", &entries, |r| r
+                .targets(vec![linux])
+                .top_level_options(HashMap::from([(top_level, options)])))
+            .unwrap(),
+            ["mio", "winapi", "windows-sys"]
+        );
+    }
+
+    #[test]
+    fn optional_dependencies_follow_the_features() {
+        let entries = [
+            entry(
+                "app",
+                json!([dep("serde", true, None), dep("rayon", true, None)]),
+                json!({
+                    "default": ["parallel"],
+                    "parallel": ["dep:rayon"],
+                    "derive": ["serde/derive"],
+                }),
+            ),
+            entry(
+                "serde",
+                json!([dep("serde_derive", true, None)]),
+                json!({ "derive": ["dep:serde_derive"] }),
+            ),
+            entry("serde_derive", json!([]), json!({})),
+            entry("rayon", json!([]), json!({})),
+        ];
+
+        assert_eq!(
+            resolve("features-all", &entries, |r| r).unwrap(),
+            ["rayon", "serde", "serde_derive"]
+        );
+        assert_eq!(
+            resolve("features-default", &entries, |r| r
+                .features(Some(FeatureSpec::default())))
+            .unwrap(),
+            ["rayon"]
+        );
+        let derive = FeatureSpec {
+            no_default_features: true,
+            features: vec!["derive".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            resolve("features-derive", &entries, |r| r.features(Some(derive))).unwrap(),
+            ["serde", "serde_derive"]
+        );
+    }
+
+    #[test]
+    fn at_most_max_targets_are_resolved_for() {
+        let entries = [entry("app", json!([]), json!({}))];
+        let targets = || cfg_expr::targets::ALL_BUILTINS.iter();
+
+        assert!(resolve("max-targets", &entries, |r| r
+            .targets(Vec::from_iter(targets().take(MAX_TARGETS))))
+        .is_ok());
+        let result = resolve("too-many-targets", &entries, |r| {
+            r.targets(Vec::from_iter(targets().take(MAX_TARGETS + 1)))
+        });
+        match result {
+            Err(e @ Error::TooManyTargets(_)) => {
+                assert_eq!(e.to_string(), "65 targets given, at most 64 are supported")
+            }
+            _ => panic!("expected too many targets: {:?}", result),
+        }
+    }
 }
@@ -0,0 +1,349 @@
+//! An SQLite database in the mirror's state directory recording the crate versions in the
+//! mirror, why each is there, and the digests of the mirror's files, so operations on very
+//! large mirrors query it instead of re-scanning tens of thousands of index lines and files.
+
+use crate::common::{self, Version};
+use crate::dst_registry::STATE_DIR;
+use crate::provenance::Provenance;
+use rusqlite::{params, Connection};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::{self, Display};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+pub enum Error {
+    CreateStateDir(io::Error),
+    Remove(io::Error),
+    Open {
+        path: PathBuf,
+        error: rusqlite::Error,
+    },
+    Query(rusqlite::Error),
+    SerializeEntry(common::Error),
+    ParseEntry {
+        crate_name: String,
+        crate_version: String,
+        error: serde_json::Error,
+    },
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::CreateStateDir(e) => {
+                write!(
+                    f,
+                    "error opening the state database: failed to create the {STATE_DIR} directory: {e}"
+                )
+            }
+            Error::Remove(e) => write!(f, "failed to remove the outdated state database: {e}"),
+            Error::Open { path, error } => {
+                write!(
+                    f,
+                    "error opening the state database {}: {error}",
+                    path.to_string_lossy()
+                )
+            }
+            Error::Query(e) => write!(f, "error querying the state database: {e}"),
+            Error::SerializeEntry(e) => {
+                write!(f, "error recording the mirror in the state database: {e}")
+            }
+            Error::ParseEntry {
+                crate_name,
+                crate_version,
+                error,
+            } => {
+                write!(f, "error querying the state database: invalid index entry for {crate_name} version {crate_version}: {error}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::CreateStateDir(e) => Some(e),
+            Error::Remove(e) => Some(e),
+            Error::Open { error, .. } => Some(error),
+            Error::Query(e) => Some(e),
+            Error::SerializeEntry(e) => Some(e),
+            Error::ParseEntry { error, .. } => Some(error),
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// The file in the mirror's state directory holding the database.
+pub const STATE_DB_FILE: &str = "state.sqlite3";
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS crates (
+    name TEXT NOT NULL,
+    version TEXT NOT NULL,
+    checksum TEXT NOT NULL,
+    -- The crate version's line in the index of the mirror.
+    entry TEXT NOT NULL,
+    PRIMARY KEY (name, version)
+);
+CREATE TABLE IF NOT EXISTS sources (
+    name TEXT NOT NULL,
+    version TEXT NOT NULL,
+    source TEXT NOT NULL,
+    PRIMARY KEY (name, version, source)
+);
+CREATE TABLE IF NOT EXISTS dependents (
+    name TEXT NOT NULL,
+    version TEXT NOT NULL,
+    dependent_name TEXT NOT NULL,
+    dependent_version TEXT NOT NULL,
+    PRIMARY KEY (name, version, dependent_name, dependent_version)
+);
+CREATE TABLE IF NOT EXISTS files (
+    path TEXT PRIMARY KEY NOT NULL,
+    sha256 TEXT NOT NULL
+);
+";
+
+/// A crate version in the mirror, as recorded in the state database.
+pub struct CrateRecord {
+    pub name: String,
+    pub version: String,
+    /// The SHA256 digest of the .crate file, as a lowercase hex string.
+    pub checksum: String,
+    /// The selection sources the crate version came from, sorted, if it's top-level.
+    pub sources: Vec<String>,
+    /// The mirrored crate versions directly depending on it, as `NAME@VERSION`, sorted.
+    pub dependents: Vec<String>,
+}
+
+/// The state database of a mirror.
+///
+/// Runs rewriting the index of the mirror delete the database first and, if configured to
+/// keep one, record the crate versions once the index is written, so the database never lists
+/// other crate versions than the index.
+pub struct StateDb {
+    conn: Connection,
+}
+
+impl StateDb {
+    /// Opens the state database of the mirror, creating it if needed.
+    pub fn open<P: AsRef<Path>>(mirror_dir_path: P) -> Result<Self> {
+        let state_dir_path = mirror_dir_path.as_ref().join(STATE_DIR);
+        fs::create_dir_all(&state_dir_path).map_err(Error::CreateStateDir)?;
+        let path = state_dir_path.join(STATE_DB_FILE);
+        let open_error = |e| Error::Open {
+            path: path.clone(),
+            error: e,
+        };
+        let conn = Connection::open(&path).map_err(open_error)?;
+        conn.execute_batch(SCHEMA).map_err(open_error)?;
+        Ok(StateDb { conn })
+    }
+
+    /// Opens the state database of the mirror, if it has one.
+    pub fn open_existing<P: AsRef<Path>>(mirror_dir_path: P) -> Result<Option<Self>> {
+        let mirror_dir_path = mirror_dir_path.as_ref();
+        if !state_db_path(mirror_dir_path).is_file() {
+            return Ok(None);
+        }
+        Ok(Some(StateDb::open(mirror_dir_path)?))
+    }
+
+    /// Deletes the state database of the mirror, if it has one.
+    pub fn remove<P: AsRef<Path>>(mirror_dir_path: P) -> Result<()> {
+        match fs::remove_file(state_db_path(mirror_dir_path.as_ref())) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(Error::Remove(e)),
+        }
+    }
+
+    /// Replaces the recorded crate versions with those in the mirror, along with why each is
+    /// there.
+    pub fn record_crates(
+        &mut self,
+        crates: &HashSet<Version>,
+        provenance: &Provenance,
+    ) -> Result<()> {
+        let tx = self.conn.transaction().map_err(Error::Query)?;
+        tx.execute_batch("DELETE FROM crates; DELETE FROM sources; DELETE FROM dependents;")
+            .map_err(Error::Query)?;
+        {
+            let mut insert_crate = tx
+                .prepare(
+                    "INSERT INTO crates (name, version, checksum, entry) VALUES (?1, ?2, ?3, ?4)",
+                )
+                .map_err(Error::Query)?;
+            let mut insert_source = tx
+                .prepare("INSERT INTO sources (name, version, source) VALUES (?1, ?2, ?3)")
+                .map_err(Error::Query)?;
+            let mut insert_dependent = tx
+                .prepare(
+                    "INSERT OR IGNORE INTO dependents (name, version, dependent_name, dependent_version) VALUES (?1, ?2, ?3, ?4)",
+                )
+                .map_err(Error::Query)?;
+            for crat in crates {
+                let (name, version) = (crat.name(), crat.version());
+                let entry = crat.to_json().map_err(Error::SerializeEntry)?;
+                insert_crate
+                    .execute(params![name, version, crat.checksum(), entry])
+                    .map_err(Error::Query)?;
+                for source in provenance.sources(crat) {
+                    insert_source
+                        .execute(params![name, version, source])
+                        .map_err(Error::Query)?;
+                }
+                for dependent in provenance.dependents_of(crat) {
+                    insert_dependent
+                        .execute(params![
+                            name,
+                            version,
+                            dependent.name(),
+                            dependent.version()
+                        ])
+                        .map_err(Error::Query)?;
+                }
+            }
+        }
+        tx.commit().map_err(Error::Query)
+    }
+
+    /// Returns the crate versions in the mirror, as the index of the mirror lists them.
+    pub fn crates(&self) -> Result<HashSet<Version>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT name, version, entry FROM crates")
+            .map_err(Error::Query)?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                ))
+            })
+            .map_err(Error::Query)?;
+        let mut crates = HashSet::new();
+        for row in rows {
+            let (name, version, entry) = row.map_err(Error::Query)?;
+            let entry = Version::from_json(&entry).map_err(|e| Error::ParseEntry {
+                crate_name: name,
+                crate_version: version,
+                error: e,
+            })?;
+            crates.insert(entry);
+        }
+        Ok(crates)
+    }
+
+    /// Returns the recorded crate versions with why each is in the mirror, sorted by name and
+    /// version.
+    pub fn list_crates(&self) -> Result<Vec<CrateRecord>> {
+        let mut records = BTreeMap::new();
+        let mut stmt = self
+            .conn
+            .prepare("SELECT name, version, checksum FROM crates")
+            .map_err(Error::Query)?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok(CrateRecord {
+                    name: row.get(0)?,
+                    version: row.get(1)?,
+                    checksum: row.get(2)?,
+                    sources: Vec::new(),
+                    dependents: Vec::new(),
+                })
+            })
+            .map_err(Error::Query)?;
+        for row in rows {
+            let record = row.map_err(Error::Query)?;
+            let key = (record.name.clone(), record.version.clone());
+            records.insert(key, record);
+        }
+
+        let mut stmt = self
+            .conn
+            .prepare("SELECT name, version, source FROM sources ORDER BY source")
+            .map_err(Error::Query)?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .map_err(Error::Query)?;
+        for row in rows {
+            let (name, version, source): (String, String, String) = row.map_err(Error::Query)?;
+            if let Some(record) = records.get_mut(&(name, version)) {
+                record.sources.push(source);
+            }
+        }
+
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT name, version, dependent_name || '@' || dependent_version FROM dependents ORDER BY 3",
+            )
+            .map_err(Error::Query)?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .map_err(Error::Query)?;
+        for row in rows {
+            let (name, version, dependent): (String, String, String) = row.map_err(Error::Query)?;
+            if let Some(record) = records.get_mut(&(name, version)) {
+                record.dependents.push(dependent);
+            }
+        }
+        Ok(Vec::from_iter(records.into_values()))
+    }
+
+    /// Returns the name and version of each recorded crate version with the SHA256 digest of
+    /// its .crate file.
+    pub fn crate_checksums(&self) -> Result<Vec<(String, String, String)>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT name, version, checksum FROM crates")
+            .map_err(Error::Query)?;
+        let rows = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .map_err(Error::Query)?;
+        rows.collect::<rusqlite::Result<_>>().map_err(Error::Query)
+    }
+
+    /// Replaces the recorded digests of the mirror's files, given as paths relative to the
+    /// mirror directory with their SHA256 digests.
+    pub fn record_file_digests(&mut self, digests: &[(PathBuf, String)]) -> Result<()> {
+        let tx = self.conn.transaction().map_err(Error::Query)?;
+        tx.execute("DELETE FROM files", []).map_err(Error::Query)?;
+        {
+            let mut insert = tx
+                .prepare("INSERT INTO files (path, sha256) VALUES (?1, ?2)")
+                .map_err(Error::Query)?;
+            for (path, digest) in digests {
+                let path = path.to_string_lossy().replace('\\', "/");
+                insert
+                    .execute(params![path, digest])
+                    .map_err(Error::Query)?;
+            }
+        }
+        tx.commit().map_err(Error::Query)
+    }
+
+    /// Returns the recorded digests of the mirror's files, sorted by path, or an empty list
+    /// if they weren't recorded.
+    pub fn file_digests(&self) -> Result<Vec<(PathBuf, String)>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT path, sha256 FROM files ORDER BY path")
+            .map_err(Error::Query)?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((PathBuf::from(row.get::<_, String>(0)?), row.get(1)?))
+            })
+            .map_err(Error::Query)?;
+        rows.collect::<rusqlite::Result<_>>().map_err(Error::Query)
+    }
+}
+
+fn state_db_path(mirror_dir_path: &Path) -> PathBuf {
+    mirror_dir_path.join(STATE_DIR).join(STATE_DB_FILE)
+}
@@ -0,0 +1,285 @@
+//! The live status of mirror runs and scheduled jobs, kept in the mirror's state directory
+//! so dashboards can watch a long-lived daemon from another process.
+
+use crate::advisory::Advisory;
+use crate::common::Version;
+use crate::dst_registry::STATE_DIR;
+use crate::progress::{Phase, ProgressObserver};
+use crate::provenance::GraphStats;
+use crate::run_report::FailureRecord;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::debug;
+
+/// The file in the mirror's state directory with the status of the latest run.
+pub const STATUS_FILE: &str = "status.json";
+
+/// The file in the mirror's state directory with the status of the scheduler's jobs.
+pub const SCHEDULE_FILE: &str = "schedule.json";
+
+/// The maximum number of failures kept in the run status.
+const MAX_RECENT_FAILURES: usize = 20;
+
+/// The status of the latest mirror run, in progress or not.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RunStatus {
+    /// The ID of the process running it.
+    pub pid: u32,
+    /// When the run started, in seconds since the Unix epoch.
+    pub started_at: u64,
+    /// When the status was last written, in seconds since the Unix epoch.
+    pub updated_at: u64,
+    pub running: bool,
+    /// `None` while the run is in progress.
+    pub succeeded: Option<bool>,
+    /// The description of the phase in progress, e.g. `downloading crates`.
+    pub phase: Option<String>,
+    /// How far the phase in progress got. `total` is 0 when it isn't known.
+    pub completed: usize,
+    pub total: usize,
+    pub bytes_downloaded: u64,
+    /// The most recent failures, oldest first, up to 20.
+    pub recent_failures: Vec<FailureRecord>,
+}
+
+/// The status of the jobs of a `MirrorScheduler`.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ScheduleStatus {
+    /// The ID of the process running the scheduler.
+    pub pid: u32,
+    pub jobs: Vec<JobStatus>,
+}
+
+/// Times are in seconds since the Unix epoch.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct JobStatus {
+    pub name: String,
+    pub interval_secs: u64,
+    pub running: bool,
+    pub last_started: Option<u64>,
+    pub last_finished: Option<u64>,
+    /// Why the last run of the job failed, if it did.
+    pub last_error: Option<String>,
+    /// When the job is due next. `None` while it runs.
+    pub next_run: Option<u64>,
+}
+
+/// Reads the status of the latest run of the mirror, if any.
+pub fn read_run_status<P: AsRef<Path>>(mirror_dir_path: P) -> io::Result<Option<RunStatus>> {
+    read_state_file(&mirror_dir_path.as_ref().join(STATE_DIR).join(STATUS_FILE))
+}
+
+/// Reads the status of the scheduler maintaining the mirror, if any.
+pub fn read_schedule_status<P: AsRef<Path>>(
+    mirror_dir_path: P,
+) -> io::Result<Option<ScheduleStatus>> {
+    read_state_file(&mirror_dir_path.as_ref().join(STATE_DIR).join(SCHEDULE_FILE))
+}
+
+fn read_state_file<T: DeserializeOwned>(path: &Path) -> io::Result<Option<T>> {
+    let contents = match fs::read(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    Ok(Some(serde_json::from_slice(&contents)?))
+}
+
+/// Writes the file in the mirror's state directory, through a temporary file renamed over it
+/// so readers never see it half-written. Nothing is written before the mirror directory
+/// is created.
+pub(crate) fn write_state_file<T: Serialize>(
+    mirror_dir_path: &Path,
+    file_name: &str,
+    contents: &T,
+) -> io::Result<()> {
+    if !mirror_dir_path.is_dir() {
+        return Ok(());
+    }
+    let state_dir_path = mirror_dir_path.join(STATE_DIR);
+    fs::create_dir_all(&state_dir_path)?;
+    let tmp_path = state_dir_path.join(format!("{file_name}.tmp"));
+    fs::write(&tmp_path, serde_json::to_vec_pretty(contents)?)?;
+    fs::rename(&tmp_path, state_dir_path.join(file_name))
+}
+
+pub(crate) fn unix_time(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Keeps the status file of the mirror up to date with the progress events of a run,
+/// passing them on to another observer.
+pub(crate) struct StatusWriter {
+    inner: Arc<dyn ProgressObserver>,
+    mirror_dir_path: PathBuf,
+    /// The status, and when it was last written.
+    status: Mutex<(RunStatus, Option<Instant>)>,
+}
+
+impl StatusWriter {
+    pub fn new(inner: Arc<dyn ProgressObserver>, mirror_dir_path: PathBuf) -> Self {
+        let now = unix_time(SystemTime::now());
+        StatusWriter {
+            inner,
+            mirror_dir_path,
+            status: Mutex::new((
+                RunStatus {
+                    pid: std::process::id(),
+                    started_at: now,
+                    updated_at: now,
+                    running: true,
+                    succeeded: None,
+                    phase: None,
+                    completed: 0,
+                    total: 0,
+                    bytes_downloaded: 0,
+                    recent_failures: Vec::new(),
+                },
+                None,
+            )),
+        }
+    }
+
+    fn status(&self) -> MutexGuard<'_, (RunStatus, Option<Instant>)> {
+        // The status is only counters and lists, so it's still usable if a thread panicked.
+        self.status.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Updates the status and writes it. Updates that aren't `significant`, e.g. another crate
+    /// downloaded, are only written if the status wasn't written in the last second.
+    fn update(&self, significant: bool, f: impl FnOnce(&mut RunStatus)) {
+        const MIN_WRITE_INTERVAL: Duration = Duration::from_secs(1);
+        let mut guard = self.status();
+        let (status, last_written) = &mut *guard;
+        f(status);
+        let recently_written = match last_written {
+            Some(t) => t.elapsed() < MIN_WRITE_INTERVAL,
+            None => false,
+        };
+        if !significant && recently_written {
+            return;
+        }
+        status.updated_at = unix_time(SystemTime::now());
+        *last_written = Some(Instant::now());
+        // The status is informational, so failing to write it doesn't fail the run.
+        if let Err(e) = write_state_file(&self.mirror_dir_path, STATUS_FILE, status) {
+            debug!("failed to write the run status: {e}");
+        }
+    }
+}
+
+impl ProgressObserver for StatusWriter {
+    fn phase_started(&self, phase: Phase) {
+        self.update(true, |status| {
+            status.phase = Some(phase.to_string());
+            status.completed = 0;
+            status.total = 0;
+        });
+        self.inner.phase_started(phase);
+    }
+
+    fn phase_finished(&self, phase: Phase) {
+        self.inner.phase_finished(phase);
+    }
+
+    fn phase_crates(&self, phase: Phase, num_crates: usize) {
+        self.inner.phase_crates(phase, num_crates);
+    }
+
+    fn fetching_most_downloaded_page(&self, page: u64, num_pages: u64) {
+        self.update(false, |status| {
+            status.completed = page as usize;
+            status.total = num_pages as usize;
+        });
+        self.inner.fetching_most_downloaded_page(page, num_pages);
+    }
+
+    fn resolution_started(&self, num_top_level: usize) {
+        self.inner.resolution_started(num_top_level);
+    }
+
+    fn top_level_analyzed(&self, crat: &Version, completed: usize, total: usize) {
+        self.update(false, |status| {
+            status.completed = completed;
+            status.total = total;
+        });
+        self.inner.top_level_analyzed(crat, completed, total);
+    }
+
+    fn dependency_analyzed(&self, crat: &Version) {
+        self.inner.dependency_analyzed(crat);
+    }
+
+    fn resolution_finished(&self, num_dependencies: usize) {
+        self.inner.resolution_finished(num_dependencies);
+    }
+
+    fn graph_analyzed(&self, stats: &GraphStats) {
+        self.inner.graph_analyzed(stats);
+    }
+
+    fn advisory_found(&self, crat: &Version, advisory: &Advisory) {
+        self.inner.advisory_found(crat, advisory);
+    }
+
+    fn crate_downloaded(&self, crat: &Version, completed: usize, total: usize) {
+        self.update(false, |status| {
+            status.completed = completed;
+            status.total = total;
+        });
+        self.inner.crate_downloaded(crat, completed, total);
+    }
+
+    fn crate_stored(&self, crat: &Version, size: u64) {
+        self.update(false, |status| status.bytes_downloaded += size);
+        self.inner.crate_stored(crat, size);
+    }
+
+    fn crate_published(&self, crat: &Version, completed: usize, total: usize) {
+        self.update(false, |status| {
+            status.completed = completed;
+            status.total = total;
+        });
+        self.inner.crate_published(crat, completed, total);
+    }
+
+    fn failure(
+        &self,
+        phase: Phase,
+        crat: Option<&Version>,
+        error: &(dyn std::error::Error + 'static),
+    ) {
+        self.update(true, |status| {
+            if status.recent_failures.len() == MAX_RECENT_FAILURES {
+                status.recent_failures.remove(0);
+            }
+            status.recent_failures.push(FailureRecord {
+                phase: phase.as_str().to_string(),
+                crate_name: crat.map(|c| c.name().to_string()),
+                crate_version: crat.map(|c| c.version().to_string()),
+                error: error.to_string(),
+            });
+        });
+        self.inner.failure(phase, crat, error);
+    }
+
+    fn info(&self, msg: &str) {
+        self.inner.info(msg);
+    }
+
+    fn run_finished(&self, succeeded: bool) {
+        self.update(true, |status| {
+            status.running = false;
+            status.succeeded = Some(succeeded);
+            status.phase = None;
+        });
+        self.inner.run_finished(succeeded);
+    }
+}
@@ -0,0 +1,90 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use url::Url;
+
+/// Stores the registry's .crate files.
+///
+/// Objects are addressed by keys: `/`-separated paths relative to the root of the registry,
+/// e.g. `serde/1.0.188/download`.
+pub trait StorageBackend: Send + Sync {
+    /// Writes the object, replacing it if it already exists.
+    fn write(&self, key: &str, contents: &[u8]) -> io::Result<()>;
+
+    fn read(&self, key: &str) -> io::Result<Vec<u8>>;
+
+    /// Stores the object as a hard link to the local file, replacing it if it already exists.
+    /// Backends that can't link fail with `io::ErrorKind::Unsupported`, and callers then write
+    /// the contents instead.
+    fn link(&self, _key: &str, _path: &Path) -> io::Result<()> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    /// Returns the base URL Cargo should download .crate files from,
+    /// written to the `dl` field of the index's config.json.
+    fn dl_url(&self) -> io::Result<Url>;
+}
+
+/// Stores objects as files under a directory on the local file system.
+pub struct LocalStorage {
+    root: PathBuf,
+}
+
+impl LocalStorage {
+    pub fn new<P: Into<PathBuf>>(root: P) -> Self {
+        LocalStorage { root: root.into() }
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        // Joined component by component so the separator is right on every platform.
+        key.split('/')
+            .fold(self.root.clone(), |path, c| path.join(c))
+    }
+}
+
+impl StorageBackend for LocalStorage {
+    fn write(&self, key: &str, contents: &[u8]) -> io::Result<()> {
+        let path = self.path(key);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // Writing in place would change every hard link to the file, e.g. in another mirror.
+        remove_if_exists(&path)?;
+        fs::write(path, contents)
+    }
+
+    fn read(&self, key: &str) -> io::Result<Vec<u8>> {
+        fs::read(self.path(key))
+    }
+
+    /// Fails if the file is on another file system, which hard links can't span.
+    fn link(&self, key: &str, path: &Path) -> io::Result<()> {
+        let link_path = self.path(key);
+        if let Some(parent) = link_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        remove_if_exists(&link_path)?;
+        fs::hard_link(path, link_path)
+    }
+
+    /// Fails if the root isn't an absolute path, which a file URL requires.
+    fn dl_url(&self) -> io::Result<Url> {
+        Url::from_file_path(&self.root).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{} can't be converted to a file URL; an absolute path is required",
+                    self.root.display()
+                ),
+            )
+        })
+    }
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
@@ -1,10 +1,24 @@
 use crate::common::{self, Version};
+use crate::dst_registry::INDEX_DIR;
+use crate::endpoints::CRATES_IO_API;
+use crate::index::Index;
+use crate::progress::ProgressObserver;
+use crate::rate_limit::RateLimiter;
+use crate::selection::{self, CategorySpec, CrateSpec, KeywordSpec, ListFormat, SearchSpec};
+use crate::vendor;
+use crate::warnings;
 use crates_io_api::{CratesQuery, Sort, SyncClient};
-use log::{warn, trace};
+use semver::{Op, VersionReq};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
 use std::fmt::{self, Display};
 use std::fs::File;
-use std::io::{BufRead, BufReader};
-use std::path::Path;
+use std::io::{self, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::{trace, warn};
+use url::Url;
+use walkdir::WalkDir;
 
 #[derive(Debug)]
 pub enum Error {
@@ -12,6 +26,42 @@ pub enum Error {
     QueryMostDownloadedCrates(crates_io_api::Error),
     MostDownloadedCrateNotFound(common::Error),
     FromFile(Box<dyn std::error::Error + Send + Sync + 'static>),
+    CrateNotFound(common::Error),
+    NoMatchingVersion {
+        crate_name: String,
+        spec: String,
+    },
+    NoVersions {
+        crate_name: String,
+        /// Describes the crate list, e.g. `the crates.txt file` or `standard input`.
+        list: String,
+    },
+    FromLockfile(Box<dyn std::error::Error + Send + Sync + 'static>),
+    FromVendorDir(Box<dyn std::error::Error + Send + Sync + 'static>),
+    FromMirror {
+        mirror: String,
+        error: Box<dyn std::error::Error + Send + Sync + 'static>,
+    },
+    QueryCategory {
+        category: String,
+        error: crates_io_api::Error,
+    },
+    QueryKeyword {
+        keyword: String,
+        error: Box<dyn std::error::Error + Send + Sync + 'static>,
+    },
+    QueryRecentlyUpdated(Box<dyn std::error::Error + Send + Sync + 'static>),
+    QueryOwner {
+        owner: String,
+        error: Box<dyn std::error::Error + Send + Sync + 'static>,
+    },
+    Search {
+        query: String,
+        error: Box<dyn std::error::Error + Send + Sync + 'static>,
+    },
+    GitDependencies {
+        num_packages: usize,
+    },
 }
 
 impl Display for Error {
@@ -29,6 +79,54 @@ impl Display for Error {
             Error::FromFile(e) => {
                 write!(f, "failed to get crates from the file: {e}")
             }
+            Error::CrateNotFound(e) => {
+                write!(f, "failed to get requested crate: {e}")
+            }
+            Error::NoMatchingVersion { crate_name, spec } => {
+                write!(f, "no version of the {crate_name} crate matches {spec}")
+            }
+            Error::NoVersions { crate_name, list } => {
+                write!(
+                    f,
+                    "no versions available for the {crate_name} crate in {list}"
+                )
+            }
+            Error::FromLockfile(e) => {
+                write!(f, "failed to get crates from the lockfile: {e}")
+            }
+            Error::FromVendorDir(e) => {
+                write!(f, "failed to get crates from the vendor directory: {e}")
+            }
+            Error::FromMirror { mirror, error } => {
+                write!(f, "failed to get crates from the {mirror} mirror: {error}")
+            }
+            Error::QueryCategory { category, error } => {
+                write!(
+                    f,
+                    "failed to query the crates in the {category} category: {error}"
+                )
+            }
+            Error::QueryKeyword { keyword, error } => {
+                write!(
+                    f,
+                    "failed to query the crates with the {keyword} keyword: {error}"
+                )
+            }
+            Error::QueryRecentlyUpdated(e) => {
+                write!(f, "failed to query the recently updated crates: {e}")
+            }
+            Error::QueryOwner { owner, error } => {
+                write!(f, "failed to query the crates owned by {owner}: {error}")
+            }
+            Error::Search { query, error } => {
+                write!(f, "failed to search crates.io for {query}: {error}")
+            }
+            Error::GitDependencies { num_packages } => {
+                write!(
+                    f,
+                    "{num_packages} dependencies come from git repositories, which the mirror can't cover"
+                )
+            }
         }
     }
 }
@@ -40,6 +138,18 @@ impl std::error::Error for Error {
             Error::QueryMostDownloadedCrates(e) => Some(e),
             Error::MostDownloadedCrateNotFound(e) => Some(e),
             Error::FromFile(e) => Some(e.as_ref()),
+            Error::CrateNotFound(e) => Some(e),
+            Error::NoMatchingVersion { .. } => None,
+            Error::NoVersions { .. } => None,
+            Error::FromLockfile(e) => Some(e.as_ref()),
+            Error::FromVendorDir(e) => Some(e.as_ref()),
+            Error::FromMirror { error, .. } => Some(error.as_ref()),
+            Error::QueryCategory { error, .. } => Some(error),
+            Error::QueryKeyword { error, .. } => Some(error.as_ref()),
+            Error::QueryRecentlyUpdated(e) => Some(e.as_ref()),
+            Error::QueryOwner { error, .. } => Some(error.as_ref()),
+            Error::Search { error, .. } => Some(error.as_ref()),
+            Error::GitDependencies { .. } => None,
         }
     }
 }
@@ -58,18 +168,100 @@ impl From<crates_io_api::Error> for Error {
 
 type Result<T> = std::result::Result<T, Error>;
 
+/// A package found in a lockfile or vendor directory that doesn't come from a registry, so it
+/// can't be mirrored, and builds still need its git repository or local path.
+#[derive(Clone, Debug)]
+pub struct Unmirrorable {
+    pub name: String,
+    pub version: String,
+    pub origin: Origin,
+}
+
+/// Where an unmirrorable package comes from.
+#[derive(Clone, Debug)]
+pub enum Origin {
+    /// A git repository, e.g. `https://github.com/org/repo?branch=main#1a2b3c`. Unknown for
+    /// the git packages of vendor directories.
+    Git(Option<String>),
+    /// A local path, e.g. a path dependency or a workspace member.
+    Path,
+}
+
 pub struct TopLevelBuilder<'i> {
-    index: &'i crates_index::Index,
+    index: &'i Index,
     client: SyncClient,
+    http_client: reqwest::Client,
+    progress: &'i dyn ProgressObserver,
+    deny_warnings: bool,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    min_downloads: Option<u64>,
 }
 
 impl<'i> TopLevelBuilder<'i> {
-    pub fn new(index: &'i crates_index::Index) -> Result<Self> {
+    pub fn new(index: &'i Index, progress: &'i dyn ProgressObserver) -> Result<Self> {
         let client = SyncClient::new(
             "my-user-agent (my-contact@domain.com)",
             std::time::Duration::from_millis(1000),
         )?;
-        Ok(TopLevelBuilder { index, client })
+        Ok(TopLevelBuilder {
+            index,
+            client,
+            http_client: reqwest::Client::new(),
+            progress,
+            deny_warnings: false,
+            rate_limiter: None,
+            min_downloads: None,
+        })
+    }
+
+    /// Fetches the crate lists given as URLs with the client, e.g. one with the settings of
+    /// `http_config::HttpConfig`.
+    pub fn http_client(mut self, http_client: reqwest::Client) -> Self {
+        self.http_client = http_client;
+        self
+    }
+
+    /// Fails when a crate listed in a file has no versions available or a lockfile package
+    /// can't be read, instead of skipping it.
+    pub fn deny_warnings(mut self, deny_warnings: bool) -> Self {
+        self.deny_warnings = deny_warnings;
+        self
+    }
+
+    /// Makes the crates.io API queries through the limiter, on top of the client's own pacing.
+    pub fn rate_limiter(mut self, rate_limiter: Arc<RateLimiter>) -> Self {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
+
+    /// Skips the crates found by crates.io queries, e.g. in a category or with a keyword, that
+    /// were downloaded fewer than N times in total, so obscure crates stay out of broad
+    /// selections.
+    pub fn min_downloads(mut self, n: Option<u64>) -> Self {
+        self.min_downloads = n;
+        self
+    }
+
+    /// Whether the crate found by a crates.io query was downloaded often enough to select.
+    fn downloaded_enough(&self, crate_name: &str, downloads: u64) -> bool {
+        match self.min_downloads {
+            Some(min_downloads) if downloads < min_downloads => {
+                trace!("skipping the {crate_name} crate, downloaded {downloads} times");
+                false
+            }
+            _ => true,
+        }
+    }
+
+    fn query_crates(
+        &self,
+        query: CratesQuery,
+    ) -> std::result::Result<crates_io_api::CratesPage, crates_io_api::Error> {
+        let _permit = self
+            .rate_limiter
+            .as_ref()
+            .map(|limiter| limiter.acquire_blocking());
+        self.client.crates(query)
     }
 
     pub fn get_n_most_downloaded(&self, n: u64) -> Result<Vec<Version>> {
@@ -77,7 +269,7 @@ impl<'i> TopLevelBuilder<'i> {
 
         let mut num_pages = n / PAGE_SIZE;
         let mut trim_results = false;
-        if n % PAGE_SIZE != 0 {
+        if !n.is_multiple_of(PAGE_SIZE) {
             num_pages += 1;
             trim_results = true;
         }
@@ -89,12 +281,16 @@ impl<'i> TopLevelBuilder<'i> {
             .page_size(PAGE_SIZE)
             .build();
         for page_index in 0..num_pages {
-            println!("Most downloaded crates - retrieving page {:>3} of {:>3}...", page_index+1 , num_pages);
+            self.progress
+                .fetching_most_downloaded_page(page_index + 1, num_pages);
             query.set_page(page_index + 1);
-            let page = self.client.crates(query.clone())?;
+            let page = self.query_crates(query.clone())?;
             for crat in page.crates {
+                if !self.downloaded_enough(&crat.name, crat.downloads) {
+                    continue;
+                }
                 let crat = common::get_crate(self.index, &crat.name)
-                    .map_err(|e| Error::MostDownloadedCrateNotFound(e))?;
+                    .map_err(Error::MostDownloadedCrateNotFound)?;
                 let version = crat.highest_normal_version();
                 if version.is_none() {
                     // No versions available for this crate. Skip over it.
@@ -105,7 +301,7 @@ impl<'i> TopLevelBuilder<'i> {
                     );
                     continue;
                 }
-                let version = common::Version(version.unwrap().clone());
+                let version = version.unwrap().clone();
                 most_downloaded.push(version);
             }
         }
@@ -116,27 +312,571 @@ impl<'i> TopLevelBuilder<'i> {
         Ok(most_downloaded)
     }
 
-    pub fn from_file<P: AsRef<Path>>(&self, file_path: P) -> Result<Vec<Version>> {
-        let file =
-            BufReader::new(File::open(&file_path).map_err(|e| Error::FromFile(Box::new(e)))?);
+    /// Gets the crates listed in the file, one crate name per line. The file can also be
+    /// an http:// or https:// URL, e.g. a list maintained centrally for many mirrors, or `-`
+    /// for standard input.
+    ///
+    /// A .toml or .json file is a structured crate list instead, see
+    /// `selection::parse_crate_list`: the highest version of each crate matching its
+    /// requirement is selected, and its entry is returned along with the crates for its
+    /// features, targets, and dev-dependencies to be resolved.
+    pub fn from_file<P: AsRef<Path>>(
+        &self,
+        file_path: P,
+    ) -> Result<(Vec<Version>, Vec<CrateSpec>)> {
+        let file_path = file_path.as_ref();
+        if selection::is_stdin_list(file_path) {
+            let crates = self.from_reader(io::stdin().lock(), "standard input")?;
+            return Ok((crates, Vec::new()));
+        }
+        let contents = match selection::list_url(file_path) {
+            Some(url) => fetch_list(&self.http_client, url),
+            None => std::fs::read_to_string(file_path).map_err(|e| e.into()),
+        }
+        .map_err(Error::FromFile)?;
+        let format = ListFormat::of(file_path);
+        if format == ListFormat::Plain {
+            let list = format!("the {} file", file_path.to_string_lossy());
+            return Ok((self.from_reader(contents.as_bytes(), &list)?, Vec::new()));
+        }
+        let specs = selection::parse_crate_list(&contents, format)
+            .map_err(|e| Error::FromFile(Box::new(e)))?;
         let mut crates = Vec::new();
-        for line in file.lines() {
-            let crate_name = line.map_err(|e| Error::FromFile(Box::new(e)))?;
+        for spec in &specs {
+            crates.push(self.get_crate(spec)?);
+        }
+        Ok((crates, specs))
+    }
+
+    /// Gets the crates listed by the reader, one crate name per line, e.g. piped in from
+    /// another tool. The list describes where they come from in warnings and errors, e.g.
+    /// `standard input`.
+    pub fn from_reader<R: BufRead>(&self, reader: R, list: &str) -> Result<Vec<Version>> {
+        let mut crates = Vec::new();
+        for line in reader.lines() {
+            let line = line.map_err(|e| Error::FromFile(Box::new(e)))?;
+            let crate_name = line.trim().to_string();
+            if crate_name.is_empty() {
+                continue;
+            }
             let crat = common::get_crate(self.index, &crate_name)
                 .map_err(|e| Error::FromFile(Box::new(e)))?;
             let version = crat.highest_normal_version();
             if version.is_none() {
                 // No versions available for this crate. Skip over it.
-                let file_path = file_path.as_ref();
+                if self.deny_warnings {
+                    return Err(Error::NoVersions {
+                        crate_name,
+                        list: list.to_string(),
+                    });
+                }
                 warn!(
-                    "no versions available for the {crate_name} crate in the {} file",
-                    file_path.to_string_lossy()
+                    target: warnings::NO_VERSIONS,
+                    "no versions available for the {crate_name} crate in {list}"
                 );
                 continue;
             }
-            let version = common::Version(version.unwrap().clone());
+            let version = version.unwrap().clone();
             crates.push(version);
         }
         Ok(crates)
     }
+
+    /// Gets the highest normal version of the crate matching the spec's requirement.
+    /// Pre-release versions are only considered if no normal version matches.
+    /// Yanked versions are only considered if the requirement pins an exact version,
+    /// e.g. `=1.2.3`, as a lockfile would.
+    pub fn get_crate(&self, spec: &CrateSpec) -> Result<Version> {
+        let crat = common::get_crate(self.index, &spec.name).map_err(Error::CrateNotFound)?;
+        let pinned = match &spec.req {
+            Some(req) => is_exact_version(req),
+            None => false,
+        };
+        let mut matching: Vec<(semver::Version, &Version)> = crat
+            .versions()
+            .iter()
+            .filter(|v| pinned || !v.is_yanked())
+            .filter_map(|v| Some((semver::Version::parse(v.version()).ok()?, v)))
+            .filter(|(version, _)| match &spec.req {
+                Some(req) => req.matches(version),
+                None => true,
+            })
+            .collect();
+        matching.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let version = matching
+            .iter()
+            .rev()
+            .find(|(version, _)| version.pre.is_empty())
+            .or_else(|| matching.last())
+            .ok_or_else(|| Error::NoMatchingVersion {
+                crate_name: spec.name.clone(),
+                spec: spec.to_string(),
+            })?;
+        Ok(version.1.clone())
+    }
+
+    /// Gets the exact version of every package from crates.io locked in the Cargo.lock file,
+    /// even if it was yanked since. Other registry packages are skipped, and path and git
+    /// packages are returned as unmirrorable.
+    pub fn from_lockfile<P: AsRef<Path>>(
+        &self,
+        lockfile_path: P,
+    ) -> Result<(Vec<Version>, Vec<Unmirrorable>)> {
+        const CRATES_IO_SOURCES: [&str; 2] = [
+            "registry+https://github.com/rust-lang/crates.io-index",
+            "sparse+https://index.crates.io/",
+        ];
+
+        let contents = std::fs::read_to_string(&lockfile_path)
+            .map_err(|e| Error::FromLockfile(Box::new(e)))?;
+        let lockfile = contents
+            .parse::<toml::Value>()
+            .map_err(|e| Error::FromLockfile(Box::new(e)))?;
+        let packages = lockfile
+            .get("package")
+            .and_then(|p| p.as_array())
+            .map(|p| p.as_slice())
+            .unwrap_or_default();
+
+        let mut crates = Vec::new();
+        let mut unmirrorable = Vec::new();
+        for package in packages {
+            let origin = match package.get("source").and_then(|s| s.as_str()) {
+                Some(s) if CRATES_IO_SOURCES.contains(&s) => None,
+                Some(s) => match s.strip_prefix("git+") {
+                    Some(url) => Some(Origin::Git(Some(url.to_string()))),
+                    None => continue,
+                },
+                None => Some(Origin::Path),
+            };
+            let (name, version) = match (
+                package.get("name").and_then(|n| n.as_str()),
+                package.get("version").and_then(|v| v.as_str()),
+            ) {
+                (Some(name), Some(version)) => (name, version),
+                _ if self.deny_warnings => {
+                    return Err(Error::FromLockfile(
+                        "a package has no name or version".into(),
+                    ));
+                }
+                _ => {
+                    warn!(
+                        "skipping a package without a name or version in the {} file",
+                        lockfile_path.as_ref().to_string_lossy()
+                    );
+                    continue;
+                }
+            };
+            if let Some(origin) = origin {
+                unmirrorable.push(Unmirrorable {
+                    name: name.to_string(),
+                    version: version.to_string(),
+                    origin,
+                });
+                continue;
+            }
+            let crat = common::get_crate(self.index, name)
+                .map_err(|e| Error::FromLockfile(Box::new(e)))?;
+            let locked = crat
+                .versions()
+                .iter()
+                .find(|v| v.version() == version)
+                .ok_or_else(|| Error::NoMatchingVersion {
+                    crate_name: name.to_string(),
+                    spec: format!("{name}@={version}"),
+                })?;
+            crates.push(locked.clone());
+        }
+        Ok((crates, unmirrorable))
+    }
+
+    /// Gets the exact version of every registry package in the `cargo vendor` directory.
+    /// Packages whose checksum differs from the index's are skipped, since they come from
+    /// another registry, and git packages are returned as unmirrorable.
+    pub fn from_vendor_dir<P: AsRef<Path>>(
+        &self,
+        vendor_dir_path: P,
+    ) -> Result<(Vec<Version>, Vec<Unmirrorable>)> {
+        let packages = vendor::read_vendor_dir(&vendor_dir_path)
+            .map_err(|e| Error::FromVendorDir(Box::new(e)))?;
+        let mut crates = Vec::new();
+        let mut unmirrorable = Vec::new();
+        for package in packages {
+            let checksum = match package.checksum {
+                Some(checksum) => checksum,
+                None => {
+                    unmirrorable.push(Unmirrorable {
+                        name: package.name,
+                        version: package.version,
+                        origin: Origin::Git(None),
+                    });
+                    continue;
+                }
+            };
+            let crat = common::get_crate(self.index, &package.name)
+                .map_err(|e| Error::FromVendorDir(Box::new(e)))?;
+            let vendored = crat
+                .versions()
+                .iter()
+                .find(|v| v.version() == package.version)
+                .ok_or_else(|| Error::NoMatchingVersion {
+                    spec: format!("{}@={}", package.name, package.version),
+                    crate_name: package.name.clone(),
+                })?;
+            let vendored = vendored.clone();
+            if vendored.checksum() != checksum {
+                if self.deny_warnings {
+                    return Err(Error::FromVendorDir(
+                        format!(
+                            "{} version {} doesn't match the index checksum",
+                            package.name, package.version
+                        )
+                        .into(),
+                    ));
+                }
+                warn!(
+                    "skipping {} version {} in the {} vendor directory: it doesn't match the index checksum",
+                    package.name,
+                    package.version,
+                    vendor_dir_path.as_ref().to_string_lossy()
+                );
+                continue;
+            }
+            crates.push(vendored);
+        }
+        Ok((crates, unmirrorable))
+    }
+
+    /// Gets the exact version of every crate in another micrio mirror, given by the path of its
+    /// directory or the URL of its index git repo, which is cloned to a temporary directory.
+    pub fn from_mirror(&self, mirror: &str) -> Result<Vec<Version>> {
+        let mirror_error =
+            |e: Box<dyn std::error::Error + Send + Sync + 'static>| Error::FromMirror {
+                mirror: mirror.to_string(),
+                error: e,
+            };
+        // Single letter schemes are Windows drive letters.
+        let clone_dir_path = match Url::parse(mirror) {
+            Ok(url) if url.scheme().len() > 1 => {
+                let clone_dir_path =
+                    std::env::temp_dir().join(format!("micrio-mirror-{}", std::process::id()));
+                if clone_dir_path.exists() {
+                    std::fs::remove_dir_all(&clone_dir_path)
+                        .map_err(|e| mirror_error(Box::new(e)))?;
+                }
+                git2::Repository::clone(mirror, &clone_dir_path)
+                    .map_err(|e| mirror_error(Box::new(e)))?;
+                Some(clone_dir_path)
+            }
+            _ => None,
+        };
+        let index_dir_path = clone_dir_path
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(mirror).join(INDEX_DIR));
+        let entries = read_index_entries(&index_dir_path);
+        if let Some(clone_dir_path) = &clone_dir_path {
+            // Best effort: a leftover clone in the temp directory is harmless.
+            let _ = std::fs::remove_dir_all(clone_dir_path);
+        }
+
+        let mut crates = Vec::new();
+        for entry in entries.map_err(mirror_error)? {
+            let crat = common::get_crate(self.index, &entry.name)
+                .map_err(|e| mirror_error(Box::new(e)))?;
+            let mirrored = crat
+                .versions()
+                .iter()
+                .find(|v| v.version() == entry.vers)
+                .ok_or_else(|| Error::NoMatchingVersion {
+                    spec: format!("{}@={}", entry.name, entry.vers),
+                    crate_name: entry.name.clone(),
+                })?;
+            crates.push(mirrored.clone());
+        }
+        Ok(crates)
+    }
+
+    /// Gets the highest normal version of the most downloaded crates in the crates.io category.
+    pub fn from_category(&self, category: &CategorySpec) -> Result<Vec<Version>> {
+        const PAGE_SIZE: u64 = 50;
+
+        let query_error = |e| Error::QueryCategory {
+            category: category.slug.clone(),
+            error: e,
+        };
+        let mut query = CratesQuery::builder()
+            .sort(Sort::Downloads)
+            .category(category.slug.clone())
+            .page_size(PAGE_SIZE)
+            .build();
+        let mut crates = Vec::new();
+        let limit = category.limit.map_or(usize::MAX, |n| n as usize);
+        let mut page_index = 1;
+        while crates.len() < limit {
+            query.set_page(page_index);
+            let page = self.query_crates(query.clone()).map_err(query_error)?;
+            let num_on_page = page.crates.len() as u64;
+            for crat in page.crates {
+                if !self.downloaded_enough(&crat.name, crat.downloads) {
+                    continue;
+                }
+                let crat =
+                    common::get_crate(self.index, &crat.name).map_err(Error::CrateNotFound)?;
+                match crat.highest_normal_version() {
+                    Some(version) => crates.push(version.clone()),
+                    None => trace!(
+                        "no versions available for the {} crate in the {} category",
+                        crat.name(),
+                        category.slug
+                    ),
+                }
+            }
+            if num_on_page < PAGE_SIZE || page_index * PAGE_SIZE >= page.meta.total {
+                break;
+            }
+            page_index += 1;
+        }
+        crates.truncate(limit);
+        Ok(crates)
+    }
+
+    /// Gets the highest normal version of the N crates most recently updated on crates.io,
+    /// the most recent first. As with the other crates.io queries, a crate the index hasn't
+    /// caught up with yet is an error rather than being skipped.
+    pub fn get_n_recently_updated(&self, n: u64) -> Result<Vec<Version>> {
+        self.query_api_crates(
+            &[("sort", "recent-updates")],
+            Some(n),
+            "among the recently updated crates",
+            &Error::QueryRecentlyUpdated,
+        )
+    }
+
+    /// Gets the highest normal version of the most downloaded crates tagged with the crates.io
+    /// keyword.
+    pub fn from_keyword(&self, keyword: &KeywordSpec) -> Result<Vec<Version>> {
+        let query_error = |e| Error::QueryKeyword {
+            keyword: keyword.keyword.clone(),
+            error: e,
+        };
+        self.query_api_crates(
+            &[("keyword", keyword.keyword.as_str()), ("sort", "downloads")],
+            keyword.limit,
+            &format!("with the {} keyword", keyword.keyword),
+            &query_error,
+        )
+    }
+
+    /// Gets the highest normal version of the crates the crates.io search finds for the query,
+    /// most relevant first, the way the search box on crates.io does.
+    pub fn from_search(&self, search: &SearchSpec) -> Result<Vec<Version>> {
+        let query_error = |e| Error::Search {
+            query: search.query.clone(),
+            error: e,
+        };
+        self.query_api_crates(
+            &[("q", search.query.as_str()), ("sort", "relevance")],
+            search.limit,
+            &format!("found by the search {}", search.query),
+            &query_error,
+        )
+    }
+
+    /// Gets the highest normal version of every crate owned by the crates.io user or team,
+    /// given by the user's login, e.g. `dtolnay`, or the team's name, e.g.
+    /// `github:rust-lang:libs`.
+    pub fn from_owner(&self, owner: &str) -> Result<Vec<Version>> {
+        let query_error = |e| Error::QueryOwner {
+            owner: owner.to_string(),
+            error: e,
+        };
+        let (key, id) = if owner.contains(':') {
+            let team: ApiTeamResponse = self
+                .api_get(&format!("teams/{owner}"))
+                .map_err(query_error)?;
+            ("team_id", team.team.id)
+        } else {
+            let user: ApiUserResponse = self
+                .api_get(&format!("users/{owner}"))
+                .map_err(query_error)?;
+            ("user_id", user.user.id)
+        };
+        let id = id.to_string();
+        self.query_api_crates(
+            &[(key, id.as_str()), ("sort", "downloads")],
+            None,
+            &format!("owned by {owner}"),
+            &query_error,
+        )
+    }
+
+    /// Gets the highest normal version of the crates the crates.io API lists for the query,
+    /// in the order it sorts them, up to the limit. The crates.io API client can't filter by
+    /// keyword, so the API is queried directly. The description of the crates, e.g.
+    /// `with the async keyword`, is traced for those without versions.
+    fn query_api_crates(
+        &self,
+        query: &[(&str, &str)],
+        limit: Option<u64>,
+        description: &str,
+        query_error: &dyn Fn(ApiError) -> Error,
+    ) -> Result<Vec<Version>> {
+        const PAGE_SIZE: u64 = 50;
+
+        let mut crates = Vec::new();
+        let limit = limit.map_or(usize::MAX, |n| n as usize);
+        let mut page_index = 1;
+        while crates.len() < limit {
+            let mut params = url::form_urlencoded::Serializer::new(String::new());
+            params
+                .extend_pairs(query)
+                .append_pair("per_page", &PAGE_SIZE.to_string())
+                .append_pair("page", &page_index.to_string());
+            let page: ApiCratesPage = self
+                .api_get(&format!("crates?{}", params.finish()))
+                .map_err(query_error)?;
+            let num_on_page = page.crates.len() as u64;
+            for crat in page.crates {
+                if !self.downloaded_enough(&crat.name, crat.downloads) {
+                    continue;
+                }
+                let crat =
+                    common::get_crate(self.index, &crat.name).map_err(Error::CrateNotFound)?;
+                match crat.highest_normal_version() {
+                    Some(version) => crates.push(version.clone()),
+                    None => trace!(
+                        "no versions available for the {} crate {description}",
+                        crat.name()
+                    ),
+                }
+            }
+            if num_on_page < PAGE_SIZE || page_index * PAGE_SIZE >= page.meta.total {
+                break;
+            }
+            page_index += 1;
+        }
+        crates.truncate(limit);
+        Ok(crates)
+    }
+
+    /// Gets and parses the crates.io API's response at the path, e.g. `users/dtolnay`,
+    /// through the rate limiter.
+    fn api_get<T: DeserializeOwned>(&self, path: &str) -> std::result::Result<T, ApiError> {
+        let _permit = self
+            .rate_limiter
+            .as_ref()
+            .map(|limiter| limiter.acquire_blocking());
+        let url = format!("{CRATES_IO_API}/{path}");
+        let rt = tokio::runtime::Runtime::new()?;
+        let body = rt.block_on(async {
+            let response = self.http_client.get(url).send().await?.error_for_status()?;
+            response.text().await
+        })?;
+        Ok(serde_json::from_str(&body)?)
+    }
+}
+
+/// An error querying the crates.io API directly.
+type ApiError = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+/// A page of the crates.io API's `GET /api/v1/crates` response.
+#[derive(Deserialize)]
+struct ApiCratesPage {
+    crates: Vec<ApiCrate>,
+    meta: ApiMeta,
+}
+
+#[derive(Deserialize)]
+struct ApiCrate {
+    name: String,
+    downloads: u64,
+}
+
+#[derive(Deserialize)]
+struct ApiMeta {
+    total: u64,
+}
+
+/// The crates.io API's `GET /api/v1/users/{login}` response.
+#[derive(Deserialize)]
+struct ApiUserResponse {
+    user: ApiOwner,
+}
+
+/// The crates.io API's `GET /api/v1/teams/{name}` response.
+#[derive(Deserialize)]
+struct ApiTeamResponse {
+    team: ApiOwner,
+}
+
+#[derive(Deserialize)]
+struct ApiOwner {
+    id: u64,
+}
+
+/// Fetches the crate list at the URL.
+fn fetch_list(
+    client: &reqwest::Client,
+    url: &str,
+) -> std::result::Result<String, Box<dyn std::error::Error + Send + Sync>> {
+    let rt = tokio::runtime::Runtime::new()?;
+    let contents = rt.block_on(async {
+        let response = client.get(url).send().await?.error_for_status()?;
+        response.text().await
+    })?;
+    Ok(contents)
+}
+
+/// Whether the requirement only matches one version, e.g. `=1.2.3`.
+fn is_exact_version(req: &VersionReq) -> bool {
+    match req.comparators.as_slice() {
+        [comparator] => {
+            comparator.op == Op::Exact && comparator.minor.is_some() && comparator.patch.is_some()
+        }
+        _ => false,
+    }
+}
+
+/// The fields of an index line needed to identify the crate version.
+#[derive(Deserialize)]
+pub(crate) struct IndexEntry {
+    pub name: String,
+    pub vers: String,
+    #[serde(default)]
+    pub yanked: bool,
+}
+
+/// Reads every crate version listed in a registry index directory.
+pub(crate) fn read_index_entries(
+    index_dir_path: &Path,
+) -> std::result::Result<Vec<IndexEntry>, Box<dyn std::error::Error + Send + Sync + 'static>> {
+    read_index(index_dir_path)
+}
+
+/// Reads every entry of a registry index directory, each parsed as a `T`, e.g. a whole
+/// `Version`.
+pub(crate) fn read_index<T: DeserializeOwned>(
+    index_dir_path: &Path,
+) -> std::result::Result<Vec<T>, Box<dyn std::error::Error + Send + Sync + 'static>> {
+    if !index_dir_path.is_dir() {
+        return Err(format!("{} is not a directory", index_dir_path.to_string_lossy()).into());
+    }
+    let mut entries = Vec::new();
+    let walker = WalkDir::new(index_dir_path)
+        .into_iter()
+        .filter_entry(|e| e.file_name() != ".git");
+    for dir_entry in walker {
+        let dir_entry = dir_entry?;
+        if !dir_entry.file_type().is_file() || dir_entry.file_name() == "config.json" {
+            continue;
+        }
+        let file = BufReader::new(File::open(dir_entry.path())?);
+        for line in file.lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                entries.push(serde_json::from_str(&line)?);
+            }
+        }
+    }
+    Ok(entries)
 }
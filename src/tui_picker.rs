@@ -0,0 +1,160 @@
+use crate::tui::TerminalGuard;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use micrio::common::Version;
+use micrio::picker::{Candidate, TopLevelPicker};
+use micrio::site::format_size;
+use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::style::{Modifier, Style};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Borders, Cell, Paragraph, Row, Table, TableState};
+use ratatui::Frame;
+use std::collections::HashSet;
+use std::io;
+
+const HELP: &str =
+    "↑/↓ move  space toggle  a all  n none  enter mirror the checked crates  q/esc cancel";
+
+/// Lists the top-level crates in the terminal, all checked, for the user to uncheck the ones
+/// they don't want before dependency resolution begins.
+pub struct TuiPicker;
+
+impl TopLevelPicker for TuiPicker {
+    fn pick(&self, candidates: Vec<Candidate>) -> io::Result<Option<HashSet<Version>>> {
+        let mut terminal = TerminalGuard::enter()?;
+        let mut state = PickerState {
+            checked: vec![true; candidates.len()],
+            table: TableState::default().with_selected(Some(0)),
+            candidates,
+        };
+        loop {
+            terminal.draw(|frame| state.render(frame))?;
+            let key = match event::read()? {
+                // Windows also reports key releases.
+                Event::Key(key) if key.kind == KeyEventKind::Press => key,
+                _ => continue,
+            };
+            match key.code {
+                KeyCode::Up | KeyCode::Char('k') => state.move_by(-1),
+                KeyCode::Down | KeyCode::Char('j') => state.move_by(1),
+                KeyCode::PageUp => state.move_by(-20),
+                KeyCode::PageDown => state.move_by(20),
+                KeyCode::Home => state.move_by(isize::MIN),
+                KeyCode::End => state.move_by(isize::MAX),
+                KeyCode::Char(' ') => state.toggle(),
+                KeyCode::Char('a') => state.checked.fill(true),
+                KeyCode::Char('n') => state.checked.fill(false),
+                KeyCode::Enter => return Ok(Some(state.picked())),
+                KeyCode::Esc | KeyCode::Char('q') => return Ok(None),
+                _ => {}
+            }
+        }
+    }
+}
+
+struct PickerState {
+    candidates: Vec<Candidate>,
+    checked: Vec<bool>,
+    table: TableState,
+}
+
+impl PickerState {
+    fn move_by(&mut self, delta: isize) {
+        if self.candidates.is_empty() {
+            return;
+        }
+        let selected = self.table.selected().unwrap_or(0) as isize;
+        let last = self.candidates.len() as isize - 1;
+        let selected = selected.saturating_add(delta).clamp(0, last);
+        self.table.select(Some(selected as usize));
+    }
+
+    fn toggle(&mut self) {
+        if let Some(checked) = self.table.selected().and_then(|i| self.checked.get_mut(i)) {
+            *checked = !*checked;
+        }
+    }
+
+    fn picked(&self) -> HashSet<Version> {
+        HashSet::from_iter(
+            self.candidates
+                .iter()
+                .zip(&self.checked)
+                .filter(|(_, checked)| **checked)
+                .map(|(candidate, _)| candidate.crat.clone()),
+        )
+    }
+
+    fn render(&mut self, frame: &mut Frame) {
+        let areas = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(3), Constraint::Length(2)])
+            .split(frame.size());
+
+        let rows = self
+            .candidates
+            .iter()
+            .zip(&self.checked)
+            .map(|(candidate, checked)| {
+                let size = match candidate.size {
+                    Some(size) => format_size(size),
+                    None => "?".to_string(),
+                };
+                Row::new(vec![
+                    Cell::from(if *checked { "[x]" } else { "[ ]" }),
+                    Cell::from(candidate.crat.name().to_string()),
+                    Cell::from(candidate.crat.version().to_string()),
+                    Cell::from(size),
+                    Cell::from(candidate.num_dependencies.to_string()),
+                    Cell::from(candidate.sources.join(", ")),
+                ])
+            });
+        let widths = [
+            Constraint::Length(3),
+            Constraint::Percentage(25),
+            Constraint::Length(16),
+            Constraint::Length(10),
+            Constraint::Length(5),
+            Constraint::Min(10),
+        ];
+        let header = Row::new(vec!["", "Crate", "Version", "Size", "Deps", "Selected by"])
+            .style(Style::default().add_modifier(Modifier::BOLD));
+        let table = Table::new(rows, widths)
+            .header(header)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(" Top-level crates "),
+            )
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(table, areas[0], &mut self.table);
+
+        let (num_checked, checked_size, num_unknown) = self.checked_totals();
+        let mut summary = format!(
+            "{num_checked} of {} crates checked, {}",
+            self.candidates.len(),
+            format_size(checked_size)
+        );
+        if num_unknown > 0 {
+            summary += &format!(" ({num_unknown} of unknown size)");
+        }
+        summary += " before dependencies";
+        let footer = Paragraph::new(vec![Line::from(summary), Line::from(HELP)]);
+        frame.render_widget(footer, areas[1]);
+    }
+
+    /// The number of checked crates, their known total size, and how many have no known size.
+    fn checked_totals(&self) -> (usize, u64, usize) {
+        let mut totals = (0, 0, 0);
+        for (candidate, checked) in self.candidates.iter().zip(&self.checked) {
+            if !*checked {
+                continue;
+            }
+            totals.0 += 1;
+            match candidate.size {
+                Some(size) => totals.1 += size,
+                None => totals.2 += 1,
+            }
+        }
+        totals
+    }
+}
@@ -0,0 +1,259 @@
+//! Bridges micrio mirrors and `cargo vendor` directories, the other common way of building
+//! offline.
+
+use crate::dst_registry::{self, DstRegistry, INDEX_DIR};
+use crate::top_level;
+use flate2::read::GzDecoder;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Display};
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Component, Path, PathBuf};
+
+#[derive(Debug)]
+pub enum Error {
+    ReadIndex(Box<dyn std::error::Error + Send + Sync + 'static>),
+    OpenMirror(dst_registry::Error),
+    ReadCrateFile {
+        crate_name: String,
+        crate_version: String,
+        error: io::Error,
+    },
+    Unpack {
+        crate_name: String,
+        crate_version: String,
+        error: io::Error,
+    },
+    ReadVendorDir {
+        path: PathBuf,
+        error: Box<dyn std::error::Error + Send + Sync + 'static>,
+    },
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::ReadIndex(e) => write!(f, "failed to read the mirror's index: {e}"),
+            Error::OpenMirror(e) => write!(f, "{e}"),
+            Error::ReadCrateFile {
+                crate_name,
+                crate_version,
+                error,
+            } => {
+                write!(
+                    f,
+                    "failed to read the .crate file for {crate_name} version {crate_version}: {error}"
+                )
+            }
+            Error::Unpack {
+                crate_name,
+                crate_version,
+                error,
+            } => {
+                write!(
+                    f,
+                    "failed to unpack {crate_name} version {crate_version} into the vendor directory: {error}"
+                )
+            }
+            Error::ReadVendorDir { path, error } => {
+                write!(
+                    f,
+                    "failed to read the vendored package in {}: {error}",
+                    path.to_string_lossy()
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::ReadIndex(e) => Some(e.as_ref()),
+            Error::OpenMirror(e) => Some(e),
+            Error::ReadCrateFile { error, .. } => Some(error),
+            Error::Unpack { error, .. } => Some(error),
+            Error::ReadVendorDir { error, .. } => Some(error.as_ref()),
+        }
+    }
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+/// The file Cargo checks a vendored package's files against.
+const CHECKSUM_FILE: &str = ".cargo-checksum.json";
+
+/// The contents of `.cargo-checksum.json`: the SHA-256 of each file of the package, and of
+/// the .crate file it was unpacked from, which is `None` for git dependencies.
+#[derive(Serialize, Deserialize)]
+struct CargoChecksum {
+    files: BTreeMap<String, String>,
+    package: Option<String>,
+}
+
+/// A package in a `cargo vendor` directory.
+pub struct VendoredPackage {
+    pub name: String,
+    pub version: String,
+    /// The SHA-256 of the .crate file the package was unpacked from. `None` for packages that
+    /// don't come from a registry, e.g. git dependencies.
+    pub checksum: Option<String>,
+}
+
+/// Reads the name, version, and checksum of every package in the `cargo vendor` directory.
+pub fn read_vendor_dir<P: AsRef<Path>>(vendor_dir_path: P) -> Result<Vec<VendoredPackage>> {
+    let vendor_dir_path = vendor_dir_path.as_ref();
+    let read_error =
+        |path: &Path, e: Box<dyn std::error::Error + Send + Sync + 'static>| Error::ReadVendorDir {
+            path: path.to_path_buf(),
+            error: e,
+        };
+    let entries =
+        fs::read_dir(vendor_dir_path).map_err(|e| read_error(vendor_dir_path, e.into()))?;
+
+    let mut packages = Vec::new();
+    for entry in entries {
+        let package_dir_path = entry
+            .map_err(|e| read_error(vendor_dir_path, e.into()))?
+            .path();
+        let checksum_path = package_dir_path.join(CHECKSUM_FILE);
+        if !checksum_path.is_file() {
+            continue;
+        }
+        let package_error = |e| read_error(&package_dir_path, e);
+        let checksum = fs::read_to_string(&checksum_path).map_err(|e| package_error(e.into()))?;
+        let checksum: CargoChecksum =
+            serde_json::from_str(&checksum).map_err(|e| package_error(e.into()))?;
+        let manifest = fs::read_to_string(package_dir_path.join("Cargo.toml"))
+            .map_err(|e| package_error(e.into()))?;
+        let manifest = manifest
+            .parse::<toml::Value>()
+            .map_err(|e| package_error(e.into()))?;
+        let field = |key: &str| {
+            manifest
+                .get("package")
+                .and_then(|p| p.get(key))
+                .and_then(|v| v.as_str())
+                .map(|v| v.to_string())
+        };
+        let (name, version) = match (field("name"), field("version")) {
+            (Some(name), Some(version)) => (name, version),
+            _ => {
+                return Err(package_error(
+                    "Cargo.toml has no package name or version".into(),
+                ))
+            }
+        };
+        packages.push(VendoredPackage {
+            name,
+            version,
+            checksum: checksum.package,
+        });
+    }
+    Ok(packages)
+}
+
+/// Unpacks every crate version in the mirror into the directory the way `cargo vendor` does,
+/// with a `.cargo-checksum.json` file in each package, so Cargo can build from it as a
+/// directory source. Crates with a single version get a directory named after the crate,
+/// the others one named `{name}-{version}`, or all of them if `versioned_dirs` is set.
+///
+/// Returns the number of packages written.
+pub fn export_vendor_dir<P: AsRef<Path>, Q: AsRef<Path>>(
+    mirror_dir_path: P,
+    vendor_dir_path: Q,
+    versioned_dirs: bool,
+) -> Result<usize> {
+    let mirror_dir_path = mirror_dir_path.as_ref();
+    let vendor_dir_path = vendor_dir_path.as_ref();
+    let entries = top_level::read_index_entries(&mirror_dir_path.join(INDEX_DIR))
+        .map_err(Error::ReadIndex)?;
+    let dst_registry = DstRegistry::open(mirror_dir_path).map_err(Error::OpenMirror)?;
+
+    let mut num_versions: HashMap<&str, usize> = HashMap::new();
+    for entry in &entries {
+        *num_versions.entry(&entry.name).or_default() += 1;
+    }
+    for entry in &entries {
+        let (name, version) = (entry.name.as_str(), entry.vers.as_str());
+        let crate_file =
+            dst_registry
+                .read_crate_file(name, version)
+                .map_err(|e| Error::ReadCrateFile {
+                    crate_name: name.to_string(),
+                    crate_version: version.to_string(),
+                    error: e,
+                })?;
+        let package_dir_name = if versioned_dirs || num_versions[name] > 1 {
+            format!("{name}-{version}")
+        } else {
+            name.to_string()
+        };
+        unpack_crate(
+            &crate_file,
+            name,
+            version,
+            &vendor_dir_path.join(package_dir_name),
+        )
+        .map_err(|e| Error::Unpack {
+            crate_name: name.to_string(),
+            crate_version: version.to_string(),
+            error: e,
+        })?;
+    }
+    Ok(entries.len())
+}
+
+/// Unpacks the .crate file into the package directory, replacing what was there, and writes
+/// the checksums of its files.
+fn unpack_crate(
+    crate_file: &[u8],
+    name: &str,
+    version: &str,
+    package_dir_path: &Path,
+) -> io::Result<()> {
+    if package_dir_path.exists() {
+        fs::remove_dir_all(package_dir_path)?;
+    }
+    fs::create_dir_all(package_dir_path)?;
+
+    let root = format!("{name}-{version}");
+    let mut files = BTreeMap::new();
+    let mut archive = tar::Archive::new(GzDecoder::new(crate_file));
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let path = entry.path()?.into_owned();
+        let rel_path = match path.strip_prefix(&root) {
+            Ok(rel_path) => rel_path.to_path_buf(),
+            Err(_) => continue,
+        };
+        // Never write outside the package directory.
+        if !rel_path
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)))
+        {
+            continue;
+        }
+        let mut contents = Vec::new();
+        entry.read_to_end(&mut contents)?;
+        let file_path = package_dir_path.join(&rel_path);
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&file_path, &contents)?;
+        let key = rel_path.to_string_lossy().replace('\\', "/");
+        files.insert(key, hex::encode(Sha256::digest(&contents)));
+    }
+
+    let checksum = CargoChecksum {
+        files,
+        package: Some(hex::encode(Sha256::digest(crate_file))),
+    };
+    let contents = serde_json::to_vec(&checksum)?;
+    fs::write(package_dir_path.join(CHECKSUM_FILE), contents)
+}
@@ -0,0 +1,22 @@
+//! The tracing targets of the warnings logged for conditions that don't fail the run, so they
+//! can be filtered by category, e.g. with `RUST_LOG=micrio=warn,micrio::platform=off`.
+
+/// A dependency's platform couldn't be parsed, so the dependency was kept for every target.
+pub const PLATFORM: &str = "micrio::platform";
+/// No version of a dependency matches its requirement, so it was left out.
+pub const MISSING_DEPENDENCY: &str = "micrio::missing_dependency";
+/// A selected crate has no versions available, so it was skipped.
+pub const NO_VERSIONS: &str = "micrio::no_versions";
+/// A crate version couldn't be checked for advisories.
+pub const ADVISORY: &str = "micrio::advisory";
+/// The Cargo.toml packaged in a .crate file couldn't be parsed, so its metadata was skipped.
+pub const PACKAGED_MANIFEST: &str = "micrio::packaged_manifest";
+/// A lockfile or vendor directory has a git dependency, which the mirror can't cover, so builds
+/// still need network access to its repository.
+pub const UNMIRRORABLE: &str = "micrio::unmirrorable";
+/// A crate's source repository couldn't be cloned or fetched into, so the mirror lacks it
+/// or has an outdated copy.
+pub const REPOSITORY: &str = "micrio::repository";
+/// A top-level crate can't be resolved against the mirror, since a transitive dependency has
+/// no compatible version in it.
+pub const UNRESOLVABLE: &str = "micrio::unresolvable";